@@ -0,0 +1,63 @@
+#[macro_use]
+extern crate criterion;
+extern crate componsition_lang;
+
+use componsition_lang::parse::token::{parse_lexs, parse_tokens};
+use criterion::{Criterion, Throughput};
+
+use std::fs;
+use std::path::PathBuf;
+
+const TARGET_BYTES: usize = 4 * 1024 * 1024;
+
+/// Writes a `.clf` file of at least `TARGET_BYTES`, repeating a chunk of every kind of lexeme
+/// with each repetition's names made unique.
+fn generate_source() -> PathBuf {
+    let mut source = String::with_capacity(TARGET_BYTES + 1024);
+    let mut i = 0usize;
+    while source.len() < TARGET_BYTES {
+        source.push_str(&format!(
+            "/// A point with an index.
+type Point{i}: (int(x), int(y),)
+type Pair{i}<A, B>: (A(first), B(second),)
+
+// swaps the elements of a pair
+trans swap{i}<A, B>: Pair{i}<A, B> {{
+  Pair{i}<B, A>(second, first)
+}}
+
+calc start{i}: () {{
+  größe_{i}: 0x_ff_{i:x};
+  p: [Point{i}(1_000, {i})];
+  \"größe is {{größe_{i}}}, p is {{p}}\\n\" => print;
+  [Pair{i}<int, Point{i}>(1.5e3f, Point{i}(1, 2))] => swap{i} => print;
+  /* nested /* block */ comment */
+  0..10 by 2 => print;
+}}
+
+",
+            i = i
+        ));
+        i += 1;
+    }
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("lex_bench.clf");
+    fs::write(&path, source).unwrap();
+    path
+}
+
+fn lex_benchmark(c: &mut Criterion) {
+    let source = fs::read(generate_source()).unwrap();
+    let mut group = c.benchmark_group("lex");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.sample_size(10);
+    group.bench_function("parse_lexs", |b| {
+        b.iter(|| parse_lexs(&source).unwrap().len())
+    });
+    group.bench_function("parse_tokens", |b| {
+        b.iter(|| parse_tokens(&source).unwrap().current_scope().get_index())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, lex_benchmark);
+criterion_main!(benches);
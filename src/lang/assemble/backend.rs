@@ -0,0 +1,835 @@
+use crate::lang::assemble::context::{
+    c_identifier, AssembledTypeContext, AssemblyFormatContext, CONTEXT_SLOTS,
+};
+use crate::lang::assemble::instruction::{
+    AssembledInstruction, PrintKind, RangeBounds, StreamInstruction,
+};
+use crate::lang::assemble::AssembledMethod;
+use crate::parse::constant::Operator;
+
+use std::io::{self, Write};
+
+/// The buffers a pipeline moves its elements between.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buffer {
+    Intake,
+    Outgive,
+}
+
+/// A target language for assembled instructions. `AssembledInstruction::write` hands every
+/// operation to the backend along with the instructions of its operands, which an implementation
+/// writes in turn by calling `write` on them with itself.
+pub trait Backend: Sized {
+    /// Everything before the first method.
+    fn begin_program(
+        &mut self,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    /// Declares the function the method `name` taking in elements of `class_id` is lowered to,
+    /// so that methods may call those written after them.
+    fn declare_method(
+        &mut self,
+        class_id: u16,
+        name: &str,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    /// The function the method `name` taking in elements of `class_id` is lowered to, running
+    /// `body` on the element it is called with.
+    fn method(
+        &mut self,
+        class_id: u16,
+        name: &str,
+        body: &[AssembledInstruction],
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    /// The entry point of the program, running `body`.
+    fn start(
+        &mut self,
+        body: &[AssembledInstruction],
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    /// Everything after the last method.
+    fn end_program(&mut self, formats: &mut AssemblyFormatContext) -> io::Result<()>;
+
+    fn declare_context(&mut self) -> io::Result<()>;
+    fn init_context(&mut self) -> io::Result<()>;
+    fn declare_calculation_scope(&mut self, formats: &AssemblyFormatContext) -> io::Result<()>;
+
+    fn set_length(&mut self, length: u64) -> io::Result<()>;
+    fn alloc(&mut self, buffer: Buffer, bytes: u64) -> io::Result<()>;
+    /// Allocates an empty outgive with room for the bytes `bytes` evaluates to.
+    fn alloc_outgive_for(
+        &mut self,
+        bytes: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn free_intake(&mut self) -> io::Result<()>;
+    fn free_slot(&mut self, slot: u16) -> io::Result<()>;
+    fn load_intake(&mut self, slot: u16) -> io::Result<()>;
+    fn save_outgive(&mut self, slot: u16) -> io::Result<()>;
+    fn context_slot(&mut self, slot: u16) -> io::Result<()>;
+
+    fn add_intake(
+        &mut self,
+        value: &AssembledInstruction,
+        class_id: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn add_intake_bytes(&mut self, bytes: &[u8]) -> io::Result<()>;
+    fn add_intake_from(
+        &mut self,
+        source: &AssembledInstruction,
+        bytes: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn add_outgive(&mut self, bytes: u16) -> io::Result<()>;
+    /// Moves the intake back to its first element once it has been filled.
+    fn flip_intake(&mut self) -> io::Result<()>;
+    /// Hands the intake on as the outgive unchanged.
+    fn no_op(&mut self) -> io::Result<()>;
+    /// Rearranges the elements of `class_id` between a stream of elements and one of values.
+    fn convert(
+        &mut self,
+        class_id: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+
+    /// Runs the body of a stream instruction over elements of `class_id`.
+    fn stream_loop(
+        &mut self,
+        class_id: u16,
+        stream: &StreamInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn call(
+        &mut self,
+        class_id: u16,
+        method: &AssembledMethod,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn block(
+        &mut self,
+        instructions: &[AssembledInstruction],
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn return_code(&mut self, code: i64) -> io::Result<()>;
+    fn return_outgive(&mut self) -> io::Result<()>;
+    fn accumulate(&mut self, index: u16) -> io::Result<()>;
+
+    fn print_string(
+        &mut self,
+        len: u64,
+        value: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn print_value(
+        &mut self,
+        kind: PrintKind,
+        value: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn print_literal(&mut self, text: &str) -> io::Result<()>;
+
+    fn string_literal(&mut self, text: &str) -> io::Result<()>;
+    fn integer(&mut self, value: i64) -> io::Result<()>;
+    fn get(&mut self) -> io::Result<()>;
+    fn element(&mut self) -> io::Result<()>;
+    fn cast(&mut self, primitive: &str) -> io::Result<()>;
+    fn deref(&mut self, primitive: Option<&str>) -> io::Result<()>;
+    fn offset(
+        &mut self,
+        value: &AssembledInstruction,
+        offset: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn intake_count(&mut self, bytes: u16) -> io::Result<()>;
+    fn range_length(
+        &mut self,
+        bounds: &RangeBounds,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+    fn arithmetic(
+        &mut self,
+        op: Operator,
+        left: &AssembledInstruction,
+        right: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()>;
+}
+
+/// Writes a C program, keeping the elements of each pipeline in `malloc`ed buffers and the
+/// bindings in the slots of a `struct __CONTEXT__`. Each method declared in source becomes a
+/// function from one element to the buffer of those it gives back, with a context of its own.
+pub struct CBackend<W: Write> {
+    stream: W,
+}
+impl<W: Write> CBackend<W> {
+    pub fn new(stream: W) -> CBackend<W> {
+        CBackend { stream }
+    }
+
+    fn range_header(
+        &mut self,
+        bounds: &RangeBounds,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"for (int __i = ")?;
+        bounds.start.write(formats, types, self)?;
+        self.stream.write_all(b"; __i ")?;
+        self.stream
+            .write_all(if bounds.inclusive { b"<=" } else { b"< " })?;
+        self.stream.write_all(b" ")?;
+        bounds.end.write(formats, types, self)?;
+        self.stream.write_all(b"; __i += ")?;
+        bounds.step.write(formats, types, self)?;
+        self.stream.write_all(b")")
+    }
+
+    fn indent(&mut self, formats: &AssemblyFormatContext) -> io::Result<()> {
+        for _ in 0..formats.indentation {
+            self.stream.write_all(b"\t")?;
+        }
+        Ok(())
+    }
+    fn end_block(&mut self, formats: &mut AssemblyFormatContext) -> io::Result<()> {
+        formats.decrease_indentation();
+        self.indent(formats)?;
+        self.stream.write_all(b"}")
+    }
+    fn semicolon(&mut self) -> io::Result<()> {
+        self.stream.write_all(b";\n")
+    }
+    fn function_header(
+        &mut self,
+        class_id: u16,
+        name: &str,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        write!(
+            self.stream,
+            "char* {}(char* self, int* __len)",
+            function_name(class_id, name, types)
+        )
+    }
+    /// Calls the function of a method on the element at `in`, runs the body over each element it
+    /// gives back, then frees them and moves `in` back to the caller's intake.
+    fn call_loop(
+        &mut self,
+        class_id: u16,
+        intake: u16,
+        name: &str,
+        body: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"{\n")?;
+        formats.increase_indentation();
+        self.indent(formats)?;
+        self.stream
+            .write_all(b"char* __caller = in; int __caller_len = len;\n")?;
+        self.indent(formats)?;
+        writeln!(
+            self.stream,
+            "in = {}(in, &len);",
+            function_name(intake, name, types)
+        )?;
+        self.indent(formats)?;
+        let each = StreamInstruction::ForEach(Box::new(body.clone()));
+        self.stream_loop(class_id, &each, formats, types)?;
+        self.stream.write_all(b"\n")?;
+        self.indent(formats)?;
+        self.stream.write_all(b"free(in);\n")?;
+        self.indent(formats)?;
+        self.stream
+            .write_all(b"in = __caller; len = __caller_len;\n")?;
+        self.end_block(formats)
+    }
+    /// Moves `in` to a field of the element for the body, then back to the element.
+    fn field_block(
+        &mut self,
+        offset: u16,
+        body: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"{\n")?;
+        formats.increase_indentation();
+        self.indent(formats)?;
+        write!(self.stream, "in += {}", offset)?;
+        self.semicolon()?;
+        body.write(formats, types, self)?;
+        self.indent(formats)?;
+        write!(self.stream, "in -= {}", offset)?;
+        self.semicolon()?;
+        self.end_block(formats)
+    }
+    /// Adds up the ints the gathering instructions reach into a total, then points `in` at it for
+    /// the body.
+    fn sum_block(
+        &mut self,
+        index: u16,
+        gather: &AssembledInstruction,
+        body: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"{\n")?;
+        formats.increase_indentation();
+        self.indent(formats)?;
+        write!(self.stream, "int __sum{} = 0", index)?;
+        self.semicolon()?;
+        gather.write(formats, types, self)?;
+        self.indent(formats)?;
+        write!(
+            self.stream,
+            "in = (char*)&__sum{}; len = sizeof(int)",
+            index
+        )?;
+        self.semicolon()?;
+        body.write(formats, types, self)?;
+        self.end_block(formats)
+    }
+}
+
+impl<W: Write> Backend for CBackend<W> {
+    fn begin_program(
+        &mut self,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream
+            .write_all(b"#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n\n")?;
+        self.stream.write_all(b"typedef char* string;\n\n")?;
+        AssembledInstruction::DeclareContext.write(formats, types, self)?;
+        AssembledInstruction::InitContext.write(formats, types, self)
+    }
+    fn declare_method(
+        &mut self,
+        class_id: u16,
+        name: &str,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"\n")?;
+        self.function_header(class_id, name, types)?;
+        self.stream.write_all(b";")
+    }
+    fn method(
+        &mut self,
+        class_id: u16,
+        name: &str,
+        body: &[AssembledInstruction],
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"\n\n")?;
+        self.function_header(class_id, name, types)?;
+        self.stream.write_all(b" {\n")?;
+        formats.increase_indentation();
+        // shadows the context of the caller
+        self.indent(formats)?;
+        AssembledInstruction::InitContext.write(formats, types, self)?;
+        self.block(body, formats, types)?;
+        self.end_block(formats)
+    }
+    fn start(
+        &mut self,
+        body: &[AssembledInstruction],
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"\nint main() {\n")?;
+        formats.increase_indentation();
+        self.block(body, formats, types)?;
+        self.end_block(formats)
+    }
+    fn end_program(&mut self, _: &mut AssemblyFormatContext) -> io::Result<()> {
+        self.stream.write_all(b"\n")?;
+        self.stream.flush()
+    }
+
+    fn declare_context(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"struct __CONTEXT__ {\n")?;
+        writeln!(self.stream, "\tvoid* v[{}];", CONTEXT_SLOTS)?;
+        writeln!(self.stream, "\tint len[{}];", CONTEXT_SLOTS)?;
+        self.stream.write_all(b"};\n")
+    }
+    fn init_context(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"struct __CONTEXT__ context;\n")
+    }
+    fn declare_calculation_scope(&mut self, formats: &AssemblyFormatContext) -> io::Result<()> {
+        self.stream
+            .write_all(b"char* in; char* out; char* base;\n")?;
+        self.indent(formats)?;
+        self.stream.write_all(b"int len; int out_len; int out_cap")
+    }
+
+    fn set_length(&mut self, length: u64) -> io::Result<()> {
+        write!(self.stream, "len = {}", length)
+    }
+    fn alloc(&mut self, buffer: Buffer, bytes: u64) -> io::Result<()> {
+        match buffer {
+            Buffer::Intake => write!(self.stream, "in = malloc({})", bytes),
+            Buffer::Outgive => write!(self.stream, "out = malloc({})", bytes),
+        }
+    }
+    fn alloc_outgive_for(
+        &mut self,
+        bytes: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"out = malloc(out_cap = ")?;
+        bytes.write(formats, types, self)?;
+        self.stream.write_all(b"); out_len = 0")
+    }
+    fn free_intake(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"free(in)")
+    }
+    fn free_slot(&mut self, slot: u16) -> io::Result<()> {
+        write!(self.stream, "free(context.v[{}])", slot)
+    }
+    fn load_intake(&mut self, slot: u16) -> io::Result<()> {
+        write!(
+            self.stream,
+            "in = context.v[{}]; len = context.len[{}]",
+            slot, slot
+        )
+    }
+    fn save_outgive(&mut self, slot: u16) -> io::Result<()> {
+        write!(
+            self.stream,
+            "context.v[{}] = out; context.len[{}] = out_len",
+            slot, slot
+        )
+    }
+    fn context_slot(&mut self, slot: u16) -> io::Result<()> {
+        write!(self.stream, "context.v[{}]", slot)
+    }
+
+    fn add_intake(
+        &mut self,
+        value: &AssembledInstruction,
+        class_id: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        let t = types.get_type(&class_id).unwrap();
+        write!(self.stream, "*(({}*)in) = ", types.c_name(class_id))?;
+        value.write(formats, types, self)?;
+        let num_of_bytes = t.get_bytes(types);
+        write!(
+            self.stream,
+            "; in += {}; len += {}",
+            num_of_bytes, num_of_bytes
+        )
+    }
+    fn add_intake_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let literal = bytes
+            .iter()
+            .map(|b| format!("\\{:03o}", b))
+            .collect::<String>();
+        write!(
+            self.stream,
+            "memcpy(in, \"{}\", {}); in += {}; len += {}",
+            literal,
+            bytes.len(),
+            bytes.len(),
+            bytes.len()
+        )
+    }
+    fn add_intake_from(
+        &mut self,
+        source: &AssembledInstruction,
+        bytes: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        self.stream.write_all(b"memcpy(in, ")?;
+        source.write(formats, types, self)?;
+        write!(
+            self.stream,
+            ", {}); in += {}; len += {}",
+            bytes, bytes, bytes
+        )
+    }
+    fn add_outgive(&mut self, bytes: u16) -> io::Result<()> {
+        // a method may give back any number of elements, so the outgive grows as it fills
+        write!(
+            self.stream,
+            "if (out_len + {} > out_cap) out = realloc(out, out_cap = 2 * (out_len + {})); ",
+            bytes, bytes
+        )?;
+        write!(
+            self.stream,
+            "memcpy(out + out_len, in, {}); out_len += {}",
+            bytes, bytes
+        )
+    }
+    fn flip_intake(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"in -= len")
+    }
+    fn no_op(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"out = in")
+    }
+    fn convert(
+        &mut self,
+        class_id: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        /*for(int i = 0; out < len; ++i) {
+            in[{variable_index} * {new_t.num_of_bytes} + i] =
+                out[{variable_index} + {old_t.num_of_bytes} * i];
+            out += {old_t.num_of_bytes}
+        }*/
+        let indent = formats.indentation;
+        formats.increase_indentation();
+        let ty = types.get_type(&class_id).unwrap();
+        let num_of_bytes = ty.get_bytes(types);
+        self.stream
+            .write_all(b"for(int i = 0; out < len; ++i) {\n")?;
+        for (i, _) in ty.get_data(types).iter().enumerate() {
+            for _ in 0..indent {
+                self.stream.write_all(b"\t")?;
+            }
+            write!(
+                self.stream,
+                "in[{} * {} + i] = out[{} + {} * i];",
+                i, num_of_bytes, i, num_of_bytes,
+            )?;
+        }
+        write!(self.stream, "out += {}", num_of_bytes)?;
+        self.semicolon()?;
+        self.end_block(formats)
+    }
+
+    fn stream_loop(
+        &mut self,
+        class_id: u16,
+        stream: &StreamInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        let num_of_bytes = types.get_type(&class_id).unwrap().get_bytes(types);
+        let body = match stream {
+            StreamInstruction::Call(intake, name, body) => {
+                let intake = *formats.in_class(intake);
+                return self.call_loop(class_id, intake, name, body, formats, types);
+            }
+            StreamInstruction::Field(offset, body) => {
+                return self.field_block(*offset, body, formats, types);
+            }
+            StreamInstruction::Sum(index, gather, body) => {
+                return self.sum_block(*index, gather, body, formats, types);
+            }
+            StreamInstruction::ForEach(body) => {
+                write!(
+                    self.stream,
+                    "for (char* __end = in + len; in < __end; in += {})",
+                    num_of_bytes
+                )?;
+                body
+            }
+            StreamInstruction::Range(bounds, body) => {
+                self.range_header(bounds, formats, types)?;
+                body
+            }
+            StreamInstruction::Slice(bounds, body) => {
+                self.stream.write_all(b"base = in;\n")?;
+                self.indent(formats)?;
+                self.range_header(bounds, formats, types)?;
+                body
+            }
+        };
+        self.stream.write_all(b" {\n")?;
+        formats.increase_indentation();
+        match stream {
+            StreamInstruction::ForEach(_)
+            | StreamInstruction::Call(_, _, _)
+            | StreamInstruction::Field(_, _)
+            | StreamInstruction::Sum(_, _, _) => {}
+            StreamInstruction::Range(_, _) => {
+                self.indent(formats)?;
+                self.stream.write_all(b"in = (char*)&__i")?;
+                self.semicolon()?;
+            }
+            StreamInstruction::Slice(_, _) => {
+                self.indent(formats)?;
+                write!(self.stream, "if (__i * {} >= len) break", num_of_bytes)?;
+                self.semicolon()?;
+                self.indent(formats)?;
+                write!(self.stream, "in = base + __i * {}", num_of_bytes)?;
+                self.semicolon()?;
+            }
+        }
+        body.write(formats, types, self)?;
+        self.end_block(formats)?;
+        // leave `in` at the start of the intake, ready to be freed
+        let reset: &[u8] = match stream {
+            StreamInstruction::ForEach(_) => b"in -= len;",
+            StreamInstruction::Slice(_, _) => b"in = base;",
+            _ => b"",
+        };
+        if !reset.is_empty() {
+            self.stream.write_all(b"\n")?;
+            self.indent(formats)?;
+            self.stream.write_all(reset)?;
+        }
+        Ok(())
+    }
+    fn call(
+        &mut self,
+        class_id: u16,
+        method: &AssembledMethod,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        method.write(&class_id, formats, types, self)
+    }
+    fn block(
+        &mut self,
+        instructions: &[AssembledInstruction],
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        for i in instructions.iter() {
+            self.indent(formats)?;
+            i.write(formats, types, self)?;
+            if is_compound(i) {
+                self.stream.write_all(b"\n")?;
+            } else {
+                self.semicolon()?;
+            }
+        }
+        Ok(())
+    }
+    fn return_code(&mut self, code: i64) -> io::Result<()> {
+        write!(self.stream, "return {}", code)
+    }
+    fn return_outgive(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"*__len = out_len; return out")
+    }
+    fn accumulate(&mut self, index: u16) -> io::Result<()> {
+        write!(self.stream, "__sum{} += *(int*)in", index)
+    }
+
+    fn print_string(
+        &mut self,
+        len: u64,
+        value: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        write!(self.stream, "printf(\"%.{}s\", ", len)?;
+        value.write(formats, types, self)?;
+        self.stream.write_all(b")")
+    }
+    fn print_value(
+        &mut self,
+        kind: PrintKind,
+        value: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        let format = match kind {
+            PrintKind::Int => "%d",
+            PrintKind::Str => "%s",
+            PrintKind::Hex => "%x",
+        };
+        write!(self.stream, "printf(\"{}\", ", format)?;
+        value.write(formats, types, self)?;
+        self.stream.write_all(b")")
+    }
+    fn print_literal(&mut self, text: &str) -> io::Result<()> {
+        write!(self.stream, "printf(\"{}\")", c_escape(text))
+    }
+
+    fn string_literal(&mut self, text: &str) -> io::Result<()> {
+        write!(self.stream, "\"{}\"", c_literal(text))
+    }
+    fn integer(&mut self, value: i64) -> io::Result<()> {
+        write!(self.stream, "{}", value)
+    }
+    fn get(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"in")
+    }
+    fn element(&mut self) -> io::Result<()> {
+        self.stream.write_all(b"self")
+    }
+    fn cast(&mut self, primitive: &str) -> io::Result<()> {
+        write!(self.stream, "({} *)", primitive)
+    }
+    fn deref(&mut self, primitive: Option<&str>) -> io::Result<()> {
+        match primitive {
+            Some(primitive) => write!(self.stream, "*({} *)", primitive),
+            None => self.stream.write_all(b"*"),
+        }
+    }
+    fn offset(
+        &mut self,
+        value: &AssembledInstruction,
+        offset: u16,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        if offset == 0 {
+            return value.write(formats, types, self);
+        }
+        self.stream.write_all(b"(")?;
+        value.write(formats, types, self)?;
+        write!(self.stream, " + {})", offset)
+    }
+    fn intake_count(&mut self, bytes: u16) -> io::Result<()> {
+        write!(self.stream, "(len / {})", bytes)
+    }
+    fn range_length(
+        &mut self,
+        bounds: &RangeBounds,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        // the number of steps from start to end, or none when the range is empty
+        let end: &[u8] = if bounds.inclusive { b" + 1" } else { b"" };
+        self.stream.write_all(b"((")?;
+        bounds.end.write(formats, types, self)?;
+        self.stream.write_all(end)?;
+        self.stream.write_all(b" > ")?;
+        bounds.start.write(formats, types, self)?;
+        self.stream.write_all(b") ? (")?;
+        bounds.end.write(formats, types, self)?;
+        self.stream.write_all(end)?;
+        self.stream.write_all(b" - ")?;
+        bounds.start.write(formats, types, self)?;
+        self.stream.write_all(b" + ")?;
+        bounds.step.write(formats, types, self)?;
+        self.stream.write_all(b" - 1) / ")?;
+        bounds.step.write(formats, types, self)?;
+        self.stream.write_all(b" : 0)")
+    }
+    fn arithmetic(
+        &mut self,
+        op: Operator,
+        left: &AssembledInstruction,
+        right: &AssembledInstruction,
+        formats: &mut AssemblyFormatContext,
+        types: &AssembledTypeContext,
+    ) -> io::Result<()> {
+        let op: &[u8] = match op {
+            Operator::Plus => b" + ",
+            Operator::Minus => b" - ",
+            Operator::Multiply => b" * ",
+            Operator::Divide => b" / ",
+            _ => b" % ",
+        };
+        self.stream.write_all(b"(")?;
+        left.write(formats, types, self)?;
+        self.stream.write_all(op)?;
+        right.write(formats, types, self)?;
+        self.stream.write_all(b")")
+    }
+}
+
+/// Whether the instruction writes a braced block, which must not be followed by `;`.
+fn is_compound(instruction: &AssembledInstruction) -> bool {
+    matches!(
+        instruction,
+        AssembledInstruction::Stream(_, _) | AssembledInstruction::Block(_)
+    )
+}
+
+/// The C function a method taking in elements of `class_id` is lowered to.
+fn function_name(class_id: u16, method: &str, types: &AssembledTypeContext) -> String {
+    format!("{}__{}", types.c_name(class_id), c_identifier(method))
+}
+
+fn c_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '%' => escaped.push_str("%%"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escapes `text` as the contents of a C string literal. Bytes outside printable ASCII are written
+/// as octal escapes, which unlike `\x` cannot swallow a following digit.
+fn c_literal(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for b in text.bytes() {
+        match b {
+            b'\\' => escaped.push_str("\\\\"),
+            b'"' => escaped.push_str("\\\""),
+            b' '..=b'~' => escaped.push(b as char),
+            _ => escaped.push_str(&format!("\\{:03o}", b)),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::assemble::context::{ClassReference, CLASS_ID_INT};
+
+    fn write_c(instruction: AssembledInstruction) -> String {
+        let mut types = AssembledTypeContext::new();
+        types.setup_root_scope_types();
+        let mut out = Vec::new();
+        instruction
+            .write(
+                &mut AssemblyFormatContext::new(),
+                &types,
+                &mut CBackend::new(&mut out),
+            )
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_c_backend() {
+        assert_eq!(
+            write_c(AssembledInstruction::PrintLiteral(
+                "100% \"done\"\n".to_string()
+            )),
+            "printf(\"100%% \\\"done\\\"\\n\")"
+        );
+        assert_eq!(
+            write_c(AssembledInstruction::StringLiteral("é\t".to_string())),
+            "\"\\303\\251\\011\""
+        );
+        assert_eq!(
+            write_c(AssembledInstruction::Block(vec![
+                AssembledInstruction::SetLength(0),
+                AssembledInstruction::InitIntake(4),
+                AssembledInstruction::AddIntake(
+                    Box::new(AssembledInstruction::Integer(7)),
+                    ClassReference::ThisAs(CLASS_ID_INT),
+                ),
+                AssembledInstruction::FlipIntake,
+            ])),
+            "len = 0;\nin = malloc(4);\n*((int*)in) = 7; in += 4; len += 4;\nin -= len;\n"
+        );
+    }
+}
@@ -1,16 +1,21 @@
-use crate::lang::assemble::instruction::StreamInstruction;
+use crate::lang::assemble::backend::Backend;
+use crate::lang::assemble::instruction::{PrintKind, StreamInstruction};
 use crate::lang::assemble::{
     AssembledComposition, AssembledData, AssembledInstruction, AssembledInterface, AssembledMethod,
     AssembledObjectAcceptor, AssembledType, AssemblyError,
 };
 
 use std::collections::HashMap;
-use std::fs;
-use std::io::{BufWriter, Write};
 
 pub const CLASS_ID_VOID: u16 = 0;
 pub const CLASS_ID_PRINTABLE: u16 = 1;
 pub const CLASS_ID_INT: u16 = 2;
+pub const CLASS_ID_STRING: u16 = 3;
+pub const CLASS_ID_CHAR: u16 = 4;
+/// Chars are stored as their UTF-8 encoding, zero padded to the longest sequence.
+pub const CHAR_BYTES: u8 = 4;
+/// How many bindings a method may have, one for each slot of its context.
+pub const CONTEXT_SLOTS: u16 = 256;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ValueFormat {
@@ -20,87 +25,21 @@ pub enum ValueFormat {
 }
 
 impl ValueFormat {
-    pub fn convert(
+    /// Converts the outgive of the previous method into this format.
+    pub fn convert<B: Backend>(
         &self,
         t: &u16,
         formats: &mut AssemblyFormatContext,
         types: &AssembledTypeContext,
-        stream: &mut BufWriter<fs::File>,
+        backend: &mut B,
     ) -> std::io::Result<()> {
-        match self {
-            //To
-            ValueFormat::StreamOfValues => {
-                let top = formats.out_format_stack.len();
-                //From
-                match formats.out_format_stack.get(top).unwrap() {
-                    ValueFormat::StreamOfElements => {
-                        /*for(int i = 0; out < len; ++i) {
-                            in[{variable_index} * {new_t.num_of_bytes} + i] =
-                                out[{variable_index} + {old_t.num_of_bytes} * i];
-                            out += {old_t.num_of_bytes}
-                        }*/
-                        let indent = formats.indentation;
-                        formats.increase_indentation();
-                        let ty = types.get_type(t).unwrap();
-                        let num_of_bytes = ty.get_bytes(types);
-                        let data = ty.get_data(types);
-                        stream.write(format!("for(int i = 0; out < len; ++i) {{\n").as_bytes())?;
-                        for (i, _) in data.iter().enumerate() {
-                            for _ in 0..indent {
-                                stream.write("\t".as_bytes())?;
-                            }
-                            stream.write(
-                                format!(
-                                    "in[{} * {} + i] = out[{} + {} * i];",
-                                    i, num_of_bytes, i, num_of_bytes,
-                                )
-                                .as_bytes(),
-                            )?;
-                        }
-                        stream.write(format!("out += {}", num_of_bytes).as_bytes())?;
-                        AssembledInstruction::Semicolon.write(formats, types, stream)?;
-                        AssembledInstruction::EndBlock.write(formats, types, stream)?;
-                    }
-                    _ => {} // TODO
-                }
-            }
-            //To
-            ValueFormat::StreamOfElements => {
-                let top = formats.out_format_stack.len();
-                //From
-                match formats.out_format_stack.get(top).unwrap() {
-                    ValueFormat::StreamOfValues => {
-                        /*for(int i = 0; out < len; ++i) {
-                            in[{variable_index} + {new_t.num_of_bytes} * i] =
-                                out[{variable_index} * {old_t.num_of_bytes} + i];
-                            out += {old_t.num_of_bytes}
-                        }*/
-                        let indent = formats.indentation;
-                        formats.increase_indentation();
-                        let ty = types.get_type(t).unwrap();
-                        let num_of_bytes = ty.get_bytes(types);
-                        stream.write(format!("for(int i = 0; out < len; ++i) {{\n").as_bytes())?;
-                        for (i, _) in ty.get_data(types).iter().enumerate() {
-                            for _ in 0..indent {
-                                stream.write("\t".as_bytes())?;
-                            }
-                            stream.write(
-                                format!(
-                                    "in[{} * {} + i] = out[{} + {} * i];",
-                                    i, num_of_bytes, i, num_of_bytes,
-                                )
-                                .as_bytes(),
-                            )?;
-                        }
-                        stream.write(format!("out += {}", num_of_bytes).as_bytes())?;
-                        AssembledInstruction::Semicolon.write(formats, types, stream)?;
-                        AssembledInstruction::EndBlock.write(formats, types, stream)?;
-                    }
-                    _ => {} // TODO
-                }
+        match (formats.out_format_stack.last(), self) {
+            (Some(ValueFormat::StreamOfElements), ValueFormat::StreamOfValues)
+            | (Some(ValueFormat::StreamOfValues), ValueFormat::StreamOfElements) => {
+                backend.convert(*t, formats, types)
             }
-        };
-        Ok(())
+            _ => Ok(()), // TODO
+        }
     }
 }
 
@@ -133,19 +72,21 @@ impl AssemblyFormatContext {
         self.indentation += 1;
     }
 
-    pub fn in_class(&self, rf: &ClassReference) -> &u16 {
-        let top = self.in_class_id_stack.len() - 1;
+    pub fn decrease_indentation(&mut self) {
+        self.indentation -= 1;
+    }
+
+    pub fn in_class<'a>(&'a self, rf: &'a ClassReference) -> &'a u16 {
         match rf {
-            ClassReference::This => self.in_class_id_stack.get(top).unwrap(),
-            ClassReference::ThisAs(_) => self.in_class_id_stack.get(top).unwrap(),
+            ClassReference::This => self.in_class_id_stack.last().unwrap(),
+            ClassReference::ThisAs(class_id) => class_id,
         }
     }
 
-    pub fn out_class(&self, rf: &ClassReference) -> &u16 {
-        let top = self.out_class_id_stack.len() - 1;
+    pub fn out_class<'a>(&'a self, rf: &'a ClassReference) -> &'a u16 {
         match rf {
-            ClassReference::This => self.out_class_id_stack.get(top).unwrap(),
-            ClassReference::ThisAs(_) => self.out_class_id_stack.get(top).unwrap(),
+            ClassReference::This => self.out_class_id_stack.last().unwrap(),
+            ClassReference::ThisAs(class_id) => class_id,
         }
     }
 
@@ -168,10 +109,64 @@ impl AssemblyFormatContext {
         *self.out_format_stack.get(top).unwrap() == *format
     }
 }
+/// A `type Name<A, B>: (...)` declaration, kept until each instantiation is monomorphised.
+#[derive(Debug, Clone)]
+pub struct GenericTemplate {
+    parameters: Vec<String>,
+    body: usize,
+}
+impl GenericTemplate {
+    pub fn new(parameters: Vec<String>, body: usize) -> GenericTemplate {
+        GenericTemplate { parameters, body }
+    }
+    pub fn get_parameters(&self) -> &Vec<String> {
+        &self.parameters
+    }
+    pub fn get_body(&self) -> usize {
+        self.body
+    }
+}
+
+/// A `trans name<A, B>: ...` declaration, identified by its position in the declaring scope.
+#[derive(Debug, Clone)]
+pub struct GenericMethodTemplate {
+    name: String,
+    parameters: Vec<String>,
+    scope: usize,
+    position: usize,
+}
+impl GenericMethodTemplate {
+    pub fn new(
+        name: String,
+        parameters: Vec<String>,
+        scope: usize,
+        position: usize,
+    ) -> GenericMethodTemplate {
+        GenericMethodTemplate {
+            name,
+            parameters,
+            scope,
+            position,
+        }
+    }
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+    pub fn get_parameters(&self) -> &Vec<String> {
+        &self.parameters
+    }
+    pub fn get_declaration(&self) -> (usize, usize) {
+        (self.scope, self.position)
+    }
+}
+
 pub struct AssembledTypeContext {
     types: HashMap<u16, AssembledType>,
     type_names: HashMap<String, u16>,
     impls: HashMap<u16, HashMap<String, AssembledMethod>>,
+    generic_types: HashMap<String, GenericTemplate>,
+    generic_methods: Vec<GenericMethodTemplate>,
+    instances: HashMap<(String, Vec<u16>), u16>,
 }
 impl AssembledTypeContext {
     pub fn new() -> AssembledTypeContext {
@@ -179,19 +174,40 @@ impl AssembledTypeContext {
             types: HashMap::new(),
             type_names: HashMap::new(),
             impls: HashMap::new(),
+            generic_types: HashMap::new(),
+            generic_methods: Vec::new(),
+            instances: HashMap::new(),
         }
     }
 
     pub fn get_impl_name(&self, type_name: &String) -> Option<&HashMap<String, AssembledMethod>> {
         self.impls.get(self.type_names.get(type_name).unwrap())
     }
+    pub fn get_mut_method(&mut self, type_id: u16, name: &str) -> Option<&mut AssembledMethod> {
+        self.impls
+            .get_mut(&type_id)
+            .and_then(|imp| imp.get_mut(name))
+    }
+    pub fn get_type_names(&self) -> Vec<&String> {
+        self.type_names.keys().collect()
+    }
+    pub fn get_method_names(&self) -> Vec<&String> {
+        self.impls.values().flat_map(|imp| imp.keys()).collect()
+    }
     pub fn get_impl(&self, type_id: &u16) -> Option<&HashMap<String, AssembledMethod>> {
         self.impls.get(type_id)
     }
     pub fn record(&mut self, types: HashMap<String, u16>) {
-        self.type_names = types;
+        self.type_names.extend(types);
     }
     pub fn setup_root_scope_types(&mut self) {
+        self.type_names.insert("()".to_string(), CLASS_ID_VOID);
+        self.type_names
+            .insert("Printable".to_string(), CLASS_ID_PRINTABLE);
+        self.type_names.insert("int".to_string(), CLASS_ID_INT);
+        self.type_names
+            .insert("string".to_string(), CLASS_ID_STRING);
+        self.type_names.insert("char".to_string(), CLASS_ID_CHAR);
         self.types.insert(
             CLASS_ID_VOID,
             AssembledType::Interface(
@@ -216,7 +232,7 @@ impl AssembledTypeContext {
         printable_impls.insert("print".to_string(), print_method);
         self.types.insert(
             CLASS_ID_INT,
-            AssembledType::Data("int".to_string(), AssembledData::filled(1u16, 0, 4)),
+            AssembledType::Data("int".to_string(), AssembledData::filled(CLASS_ID_INT, 0, 4)),
         );
         let mut int_impls = HashMap::new();
         let mut print_method = AssembledMethod::new(
@@ -226,7 +242,7 @@ impl AssembledTypeContext {
             CLASS_ID_VOID,
         );
         print_method.add_instruction(AssembledInstruction::PrintValue(
-            "%d".to_string(),
+            PrintKind::Int,
             Box::new(AssembledInstruction::Chain(
                 Box::new(AssembledInstruction::Deref(Some("int".to_string()))),
                 Box::new(AssembledInstruction::Get(ClassReference::This)),
@@ -247,6 +263,52 @@ impl AssembledTypeContext {
         int_impls.insert("cast".to_string(), cast_method);
         self.impls.insert(CLASS_ID_INT, int_impls);
 
+        // strings are pointers to literals, written into C through `typedef char* string;`
+        self.types.insert(
+            CLASS_ID_STRING,
+            AssembledType::Data(
+                "string".to_string(),
+                AssembledData::filled(CLASS_ID_STRING, 0, std::mem::size_of::<usize>() as u8),
+            ),
+        );
+        let mut string_impls = HashMap::new();
+        let mut print_method = AssembledMethod::new(
+            "print".to_string(),
+            ValueFormat::StreamOfElements,
+            ValueFormat::StreamOfElements,
+            CLASS_ID_VOID,
+        );
+        print_method.add_instruction(AssembledInstruction::PrintValue(
+            PrintKind::Str,
+            Box::new(AssembledInstruction::Chain(
+                Box::new(AssembledInstruction::Deref(Some("string".to_string()))),
+                Box::new(AssembledInstruction::Get(ClassReference::This)),
+            )),
+        ));
+        string_impls.insert("print".to_string(), print_method);
+        self.impls.insert(CLASS_ID_STRING, string_impls);
+
+        self.types.insert(
+            CLASS_ID_CHAR,
+            AssembledType::Data(
+                "char".to_string(),
+                AssembledData::filled(CLASS_ID_CHAR, 0, CHAR_BYTES),
+            ),
+        );
+        let mut char_impls = HashMap::new();
+        let mut print_method = AssembledMethod::new(
+            "print".to_string(),
+            ValueFormat::StreamOfElements,
+            ValueFormat::StreamOfElements,
+            CLASS_ID_VOID,
+        );
+        print_method.add_instruction(AssembledInstruction::PrintString(
+            CHAR_BYTES as u64,
+            Box::new(AssembledInstruction::Get(ClassReference::This)),
+        ));
+        char_impls.insert("print".to_string(), print_method);
+        self.impls.insert(CLASS_ID_CHAR, char_impls);
+
         let mut obj_impls = HashMap::new();
         let mut print_method = AssembledMethod::new(
             "print".to_string(),
@@ -290,14 +352,86 @@ impl AssembledTypeContext {
         self.type_names.get(name)
     }
 
+    pub fn create_generic_type(&mut self, identifier: &str, template: GenericTemplate) {
+        self.generic_types.insert(identifier.to_string(), template);
+    }
+    pub fn get_generic_type(&self, identifier: &str) -> Option<&GenericTemplate> {
+        self.generic_types.get(identifier)
+    }
+    /// The names of the generic types, sorted.
+    pub fn get_generic_type_names(&self) -> Vec<&String> {
+        let mut names = self.generic_types.keys().collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Creates the concrete type for `identifier<arguments>`, named as it is written in source,
+    /// e.g. `Pair<int, Point>`. Callers are expected to check `get_instance` first, as every call
+    /// yields a new id.
+    pub fn create_instance(&mut self, identifier: &str, arguments: Vec<u16>) -> u16 {
+        let names = arguments
+            .iter()
+            .map(|argument| self.types.get(argument).unwrap().get_name())
+            .collect::<Vec<String>>();
+        let id = self.create_type(&format!("{}<{}>", identifier, names.join(", ")));
+        self.instances
+            .insert((identifier.to_string(), arguments), id);
+        id
+    }
+    pub fn get_instance(&self, identifier: &str, arguments: &[u16]) -> Option<&u16> {
+        self.instances
+            .get(&(identifier.to_string(), arguments.to_vec()))
+    }
+    /// Every instantiation of the generic type `identifier`, ordered by type id.
+    pub fn get_instances_of(&self, identifier: &str) -> Vec<(u16, Vec<u16>)> {
+        let mut instances = self
+            .instances
+            .iter()
+            .filter(|((name, _), _)| name == identifier)
+            .map(|((_, arguments), id)| (*id, arguments.clone()))
+            .collect::<Vec<(u16, Vec<u16>)>>();
+        instances.sort();
+        instances
+    }
+
+    pub fn create_generic_method(&mut self, template: GenericMethodTemplate) {
+        self.generic_methods.push(template);
+    }
+    pub fn get_generic_methods(&self) -> &Vec<GenericMethodTemplate> {
+        &self.generic_methods
+    }
+
+    /// The name of a type as it may be written in C. Instances of generic types are mangled, e.g.
+    /// `Pair<int, Point>` becomes `Pair__3int5Point`, with the arguments length prefixed so that
+    /// nested instances stay unique.
+    pub fn c_name(&self, id: u16) -> String {
+        let instance = self.instances.iter().find(|(_, instance)| **instance == id);
+        match instance {
+            Some(((identifier, arguments), _)) => {
+                let mut mangled = format!("{}__", c_identifier(identifier));
+                for argument in arguments.iter() {
+                    let name = self.c_name(*argument);
+                    mangled.push_str(&format!("{}{}", name.len(), name));
+                }
+                mangled
+            }
+            None => c_identifier(&self.types.get(&id).unwrap().get_name()),
+        }
+    }
+
+    pub fn add_method(&mut self, type_id: u16, source_name: &str, method: AssembledMethod) {
+        self.impls
+            .entry(type_id)
+            .or_default()
+            .insert(source_name.to_string(), method);
+    }
+
     pub fn add_variable(
         &mut self,
-        _scope: usize,
-        identifier: &String,
+        id: u16,
         variable: AssembledObjectAcceptor,
     ) -> Result<(), AssemblyError> {
-        let id = self.type_names.get(identifier).unwrap();
-        if let AssembledType::Composition(_, data) = self.types.get_mut(id).unwrap() {
+        if let AssembledType::Composition(_, data) = self.types.get_mut(&id).unwrap() {
             data.add_variable(variable);
             Ok(())
         } else {
@@ -305,3 +439,11 @@ impl AssembledTypeContext {
         }
     }
 }
+
+/// The name as it may be written in C.
+pub fn c_identifier(name: &str) -> String {
+    match name {
+        "()" => "void".to_string(),
+        _ => name.replace('-', "_"),
+    }
+}
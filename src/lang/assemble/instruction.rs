@@ -1,14 +1,46 @@
+use crate::lang::assemble::backend::{Backend, Buffer};
 use crate::lang::assemble::context::{AssembledTypeContext, AssemblyFormatContext, ClassReference};
 
-use std::fs;
-use std::io::{BufWriter, Write};
+use crate::parse::constant::Operator;
 
-#[derive(Debug)]
+use std::io;
+
+#[derive(Debug, Clone)]
 pub enum StreamInstruction {
     ForEach(Box<AssembledInstruction>),
+    Range(RangeBounds, Box<AssembledInstruction>),
+    Slice(RangeBounds, Box<AssembledInstruction>),
+    /// Calls the method of the given name on the element of the intake class, then runs the body
+    /// over each element it gives back.
+    Call(ClassReference, String, Box<AssembledInstruction>),
+    /// Runs the body over the field at the given offset of the element.
+    Field(u16, Box<AssembledInstruction>),
+    /// Runs the first instructions, which add every int reaching them to the total of the `sum`
+    /// stage of the given index, then runs the body once over that total.
+    Sum(u16, Box<AssembledInstruction>, Box<AssembledInstruction>),
+}
+
+/// Bounds of a `start..end by step` range, evaluated once before the loop starts.
+#[derive(Debug, Clone)]
+pub struct RangeBounds {
+    pub start: Box<AssembledInstruction>,
+    pub end: Box<AssembledInstruction>,
+    pub step: Box<AssembledInstruction>,
+    pub inclusive: bool,
 }
 
-#[derive(Debug)]
+/// How a value of a data type is printed, so that each backend picks its own way of writing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrintKind {
+    /// A signed decimal int.
+    Int,
+    /// A string up to the zero ending it.
+    Str,
+    /// The bits of any other data type, in hexadecimal.
+    Hex,
+}
+
+#[derive(Debug, Clone)]
 pub enum AssembledInstruction {
     DeclareContext,
     InitContext,
@@ -18,148 +50,125 @@ pub enum AssembledInstruction {
     InitOutgive(u64),
     LoadIntake(u16),
     SaveOutgive(u16),
+    InitOutgiveFor(Box<AssembledInstruction>),
+    AddOutgive(u16),
+    FreeIntake,
+    FreeOutgive(u16),
+    ContextSlot(u16),
+    IntakeCount(u16),
+    RangeLength(RangeBounds),
     NoOp,
     AddIntake(Box<AssembledInstruction>, ClassReference),
+    AddIntakeBytes(Vec<u8>),
+    AddIntakeFrom(Box<AssembledInstruction>, u16), //source, bytes
     FlipIntake,
     PrintString(u64, Box<AssembledInstruction>), //id, len
-    PrintValue(String, Box<AssembledInstruction>), // format, value
+    PrintValue(PrintKind, Box<AssembledInstruction>),
+    PrintLiteral(String),
+    StringLiteral(String),
+    Integer(i64),
+    Offset(Box<AssembledInstruction>, u16),
     CallMethod(ClassReference, String),
     Cast(String),
     Deref(Option<String>),
     Get(ClassReference),
+    /// The element a method declared in source was called on.
+    Element,
     Stream(ClassReference, StreamInstruction),
-    Multiply(Box<AssembledInstruction>, Box<AssembledInstruction>),
+    /// Integer arithmetic: `+`, `-`, `*`, `/` or `%`.
+    Arithmetic(
+        Operator,
+        Box<AssembledInstruction>,
+        Box<AssembledInstruction>,
+    ),
     Chain(Box<AssembledInstruction>, Box<AssembledInstruction>),
-    EndBlock,
-    Semicolon,
-    Indent,
+    Block(Vec<AssembledInstruction>),
+    Return(i64),
+    /// Gives the outgive back to the caller of a method declared in source.
+    ReturnOutgive,
+    /// Adds the int of the element to the total of the `sum` stage of the given index.
+    Accumulate(u16),
 }
 
 impl AssembledInstruction {
-    pub fn write(
+    /// Writes the instruction through `backend`, resolving the classes it refers to.
+    pub fn write<B: Backend>(
         &self,
         formats: &mut AssemblyFormatContext,
         types: &AssembledTypeContext,
-        stream: &mut BufWriter<fs::File>,
-    ) -> std::io::Result<()> {
+        backend: &mut B,
+    ) -> io::Result<()> {
         match self {
-            AssembledInstruction::DeclareContext => {
-                stream.write(format!("struct __CONTEXT__ {{\n",).as_bytes())?;
-                stream.write(format!("\tvoid* [256] v\n",).as_bytes())?;
-            }
-            AssembledInstruction::InitContext => {
-                stream.write(format!("struct __CONTEXT__ c;\n").as_bytes())?;
-            }
+            AssembledInstruction::DeclareContext => backend.declare_context(),
+            AssembledInstruction::InitContext => backend.init_context(),
             AssembledInstruction::DeclareCalculationScope => {
-                stream.write(format!("void* in; void* out;\nint len;\n\n",).as_bytes())?;
-            }
-            AssembledInstruction::SetLength(length) => {
-                stream.write(format!("len = {};\n", length).as_bytes())?;
-            }
-            AssembledInstruction::InitIntake(byte_size) => {
-                stream.write(format!("in = malloc({});\n", byte_size).as_bytes())?;
-            }
-            AssembledInstruction::InitOutgive(byte_size) => {
-                stream.write(format!("out = malloc({});\n", byte_size).as_bytes())?;
-            }
-            AssembledInstruction::LoadIntake(ptr_id) => {
-                stream.write(format!("in = formats.v[{}];\n", ptr_id).as_bytes())?;
-            }
-            AssembledInstruction::SaveOutgive(ptr_id) => {
-                stream.write(format!("context.v[{}] = out;\n", ptr_id).as_bytes())?;
-            }
-            AssembledInstruction::NoOp => {
-                stream.write(format!("out = in;\n").as_bytes())?;
-            }
+                backend.declare_calculation_scope(formats)
+            }
+            AssembledInstruction::SetLength(length) => backend.set_length(*length),
+            AssembledInstruction::InitIntake(bytes) => backend.alloc(Buffer::Intake, *bytes),
+            AssembledInstruction::InitOutgive(bytes) => backend.alloc(Buffer::Outgive, *bytes),
+            AssembledInstruction::LoadIntake(slot) => backend.load_intake(*slot),
+            AssembledInstruction::SaveOutgive(slot) => backend.save_outgive(*slot),
+            AssembledInstruction::InitOutgiveFor(bytes) => {
+                backend.alloc_outgive_for(bytes, formats, types)
+            }
+            AssembledInstruction::AddOutgive(bytes) => backend.add_outgive(*bytes),
+            AssembledInstruction::FreeIntake => backend.free_intake(),
+            AssembledInstruction::FreeOutgive(slot) => backend.free_slot(*slot),
+            AssembledInstruction::ContextSlot(slot) => backend.context_slot(*slot),
+            AssembledInstruction::IntakeCount(bytes) => backend.intake_count(*bytes),
+            AssembledInstruction::RangeLength(bounds) => {
+                backend.range_length(bounds, formats, types)
+            }
+            AssembledInstruction::NoOp => backend.no_op(),
             AssembledInstruction::AddIntake(value, class_id) => {
-                {
-                    let class_id = formats.in_class(class_id);
-                    let t = types.get_type(class_id).unwrap();
-                    stream.write(format!("*(({}*)in) = ", t.get_name()).as_bytes())?;
-                }
-                value.as_ref().write(formats, types, stream)?;
-                stream.write(format!(";\n",).as_bytes())?;
-                {
-                    let class_id = formats.in_class(class_id);
-                    let t = types.get_type(class_id).unwrap();
-                    let num_of_bytes = t.get_bytes(types);
-                    stream.write(format!("in += {};\n", num_of_bytes).as_bytes())?;
-                    stream.write(format!("len += {};\n", num_of_bytes).as_bytes())?;
-                }
+                let class_id = *formats.in_class(class_id);
+                backend.add_intake(value, class_id, formats, types)
             }
-            AssembledInstruction::FlipIntake => {
-                stream.write(format!("in -= len;\n").as_bytes())?;
+            AssembledInstruction::AddIntakeBytes(bytes) => backend.add_intake_bytes(bytes),
+            AssembledInstruction::AddIntakeFrom(source, bytes) => {
+                backend.add_intake_from(source, *bytes, formats, types)
             }
+            AssembledInstruction::FlipIntake => backend.flip_intake(),
             AssembledInstruction::PrintString(len, value) => {
-                stream.write(format!("printf(\"%.{}s\", ", len).as_bytes())?;
-                value.as_ref().write(formats, types, stream)?;
-                stream.write(format!(")").as_bytes())?;
+                backend.print_string(*len, value, formats, types)
             }
-            AssembledInstruction::PrintValue(f, value) => {
-                stream.write(format!("printf(\"{}\", \n", f,).as_bytes())?;
-                value.as_ref().write(formats, types, stream)?;
-                stream.write(format!(")").as_bytes())?;
+            AssembledInstruction::PrintValue(kind, value) => {
+                backend.print_value(*kind, value, formats, types)
             }
-            AssembledInstruction::Cast(primitive_type) => {
-                stream.write(format!("({} *)", primitive_type,).as_bytes())?;
-            }
-            AssembledInstruction::Deref(primitive_type_o) => {
-                if let Some(primitive_type) = primitive_type_o {
-                    stream.write(format!("*({} *)", primitive_type,).as_bytes())?;
-                } else {
-                    stream.write(format!("*",).as_bytes())?;
-                }
+            AssembledInstruction::PrintLiteral(text) => backend.print_literal(text),
+            AssembledInstruction::StringLiteral(text) => backend.string_literal(text),
+            AssembledInstruction::Integer(value) => backend.integer(*value),
+            AssembledInstruction::Offset(value, offset) => {
+                backend.offset(value, *offset, formats, types)
             }
+            AssembledInstruction::Cast(primitive) => backend.cast(primitive),
+            AssembledInstruction::Deref(primitive) => backend.deref(primitive.as_deref()),
             AssembledInstruction::CallMethod(class_id, method_name) => {
                 let class_id = *formats.out_class(class_id);
                 let imp = types.get_impl(&class_id).unwrap();
                 let method = imp.get(method_name).unwrap();
-                method.write(&class_id, formats, types, stream)?;
+                backend.call(class_id, method, formats, types)
             }
-            AssembledInstruction::Multiply(op1, op2) => {
-                stream.write(format!("(").as_bytes())?;
-                op1.as_ref().write(formats, types, stream)?;
-                stream.write(format!(" * ").as_bytes())?;
-                op2.as_ref().write(formats, types, stream)?;
-                stream.write(format!(")").as_bytes())?;
+            AssembledInstruction::Arithmetic(op, left, right) => {
+                backend.arithmetic(*op, left, right, formats, types)
             }
             AssembledInstruction::Chain(op1, op2) => {
-                op1.as_ref().write(formats, types, stream)?;
-                op2.as_ref().write(formats, types, stream)?;
-            }
-            AssembledInstruction::Get(_) => {
-                stream.write(format!("in",).as_bytes())?;
-            }
-            AssembledInstruction::Stream(class_id, _stream_instruction) => {
-                let t = types.get_type(&formats.in_class(class_id)).unwrap();
-                let num_of_bytes = t.get_bytes(types);
-                AssembledInstruction::FlipIntake.write(formats, types, stream)?;
-                stream.write(format!("while(in) {{",).as_bytes())?;
-                formats.increase_indentation();
-                AssembledInstruction::Indent.write(formats, types, stream)?;
-                stream.write(format!("in += {};", num_of_bytes).as_bytes())?;
-                AssembledInstruction::EndBlock.write(formats, types, stream)?;
-            }
-            AssembledInstruction::Indent => {
-                for _ in 0..formats.indentation {
-                    stream.write("\t".as_bytes())?;
-                }
-            }
-            AssembledInstruction::EndBlock => {
-                stream.write(format!("}}\n").as_bytes())?;
-                AssembledInstruction::Indent.write(formats, types, stream)?;
-            }
-            AssembledInstruction::Semicolon => {
-                if formats.indentation > 0 {
-                    stream.write(format!(";\n").as_bytes())?;
-                    for _ in 0..formats.indentation {
-                        stream.write("\t".as_bytes())?;
-                    }
-                } else {
-                    stream.write(format!(";\n").as_bytes())?;
-                }
-            }
-        };
-        Ok(())
+                op1.write(formats, types, backend)?;
+                op2.write(formats, types, backend)
+            }
+            AssembledInstruction::Block(instructions) => {
+                backend.block(instructions, formats, types)
+            }
+            AssembledInstruction::Return(code) => backend.return_code(*code),
+            AssembledInstruction::ReturnOutgive => backend.return_outgive(),
+            AssembledInstruction::Accumulate(index) => backend.accumulate(*index),
+            AssembledInstruction::Get(_) => backend.get(),
+            AssembledInstruction::Element => backend.element(),
+            AssembledInstruction::Stream(class_id, stream) => {
+                let class_id = *formats.in_class(class_id);
+                backend.stream_loop(class_id, stream, formats, types)
+            }
+        }
     }
 }
@@ -0,0 +1,976 @@
+use crate::lang::assemble::context::{
+    AssembledTypeContext, ClassReference, CHAR_BYTES, CLASS_ID_CHAR, CLASS_ID_INT, CLASS_ID_STRING,
+    CLASS_ID_VOID, CONTEXT_SLOTS,
+};
+use crate::lang::assemble::instruction::{
+    AssembledInstruction, PrintKind, RangeBounds, StreamInstruction,
+};
+use crate::lang::assemble::{AssembledMethod, AssembledType, AssemblyError};
+use crate::parse::constant::{Brace, Number, Operator};
+use crate::parse::context::{ContextScope, TokenizerContext};
+use crate::parse::token::{Literal, OperatorGroup, Token};
+
+use std::collections::{HashMap, HashSet};
+
+/// How the elements flowing through a pipeline are visited once the intake is set up.
+enum Iteration {
+    /// Every element of the intake.
+    ForEach,
+    /// Integers produced by the loop counter itself, so nothing is allocated.
+    Range(RangeBounds),
+    /// The elements of the intake whose index lies within the bounds.
+    Slice(RangeBounds),
+}
+
+struct Stream {
+    class_id: u16,
+    setup: Vec<AssembledInstruction>,
+    iteration: Iteration,
+    owned: bool,
+}
+
+impl Stream {
+    /// An upper bound on the number of elements, evaluated once the setup has run.
+    fn count(&self, types: &AssembledTypeContext) -> AssembledInstruction {
+        match &self.iteration {
+            Iteration::Range(bounds) => AssembledInstruction::RangeLength(bounds.clone()),
+            _ => AssembledInstruction::IntakeCount(
+                types.get_type(&self.class_id).unwrap().get_bytes(types),
+            ),
+        }
+    }
+
+    fn into_instructions(self, body: Vec<AssembledInstruction>) -> Vec<AssembledInstruction> {
+        let this = ClassReference::ThisAs(self.class_id);
+        let body = Box::new(AssembledInstruction::Block(body));
+        let mut instructions = self.setup;
+        instructions.push(AssembledInstruction::Stream(
+            this,
+            match self.iteration {
+                Iteration::ForEach => StreamInstruction::ForEach(body),
+                Iteration::Range(bounds) => StreamInstruction::Range(bounds, body),
+                Iteration::Slice(bounds) => StreamInstruction::Slice(bounds, body),
+            },
+        ));
+        if self.owned {
+            instructions.push(AssembledInstruction::FreeIntake);
+        }
+        instructions
+    }
+}
+
+fn allocate(values: Vec<AssembledInstruction>, bytes: u64) -> Vec<AssembledInstruction> {
+    let mut instructions = vec![
+        AssembledInstruction::SetLength(0),
+        AssembledInstruction::InitIntake(bytes),
+    ];
+    instructions.extend(values);
+    instructions.push(AssembledInstruction::FlipIntake);
+    instructions
+}
+
+/// The local bindings of a method body, each owning a slot of the generated `__CONTEXT__`, along
+/// with the fields of the element the method takes in.
+pub struct Bindings<'a> {
+    scope: &'a ContextScope,
+    slots: HashMap<String, (u16, u16)>,
+    /// The bindings known to hold exactly one element.
+    singles: HashSet<String>,
+    /// The offset and class of each field of the element, under its own name and under the one
+    /// the intake pattern gives it.
+    fields: HashMap<String, (u16, u16)>,
+    /// The types the type parameters of a monomorphised method stand for.
+    type_arguments: HashMap<String, u16>,
+}
+impl<'a> Bindings<'a> {
+    pub fn new(scope: &'a ContextScope) -> Bindings<'a> {
+        Bindings {
+            scope,
+            slots: HashMap::new(),
+            singles: HashSet::new(),
+            fields: HashMap::new(),
+            type_arguments: HashMap::new(),
+        }
+    }
+    /// The bindings a method taking in elements of `class_id` starts with.
+    pub fn for_method(
+        scope: &'a ContextScope,
+        class_id: u16,
+        method: &AssembledMethod,
+        types: &AssembledTypeContext,
+    ) -> Bindings<'a> {
+        let mut bindings = Bindings::new(scope);
+        bindings.type_arguments = method.get_type_arguments().clone();
+        if let Some(AssembledType::Composition(_, comp)) = types.get_type(&class_id) {
+            let mut offset = 0;
+            for (i, field) in comp.data.iter().enumerate() {
+                let field_binding = (offset, field.class_id);
+                bindings
+                    .fields
+                    .insert(field.source_name.clone(), field_binding);
+                if let Some(parameter) = method.get_parameters().get(i) {
+                    if parameter.source_name != "_" {
+                        bindings
+                            .fields
+                            .insert(parameter.source_name.clone(), field_binding);
+                    }
+                }
+                offset += types.get_type(&field.class_id).unwrap().get_bytes(types);
+            }
+        }
+        bindings
+    }
+    fn bind(&mut self, name: &str, class_id: u16) -> Result<u16, AssemblyError> {
+        if self.slots.contains_key(name) {
+            return Err(AssemblyError::BindingRedefined(name.to_string()));
+        }
+        let slot = self.slots.len() as u16;
+        if slot >= CONTEXT_SLOTS {
+            return Err(AssemblyError::TooManyBindings(name.to_string()));
+        }
+        self.slots.insert(name.to_string(), (slot, class_id));
+        Ok(slot)
+    }
+    /// Returns the slot and class of `name`, which must have been bound by an earlier statement.
+    fn get(&self, name: &str) -> Result<(u16, u16), AssemblyError> {
+        match self.slots.get(name) {
+            Some(binding) => Ok(*binding),
+            None if self.scope.is_declared(name) => {
+                Err(AssemblyError::BindingUsedBeforeDefinition(name.to_string()))
+            }
+            None => Err(AssemblyError::UnknownIdentifier(name.to_string())),
+        }
+    }
+    /// Whether `name` stands for a single value: a field of the element, or a binding known to
+    /// hold exactly one element.
+    fn is_single(&self, name: &str) -> bool {
+        self.is_field(name) || self.singles.contains(name)
+    }
+    /// Whether `name` stands for a field of the element rather than a binding.
+    fn is_field(&self, name: &str) -> bool {
+        !self.slots.contains_key(name) && self.fields.contains_key(name)
+    }
+    /// The class of a single value `name` stands for and a pointer to it: the first element of a
+    /// binding, or a field of the element.
+    fn value(&self, name: &str) -> Result<(u16, AssembledInstruction), AssemblyError> {
+        if self.is_field(name) {
+            let (offset, class_id) = self.fields[name];
+            let element = Box::new(AssembledInstruction::Element);
+            return Ok((class_id, AssembledInstruction::Offset(element, offset)));
+        }
+        let (slot, class_id) = self.get(name)?;
+        Ok((class_id, AssembledInstruction::ContextSlot(slot)))
+    }
+    /// The id of the type `name`, which may be a type parameter of the method.
+    fn type_id(&self, name: &str, types: &AssembledTypeContext) -> Result<u16, AssemblyError> {
+        match self.type_arguments.get(name) {
+            Some(id) => Ok(*id),
+            None => match types.get_type_id(&name.to_string()) {
+                Some(id) => Ok(*id),
+                None => Err(AssemblyError::TypeNotFound),
+            },
+        }
+    }
+    /// Frees every slot once the method is done with it.
+    pub fn free(&self) -> Vec<AssembledInstruction> {
+        let mut slots = self
+            .slots
+            .values()
+            .map(|(slot, _)| *slot)
+            .collect::<Vec<u16>>();
+        slots.sort_unstable();
+        slots
+            .into_iter()
+            .map(AssembledInstruction::FreeOutgive)
+            .collect()
+    }
+}
+
+/// Lowers the statements of a method body into instructions, one fused loop per pipeline.
+pub fn lower_method(
+    body: usize,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &mut Bindings,
+) -> Result<Vec<AssembledInstruction>, AssemblyError> {
+    let mut instructions = Vec::new();
+    for statement in tokens.get_scope(body).unwrap().get_tokens().iter() {
+        instructions.extend(lower_statement(statement, tokens, types, bindings)?.1);
+    }
+    Ok(instructions)
+}
+
+/// Lowers a method declared in source, taking in elements of `class_id`, into the body of a
+/// function run on each element. The elements of its last statement are collected into the
+/// outgive, which the function gives back. Returns their class, the body and the frees of its
+/// bindings.
+pub fn lower_function(
+    class_id: u16,
+    method: &AssembledMethod,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+) -> Result<LoweredMethod, AssemblyError> {
+    let scope = method.get_scope().unwrap();
+    let mut bindings =
+        Bindings::for_method(tokens.get_scope(scope).unwrap(), class_id, method, types);
+    let statements = tokens.get_scope(scope).unwrap().get_tokens();
+    let (last, statements) = match statements.split_last() {
+        Some((last, statements)) => (Some(last.as_ref()), statements),
+        None => (None, &statements[..]),
+    };
+    let mut body = Vec::new();
+    for statement in statements.iter() {
+        body.extend(lower_statement(statement, tokens, types, &mut bindings)?.1);
+    }
+    let (source, stages) = match last {
+        Some(Token::Binding(_, _)) | None => (None, Vec::new()),
+        Some(last) => match pipeline(last) {
+            (Token::AgrandizedString(_, _, _), _) => (None, Vec::new()),
+            (source, stages) => (Some(source), stages),
+        },
+    };
+    let produced = match source {
+        Some(source) => {
+            let stream = lower_source(source, tokens, types, &bindings)?;
+            let (class_id, instructions) = collect(stream, &stages, types)?;
+            body.extend(instructions);
+            class_id
+        }
+        // a binding or an interpolation gives back no elements
+        None => {
+            if let Some(last) = last {
+                body.extend(lower_statement(last, tokens, types, &mut bindings)?.1);
+            }
+            body.push(AssembledInstruction::InitOutgiveFor(Box::new(
+                AssembledInstruction::Integer(0),
+            )));
+            CLASS_ID_VOID
+        }
+    };
+    Ok(LoweredMethod {
+        produce_type: produced,
+        body,
+        free: bindings.free(),
+    })
+}
+
+/// The result of lowering a method declared in source.
+pub struct LoweredMethod {
+    pub produce_type: u16,
+    pub body: Vec<AssembledInstruction>,
+    pub free: Vec<AssembledInstruction>,
+}
+
+/// Lowers a single statement, binding its result if it is `name: value`. Returns the class of
+/// the elements at the end of its pipeline, void once they have been printed.
+pub fn lower_statement(
+    statement: &Token,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &mut Bindings,
+) -> Result<(u16, Vec<AssembledInstruction>), AssemblyError> {
+    let (name, statement) = match statement {
+        Token::Binding(name, value) => (Some(name), value.as_ref()),
+        _ => (None, statement),
+    };
+    let (source, stages) = pipeline(statement);
+    if let Token::AgrandizedString(prefix, parts, suffix) = source {
+        if name.is_some() {
+            return Err(AssemblyError::UnsupportedExpression);
+        }
+        let parts = (prefix, parts.as_ref(), suffix);
+        let instructions = lower_interpolation(parts, stages, tokens, types, bindings)?;
+        return Ok((CLASS_ID_VOID, instructions));
+    }
+    let single = stages.is_empty() && is_single(source, tokens, bindings);
+    let stream = lower_source(source, tokens, types, bindings)?;
+    let name = match name {
+        Some(name) => name,
+        None => {
+            return lower_flow(stream, &stages, types, &|_| Vec::new());
+        }
+    };
+    let (class_id, mut instructions) = collect(stream, &stages, types)?;
+    if class_id == CLASS_ID_VOID {
+        return Err(AssemblyError::CannotBindVoid(name.clone()));
+    }
+    let slot = bindings.bind(name, class_id)?;
+    if single {
+        bindings.singles.insert(name.clone());
+    }
+    instructions.push(AssembledInstruction::SaveOutgive(slot));
+    Ok((class_id, instructions))
+}
+
+/// Whether a source is known to give exactly one element: a value, an array of one, or a binding
+/// holding one. Ranges, indexing and everything a method gives back may give any number.
+fn is_single(source: &Token, tokens: &TokenizerContext, bindings: &Bindings) -> bool {
+    match source {
+        Token::Array(elements) => matches!(block_tokens(elements, tokens), Ok([_])),
+        Token::Literal(Literal::Identifier(name)) => bindings.is_single(name),
+        Token::Operator(OperatorGroup::BiOperator(op, _, _)) => {
+            !matches!(op, Operator::RangeMiddle | Operator::RangeInclusive)
+        }
+        Token::Index(_, _) | Token::Operator(OperatorGroup::TriOperator(..)) => false,
+        _ => true,
+    }
+}
+
+/// Lowers a pipeline whose elements are gathered into the outgive rather than dropped, as the
+/// value of a binding or what a method gives back. Returns the class of the elements.
+fn collect(
+    stream: Stream,
+    stages: &[&Token],
+    types: &AssembledTypeContext,
+) -> Result<(u16, Vec<AssembledInstruction>), AssemblyError> {
+    // the outgive is sized once the setup has run, and grows if a method gives back more
+    let count = stream.count(types);
+    let mut instructions = stream.setup;
+    let stream = Stream {
+        setup: Vec::new(),
+        ..stream
+    };
+    let (class_id, body) = lower_flow(stream, stages, types, &|class_id| {
+        vec![AssembledInstruction::AddOutgive(
+            types.get_type(&class_id).unwrap().get_bytes(types),
+        )]
+    })?;
+    let bytes = types.get_type(&class_id).unwrap().get_bytes(types);
+    instructions.push(AssembledInstruction::InitOutgiveFor(Box::new(
+        AssembledInstruction::Arithmetic(
+            Operator::Multiply,
+            Box::new(count),
+            Box::new(AssembledInstruction::Integer(bytes as i64)),
+        ),
+    )));
+    instructions.extend(body);
+    Ok((class_id, instructions))
+}
+
+/// Lowers `"a{x}b" => print`. The interpolated values are written into a single intake, then
+/// printed between the text segments through the print implementation of their type.
+fn lower_interpolation(
+    (prefix, parts, suffix): (&String, &Token, &String),
+    stages: Vec<&Token>,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &Bindings,
+) -> Result<Vec<AssembledInstruction>, AssemblyError> {
+    match stages.as_slice() {
+        [Token::Literal(Literal::Identifier(name))] if name == "print" => {}
+        [Token::Literal(Literal::Identifier(name))] => {
+            return Err(AssemblyError::UnsupportedStage(name.clone()))
+        }
+        _ => return Err(AssemblyError::UnsupportedExpression),
+    }
+    let mut values = Vec::new();
+    let mut prints = vec![AssembledInstruction::PrintLiteral(prefix.clone())];
+    let mut bytes = 0u16;
+    for part in block_tokens(parts, tokens)?.iter() {
+        if let Token::Literal(Literal::String(text)) = part.as_ref() {
+            prints.push(AssembledInstruction::PrintLiteral(text.clone()));
+            continue;
+        }
+        let (class_id, part_values) = match part.as_ref() {
+            Token::Literal(Literal::Identifier(name)) => {
+                if !bindings.is_single(name) {
+                    return Err(AssemblyError::NotSingleValue(name.clone()));
+                }
+                let (class_id, value) = bindings.value(name)?;
+                let size = types.get_type(&class_id).unwrap().get_bytes(types);
+                (
+                    class_id,
+                    vec![AssembledInstruction::AddIntakeFrom(Box::new(value), size)],
+                )
+            }
+            _ => lower_value(part, tokens, types, bindings)?,
+        };
+        if !is_printable(class_id, types) {
+            return Err(AssemblyError::NotPrintable(
+                types.get_type(&class_id).unwrap().get_name(),
+            ));
+        }
+        values.extend(part_values);
+        print_value(class_id, bytes, types, &mut prints);
+        bytes += types.get_type(&class_id).unwrap().get_bytes(types);
+    }
+    prints.push(AssembledInstruction::PrintLiteral(format!("{}\n", suffix)));
+    prints.retain(|p| !matches!(p, AssembledInstruction::PrintLiteral(text) if text.is_empty()));
+    let mut instructions = allocate(values, bytes as u64);
+    instructions.extend(prints);
+    instructions.push(AssembledInstruction::FreeIntake);
+    Ok(instructions)
+}
+
+/// Whether values of `class_id` implement `Printable`, a composition being printable when all of
+/// its fields are.
+pub fn is_printable(class_id: u16, types: &AssembledTypeContext) -> bool {
+    match types.get_type(&class_id) {
+        Some(AssembledType::Data(_, _)) => types
+            .get_impl(&class_id)
+            .is_some_and(|imp| imp.contains_key("print")),
+        Some(AssembledType::Composition(_, comp)) => comp
+            .data
+            .iter()
+            .all(|field| is_printable(field.class_id, types)),
+        _ => false,
+    }
+}
+
+/// Splits `source => a => b` into its source and the stages applied to each element.
+fn pipeline(token: &Token) -> (&Token, Vec<&Token>) {
+    match token {
+        Token::Operator(OperatorGroup::BiOperator(Operator::Into, source, stage)) => {
+            let (source, mut stages) = pipeline(source);
+            stages.push(stage);
+            (source, stages)
+        }
+        _ => (token, Vec::new()),
+    }
+}
+
+fn lower_source(
+    token: &Token,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &Bindings,
+) -> Result<Stream, AssemblyError> {
+    if let Some(bounds) = range_bounds(token, tokens, types, bindings)? {
+        return Ok(Stream {
+            class_id: CLASS_ID_INT,
+            setup: Vec::new(),
+            iteration: Iteration::Range(bounds),
+            owned: false,
+        });
+    }
+    match token {
+        Token::Literal(Literal::Identifier(name)) if !bindings.is_field(name) => {
+            let (slot, class_id) = bindings.get(name)?;
+            Ok(Stream {
+                class_id,
+                setup: vec![AssembledInstruction::LoadIntake(slot)],
+                iteration: Iteration::ForEach,
+                owned: false,
+            })
+        }
+        Token::Array(elements) => {
+            let mut class_id = None;
+            let mut values = Vec::new();
+            let mut bytes = 0u64;
+            for element in block_tokens(elements, tokens)?.iter() {
+                let (element_class, element_values) =
+                    lower_value(element, tokens, types, bindings)?;
+                if *class_id.get_or_insert(element_class) != element_class {
+                    return Err(AssemblyError::TypeMismatch(
+                        types.get_type(&class_id.unwrap()).unwrap().get_name(),
+                        types.get_type(&element_class).unwrap().get_name(),
+                    ));
+                }
+                bytes += types.get_type(&element_class).unwrap().get_bytes(types) as u64;
+                values.extend(element_values);
+            }
+            Ok(Stream {
+                class_id: class_id.unwrap_or(CLASS_ID_VOID),
+                setup: allocate(values, bytes),
+                iteration: Iteration::ForEach,
+                owned: true,
+            })
+        }
+        Token::Index(collection, indices) => {
+            let stream = lower_source(collection, tokens, types, bindings)?;
+            let indices = block_tokens(indices, tokens)?;
+            if indices.len() != 1 {
+                return Err(AssemblyError::UnsupportedExpression);
+            }
+            let bounds = match range_bounds(&indices[0], tokens, types, bindings)? {
+                Some(bounds) => bounds,
+                None => {
+                    let index = lower_scalar(&indices[0], tokens, types, bindings)?;
+                    RangeBounds {
+                        start: Box::new(index.clone()),
+                        end: Box::new(index),
+                        step: Box::new(AssembledInstruction::Integer(1)),
+                        inclusive: true,
+                    }
+                }
+            };
+            match stream.iteration {
+                Iteration::ForEach => Ok(Stream {
+                    iteration: Iteration::Slice(bounds),
+                    ..stream
+                }),
+                _ => Err(AssemblyError::UnsupportedExpression),
+            }
+        }
+        _ => {
+            let (class_id, values) = lower_value(token, tokens, types, bindings)?;
+            let bytes = types.get_type(&class_id).unwrap().get_bytes(types) as u64;
+            Ok(Stream {
+                class_id,
+                setup: allocate(values, bytes),
+                iteration: Iteration::ForEach,
+                owned: true,
+            })
+        }
+    }
+}
+
+/// The bounds of `start..end`, `start..=end` or either followed by `by step`.
+fn range_bounds(
+    token: &Token,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &Bindings,
+) -> Result<Option<RangeBounds>, AssemblyError> {
+    let (op, start, end, step) = match token {
+        Token::Operator(OperatorGroup::BiOperator(op, start, end)) => {
+            (op, start, end, AssembledInstruction::Integer(1))
+        }
+        Token::Operator(OperatorGroup::TriOperator(op, start, end, step)) => {
+            (op, start, end, lower_scalar(step, tokens, types, bindings)?)
+        }
+        _ => return Ok(None),
+    };
+    let inclusive = match op {
+        Operator::RangeMiddle => false,
+        Operator::RangeInclusive => true,
+        _ => return Ok(None),
+    };
+    if let AssembledInstruction::Integer(step) = step {
+        if step <= 0 {
+            return Err(AssemblyError::InvalidRangeStep(step));
+        }
+    }
+    Ok(Some(RangeBounds {
+        start: Box::new(lower_scalar(start, tokens, types, bindings)?),
+        end: Box::new(lower_scalar(end, tokens, types, bindings)?),
+        step: Box::new(step),
+        inclusive,
+    }))
+}
+
+/// Lowers an expression used as a single integer, such as a range bound or arithmetic. A binding
+/// used this way stands for its first element.
+fn lower_scalar(
+    token: &Token,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &Bindings,
+) -> Result<AssembledInstruction, AssemblyError> {
+    match token {
+        Token::Literal(Literal::Number(Number::Integer(i))) => {
+            Ok(AssembledInstruction::Integer(*i))
+        }
+        Token::Operator(OperatorGroup::BiOperator(op, left, right)) if is_arithmetic(*op) => {
+            Ok(AssembledInstruction::Arithmetic(
+                *op,
+                Box::new(lower_scalar(left, tokens, types, bindings)?),
+                Box::new(lower_scalar(right, tokens, types, bindings)?),
+            ))
+        }
+        // `-a` is `0 - a`, which wraps the same way
+        Token::Operator(OperatorGroup::UniOperator(Operator::Minus, value)) => {
+            Ok(AssembledInstruction::Arithmetic(
+                Operator::Minus,
+                Box::new(AssembledInstruction::Integer(0)),
+                Box::new(lower_scalar(value, tokens, types, bindings)?),
+            ))
+        }
+        Token::Block(Brace::Brace, _) => match block_tokens(token, tokens)? {
+            [value] => lower_scalar(value, tokens, types, bindings),
+            _ => Err(AssemblyError::UnsupportedExpression),
+        },
+        Token::Literal(Literal::Identifier(name)) => {
+            let (class_id, value) = bindings.value(name)?;
+            if class_id != CLASS_ID_INT {
+                return Err(AssemblyError::TypeMismatch(
+                    "int".to_string(),
+                    types.get_type(&class_id).unwrap().get_name(),
+                ));
+            }
+            Ok(AssembledInstruction::Chain(
+                Box::new(AssembledInstruction::Deref(Some("int".to_string()))),
+                Box::new(value),
+            ))
+        }
+        _ => Err(AssemblyError::UnsupportedExpression),
+    }
+}
+
+fn is_arithmetic(op: Operator) -> bool {
+    matches!(
+        op,
+        Operator::Plus
+            | Operator::Minus
+            | Operator::Multiply
+            | Operator::Divide
+            | Operator::Modulus
+    )
+}
+
+/// Lowers a value into the instructions writing each of its primitives into the intake. A name
+/// stands for a field of the element the method takes in.
+fn lower_value(
+    token: &Token,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &Bindings,
+) -> Result<(u16, Vec<AssembledInstruction>), AssemblyError> {
+    let (class_id, arguments) = match token {
+        Token::Literal(Literal::Identifier(name)) => {
+            if !bindings.is_field(name) {
+                return Err(AssemblyError::UnsupportedExpression);
+            }
+            let (class_id, value) = bindings.value(name)?;
+            let size = types.get_type(&class_id).unwrap().get_bytes(types);
+            return Ok((
+                class_id,
+                vec![AssembledInstruction::AddIntakeFrom(Box::new(value), size)],
+            ));
+        }
+        Token::Literal(Literal::Number(Number::Integer(i))) => {
+            return Ok((
+                CLASS_ID_INT,
+                vec![AssembledInstruction::AddIntake(
+                    Box::new(AssembledInstruction::Integer(*i)),
+                    ClassReference::ThisAs(CLASS_ID_INT),
+                )],
+            ));
+        }
+        Token::Literal(Literal::String(text)) => {
+            return Ok((
+                CLASS_ID_STRING,
+                vec![AssembledInstruction::AddIntake(
+                    Box::new(AssembledInstruction::StringLiteral(text.clone())),
+                    ClassReference::ThisAs(CLASS_ID_STRING),
+                )],
+            ));
+        }
+        Token::Literal(Literal::Number(Number::Char(c))) => {
+            let mut bytes = vec![0u8; CHAR_BYTES as usize];
+            c.encode_utf8(&mut bytes);
+            return Ok((
+                CLASS_ID_CHAR,
+                vec![AssembledInstruction::AddIntakeBytes(bytes)],
+            ));
+        }
+        Token::Constructor(name, arguments) => (bindings.type_id(name, types)?, arguments),
+        Token::GenericConstructor(name, type_arguments, arguments) => {
+            let mut ids = Vec::new();
+            for argument in block_tokens(type_arguments, tokens)?.iter() {
+                ids.push(lookup_type(argument, tokens, types, bindings)?);
+            }
+            match types.get_instance(name, &ids) {
+                Some(class_id) => (*class_id, arguments),
+                None => return Err(AssemblyError::TypeNotFound),
+            }
+        }
+        Token::Operator(_) | Token::Block(Brace::Brace, _) => {
+            let value = lower_scalar(token, tokens, types, bindings)?;
+            return Ok((
+                CLASS_ID_INT,
+                vec![AssembledInstruction::AddIntake(
+                    Box::new(value),
+                    ClassReference::ThisAs(CLASS_ID_INT),
+                )],
+            ));
+        }
+        _ => return Err(AssemblyError::UnsupportedExpression),
+    };
+    let arguments = block_tokens(arguments, tokens)?;
+    let fields = match types.get_type(&class_id).unwrap() {
+        AssembledType::Composition(_, comp) => &comp.data,
+        // `int(x)` is `x`, so long as it is an int
+        AssembledType::Data(name, _) => match arguments {
+            [argument] => {
+                let (argument_class, values) = lower_value(argument, tokens, types, bindings)?;
+                if argument_class != class_id {
+                    return Err(AssemblyError::TypeMismatch(
+                        name.clone(),
+                        types.get_type(&argument_class).unwrap().get_name(),
+                    ));
+                }
+                return Ok((class_id, values));
+            }
+            _ => {
+                return Err(AssemblyError::WrongNumberOfArguments(
+                    name.clone(),
+                    1,
+                    arguments.len(),
+                ))
+            }
+        },
+        _ => return Err(AssemblyError::UnsupportedExpression),
+    };
+    if arguments.len() != fields.len() {
+        return Err(AssemblyError::WrongNumberOfArguments(
+            types.get_type(&class_id).unwrap().get_name(),
+            fields.len(),
+            arguments.len(),
+        ));
+    }
+    let mut values = Vec::new();
+    for (field, argument) in fields.iter().zip(arguments.iter()) {
+        let (argument_class, argument_values) = lower_value(argument, tokens, types, bindings)?;
+        if argument_class != field.class_id {
+            return Err(AssemblyError::TypeMismatch(
+                types.get_type(&field.class_id).unwrap().get_name(),
+                types.get_type(&argument_class).unwrap().get_name(),
+            ));
+        }
+        values.extend(argument_values);
+    }
+    Ok((class_id, values))
+}
+
+/// Resolves a type named in already monomorphised code, such as the arguments of `Pair<int, int>`
+/// or of `Pair<B, A>` in a method whose type parameters are bound.
+fn lookup_type(
+    token: &Token,
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    bindings: &Bindings,
+) -> Result<u16, AssemblyError> {
+    match token {
+        Token::Literal(Literal::Identifier(name)) => bindings.type_id(name, types),
+        Token::Generic(name, arguments) => {
+            let mut ids = Vec::new();
+            for argument in block_tokens(arguments, tokens)?.iter() {
+                ids.push(lookup_type(argument, tokens, types, bindings)?);
+            }
+            match types.get_instance(name, &ids) {
+                Some(id) => Ok(*id),
+                None => Err(AssemblyError::TypeNotFound),
+            }
+        }
+        _ => Err(AssemblyError::TypeNotFound),
+    }
+}
+
+fn block_tokens<'a>(
+    block: &Token,
+    tokens: &'a TokenizerContext,
+) -> Result<&'a [Box<Token>], AssemblyError> {
+    match block {
+        Token::Block(_, scope) => Ok(tokens.get_scope(*scope).unwrap().get_tokens()),
+        _ => Err(AssemblyError::ExpectedBlock),
+    }
+}
+
+/// What a stage does to the elements reaching it.
+enum Stage<'t> {
+    Print,
+    /// Leaves the elements as they are, as casting an int to an int does.
+    Keep,
+    /// Calls a method declared in source on each element.
+    Call(&'t AssembledMethod),
+    /// Adds up every int reaching it into one.
+    Sum,
+    /// Takes the field at the given offset of each element, of the given class.
+    Field(u16, u16),
+}
+
+fn stage_name(stage: &Token) -> Result<&String, AssemblyError> {
+    match stage {
+        Token::Literal(Literal::Identifier(name)) => Ok(name),
+        _ => Err(AssemblyError::UnsupportedExpression),
+    }
+}
+
+/// Resolves the stage `name` on elements of `class_id`. A method of their type comes before `sum`
+/// on ints and the fields of a composition.
+fn resolve_stage<'t>(
+    name: &str,
+    class_id: u16,
+    types: &'t AssembledTypeContext,
+) -> Result<Stage<'t>, AssemblyError> {
+    if name == "print" {
+        return Ok(Stage::Print);
+    }
+    // casting an int to an int leaves it as it is
+    if name == "cast" && class_id == CLASS_ID_INT {
+        return Ok(Stage::Keep);
+    }
+    match types.get_impl(&class_id).and_then(|imp| imp.get(name)) {
+        Some(method) if method.get_scope().is_none() => {
+            return Err(AssemblyError::UnsupportedStage(name.to_string()))
+        }
+        Some(method) if !method.is_lowered() => {
+            return Err(AssemblyError::RecursiveMethod(name.to_string()))
+        }
+        Some(method) => return Ok(Stage::Call(method)),
+        None => {}
+    }
+    if name == "sum" && class_id == CLASS_ID_INT {
+        return Ok(Stage::Sum);
+    }
+    if let Some(AssembledType::Composition(_, comp)) = types.get_type(&class_id) {
+        let mut offset = 0;
+        for field in comp.data.iter() {
+            if field.source_name == name {
+                return Ok(Stage::Field(offset, field.class_id));
+            }
+            offset += types.get_type(&field.class_id).unwrap().get_bytes(types);
+        }
+    }
+    Err(AssemblyError::MethodNotFound(
+        types.get_type(&class_id).unwrap().get_name(),
+        name.to_string(),
+    ))
+}
+
+/// Lowers a stream through its stages. A `sum` gathers every element before it, so the stages
+/// after the last one run once, over the total.
+fn lower_flow(
+    stream: Stream,
+    stages: &[&Token],
+    types: &AssembledTypeContext,
+    tail: &dyn Fn(u16) -> Vec<AssembledInstruction>,
+) -> Result<(u16, Vec<AssembledInstruction>), AssemblyError> {
+    let mut class_id = stream.class_id;
+    let mut sums = Vec::new();
+    for (i, stage) in stages.iter().enumerate() {
+        class_id = match resolve_stage(stage_name(stage)?, class_id, types)? {
+            Stage::Print => CLASS_ID_VOID,
+            Stage::Keep => class_id,
+            Stage::Call(method) => method.get_produce_type(),
+            Stage::Sum => {
+                sums.push(i);
+                class_id
+            }
+            Stage::Field(_, field_class) => field_class,
+        };
+    }
+    let at = match sums.last() {
+        Some(at) => *at,
+        None => {
+            let (class_id, body) = lower_stages(stages, stream.class_id, types, tail)?;
+            return Ok((class_id, stream.into_instructions(body)));
+        }
+    };
+    let index = (sums.len() - 1) as u16;
+    let (_, gather) = lower_flow(stream, &stages[..at], types, &|_| {
+        vec![AssembledInstruction::Accumulate(index)]
+    })?;
+    let (class_id, body) = lower_stages(&stages[at + 1..], CLASS_ID_INT, types, tail)?;
+    Ok((
+        class_id,
+        vec![AssembledInstruction::Stream(
+            ClassReference::ThisAs(CLASS_ID_INT),
+            StreamInstruction::Sum(
+                index,
+                Box::new(AssembledInstruction::Block(gather)),
+                Box::new(AssembledInstruction::Block(body)),
+            ),
+        )],
+    ))
+}
+
+/// Lowers the stages applied to each element of `class_id`, ending with the instructions `tail`
+/// gives for the class of what comes out of the last. A method declared in source is called on
+/// the element, the rest of the stages running on each element it gives back. Returns the class
+/// at the end, void once the elements have been printed. None of the stages is a `sum`.
+fn lower_stages(
+    stages: &[&Token],
+    class_id: u16,
+    types: &AssembledTypeContext,
+    tail: &dyn Fn(u16) -> Vec<AssembledInstruction>,
+) -> Result<(u16, Vec<AssembledInstruction>), AssemblyError> {
+    let (stage, rest) = match stages.split_first() {
+        Some(split) => split,
+        None => return Ok((class_id, tail(class_id))),
+    };
+    let name = stage_name(stage)?;
+    match resolve_stage(name, class_id, types)? {
+        Stage::Print => {
+            let mut instructions = Vec::new();
+            print_value(class_id, 0, types, &mut instructions);
+            instructions.push(AssembledInstruction::PrintLiteral("\n".to_string()));
+            let (class_id, rest) = lower_stages(rest, CLASS_ID_VOID, types, tail)?;
+            instructions.extend(rest);
+            Ok((class_id, instructions))
+        }
+        Stage::Keep => lower_stages(rest, class_id, types, tail),
+        // split off by `lower_flow`
+        Stage::Sum => Err(AssemblyError::UnsupportedStage(name.clone())),
+        Stage::Field(offset, field_class) => {
+            let (end, body) = lower_stages(rest, field_class, types, tail)?;
+            Ok((
+                end,
+                vec![AssembledInstruction::Stream(
+                    ClassReference::ThisAs(field_class),
+                    StreamInstruction::Field(offset, Box::new(AssembledInstruction::Block(body))),
+                )],
+            ))
+        }
+        Stage::Call(method) => {
+            let produced = method.get_produce_type();
+            let (end, body) = lower_stages(rest, produced, types, tail)?;
+            Ok((
+                end,
+                vec![AssembledInstruction::Stream(
+                    ClassReference::ThisAs(produced),
+                    StreamInstruction::Call(
+                        ClassReference::ThisAs(class_id),
+                        name.clone(),
+                        Box::new(AssembledInstruction::Block(body)),
+                    ),
+                )],
+            ))
+        }
+    }
+}
+
+fn print_value(
+    class_id: u16,
+    offset: u16,
+    types: &AssembledTypeContext,
+    instructions: &mut Vec<AssembledInstruction>,
+) {
+    match types.get_type(&class_id).unwrap() {
+        AssembledType::Data(_, _) if class_id == CLASS_ID_CHAR => {
+            instructions.push(AssembledInstruction::PrintString(
+                CHAR_BYTES as u64,
+                Box::new(AssembledInstruction::Offset(
+                    Box::new(AssembledInstruction::Get(ClassReference::This)),
+                    offset,
+                )),
+            ));
+        }
+        AssembledType::Data(name, _) => {
+            instructions.push(AssembledInstruction::PrintValue(
+                print_kind(name),
+                Box::new(AssembledInstruction::Chain(
+                    Box::new(AssembledInstruction::Deref(Some(name.clone()))),
+                    Box::new(AssembledInstruction::Offset(
+                        Box::new(AssembledInstruction::Get(ClassReference::This)),
+                        offset,
+                    )),
+                )),
+            ));
+        }
+        AssembledType::Composition(name, comp) => {
+            instructions.push(AssembledInstruction::PrintLiteral(format!("{}(", name)));
+            let mut field_offset = offset;
+            for (i, field) in comp.data.iter().enumerate() {
+                if i > 0 {
+                    instructions.push(AssembledInstruction::PrintLiteral(", ".to_string()));
+                }
+                print_value(field.class_id, field_offset, types, instructions);
+                field_offset += types.get_type(&field.class_id).unwrap().get_bytes(types);
+            }
+            instructions.push(AssembledInstruction::PrintLiteral(")".to_string()));
+        }
+        AssembledType::Interface(name, _) => {
+            instructions.push(AssembledInstruction::PrintLiteral(name.clone()));
+        }
+    }
+}
+
+fn print_kind(primitive: &str) -> PrintKind {
+    match primitive {
+        "int" => PrintKind::Int,
+        "string" => PrintKind::Str,
+        _ => PrintKind::Hex,
+    }
+}
@@ -1,16 +1,27 @@
 use crate::parse::context::TokenizerContext;
 use crate::parse::token::{parse_tokens, Literal, Token, TokenParseError};
+use crate::parse::visit::{walk_token, Visitor};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::fs;
-use std::io::BufWriter;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 
-use self::context::{AssembledTypeContext, AssemblyFormatContext, ValueFormat, CLASS_ID_VOID};
+use self::backend::{Backend, CBackend};
+
+use self::context::{
+    AssembledTypeContext, AssemblyFormatContext, GenericMethodTemplate, GenericTemplate,
+    ValueFormat, CLASS_ID_VOID,
+};
 use self::instruction::AssembledInstruction;
 
+pub mod backend;
 pub mod context;
 pub mod instruction;
+pub mod lower;
+pub mod print;
+pub mod resolve;
 
 #[derive(Debug)]
 pub enum AssemblyError {
@@ -18,6 +29,35 @@ pub enum AssemblyError {
     TriedToAddVariableToData,
     TokenParseError(TokenParseError),
     NoStartMethodFound,
+    ExpectedBlock,
+    ExpectedTypeParameter,
+    WrongNumberOfTypeArguments(String, usize, usize),
+    UnboundTypeParameter(String),
+    WrongNumberOfArguments(String, usize, usize),
+    TypeMismatch(String, String),
+    InvalidRangeStep(i64),
+    UnsupportedExpression,
+    UnsupportedStage(String),
+    /// A stage names a method the type of the elements reaching it does not implement.
+    MethodNotFound(String, String),
+    /// A method calls itself, directly or through others, so what it produces is never known.
+    RecursiveMethod(String),
+    UnknownIdentifier(String),
+    BindingUsedBeforeDefinition(String),
+    BindingRedefined(String),
+    /// A type is declared twice, or shares its name with a builtin type.
+    TypeRedefined(String),
+    /// A generic type or method lists the same type parameter twice.
+    TypeParameterRedefined(String),
+    /// A method binds more names than its context has slots for.
+    TooManyBindings(String),
+    CannotBindVoid(String),
+    NotPrintable(String),
+    /// A binding that may hold more than one element is interpolated into a string.
+    NotSingleValue(String),
+    UnresolvedIdentifiers(Vec<resolve::UnresolvedIdentifier>),
+    UnsupportedInstruction(String),
+    Io(String),
 }
 
 #[derive(Debug, Clone)]
@@ -59,6 +99,9 @@ impl AssembledComposition {
     pub fn add_variable(&mut self, v: AssembledObjectAcceptor) {
         self.data.push(v);
     }
+    pub fn get_fields(&self) -> &Vec<AssembledObjectAcceptor> {
+        &self.data
+    }
 }
 #[derive(Debug, Clone)]
 pub struct AssembledInterface {
@@ -124,6 +167,12 @@ impl AssembledObjectAcceptor {
             class_id: c,
         }
     }
+    pub fn get_name(&self) -> &String {
+        &self.source_name
+    }
+    pub fn get_class_id(&self) -> u16 {
+        self.class_id
+    }
 }
 
 #[derive(Debug)]
@@ -132,7 +181,9 @@ pub struct AssembledMethod {
     consume: ValueFormat,
     produce: ValueFormat,
     produce_type: u16,
+    scope: Option<usize>,
     parameters: Vec<AssembledObjectAcceptor>,
+    type_arguments: HashMap<String, u16>,
     allocation: Vec<AssembledInstruction>,
     body: Vec<AssembledInstruction>,
     free: Vec<AssembledInstruction>,
@@ -149,12 +200,57 @@ impl AssembledMethod {
             consume: consumes,
             produce: produces,
             produce_type: produce_type,
+            scope: None,
             parameters: Vec::new(),
+            type_arguments: HashMap::new(),
             allocation: Vec::new(),
             body: Vec::new(),
             free: Vec::new(),
         }
     }
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+    /// The scope holding the body tokens of a method declared in source.
+    pub fn get_parameters(&self) -> &Vec<AssembledObjectAcceptor> {
+        &self.parameters
+    }
+    pub fn get_produce_type(&self) -> u16 {
+        self.produce_type
+    }
+    pub fn set_produce_type(&mut self, produce_type: u16) {
+        self.produce_type = produce_type;
+    }
+    /// The types the type parameters of a monomorphised method stand for.
+    pub fn get_type_arguments(&self) -> &HashMap<String, u16> {
+        &self.type_arguments
+    }
+    pub fn set_type_arguments(&mut self, type_arguments: HashMap<String, u16>) {
+        self.type_arguments = type_arguments;
+    }
+    /// Whether the body has been lowered, which every method declared in source is before it
+    /// can be called. A lowered body is never empty, as it always sets up the outgive.
+    pub fn is_lowered(&self) -> bool {
+        self.scope.is_none() || !self.body.is_empty()
+    }
+    pub fn get_consume(&self) -> ValueFormat {
+        self.consume
+    }
+    pub fn get_produce(&self) -> ValueFormat {
+        self.produce
+    }
+    pub fn get_scope(&self) -> Option<usize> {
+        self.scope
+    }
+    pub fn set_scope(&mut self, scope: usize) {
+        self.scope = Some(scope);
+    }
+    pub fn get_body(&self) -> &Vec<AssembledInstruction> {
+        &self.body
+    }
+    pub fn get_free(&self) -> &Vec<AssembledInstruction> {
+        &self.free
+    }
     pub fn add_parameter(&mut self, parameter: AssembledObjectAcceptor) {
         self.parameters.push(parameter);
     }
@@ -167,58 +263,204 @@ impl AssembledMethod {
     pub fn add_free(&mut self, free: AssembledInstruction) {
         self.free.push(free);
     }
-    pub fn write(
+    pub fn write<B: Backend>(
         &self,
         t: &u16,
         formats: &mut AssemblyFormatContext,
         types: &AssembledTypeContext,
-        stream: &mut BufWriter<fs::File>,
+        backend: &mut B,
     ) -> std::io::Result<()> {
         // convert in into the right format, if neccessary
         if formats.already_in_format(&self.consume) {
-            AssembledInstruction::NoOp.write(formats, types, stream)?;
+            AssembledInstruction::NoOp.write(formats, types, backend)?;
         } else {
-            self.consume.convert(t, formats, types, stream)?;
+            self.consume.convert(t, formats, types, backend)?;
         }
         formats.push(t, self.consume);
         for i in self.body.iter() {
-            i.write(formats, types, stream)?;
+            i.write(formats, types, backend)?;
         }
         formats.pop(&self.produce_type, self.produce);
         Ok(())
     }
 }
 
-pub fn assemble(filename: String) -> Result<(), AssemblyError> {
-    let mut potential_error = "Could not parse file: ".to_owned();
-    let file = filename.to_owned();
-    potential_error.push_str(&file);
-    let contents = fs::read_to_string(filename).expect(&potential_error);
-    match parse_tokens(contents.into_bytes().as_ref()) {
-        Ok(context) => {
-            context.println();
-            assemble_root(context)?;
-            Ok(())
-        }
-        Err(token_error) => Err(AssemblyError::TokenParseError(token_error)),
-    }
+/// Parses and typechecks a source file, assembling its `start` method.
+pub fn assemble(filename: String) -> Result<AssembledTypeContext, AssemblyError> {
+    let contents = fs::read_to_string(&filename)
+        .map_err(|e| AssemblyError::Io(format!("{}: {}", filename, e)))?;
+    let tokens = parse_tokens(contents.as_bytes()).map_err(AssemblyError::TokenParseError)?;
+    assemble_root(&tokens)
 }
 
-pub fn assemble_root(mut root: TokenizerContext) -> Result<(), AssemblyError> {
-    let mut context = AssembledTypeContext::new();
-    context.setup_root_scope_types();
-    record_declarations(0, &mut root, &mut context)?;
-    walk_method(match context
+/// Assembles a source file and writes it to `output` as a C program.
+pub fn build(filename: String, output: &Path) -> Result<(), AssemblyError> {
+    let types = assemble(filename)?;
+    let file = fs::File::create(output)
+        .map_err(|e| AssemblyError::Io(format!("{}: {}", output.display(), e)))?;
+    write_program(&types, &mut BufWriter::new(file))
+}
+
+pub fn assemble_root(root: &TokenizerContext) -> Result<AssembledTypeContext, AssemblyError> {
+    let mut context = declare_root(root)?;
+    lower_root(root, &mut context)?;
+    Ok(context)
+}
+
+/// Checks that every identifier of `root` resolves and assembles the types it declares, without
+/// lowering any method body.
+pub fn declare_root(root: &TokenizerContext) -> Result<AssembledTypeContext, AssemblyError> {
+    let mut builtins = AssembledTypeContext::new();
+    builtins.setup_root_scope_types();
+    resolve::resolve(root, &builtins)?;
+    assemble_types(root)
+}
+
+/// Lowers the method bodies of `root` into the types `declare_root` assembled, `start` last.
+pub fn lower_root(
+    root: &TokenizerContext,
+    context: &mut AssembledTypeContext,
+) -> Result<(), AssemblyError> {
+    lower_methods(root, context)?;
+    let scope = match context
         .get_impl(&CLASS_ID_VOID)
         .unwrap()
         .get(&"start".to_string())
     {
-        Some(start_method) => Ok(start_method),
+        Some(start_method) => Ok(start_method.get_scope()),
         None => Err(AssemblyError::NoStartMethodFound),
-    }?);
+    }?;
+    if let Some(scope) = scope {
+        let mut bindings = lower::Bindings::new(root.get_scope(scope).unwrap());
+        let instructions = lower::lower_method(scope, root, context, &mut bindings)?;
+        let start = context.get_mut_method(CLASS_ID_VOID, "start").unwrap();
+        for i in instructions.into_iter() {
+            start.add_instruction(i);
+        }
+        for f in bindings.free().into_iter() {
+            start.add_free(f);
+        }
+    }
+    Ok(())
+}
+
+/// The methods declared in source other than `start`, with the id of the type they take in and
+/// their name in source, in the order they are written out.
+pub fn user_methods(types: &AssembledTypeContext) -> Vec<(u16, &String, &AssembledMethod)> {
+    let mut methods = Vec::new();
+    let mut id = 0u16;
+    while types.get_type(&id).is_some() {
+        if let Some(imp) = types.get_impl(&id) {
+            let mut names = imp.keys().collect::<Vec<_>>();
+            names.sort();
+            for name in names {
+                let method = &imp[name];
+                if method.get_scope().is_some() && !(id == CLASS_ID_VOID && name == "start") {
+                    methods.push((id, name, method));
+                }
+            }
+        }
+        id += 1;
+    }
+    methods
+}
+
+/// Lowers every method declared in source other than `start`, each once the methods it calls
+/// have been, so that the class of what a call gives back is known where it is made.
+pub fn lower_methods(
+    root: &TokenizerContext,
+    context: &mut AssembledTypeContext,
+) -> Result<(), AssemblyError> {
+    let mut pending = user_methods(context)
+        .into_iter()
+        .map(|(id, name, _)| (id, name.clone()))
+        .collect::<Vec<_>>();
+    while !pending.is_empty() {
+        let mut waiting = Vec::new();
+        let mut blocked = None;
+        for (id, name) in pending.iter() {
+            let method = &context.get_impl(id).unwrap()[name];
+            match lower::lower_function(*id, method, root, context) {
+                Ok(lowered) => {
+                    let method = context.get_mut_method(*id, name).unwrap();
+                    method.set_produce_type(lowered.produce_type);
+                    for i in lowered.body.into_iter() {
+                        method.add_instruction(i);
+                    }
+                    for f in lowered.free.into_iter() {
+                        method.add_free(f);
+                    }
+                }
+                Err(AssemblyError::RecursiveMethod(callee)) => {
+                    blocked = Some(callee);
+                    waiting.push((*id, name.clone()));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if waiting.len() == pending.len() {
+            return Err(AssemblyError::RecursiveMethod(blocked.unwrap()));
+        }
+        pending = waiting;
+    }
     Ok(())
 }
 
+/// Writes the assembled `start` method as a C program.
+pub fn write_program<W: Write>(
+    types: &AssembledTypeContext,
+    stream: &mut W,
+) -> Result<(), AssemblyError> {
+    generate(types, &mut CBackend::new(stream))
+}
+
+/// The instructions of a lowered method in the order they run, ending with `exit`.
+pub fn function_body(
+    method: &AssembledMethod,
+    exit: AssembledInstruction,
+) -> Vec<AssembledInstruction> {
+    let mut body = vec![AssembledInstruction::DeclareCalculationScope];
+    body.extend(method.get_body().iter().cloned());
+    body.extend(method.get_free().iter().cloned());
+    body.push(exit);
+    body
+}
+
+/// Writes the methods declared in source as functions and the assembled `start` method as the
+/// entry point, each followed by the frees of its bindings, through `backend`.
+pub fn generate<B: Backend>(
+    types: &AssembledTypeContext,
+    backend: &mut B,
+) -> Result<(), AssemblyError> {
+    let start = match types.get_impl(&CLASS_ID_VOID).unwrap().get("start") {
+        Some(start) => start,
+        None => return Err(AssemblyError::NoStartMethodFound),
+    };
+    let methods = user_methods(types);
+    let mut formats = AssemblyFormatContext::new();
+    let mut write = || -> std::io::Result<()> {
+        backend.begin_program(&mut formats, types)?;
+        for (id, name, _) in methods.iter() {
+            backend.declare_method(*id, name, types)?;
+        }
+        for (id, name, method) in methods.iter() {
+            let body = function_body(method, AssembledInstruction::ReturnOutgive);
+            backend.method(*id, name, &body, &mut formats, types)?;
+        }
+        let body = function_body(start, AssembledInstruction::Return(0));
+        backend.start(&body, &mut formats, types)?;
+        backend.end_program(&mut formats)
+    };
+    write().map_err(|e| AssemblyError::Io(e.to_string()))
+}
+
+pub fn assemble_types(root: &TokenizerContext) -> Result<AssembledTypeContext, AssemblyError> {
+    let mut context = AssembledTypeContext::new();
+    context.setup_root_scope_types();
+    record_declarations(0, root, &mut context)?;
+    Ok(context)
+}
+
 fn literal_identifier(identifier: &Literal) -> Result<String, AssemblyError> {
     match identifier.as_identifier_string() {
         Ok(s) => Ok(s),
@@ -226,59 +468,469 @@ fn literal_identifier(identifier: &Literal) -> Result<String, AssemblyError> {
     }
 }
 
+fn block_scope(block: &Token) -> Result<usize, AssemblyError> {
+    match block {
+        Token::Block(_, scope) => Ok(*scope),
+        _ => Err(AssemblyError::ExpectedBlock),
+    }
+}
+
+/// The type and method declarations directly in a scope, with the position of each method in
+/// it. Declarations are not walked into, so every token visited is one of the scope.
+#[derive(Default)]
+struct Declarations<'t> {
+    position: usize,
+    types: Vec<&'t Token>,
+    methods: Vec<(usize, &'t Token)>,
+}
+impl<'t> Visitor<'t> for Declarations<'t> {
+    type Error = AssemblyError;
+    fn visit_token(
+        &mut self,
+        _: &'t TokenizerContext,
+        token: &'t Token,
+    ) -> Result<(), AssemblyError> {
+        match token {
+            Token::TypeDef(_, _, _) => self.types.push(token),
+            Token::MethodDef(_, _, _, _, _) => self.methods.push((self.position, token)),
+            _ => {}
+        }
+        self.position += 1;
+        Ok(())
+    }
+}
+
 pub fn record_declarations(
     scope: usize,
-    tokens: &mut TokenizerContext,
+    tokens: &TokenizerContext,
     type_context: &mut AssembledTypeContext,
 ) -> Result<(), AssemblyError> {
+    let mut declarations = Declarations::default();
+    declarations.visit_scope(tokens, scope)?;
     let mut types = HashMap::new();
-    for t in tokens.get_scope(scope).unwrap().get_tokens().iter() {
-        match t.as_ref() {
-            Token::TypeDef(identifier, _body) => {
-                let type_name = literal_identifier(identifier)?;
+    for t in declarations.types.iter() {
+        if let Token::TypeDef(identifier, parameters, body) = t {
+            let type_name = literal_identifier(identifier)?;
+            if type_context.get_type_id(&type_name).is_some()
+                || type_context.get_generic_type(&type_name).is_some()
+            {
+                return Err(AssemblyError::TypeRedefined(type_name));
+            }
+            if let Some(parameters) = parameters {
+                let template = GenericTemplate::new(
+                    generic_parameters(tokens, parameters)?,
+                    block_scope(body)?,
+                );
+                type_context.create_generic_type(&type_name, template);
+            } else {
                 types.insert(type_name.clone(), type_context.create_type(&type_name));
             }
-            _ => {}
         }
     }
     type_context.record(types);
-    for t in tokens.get_scope(scope).unwrap().get_tokens().iter() {
-        match t.as_ref() {
-            Token::TypeDef(identifier, body) => {
-                if let Literal::Identifier(_) = identifier {
-                    if let Token::Block(_, inner_scope) = body.as_ref() {
-                        for v in tokens.get_scope(*inner_scope).unwrap().get_tokens().iter() {
-                            match v.as_ref() {
-                                Token::VariableDef(type_identifier, variable_name) => {
-                                    if let Some(var_type) = type_context
-                                        .get_type_id(&literal_identifier(type_identifier)?)
-                                    {
-                                        let variable = AssembledObjectAcceptor::new(
-                                            variable_name.clone(),
-                                            *var_type,
-                                        );
-                                        type_context.add_variable(
-                                            scope,
-                                            &variable_name,
-                                            variable,
-                                        )?;
-                                    } else {
-                                        return Err(AssemblyError::TypeNotFound);
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
+    let no_bindings = HashMap::new();
+    for t in declarations.types.iter() {
+        if let Token::TypeDef(identifier, None, body) = t {
+            let type_id = *type_context
+                .get_type_id(&literal_identifier(identifier)?)
+                .unwrap();
+            record_fields(
+                block_scope(body)?,
+                type_id,
+                tokens,
+                type_context,
+                &no_bindings,
+            )?;
+        }
+    }
+    for (position, t) in declarations.methods.into_iter() {
+        if let Token::MethodDef(_, name, type_parameters, parameters, body) = t {
+            if let Some(type_parameters) = type_parameters {
+                type_context.create_generic_method(GenericMethodTemplate::new(
+                    name.clone(),
+                    generic_parameters(tokens, type_parameters)?,
+                    scope,
+                    position,
+                ));
+            } else {
+                let intake = intake_type(parameters, tokens, type_context, &no_bindings)?;
+                let mut method = user_method(name.clone());
+                method.set_scope(block_scope(body)?);
+                record_parameters(&mut method, parameters, intake, tokens, type_context)?;
+                type_context.add_method(intake, name, method);
+                record_type_uses(body, tokens, type_context, &no_bindings)?;
+            }
+        }
+    }
+    monomorphise_methods(tokens, type_context)
+}
+
+fn user_method(name: String) -> AssembledMethod {
+    AssembledMethod::new(
+        name,
+        ValueFormat::StreamOfElements,
+        ValueFormat::StreamOfElements,
+        CLASS_ID_VOID,
+    )
+}
+
+/// Records the names an intake such as `Point(x1, _)` gives the fields of the elements a method
+/// takes in as its parameters, one for each field.
+fn record_parameters(
+    method: &mut AssembledMethod,
+    intake: &Token,
+    class_id: u16,
+    tokens: &TokenizerContext,
+    type_context: &AssembledTypeContext,
+) -> Result<(), AssemblyError> {
+    let (type_name, names) = match intake {
+        Token::Constructor(name, names) | Token::GenericConstructor(name, _, names) => {
+            (name, field_names(names, tokens)?)
+        }
+        _ => return Ok(()),
+    };
+    let fields = match type_context.get_type(&class_id) {
+        Some(AssembledType::Composition(_, comp)) => comp.get_fields().as_slice(),
+        _ => &[],
+    };
+    if names.len() != fields.len() {
+        return Err(AssemblyError::WrongNumberOfArguments(
+            type_name.clone(),
+            fields.len(),
+            names.len(),
+        ));
+    }
+    for (name, field) in names.into_iter().zip(fields.iter()) {
+        method.add_parameter(AssembledObjectAcceptor::new(name, field.class_id));
+    }
+    Ok(())
+}
+
+fn generic_parameters(
+    tokens: &TokenizerContext,
+    parameters: &Token,
+) -> Result<Vec<String>, AssemblyError> {
+    let mut names = Vec::new();
+    for p in tokens
+        .get_scope(block_scope(parameters)?)
+        .unwrap()
+        .get_tokens()
+        .iter()
+    {
+        match p.as_ref() {
+            Token::Literal(identifier) => {
+                let name = literal_identifier(identifier)?;
+                if names.contains(&name) {
+                    return Err(AssemblyError::TypeParameterRedefined(name));
                 }
+                names.push(name);
             }
-            _ => {}
+            _ => return Err(AssemblyError::ExpectedTypeParameter),
+        }
+    }
+    Ok(names)
+}
+
+fn record_fields(
+    body: usize,
+    type_id: u16,
+    tokens: &TokenizerContext,
+    type_context: &mut AssembledTypeContext,
+    bindings: &HashMap<String, u16>,
+) -> Result<(), AssemblyError> {
+    for v in tokens.get_scope(body).unwrap().get_tokens().iter() {
+        let (var_type, names) = match v.as_ref() {
+            Token::VariableDef(type_identifier, variable_name) => (
+                resolve_type_name(
+                    &literal_identifier(type_identifier)?,
+                    type_context,
+                    bindings,
+                )?,
+                vec![variable_name.clone()],
+            ),
+            Token::Constructor(type_name, names) => (
+                resolve_type_name(type_name, type_context, bindings)?,
+                field_names(names, tokens)?,
+            ),
+            Token::GenericConstructor(type_name, arguments, names) => {
+                let arguments = type_arguments(arguments, tokens, type_context, bindings)?;
+                (
+                    instantiate(type_name, arguments, tokens, type_context)?,
+                    field_names(names, tokens)?,
+                )
+            }
+            _ => continue,
+        };
+        for name in names.into_iter() {
+            type_context.add_variable(type_id, AssembledObjectAcceptor::new(name, var_type))?;
         }
     }
     Ok(())
 }
 
-pub fn walk_method(_method: &AssembledMethod) {}
+fn field_names(names: &Token, tokens: &TokenizerContext) -> Result<Vec<String>, AssemblyError> {
+    let mut result = Vec::new();
+    for n in tokens
+        .get_scope(block_scope(names)?)
+        .unwrap()
+        .get_tokens()
+        .iter()
+    {
+        if let Token::Literal(identifier) = n.as_ref() {
+            result.push(literal_identifier(identifier)?);
+        }
+    }
+    Ok(result)
+}
+
+fn resolve_type_name(
+    name: &str,
+    type_context: &AssembledTypeContext,
+    bindings: &HashMap<String, u16>,
+) -> Result<u16, AssemblyError> {
+    match bindings.get(name) {
+        Some(id) => Ok(*id),
+        None => match type_context.get_type_id(&name.to_string()) {
+            Some(id) => Ok(*id),
+            None => Err(AssemblyError::TypeNotFound),
+        },
+    }
+}
+
+fn resolve_type(
+    token: &Token,
+    tokens: &TokenizerContext,
+    type_context: &mut AssembledTypeContext,
+    bindings: &HashMap<String, u16>,
+) -> Result<u16, AssemblyError> {
+    match token {
+        Token::Literal(Literal::Void) => Ok(CLASS_ID_VOID),
+        Token::Literal(identifier) => {
+            resolve_type_name(&literal_identifier(identifier)?, type_context, bindings)
+        }
+        Token::Generic(name, arguments) => {
+            let arguments = type_arguments(arguments, tokens, type_context, bindings)?;
+            instantiate(name, arguments, tokens, type_context)
+        }
+        _ => Err(AssemblyError::TypeNotFound),
+    }
+}
+
+fn type_arguments(
+    arguments: &Token,
+    tokens: &TokenizerContext,
+    type_context: &mut AssembledTypeContext,
+    bindings: &HashMap<String, u16>,
+) -> Result<Vec<u16>, AssemblyError> {
+    let mut ids = Vec::new();
+    for a in tokens
+        .get_scope(block_scope(arguments)?)
+        .unwrap()
+        .get_tokens()
+        .iter()
+    {
+        ids.push(resolve_type(a, tokens, type_context, bindings)?);
+    }
+    Ok(ids)
+}
+
+/// Returns the type id of `name<arguments>`, monomorphising the generic template the first
+/// time each distinct list of arguments is seen.
+pub fn instantiate(
+    name: &str,
+    arguments: Vec<u16>,
+    tokens: &TokenizerContext,
+    type_context: &mut AssembledTypeContext,
+) -> Result<u16, AssemblyError> {
+    if let Some(id) = type_context.get_instance(name, &arguments) {
+        return Ok(*id);
+    }
+    let template = match type_context.get_generic_type(name) {
+        Some(template) => template.clone(),
+        None => return Err(AssemblyError::TypeNotFound),
+    };
+    if template.get_parameters().len() != arguments.len() {
+        return Err(AssemblyError::WrongNumberOfTypeArguments(
+            name.to_string(),
+            template.get_parameters().len(),
+            arguments.len(),
+        ));
+    }
+    let bindings = template
+        .get_parameters()
+        .iter()
+        .cloned()
+        .zip(arguments.iter().cloned())
+        .collect::<HashMap<String, u16>>();
+    let id = type_context.create_instance(name, arguments);
+    record_fields(template.get_body(), id, tokens, type_context, &bindings)?;
+    Ok(id)
+}
+
+fn intake_type(
+    parameters: &Token,
+    tokens: &TokenizerContext,
+    type_context: &mut AssembledTypeContext,
+    bindings: &HashMap<String, u16>,
+) -> Result<u16, AssemblyError> {
+    match parameters {
+        Token::Constructor(name, _) => resolve_type_name(name, type_context, bindings),
+        Token::GenericConstructor(name, arguments, _) => {
+            let arguments = type_arguments(arguments, tokens, type_context, bindings)?;
+            instantiate(name, arguments, tokens, type_context)
+        }
+        Token::Literal(Literal::Identifier(_)) | Token::Generic(_, _) => {
+            resolve_type(parameters, tokens, type_context, bindings)
+        }
+        _ => Ok(CLASS_ID_VOID),
+    }
+}
+
+/// Instantiates every generic type named inside the tokens it visits.
+struct TypeUses<'a> {
+    type_context: &'a mut AssembledTypeContext,
+    bindings: &'a HashMap<String, u16>,
+}
+impl<'a, 't> Visitor<'t> for TypeUses<'a> {
+    type Error = AssemblyError;
+    fn visit_token(
+        &mut self,
+        tokens: &'t TokenizerContext,
+        token: &'t Token,
+    ) -> Result<(), AssemblyError> {
+        match token {
+            Token::Generic(_, _) => {
+                resolve_type(token, tokens, self.type_context, self.bindings)?;
+                Ok(())
+            }
+            Token::GenericConstructor(name, arguments, parameters) => {
+                let arguments =
+                    type_arguments(arguments, tokens, self.type_context, self.bindings)?;
+                instantiate(name, arguments, tokens, self.type_context)?;
+                self.visit_token(tokens, parameters)
+            }
+            _ => walk_token(self, tokens, token),
+        }
+    }
+}
+
+/// Instantiates every generic type named inside `token`, following blocks into their scopes.
+fn record_type_uses(
+    token: &Token,
+    tokens: &TokenizerContext,
+    type_context: &mut AssembledTypeContext,
+    bindings: &HashMap<String, u16>,
+) -> Result<(), AssemblyError> {
+    TypeUses {
+        type_context,
+        bindings,
+    }
+    .visit_token(tokens, token)
+}
+
+/// Binds the type parameters appearing in `pattern` by matching it against the type `id`.
+fn unify(
+    pattern: &Token,
+    id: u16,
+    parameters: &[String],
+    tokens: &TokenizerContext,
+    type_context: &AssembledTypeContext,
+    bindings: &mut HashMap<String, u16>,
+) -> bool {
+    match pattern {
+        Token::Literal(Literal::Identifier(name)) if parameters.contains(name) => {
+            *bindings.entry(name.clone()).or_insert(id) == id
+        }
+        Token::Literal(Literal::Identifier(name)) => type_context.get_type_id(name) == Some(&id),
+        Token::Generic(name, arguments) | Token::GenericConstructor(name, arguments, _) => {
+            let instance = type_context
+                .get_instances_of(name)
+                .into_iter()
+                .find(|(instance_id, _)| *instance_id == id);
+            match (instance, block_scope(arguments)) {
+                (Some((_, instance_arguments)), Ok(scope)) => {
+                    let patterns = tokens.get_scope(scope).unwrap().get_tokens();
+                    patterns.len() == instance_arguments.len()
+                        && patterns
+                            .iter()
+                            .zip(instance_arguments.iter())
+                            .all(|(p, a)| unify(p, *a, parameters, tokens, type_context, bindings))
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Instantiates each generic method once for every instance of the generic type it takes in.
+/// Method bodies may instantiate further types, so this repeats until no new method appears.
+fn monomorphise_methods(
+    tokens: &TokenizerContext,
+    type_context: &mut AssembledTypeContext,
+) -> Result<(), AssemblyError> {
+    let mut done = HashSet::new();
+    loop {
+        let mut pending = Vec::new();
+        for (template_index, template) in type_context.get_generic_methods().iter().enumerate() {
+            let (scope, position) = template.get_declaration();
+            if let Token::MethodDef(_, _, _, intake, _) =
+                tokens.get_scope(scope).unwrap().get_tokens()[position].as_ref()
+            {
+                // the parameters are bound by unifying with instances, so without a generic intake
+                // there is nothing to bind them to
+                let intake_name = match intake.as_ref() {
+                    Token::Generic(name, _) | Token::GenericConstructor(name, _, _) => name,
+                    _ => match template.get_parameters().first() {
+                        Some(parameter) => {
+                            return Err(AssemblyError::UnboundTypeParameter(parameter.clone()))
+                        }
+                        None => continue,
+                    },
+                };
+                for (id, _) in type_context.get_instances_of(intake_name).into_iter() {
+                    if !done.contains(&(template_index, id)) {
+                        pending.push((template_index, id));
+                    }
+                }
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        for (template_index, id) in pending.into_iter() {
+            done.insert((template_index, id));
+            let template = type_context.get_generic_methods()[template_index].clone();
+            let (scope, position) = template.get_declaration();
+            if let Token::MethodDef(_, name, _, intake, body) =
+                tokens.get_scope(scope).unwrap().get_tokens()[position].as_ref()
+            {
+                let mut bindings = HashMap::new();
+                if !unify(
+                    intake,
+                    id,
+                    template.get_parameters(),
+                    tokens,
+                    type_context,
+                    &mut bindings,
+                ) {
+                    continue;
+                }
+                for p in template.get_parameters().iter() {
+                    if !bindings.contains_key(p) {
+                        return Err(AssemblyError::UnboundTypeParameter(p.clone()));
+                    }
+                }
+                let mut method = user_method(name.clone());
+                method.set_scope(block_scope(body)?);
+                record_parameters(&mut method, intake, id, tokens, type_context)?;
+                method.set_type_arguments(bindings.clone());
+                type_context.add_method(id, name, method);
+                record_type_uses(body, tokens, type_context, &bindings)?;
+            }
+        }
+    }
+}
 
   //--------=====================----------\\
  //---------========TESTS========-----------\\
@@ -292,4 +944,212 @@ mod tests {
         assemble("res/test/simple_0.geo".to_string())?;
         Ok(())
     }
+    fn write_c(filename: &str) -> Result<String, AssemblyError> {
+        let contents = fs::read_to_string(filename).unwrap();
+        let tokens = parse_tokens(contents.as_bytes()).map_err(AssemblyError::TokenParseError)?;
+        let context = assemble_root(&tokens)?;
+        let mut program = Vec::new();
+        write_program(&context, &mut program)?;
+        Ok(String::from_utf8(program).unwrap())
+    }
+    #[test]
+    fn test_assemble_ranges() -> Result<(), AssemblyError> {
+        let program = write_c("res/test/range_0.geo")?;
+        assert!(program.contains("for (int __i = 0; __i <  3; __i += 1) {"));
+        assert!(program.contains("for (int __i = 1; __i <= 9; __i += 4) {"));
+        // ranges are generated by the loop counter, only the array literal is allocated
+        assert_eq!(program.matches("malloc").count(), 1);
+        assert!(program.contains("in = base + __i * 8;"));
+        Ok(())
+    }
+    /// Compiles and runs a generated program, or `None` when no C compiler is installed.
+    fn run_c(name: &str, program: &str) -> Option<String> {
+        let dir = std::env::temp_dir().join(format!("intolang_{}_{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("program.c");
+        let binary = dir.join("program");
+        fs::write(&source, program).unwrap();
+        let compiled = std::process::Command::new("cc")
+            .arg(&source)
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .ok()?;
+        assert!(compiled.success(), "generated C did not compile");
+        let output = std::process::Command::new(&binary).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+    #[test]
+    fn test_run_ranges() -> Result<(), AssemblyError> {
+        if let Some(output) = run_c("ranges", &write_c("res/test/range_0.geo")?) {
+            assert_eq!(output, "0\n1\n2\n1\n5\n9\nPoint(3, 4)\nPoint(5, 6)\n");
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_run_bindings() -> Result<(), AssemblyError> {
+        if let Some(output) = run_c("bindings", &write_c("res/test/binding_0.geo")?) {
+            assert_eq!(output, "Point(1, 2)\nPoint(3, 4)\n0\n1\n2\nPoint(3, 4)\n");
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_run_strings() -> Result<(), AssemblyError> {
+        let program = write_c("res/test/string_0.geo")?;
+        assert!(program.contains("typedef char* string;"));
+        if let Some(output) = run_c("strings", &program) {
+            assert_eq!(
+                output,
+                "tab\tquote\" 100% é 😀\na\né\n'\n☺\nNamed(Goose, G)\n"
+            );
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_run_interpolation() -> Result<(), AssemblyError> {
+        if let Some(output) = run_c("interpolation", &write_c("res/test/interpolation_0.geo")?) {
+            assert_eq!(
+                output,
+                "n is 3, p is Point(1, 2)\n{literal} Point(5, 6) and c!\n"
+            );
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_run_methods() -> Result<(), AssemblyError> {
+        let program = write_c("res/test/method_0.geo")?;
+        assert!(program.contains("char* Point__flip(char* self, int* __len);"));
+        if let Some(output) = run_c("methods", &program) {
+            assert_eq!(
+                output,
+                "Point(2, 1)\nPoint(4, 3)\n2\n1\n4\n3\n\
+                 Pair<int, Point>(1, Point(2, 1))\nPair<int, Point>(3, Point(4, 3))\n\
+                 1\n2\n3\n4\nx is 4\n"
+            );
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_assemble_generic_method_needs_generic_intake() {
+        let source = b"type Point: (int(x), int(y),)
+            trans f<A>: Point { Point(y, x) }
+            calc start: () { Point(1, 2) => print; }";
+        match assemble_root(&parse_tokens(&source[..]).unwrap()) {
+            Err(AssemblyError::UnboundTypeParameter(name)) => assert_eq!(name, "A"),
+            other => panic!("expected an unbound parameter, found {:?}", other.err()),
+        }
+    }
+    #[test]
+    fn test_assemble_too_many_bindings() -> Result<(), AssemblyError> {
+        let tokens = |bindings: u16| {
+            let statements = (0..bindings)
+                .map(|i| format!("b{}: {};", i, i))
+                .collect::<String>();
+            let source = format!("calc start: () {{ {} b0 => print; }}", statements);
+            parse_tokens(source.as_bytes()).unwrap()
+        };
+        assemble_root(&tokens(256))?;
+        match assemble_root(&tokens(257)) {
+            Err(AssemblyError::TooManyBindings(name)) => assert_eq!(name, "b256"),
+            other => panic!("expected too many bindings, found {:?}", other.err()),
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_run_generics() -> Result<(), AssemblyError> {
+        let program = write_c("res/test/generic_0.geo")?;
+        assert!(program.contains("char* Pair__3int5Point__swap(char* self, int* __len);"));
+        if let Some(output) = run_c("generics", &program) {
+            assert_eq!(output, "Pair<Point, int>(Point(1, 2), 1)\n");
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_assemble_recursive_method() {
+        let source = b"type Point: (int(x), int(y),)
+            trans f: Point { Point(y, x) => f }
+            calc start: () { Point(1, 2) => f => print; }";
+        match assemble_root(&parse_tokens(&source[..]).unwrap()) {
+            Err(AssemblyError::RecursiveMethod(name)) => assert_eq!(name, "f"),
+            other => panic!("expected a recursive method, found {:?}", other.map(|_| ())),
+        }
+    }
+    #[test]
+    fn test_assemble_binding_used_before_definition() {
+        let tokens = parse_tokens(b"calc start: () { n => print; n: 3; }").unwrap();
+        match assemble_root(&tokens) {
+            Err(AssemblyError::BindingUsedBeforeDefinition(name)) => assert_eq!(name, "n"),
+            other => panic!(
+                "expected a use before definition, found {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+    #[test]
+    fn test_assemble_range_step_must_be_positive() {
+        let tokens = parse_tokens(b"calc start: () { 0..3 by 0 => print; }").unwrap();
+        match assemble_root(&tokens) {
+            Err(AssemblyError::InvalidRangeStep(0)) => {}
+            other => panic!("expected an invalid step, found {:?}", other.map(|_| ())),
+        }
+    }
+    #[test]
+    fn test_assemble_type_parameter_redefined() {
+        let tokens = parse_tokens(b"type P<A, A>: (A(first),) calc start: () { }").unwrap();
+        match assemble_types(&tokens) {
+            Err(AssemblyError::TypeParameterRedefined(name)) => assert_eq!(name, "A"),
+            other => panic!(
+                "expected a repeated parameter, found {:?}",
+                other.map(|_| ())
+            ),
+        }
+    }
+    #[test]
+    fn test_assemble_type_redefined() {
+        let sources: [&[u8]; 3] = [
+            b"type Box<A>: (A(value),) type Box: (int(value),) calc start: () { }",
+            b"type Box: (int(value),) type Box<A>: (A(value),) calc start: () { }",
+            b"type int: (int(value),) calc start: () { }",
+        ];
+        for source in sources.iter() {
+            let tokens = parse_tokens(source).unwrap();
+            match assemble_types(&tokens) {
+                Err(AssemblyError::TypeRedefined(_)) => {}
+                other => panic!("expected a redefined type, found {:?}", other.map(|_| ())),
+            }
+        }
+    }
+    #[test]
+    fn test_assemble_generic_instances() -> Result<(), AssemblyError> {
+        let contents = fs::read_to_string("res/test/generic_0.geo").unwrap();
+        let tokens = parse_tokens(contents.as_bytes()).map_err(AssemblyError::TokenParseError)?;
+        let context = assemble_types(&tokens)?;
+        let int = context.get_type_id(&"int".to_string()).cloned().unwrap();
+        let point = context.get_type_id(&"Point".to_string()).cloned().unwrap();
+
+        let forward = *context.get_instance("Pair", &[int, point]).unwrap();
+        let backward = *context.get_instance("Pair", &[point, int]).unwrap();
+        assert_ne!(forward, backward);
+        let forward_type = context.get_type(&forward).unwrap();
+        assert_eq!(forward_type.get_name(), "Pair<int, Point>");
+        assert_eq!(forward_type.get_bytes(&context), 12);
+        assert_eq!(
+            context.get_type(&backward).unwrap().get_name(),
+            "Pair<Point, int>"
+        );
+        assert_eq!(context.c_name(forward), "Pair__3int5Point");
+        assert_eq!(context.c_name(backward), "Pair__5Point3int");
+
+        let swap = context.get_impl(&forward).unwrap().get("swap").unwrap();
+        assert_eq!(swap.get_name(), "swap");
+        assert_eq!(swap.get_type_arguments().get("A").cloned(), Some(int));
+        let swap = context.get_impl(&backward).unwrap().get("swap").unwrap();
+        assert_eq!(swap.get_type_arguments().get("A").cloned(), Some(point));
+        assert!(context
+            .get_impl(&CLASS_ID_VOID)
+            .unwrap()
+            .contains_key("start"));
+        Ok(())
+    }
 }
@@ -0,0 +1,348 @@
+use crate::lang::assemble::context::{AssembledTypeContext, ClassReference};
+use crate::lang::assemble::instruction::{RangeBounds, StreamInstruction};
+use crate::lang::assemble::{AssembledInstruction, AssembledMethod, AssembledType};
+
+use crate::parse::constant::Operator;
+
+use std::fmt;
+
+const INDENT: &str = "  ";
+
+/// Every type by id with its layout and the methods implemented on it, then the generic types
+/// and methods the instances were made from.
+impl fmt::Display for AssembledTypeContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut id = 0u16;
+        while let Some(ty) = self.get_type(&id) {
+            write_type(self, id, ty, f)?;
+            if let Some(methods) = self.get_impl(&id) {
+                let mut names = methods.keys().collect::<Vec<_>>();
+                names.sort();
+                for name in names {
+                    writeln!(f, "{}{}", INDENT, MethodSignature(self, &methods[name]))?;
+                }
+            }
+            id += 1;
+        }
+        for name in self.get_generic_type_names() {
+            let template = self.get_generic_type(name).unwrap();
+            write!(
+                f,
+                "generic {}<{}>",
+                name,
+                template.get_parameters().join(", ")
+            )?;
+            let instances = self.get_instances_of(name);
+            if instances.is_empty() {
+                writeln!(f)?;
+            } else {
+                let ids = instances
+                    .iter()
+                    .map(|(id, _)| id.to_string())
+                    .collect::<Vec<_>>();
+                writeln!(f, ", instantiated as {}", ids.join(", "))?;
+            }
+        }
+        for template in self.get_generic_methods().iter() {
+            writeln!(
+                f,
+                "generic method {}<{}>",
+                template.get_name(),
+                template.get_parameters().join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The line of a type with a line for each field of a composition, as in the listing of every
+/// type.
+pub struct TypeLayout<'a>(pub &'a AssembledTypeContext, pub u16);
+impl<'a> fmt::Display for TypeLayout<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let TypeLayout(types, id) = *self;
+        match types.get_type(&id) {
+            Some(ty) => write_type(types, id, ty, f),
+            None => writeln!(f, "type {} is not defined", id),
+        }
+    }
+}
+
+fn write_type(
+    types: &AssembledTypeContext,
+    id: u16,
+    ty: &AssembledType,
+    f: &mut fmt::Formatter,
+) -> fmt::Result {
+    match ty {
+        AssembledType::Composition(name, composition) => {
+            writeln!(
+                f,
+                "type {} {}: composition, {} bytes",
+                id,
+                name,
+                ty.get_bytes(types)
+            )?;
+            let mut offset = 0u16;
+            for field in composition.data.iter() {
+                let field_type = types.get_type(&field.class_id).unwrap();
+                let bytes = field_type.get_bytes(types);
+                writeln!(
+                    f,
+                    "{}{}: {} at {}, {} bytes",
+                    INDENT,
+                    field.source_name,
+                    field_type.get_name(),
+                    offset,
+                    bytes
+                )?;
+                offset += bytes;
+            }
+            Ok(())
+        }
+        AssembledType::Data(name, data) => {
+            writeln!(f, "type {} {}: data, {} bytes", id, name, data.memory)
+        }
+        AssembledType::Interface(name, _) => writeln!(f, "type {} {}: interface", id, name),
+    }
+}
+
+/// A method's name, parameters and the type and value format of the stream it produces.
+pub struct MethodSignature<'a>(pub &'a AssembledTypeContext, pub &'a AssembledMethod);
+impl<'a> fmt::Display for MethodSignature<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let MethodSignature(types, method) = *self;
+        let type_name = |id: &u16| match types.get_type(id) {
+            Some(ty) => ty.get_name(),
+            None => format!("#{}", id),
+        };
+        let parameters = method
+            .parameters
+            .iter()
+            .map(|p| format!("{}: {}", p.source_name, type_name(&p.class_id)))
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "{}({}) -> {}, {:?} -> {:?}",
+            method.name,
+            parameters.join(", "),
+            type_name(&method.produce_type),
+            method.consume,
+            method.produce
+        )
+    }
+}
+
+/// The instructions of every method that has any, in the order they run: allocations, the body,
+/// then the frees.
+pub struct Methods<'a>(pub &'a AssembledTypeContext);
+impl<'a> fmt::Display for Methods<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_methods(self.0, f)
+    }
+}
+
+fn write_methods(types: &AssembledTypeContext, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut id = 0u16;
+    while let Some(ty) = types.get_type(&id) {
+        if let Some(methods) = types.get_impl(&id) {
+            let mut names = methods.keys().collect::<Vec<_>>();
+            names.sort();
+            for name in names {
+                let method = &methods[name];
+                let sections = [
+                    ("allocation", &method.allocation),
+                    ("body", &method.body),
+                    ("free", &method.free),
+                ];
+                if sections.iter().all(|(_, s)| s.is_empty()) {
+                    continue;
+                }
+                writeln!(f, "{} {}", ty.get_name(), MethodSignature(types, method))?;
+                for (section, instructions) in sections.iter() {
+                    if instructions.is_empty() {
+                        continue;
+                    }
+                    writeln!(f, "{}{}:", INDENT, section)?;
+                    for instruction in instructions.iter() {
+                        write_instruction(instruction, 2, f)?;
+                        writeln!(f)?;
+                    }
+                }
+            }
+        }
+        id += 1;
+    }
+    Ok(())
+}
+
+impl fmt::Display for AssembledInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_instruction(self, 0, f)
+    }
+}
+
+impl fmt::Display for ClassReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClassReference::This => f.write_str("this"),
+            ClassReference::ThisAs(id) => write!(f, "as {}", id),
+        }
+    }
+}
+
+impl fmt::Display for RangeBounds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{} step {}",
+            Operand(&self.start),
+            if self.inclusive { "..=" } else { ".." },
+            Operand(&self.end),
+            Operand(&self.step)
+        )
+    }
+}
+
+/// An instruction used as the operand of another, in parentheses unless it is a single value.
+struct Operand<'a>(&'a AssembledInstruction);
+impl<'a> fmt::Display for Operand<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            AssembledInstruction::Integer(_)
+            | AssembledInstruction::StringLiteral(_)
+            | AssembledInstruction::Get(ClassReference::This)
+            | AssembledInstruction::Element
+            | AssembledInstruction::FreeIntake
+            | AssembledInstruction::NoOp => write!(f, "{}", self.0),
+            instruction => write!(f, "({})", instruction),
+        }
+    }
+}
+
+/// Writes one instruction on a line indented by `depth`, with the instructions of blocks and
+/// stream loops on lines of their own one level deeper.
+fn write_instruction(
+    instruction: &AssembledInstruction,
+    depth: usize,
+    f: &mut fmt::Formatter,
+) -> fmt::Result {
+    let indent = INDENT.repeat(depth);
+    f.write_str(&indent)?;
+    match instruction {
+        AssembledInstruction::DeclareContext => f.write_str("declare_context"),
+        AssembledInstruction::InitContext => f.write_str("init_context"),
+        AssembledInstruction::DeclareCalculationScope => f.write_str("declare_calculation_scope"),
+        AssembledInstruction::SetLength(length) => write!(f, "set_length {}", length),
+        AssembledInstruction::InitIntake(bytes) => write!(f, "init_intake {}", bytes),
+        AssembledInstruction::InitOutgive(bytes) => write!(f, "init_outgive {}", bytes),
+        AssembledInstruction::LoadIntake(slot) => write!(f, "load_intake {}", slot),
+        AssembledInstruction::SaveOutgive(slot) => write!(f, "save_outgive {}", slot),
+        AssembledInstruction::InitOutgiveFor(bytes) => {
+            write!(f, "init_outgive_for {}", Operand(bytes))
+        }
+        AssembledInstruction::AddOutgive(bytes) => write!(f, "add_outgive {}", bytes),
+        AssembledInstruction::FreeIntake => f.write_str("free_intake"),
+        AssembledInstruction::FreeOutgive(slot) => write!(f, "free_outgive {}", slot),
+        AssembledInstruction::ContextSlot(slot) => write!(f, "context_slot {}", slot),
+        AssembledInstruction::IntakeCount(bytes) => write!(f, "intake_count {}", bytes),
+        AssembledInstruction::RangeLength(bounds) => write!(f, "range_length {}", bounds),
+        AssembledInstruction::NoOp => f.write_str("no_op"),
+        AssembledInstruction::AddIntake(value, class) => {
+            write!(f, "add_intake {} {}", class, Operand(value))
+        }
+        AssembledInstruction::AddIntakeBytes(bytes) => {
+            let hex = bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>();
+            write!(f, "add_intake_bytes [{}]", hex.join(" "))
+        }
+        AssembledInstruction::AddIntakeFrom(source, bytes) => {
+            write!(f, "add_intake_from {} {}", Operand(source), bytes)
+        }
+        AssembledInstruction::FlipIntake => f.write_str("flip_intake"),
+        AssembledInstruction::PrintString(length, value) => {
+            write!(f, "print_string {} {}", length, Operand(value))
+        }
+        AssembledInstruction::PrintValue(kind, value) => {
+            write!(f, "print_value {:?} {}", kind, Operand(value))
+        }
+        AssembledInstruction::PrintLiteral(text) => write!(f, "print_literal {:?}", text),
+        AssembledInstruction::StringLiteral(text) => write!(f, "{:?}", text),
+        AssembledInstruction::Integer(value) => write!(f, "{}", value),
+        AssembledInstruction::Offset(value, offset) => {
+            write!(f, "offset {} {}", Operand(value), offset)
+        }
+        AssembledInstruction::CallMethod(class, name) => write!(f, "call {} {}", class, name),
+        AssembledInstruction::Cast(primitive) => write!(f, "cast {}", primitive),
+        AssembledInstruction::Deref(Some(primitive)) => write!(f, "deref {}", primitive),
+        AssembledInstruction::Deref(None) => f.write_str("deref"),
+        AssembledInstruction::Get(ClassReference::This) => f.write_str("get"),
+        AssembledInstruction::Get(class) => write!(f, "get {}", class),
+        AssembledInstruction::Element => f.write_str("element"),
+        AssembledInstruction::Arithmetic(op, left, right) => {
+            let name = match op {
+                Operator::Plus => "add",
+                Operator::Minus => "subtract",
+                Operator::Multiply => "multiply",
+                Operator::Divide => "divide",
+                _ => "modulus",
+            };
+            write!(f, "{} {} {}", name, Operand(left), Operand(right))
+        }
+        AssembledInstruction::Chain(first, second) => {
+            write!(f, "chain {} {}", Operand(first), Operand(second))
+        }
+        AssembledInstruction::Stream(class, stream) => {
+            let body = match stream {
+                StreamInstruction::ForEach(body) => {
+                    write!(f, "for_each {}", class)?;
+                    body
+                }
+                StreamInstruction::Range(bounds, body) => {
+                    write!(f, "range {} {}", class, bounds)?;
+                    body
+                }
+                StreamInstruction::Slice(bounds, body) => {
+                    write!(f, "slice {} {}", class, bounds)?;
+                    body
+                }
+                StreamInstruction::Call(intake, name, body) => {
+                    write!(f, "call_each {} {} {}", class, intake, name)?;
+                    body
+                }
+                StreamInstruction::Field(offset, body) => {
+                    write!(f, "field {} {}", class, offset)?;
+                    body
+                }
+                StreamInstruction::Sum(index, gather, body) => {
+                    write!(f, "sum {} ", index)?;
+                    write_body(gather, depth, f)?;
+                    f.write_str(" then")?;
+                    body
+                }
+            };
+            f.write_str(" ")?;
+            write_body(body, depth, f)
+        }
+        AssembledInstruction::Block(_) => write_body(instruction, depth, f),
+        AssembledInstruction::Return(code) => write!(f, "return {}", code),
+        AssembledInstruction::ReturnOutgive => f.write_str("return_outgive"),
+        AssembledInstruction::Accumulate(index) => write!(f, "accumulate {}", index),
+    }
+}
+
+/// Writes a loop body in braces, one instruction per line when it is a block.
+fn write_body(body: &AssembledInstruction, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+    let instructions = match body {
+        AssembledInstruction::Block(instructions) => instructions.as_slice(),
+        single => std::slice::from_ref(single),
+    };
+    f.write_str("{\n")?;
+    for instruction in instructions.iter() {
+        write_instruction(instruction, depth + 1, f)?;
+        f.write_str("\n")?;
+    }
+    write!(f, "{}}}", INDENT.repeat(depth))
+}
@@ -0,0 +1,537 @@
+use crate::lang::diagnostic::Span;
+use crate::parse::constant::Operator;
+use crate::parse::context::TokenizerContext;
+use crate::parse::token::{Literal, OperatorGroup, Token};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::context::AssembledTypeContext;
+use super::{block_scope, field_names, generic_parameters, literal_identifier, AssemblyError};
+
+/// What an identifier used inside a scope refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Type,
+    TypeParameter,
+    Method,
+    Binding(usize), //Declaring scope
+    Field(String),  //Owning type
+    Parameter,      //Named by the intake pattern
+}
+
+/// An identifier that could not be resolved, with the closest visible name if one is near enough.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnresolvedIdentifier {
+    pub name: String,
+    pub scope: usize,
+    /// Where the identifier is first used in its scope.
+    pub span: Option<Span>,
+    pub suggestion: Option<String>,
+}
+impl fmt::Display for UnresolvedIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot find `{}` in this scope", self.name)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// The resolution of every identifier, keyed by the scope it is used in and its name.
+#[derive(Debug, Default)]
+pub struct Resolutions {
+    resolved: HashMap<(usize, String), Resolution>,
+}
+impl Resolutions {
+    pub fn get(&self, scope: usize, name: &str) -> Option<&Resolution> {
+        self.resolved.get(&(scope, name.to_string()))
+    }
+    pub fn len(&self) -> usize {
+        self.resolved.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.resolved.is_empty()
+    }
+}
+
+/// The names declared at the root, and the names each scope introduces.
+struct Declarations {
+    types: HashSet<String>,
+    methods: HashSet<String>,
+    /// The fields of each type, by the name of the type.
+    fields: HashMap<String, Vec<String>>,
+    /// The type whose fields may be named on their own in a scope: the body of a type, or the
+    /// intake and body of a method taking it in.
+    owners: HashMap<usize, String>,
+    /// The names the intake pattern of a method gives the fields of its element.
+    patterns: HashMap<usize, Vec<String>>,
+    type_parameters: HashMap<usize, Vec<String>>,
+    /// The names used as a stage, `a => name`, in each scope.
+    stages: HashMap<usize, HashSet<String>>,
+}
+
+/// Resolves the identifiers each scope could not resolve while tokenizing, walking up through the
+/// enclosing scopes for bindings and type parameters before falling back to the root declarations.
+/// Assembly only uses this to report every unresolved name at once; lowering looks names up again
+/// through its own bindings.
+pub fn resolve(
+    tokens: &TokenizerContext,
+    builtins: &AssembledTypeContext,
+) -> Result<Resolutions, AssemblyError> {
+    let declarations = collect_declarations(tokens, builtins)?;
+    let mut resolutions = Resolutions::default();
+    let mut unresolved = Vec::new();
+    let mut scope = 0usize;
+    while let Some(context_scope) = tokens.get_scope(scope) {
+        let mut names = context_scope
+            .get_undeclared_identifiers()
+            .iter()
+            .collect::<Vec<&String>>();
+        names.sort();
+        for name in names.into_iter() {
+            match resolve_name(name, scope, tokens, &declarations) {
+                Some(resolution) => {
+                    resolutions
+                        .resolved
+                        .insert((scope, name.clone()), resolution);
+                }
+                None => unresolved.push(UnresolvedIdentifier {
+                    name: name.clone(),
+                    scope,
+                    span: context_scope
+                        .get_identifier_span(name)
+                        .map(|(start, end)| Span { start, end }),
+                    suggestion: suggest(name, scope, tokens, &declarations),
+                }),
+            }
+        }
+        scope += 1;
+    }
+    if unresolved.is_empty() {
+        Ok(resolutions)
+    } else {
+        Err(AssemblyError::UnresolvedIdentifiers(unresolved))
+    }
+}
+
+fn collect_declarations(
+    tokens: &TokenizerContext,
+    builtins: &AssembledTypeContext,
+) -> Result<Declarations, AssemblyError> {
+    let mut methods = builtins
+        .get_method_names()
+        .into_iter()
+        .cloned()
+        .collect::<HashSet<String>>();
+    // adding up ints is a stage of its own rather than a method of `int`
+    methods.insert("sum".to_string());
+    let mut declarations = Declarations {
+        types: builtins.get_type_names().into_iter().cloned().collect(),
+        methods,
+        fields: HashMap::new(),
+        owners: HashMap::new(),
+        patterns: HashMap::new(),
+        type_parameters: HashMap::new(),
+        stages: HashMap::new(),
+    };
+    let mut scope = 0usize;
+    while let Some(context_scope) = tokens.get_scope(scope) {
+        let mut names = HashSet::new();
+        for t in context_scope.get_tokens().iter() {
+            stage_names(t, &mut names);
+        }
+        declarations.stages.insert(scope, names);
+        scope += 1;
+    }
+    for t in tokens.get_scope(0).unwrap().get_tokens().iter() {
+        match t.as_ref() {
+            Token::TypeDef(identifier, parameters, body) => {
+                let name = literal_identifier(identifier)?;
+                let body = block_scope(body)?;
+                if let Some(parameters) = parameters {
+                    let names = generic_parameters(tokens, parameters)?;
+                    declarations
+                        .type_parameters
+                        .insert(block_scope(parameters)?, names.clone());
+                    declarations.type_parameters.insert(body, names);
+                }
+                let mut fields = Vec::new();
+                for field in tokens.get_scope(body).unwrap().get_tokens().iter() {
+                    match field.as_ref() {
+                        Token::VariableDef(_, field_name) => fields.push(field_name.clone()),
+                        Token::Constructor(_, names) | Token::GenericConstructor(_, _, names) => {
+                            fields.extend(field_names(names, tokens)?)
+                        }
+                        _ => {}
+                    }
+                }
+                if !declarations.types.insert(name.clone()) {
+                    return Err(AssemblyError::TypeRedefined(name));
+                }
+                declarations.owners.insert(body, name.clone());
+                declarations.fields.insert(name, fields);
+            }
+            Token::MethodDef(_, name, parameters, intake, body) => {
+                let mut intake_scopes = Vec::new();
+                blocks_of(intake, &mut intake_scopes);
+                let body_scope = block_scope(body)?;
+                if let Some(owner) = intake_type(intake) {
+                    for scope in intake_scopes.iter().chain([body_scope].iter()) {
+                        declarations.owners.insert(*scope, owner.clone());
+                    }
+                }
+                if let Token::Constructor(_, names) | Token::GenericConstructor(_, _, names) =
+                    intake.as_ref()
+                {
+                    let names = field_names(names, tokens)?;
+                    for scope in intake_scopes.iter() {
+                        declarations.patterns.insert(*scope, names.clone());
+                    }
+                    // `_` only ignores a field, it cannot be used in the body
+                    let named = names.into_iter().filter(|n| n != "_").collect();
+                    declarations.patterns.insert(body_scope, named);
+                }
+                if let Some(parameters) = parameters {
+                    let names = generic_parameters(tokens, parameters)?;
+                    let mut scopes = Vec::new();
+                    blocks_of(parameters, &mut scopes);
+                    blocks_of(intake, &mut scopes);
+                    blocks_of(body, &mut scopes);
+                    for scope in scopes.into_iter() {
+                        declarations.type_parameters.insert(scope, names.clone());
+                    }
+                }
+                declarations.methods.insert(name.clone());
+            }
+            _ => {}
+        }
+    }
+    Ok(declarations)
+}
+
+/// Adds the names `token` uses as a stage, without following it into its blocks.
+fn stage_names(token: &Token, names: &mut HashSet<String>) {
+    match token {
+        Token::Operator(OperatorGroup::BiOperator(operator, source, stage)) => {
+            if let (Operator::Into, Token::Literal(Literal::Identifier(name))) =
+                (operator, stage.as_ref())
+            {
+                names.insert(name.clone());
+            }
+            stage_names(source, names);
+            stage_names(stage, names);
+        }
+        Token::Operator(OperatorGroup::UniOperator(_, operand)) => stage_names(operand, names),
+        Token::Operator(OperatorGroup::TriOperator(_, first, second, third)) => {
+            stage_names(first, names);
+            stage_names(second, names);
+            stage_names(third, names);
+        }
+        Token::Binding(_, value) => stage_names(value, names),
+        _ => {}
+    }
+}
+
+/// The name of the type a method takes in, when its intake is written as one.
+fn intake_type(intake: &Token) -> Option<String> {
+    match intake {
+        Token::Constructor(name, _)
+        | Token::GenericConstructor(name, _, _)
+        | Token::Generic(name, _)
+        | Token::Literal(Literal::Identifier(name)) => Some(name.clone()),
+        _ => None,
+    }
+}
+
+/// The scopes referenced directly by a token, without following them into their own tokens.
+fn blocks_of(token: &Token, scopes: &mut Vec<usize>) {
+    match token {
+        Token::Block(_, scope) => scopes.push(*scope),
+        Token::Generic(_, arguments) => blocks_of(arguments, scopes),
+        Token::Constructor(_, parameters) => blocks_of(parameters, scopes),
+        Token::GenericConstructor(_, arguments, parameters) => {
+            blocks_of(arguments, scopes);
+            blocks_of(parameters, scopes);
+        }
+        _ => {}
+    }
+}
+
+/// The chain of scopes from `scope` up to the root.
+fn enclosing_scopes(scope: usize, tokens: &TokenizerContext) -> Vec<usize> {
+    let mut chain = vec![scope];
+    let mut current = tokens.get_scope(scope);
+    while let Some(parent) = current.and_then(|s| s.get_parent()) {
+        chain.push(parent);
+        current = tokens.get_scope(parent);
+    }
+    chain
+}
+
+fn resolve_name(
+    name: &str,
+    scope: usize,
+    tokens: &TokenizerContext,
+    declarations: &Declarations,
+) -> Option<Resolution> {
+    for s in enclosing_scopes(scope, tokens).into_iter() {
+        if tokens.get_scope(s).unwrap().is_declared(name) {
+            return Some(Resolution::Binding(s));
+        }
+        if let Some(parameters) = declarations.type_parameters.get(&s) {
+            if parameters.iter().any(|p| p == name) {
+                return Some(Resolution::TypeParameter);
+            }
+        }
+        if let Some(patterns) = declarations.patterns.get(&s) {
+            if patterns.iter().any(|p| p == name) {
+                return Some(Resolution::Parameter);
+            }
+        }
+        if let Some(owner) = declarations.owners.get(&s) {
+            if owner_fields(owner, declarations).any(|f| f == name) {
+                return Some(Resolution::Field(owner.clone()));
+            }
+        }
+    }
+    if declarations.types.contains(name) {
+        Some(Resolution::Type)
+    } else if declarations.methods.contains(name) {
+        Some(Resolution::Method)
+    } else if declarations
+        .stages
+        .get(&scope)
+        .is_some_and(|stages| stages.contains(name))
+    {
+        // a field used as a stage, taking it from each element
+        let mut owners = declarations
+            .fields
+            .iter()
+            .filter(|(_, fields)| fields.iter().any(|f| f == name))
+            .map(|(owner, _)| owner)
+            .collect::<Vec<&String>>();
+        owners.sort();
+        owners
+            .first()
+            .map(|owner| Resolution::Field((*owner).clone()))
+    } else {
+        None
+    }
+}
+
+fn owner_fields<'a>(
+    owner: &str,
+    declarations: &'a Declarations,
+) -> impl Iterator<Item = &'a String> {
+    declarations.fields.get(owner).into_iter().flatten()
+}
+
+/// The visible name closest to `name`, if it is within a third of its length in edits.
+fn suggest(
+    name: &str,
+    scope: usize,
+    tokens: &TokenizerContext,
+    declarations: &Declarations,
+) -> Option<String> {
+    let mut candidates = Vec::new();
+    for s in enclosing_scopes(scope, tokens).into_iter() {
+        candidates.extend(
+            tokens
+                .get_scope(s)
+                .unwrap()
+                .get_declared_identifiers()
+                .iter(),
+        );
+        if let Some(parameters) = declarations.type_parameters.get(&s) {
+            candidates.extend(parameters.iter());
+        }
+        if let Some(patterns) = declarations.patterns.get(&s) {
+            candidates.extend(patterns.iter().filter(|p| *p != "_"));
+        }
+        if let Some(owner) = declarations.owners.get(&s) {
+            candidates.extend(owner_fields(owner, declarations));
+        }
+    }
+    candidates.extend(declarations.types.iter());
+    candidates.extend(declarations.methods.iter());
+    let limit = std::cmp::max(1, name.chars().count() / 3);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, c)| c.clone())
+}
+
+/// Edit distance counting a swap of two neighbouring characters as a single edit, so `pritn`
+/// still suggests `print`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<char>>();
+    let b = b.chars().collect::<Vec<char>>();
+    let mut distances = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::token::parse_tokens;
+
+    fn resolve_source(source: &[u8]) -> Result<Resolutions, AssemblyError> {
+        let tokens = parse_tokens(source).map_err(AssemblyError::TokenParseError)?;
+        let mut builtins = AssembledTypeContext::new();
+        builtins.setup_root_scope_types();
+        resolve(&tokens, &builtins)
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("print", "print"), 0);
+        assert_eq!(edit_distance("pritn", "print"), 1);
+        assert_eq!(edit_distance("goose", "print"), 5);
+        assert_eq!(edit_distance("Pont", "Point"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+    #[test]
+    fn test_resolve_kinds() -> Result<(), AssemblyError> {
+        let tokens = parse_tokens(
+            b"type Pair<A, B>: (A(first), B(second),)
+            calc start: () { n: 3; [Pair<int, int>(1, 2)] => print; n => print; }",
+        )
+        .map_err(AssemblyError::TokenParseError)?;
+        let mut builtins = AssembledTypeContext::new();
+        builtins.setup_root_scope_types();
+        let resolutions = resolve(&tokens, &builtins)?;
+        let body = match tokens.get_scope(0).unwrap().get_tokens()[1].as_ref() {
+            Token::MethodDef(_, _, _, _, body) => block_scope(body)?,
+            _ => panic!("expected the start method"),
+        };
+        assert_eq!(resolutions.get(body, "n"), Some(&Resolution::Binding(body)));
+        assert_eq!(resolutions.get(body, "print"), Some(&Resolution::Method));
+        let mut kinds = resolutions.resolved.values().cloned().collect::<Vec<_>>();
+        kinds.sort_by_key(|k| format!("{:?}", k));
+        assert!(kinds.contains(&Resolution::TypeParameter));
+        assert!(kinds.contains(&Resolution::Type));
+        assert!(kinds.contains(&Resolution::Field("Pair".to_string())));
+        Ok(())
+    }
+    #[test]
+    fn test_resolve_intake_patterns() -> Result<(), AssemblyError> {
+        let tokens = parse_tokens(
+            b"type Point: (int(x), int(y),)
+            trans both: Point(a, _) { [a, y] }",
+        )
+        .map_err(AssemblyError::TokenParseError)?;
+        let mut builtins = AssembledTypeContext::new();
+        builtins.setup_root_scope_types();
+        let resolutions = resolve(&tokens, &builtins)?;
+        let (intake, body) = match tokens.get_scope(0).unwrap().get_tokens()[1].as_ref() {
+            Token::MethodDef(_, _, _, intake, body) => {
+                let mut scopes = Vec::new();
+                blocks_of(intake, &mut scopes);
+                (scopes[0], block_scope(body)?)
+            }
+            _ => panic!("expected the method"),
+        };
+        let array = match tokens.get_scope(body).unwrap().get_tokens()[0].as_ref() {
+            Token::Array(elements) => block_scope(elements)?,
+            _ => panic!("expected the array"),
+        };
+        assert_eq!(resolutions.get(intake, "_"), Some(&Resolution::Parameter));
+        assert_eq!(resolutions.get(array, "a"), Some(&Resolution::Parameter));
+        assert_eq!(
+            resolutions.get(array, "y"),
+            Some(&Resolution::Field("Point".to_string()))
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_resolve_fields_of_the_intake_only() {
+        // `first` is a field, but not of the `Point` taken in, and `_` ignores a field
+        let source = b"type Point: (int(x), int(y),)
+            type Pair: (int(first), int(second),)
+            trans f: Point(_, b) { [first, _] }";
+        match resolve_source(source) {
+            Err(AssemblyError::UnresolvedIdentifiers(unresolved)) => {
+                let names = unresolved
+                    .iter()
+                    .map(|u| (u.name.as_str(), u.span))
+                    .collect::<Vec<_>>();
+                // both are used in the body, after `[`
+                let first = source.windows(6).position(|w| w == b"[first").unwrap() + 1;
+                let span = |start: usize, end: usize| Some(Span { start, end });
+                assert_eq!(
+                    names,
+                    vec![
+                        ("_", span(first + 7, first + 8)),
+                        ("first", span(first, first + 5))
+                    ]
+                );
+            }
+            other => panic!("expected unresolved identifiers, found {:?}", other),
+        }
+    }
+    #[test]
+    fn test_resolve_suggests_close_names() {
+        match resolve_source(
+            b"type Point: (int(x), int(y),)
+            calc start: () { [Pont(1, 2)] => pritn; }",
+        ) {
+            Err(AssemblyError::UnresolvedIdentifiers(unresolved)) => {
+                let messages = unresolved
+                    .iter()
+                    .map(|u| u.to_string())
+                    .collect::<Vec<String>>();
+                assert_eq!(
+                    messages,
+                    vec![
+                        "cannot find `pritn` in this scope, did you mean `print`?",
+                        "cannot find `Pont` in this scope, did you mean `Point`?",
+                    ]
+                );
+            }
+            other => panic!("expected unresolved identifiers, found {:?}", other),
+        }
+    }
+    #[test]
+    fn test_resolve_type_redefined() {
+        match resolve_source(
+            b"type Point: (int(x),)
+            type Point: (int(y),)
+            calc start: () { }",
+        ) {
+            Err(AssemblyError::TypeRedefined(name)) => assert_eq!(name, "Point"),
+            other => panic!("expected a redefined type, found {:?}", other),
+        }
+    }
+    #[test]
+    fn test_resolve_without_suggestion() {
+        match resolve_source(b"calc start: () { goose => print; }") {
+            Err(AssemblyError::UnresolvedIdentifiers(unresolved)) => {
+                assert_eq!(unresolved.len(), 1);
+                assert_eq!(unresolved[0].suggestion, None);
+            }
+            other => panic!("expected unresolved identifiers, found {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,639 @@
+use crate::lang::assemble::context::{
+    AssembledTypeContext, ClassReference, CLASS_ID_INT, CLASS_ID_STRING, CLASS_ID_VOID,
+};
+use crate::lang::assemble::instruction::{
+    AssembledInstruction, PrintKind, RangeBounds, StreamInstruction,
+};
+use crate::lang::assemble::{function_body, user_methods, AssemblyError};
+use crate::lang::bytecode::{Op, Program, Register, Width};
+use crate::parse::constant::Operator;
+
+use std::collections::HashMap;
+
+/// Compiles the assembled `start` method, with the frees of its bindings, into a program that
+/// returns 0 like the generated C does. The methods declared in source follow it, each called
+/// with the element it takes in and returning the outgive and its length.
+pub fn compile(types: &AssembledTypeContext) -> Result<Program, AssemblyError> {
+    let start = match types.get_impl(&CLASS_ID_VOID).unwrap().get("start") {
+        Some(start) => start,
+        None => return Err(AssemblyError::NoStartMethodFound),
+    };
+    let mut compiler = Compiler {
+        types,
+        program: Program::default(),
+        classes: Vec::new(),
+        element: None,
+        calls: Vec::new(),
+        sums: HashMap::new(),
+    };
+    for i in function_body(start, AssembledInstruction::Return(0)).iter() {
+        compiler.statement(i)?;
+    }
+    let mut functions = HashMap::new();
+    for (class_id, name, method) in user_methods(types).into_iter() {
+        functions.insert((class_id, name.clone()), compiler.position());
+        let element = compiler.local();
+        compiler.emit(Op::StoreLocal(element));
+        compiler.element = Some(element);
+        for i in function_body(method, AssembledInstruction::ReturnOutgive).iter() {
+            compiler.statement(i)?;
+        }
+    }
+    for (at, function) in compiler.calls.iter() {
+        compiler.program.code[*at] = Op::Call(functions[function]);
+    }
+    Ok(compiler.program)
+}
+
+struct Compiler<'a> {
+    types: &'a AssembledTypeContext,
+    program: Program,
+    /// The classes of the streams being iterated, innermost last, which `This` refers to.
+    classes: Vec<u16>,
+    /// The local holding the element the method being compiled was called with.
+    element: Option<u16>,
+    /// The position of each call, with the class and name of the method it calls, to be pointed
+    /// at the method once all are compiled.
+    calls: Vec<(usize, (u16, String))>,
+    /// The cell holding the total of each `sum` stage, by its index.
+    sums: HashMap<u16, u16>,
+}
+
+impl<'a> Compiler<'a> {
+    fn emit(&mut self, op: Op) {
+        self.program.code.push(op);
+    }
+    fn emit_all(&mut self, ops: &[Op]) {
+        self.program.code.extend_from_slice(ops);
+    }
+    fn position(&self) -> u32 {
+        self.program.code.len() as u32
+    }
+    /// Emits a jump to be pointed at its target with `patch` once that is known.
+    fn jump(&mut self, op: fn(u32) -> Op) -> usize {
+        self.emit(op(0));
+        self.program.code.len() - 1
+    }
+    fn patch(&mut self, at: usize) {
+        let to = self.position();
+        self.program.code[at] = match self.program.code[at] {
+            Op::Jump(_) => Op::Jump(to),
+            Op::JumpIfFalse(_) => Op::JumpIfFalse(to),
+            _ => unreachable!(),
+        };
+    }
+    fn local(&mut self) -> u16 {
+        self.program.locals += 1;
+        self.program.locals - 1
+    }
+    fn constant(&mut self, bytes: &[u8]) -> u32 {
+        let index = match self.program.constants.iter().position(|c| c == bytes) {
+            Some(index) => index,
+            None => {
+                self.program.constants.push(bytes.to_vec());
+                self.program.constants.len() - 1
+            }
+        };
+        index as u32
+    }
+    fn class(&self, class: &ClassReference) -> Result<u16, AssemblyError> {
+        match class {
+            ClassReference::ThisAs(id) => Ok(*id),
+            ClassReference::This => self.classes.last().copied().ok_or_else(|| {
+                AssemblyError::UnsupportedInstruction(AssembledInstruction::Get(*class).to_string())
+            }),
+        }
+    }
+    fn bytes(&self, class_id: u16) -> Result<u16, AssemblyError> {
+        match self.types.get_type(&class_id) {
+            Some(t) => Ok(t.get_bytes(self.types)),
+            None => Err(AssemblyError::TypeNotFound),
+        }
+    }
+    /// Moves `in` past `bytes` just written, growing `len` with it.
+    fn advance(&mut self, bytes: u16) {
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::PushInt(bytes as i64),
+            Op::Add,
+            Op::StoreRegister(Register::In),
+            Op::LoadRegister(Register::Len),
+            Op::PushInt(bytes as i64),
+            Op::Add,
+            Op::StoreRegister(Register::Len),
+        ]);
+    }
+
+    fn statement(&mut self, instruction: &AssembledInstruction) -> Result<(), AssemblyError> {
+        match instruction {
+            AssembledInstruction::DeclareContext
+            | AssembledInstruction::InitContext
+            | AssembledInstruction::DeclareCalculationScope => {}
+            AssembledInstruction::SetLength(length) => {
+                self.emit_all(&[
+                    Op::PushInt(*length as i64),
+                    Op::StoreRegister(Register::Len),
+                ]);
+            }
+            AssembledInstruction::InitIntake(bytes) => {
+                self.emit_all(&[
+                    Op::PushInt(*bytes as i64),
+                    Op::Alloc,
+                    Op::StoreRegister(Register::In),
+                ]);
+            }
+            AssembledInstruction::InitOutgive(bytes) => {
+                self.emit_all(&[
+                    Op::PushInt(*bytes as i64),
+                    Op::Alloc,
+                    Op::StoreRegister(Register::Out),
+                ]);
+            }
+            AssembledInstruction::InitOutgiveFor(bytes) => {
+                self.expression(bytes)?;
+                self.emit_all(&[
+                    Op::Alloc,
+                    Op::StoreRegister(Register::Out),
+                    Op::PushInt(0),
+                    Op::StoreRegister(Register::OutLen),
+                ]);
+            }
+            AssembledInstruction::LoadIntake(slot) => {
+                self.emit_all(&[
+                    Op::LoadContext(*slot),
+                    Op::StoreRegister(Register::In),
+                    Op::LoadContextLen(*slot),
+                    Op::StoreRegister(Register::Len),
+                ]);
+            }
+            AssembledInstruction::SaveOutgive(slot) => {
+                self.emit_all(&[
+                    Op::LoadRegister(Register::Out),
+                    Op::LoadRegister(Register::OutLen),
+                    Op::StoreContext(*slot),
+                ]);
+            }
+            AssembledInstruction::AddOutgive(bytes) => {
+                // a method may give back any number of elements, so the outgive grows to fit
+                self.emit_all(&[
+                    Op::LoadRegister(Register::Out),
+                    Op::LoadRegister(Register::OutLen),
+                    Op::PushInt(*bytes as i64),
+                    Op::Add,
+                    Op::Realloc,
+                    Op::StoreRegister(Register::Out),
+                    Op::LoadRegister(Register::Out),
+                    Op::LoadRegister(Register::OutLen),
+                    Op::Add,
+                    Op::LoadRegister(Register::In),
+                    Op::Copy(*bytes as u32),
+                    Op::LoadRegister(Register::OutLen),
+                    Op::PushInt(*bytes as i64),
+                    Op::Add,
+                    Op::StoreRegister(Register::OutLen),
+                ]);
+            }
+            AssembledInstruction::FreeIntake => {
+                self.emit_all(&[Op::LoadRegister(Register::In), Op::Free]);
+            }
+            AssembledInstruction::FreeOutgive(slot) => {
+                self.emit_all(&[Op::LoadContext(*slot), Op::Free]);
+            }
+            AssembledInstruction::NoOp => {
+                self.emit_all(&[
+                    Op::LoadRegister(Register::In),
+                    Op::StoreRegister(Register::Out),
+                ]);
+            }
+            AssembledInstruction::AddIntake(value, class) => {
+                let class_id = self.class(class)?;
+                let width = match class_id {
+                    CLASS_ID_INT => Width::Int,
+                    CLASS_ID_STRING => Width::Handle(self.bytes(class_id)? as u8),
+                    _ => return Err(unsupported(instruction)),
+                };
+                self.emit(Op::LoadRegister(Register::In));
+                self.expression(value)?;
+                self.emit(Op::Store(width));
+                self.advance(self.bytes(class_id)?);
+            }
+            AssembledInstruction::AddIntakeBytes(bytes) => {
+                let constant = self.constant(bytes);
+                self.emit_all(&[Op::LoadRegister(Register::In), Op::WriteConst(constant)]);
+                self.advance(bytes.len() as u16);
+            }
+            AssembledInstruction::AddIntakeFrom(source, bytes) => {
+                self.emit(Op::LoadRegister(Register::In));
+                self.expression(source)?;
+                self.emit(Op::Copy(*bytes as u32));
+                self.advance(*bytes);
+            }
+            AssembledInstruction::FlipIntake => {
+                self.emit_all(&[
+                    Op::LoadRegister(Register::In),
+                    Op::LoadRegister(Register::Len),
+                    Op::Sub,
+                    Op::StoreRegister(Register::In),
+                ]);
+            }
+            AssembledInstruction::PrintString(len, value) => {
+                self.expression(value)?;
+                self.emit(Op::PrintBytes(*len as u32));
+            }
+            AssembledInstruction::PrintValue(kind, value) => {
+                let print = match kind {
+                    PrintKind::Int => Op::PrintInt,
+                    PrintKind::Str => Op::PrintStr,
+                    PrintKind::Hex => Op::PrintHex,
+                };
+                self.expression(value)?;
+                self.emit(print);
+            }
+            AssembledInstruction::PrintLiteral(text) => {
+                let constant = self.constant(text.as_bytes());
+                self.emit(Op::PrintConst(constant));
+            }
+            AssembledInstruction::CallMethod(class, name) => {
+                let class_id = self.class(class)?;
+                let method = self
+                    .types
+                    .get_impl(&class_id)
+                    .and_then(|imp| imp.get(name))
+                    .ok_or_else(|| unsupported(instruction))?;
+                self.classes.push(class_id);
+                for i in method.get_body().iter() {
+                    self.statement(i)?;
+                }
+                self.classes.pop();
+            }
+            AssembledInstruction::Stream(class, stream) => {
+                let class_id = self.class(class)?;
+                self.classes.push(class_id);
+                let bytes = self.bytes(class_id)? as i64;
+                match stream {
+                    StreamInstruction::ForEach(body) => self.for_each(bytes, body)?,
+                    StreamInstruction::Range(bounds, body) => self.range(bounds, body)?,
+                    StreamInstruction::Slice(bounds, body) => self.slice(bytes, bounds, body)?,
+                    StreamInstruction::Call(intake, name, body) => {
+                        let intake = self.class(intake)?;
+                        self.call(intake, name, bytes, body)?
+                    }
+                    StreamInstruction::Field(offset, body) => self.field(*offset, body)?,
+                    StreamInstruction::Sum(index, gather, body) => {
+                        self.sum(*index, gather, body)?
+                    }
+                }
+                self.classes.pop();
+            }
+            AssembledInstruction::Block(instructions) => {
+                for i in instructions.iter() {
+                    self.statement(i)?;
+                }
+            }
+            AssembledInstruction::Return(code) => {
+                self.emit_all(&[Op::PushInt(*code), Op::Halt]);
+            }
+            AssembledInstruction::ReturnOutgive => {
+                self.emit_all(&[
+                    Op::LoadRegister(Register::Out),
+                    Op::LoadRegister(Register::OutLen),
+                    Op::Return,
+                ]);
+            }
+            AssembledInstruction::Accumulate(index) => {
+                let cell = self.sums[index];
+                self.emit_all(&[
+                    Op::LoadLocal(cell),
+                    Op::LoadLocal(cell),
+                    Op::Load(Width::Int),
+                    Op::LoadRegister(Register::In),
+                    Op::Load(Width::Int),
+                    Op::Add,
+                    Op::Store(Width::Int),
+                ]);
+            }
+            _ => return Err(unsupported(instruction)),
+        }
+        Ok(())
+    }
+
+    /// Compiles an instruction that stands for a value, leaving it on the stack.
+    fn expression(&mut self, instruction: &AssembledInstruction) -> Result<(), AssemblyError> {
+        match instruction {
+            AssembledInstruction::Integer(value) => self.emit(Op::PushInt(*value)),
+            AssembledInstruction::StringLiteral(text) => {
+                let constant = self.constant(text.as_bytes());
+                self.emit(Op::PushConst(constant));
+            }
+            AssembledInstruction::ContextSlot(slot) => self.emit(Op::LoadContext(*slot)),
+            AssembledInstruction::Get(_) => self.emit(Op::LoadRegister(Register::In)),
+            AssembledInstruction::Element => match self.element {
+                Some(element) => self.emit(Op::LoadLocal(element)),
+                None => return Err(unsupported(instruction)),
+            },
+            AssembledInstruction::IntakeCount(bytes) => {
+                self.emit_all(&[
+                    Op::LoadRegister(Register::Len),
+                    Op::PushInt(*bytes as i64),
+                    Op::Div,
+                ]);
+            }
+            AssembledInstruction::RangeLength(bounds) => self.range_length(bounds)?,
+            AssembledInstruction::Arithmetic(op, left, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.emit(match op {
+                    Operator::Plus => Op::Add,
+                    Operator::Minus => Op::Sub,
+                    Operator::Multiply => Op::Mul,
+                    Operator::Divide => Op::Div,
+                    _ => Op::Mod,
+                });
+            }
+            AssembledInstruction::Offset(value, offset) => {
+                self.expression(value)?;
+                if *offset != 0 {
+                    self.emit_all(&[Op::PushInt(*offset as i64), Op::Add]);
+                }
+            }
+            AssembledInstruction::Chain(op, value) => match op.as_ref() {
+                AssembledInstruction::Deref(Some(primitive)) => {
+                    let width = match primitive.as_str() {
+                        "int" => Width::Int,
+                        "string" => Width::Handle(self.bytes(CLASS_ID_STRING)? as u8),
+                        _ => return Err(unsupported(op)),
+                    };
+                    self.expression(value)?;
+                    self.emit(Op::Load(width));
+                }
+                AssembledInstruction::Cast(_) => self.expression(value)?,
+                _ => return Err(unsupported(instruction)),
+            },
+            _ => return Err(unsupported(instruction)),
+        }
+        Ok(())
+    }
+
+    /// Evaluates the bounds into locals, returning those of the counter, end and step.
+    fn bounds(&mut self, bounds: &RangeBounds) -> Result<(u16, u16, u16), AssemblyError> {
+        let locals = (self.local(), self.local(), self.local());
+        for (value, local) in [
+            (&bounds.start, locals.0),
+            (&bounds.end, locals.1),
+            (&bounds.step, locals.2),
+        ] {
+            self.expression(value)?;
+            self.emit(Op::StoreLocal(local));
+        }
+        Ok(locals)
+    }
+
+    /// Jumps out of the loop once the counter has passed the end, returning the jump to patch.
+    fn check_bounds(&mut self, bounds: &RangeBounds, (i, end, _): (u16, u16, u16)) -> usize {
+        self.emit_all(&[
+            Op::LoadLocal(i),
+            Op::LoadLocal(end),
+            if bounds.inclusive { Op::Le } else { Op::Lt },
+        ]);
+        self.jump(Op::JumpIfFalse)
+    }
+
+    fn step(&mut self, (i, _, step): (u16, u16, u16), top: u32) {
+        self.emit_all(&[
+            Op::LoadLocal(i),
+            Op::LoadLocal(step),
+            Op::Add,
+            Op::StoreLocal(i),
+            Op::Jump(top),
+        ]);
+    }
+
+    fn for_each(&mut self, bytes: i64, body: &AssembledInstruction) -> Result<(), AssemblyError> {
+        let end = self.local();
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::LoadRegister(Register::Len),
+            Op::Add,
+            Op::StoreLocal(end),
+        ]);
+        let top = self.position();
+        self.emit_all(&[Op::LoadRegister(Register::In), Op::LoadLocal(end), Op::Lt]);
+        let exit = self.jump(Op::JumpIfFalse);
+        self.statement(body)?;
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::PushInt(bytes),
+            Op::Add,
+            Op::StoreRegister(Register::In),
+            Op::Jump(top),
+        ]);
+        self.patch(exit);
+        // leave `in` at the start of the intake, ready to be freed
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::LoadRegister(Register::Len),
+            Op::Sub,
+            Op::StoreRegister(Register::In),
+        ]);
+        Ok(())
+    }
+
+    /// Calls a method on the element at `in`, runs the body over each element it gives back, then
+    /// frees them and moves `in` back to the caller's intake.
+    fn call(
+        &mut self,
+        intake: u16,
+        name: &str,
+        bytes: i64,
+        body: &AssembledInstruction,
+    ) -> Result<(), AssemblyError> {
+        let (caller, caller_len) = (self.local(), self.local());
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::StoreLocal(caller),
+            Op::LoadRegister(Register::Len),
+            Op::StoreLocal(caller_len),
+            Op::LoadRegister(Register::In),
+        ]);
+        self.calls
+            .push((self.program.code.len(), (intake, name.to_string())));
+        self.emit_all(&[
+            Op::Call(0),
+            Op::StoreRegister(Register::Len),
+            Op::StoreRegister(Register::In),
+        ]);
+        self.for_each(bytes, body)?;
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::Free,
+            Op::LoadLocal(caller),
+            Op::StoreRegister(Register::In),
+            Op::LoadLocal(caller_len),
+            Op::StoreRegister(Register::Len),
+        ]);
+        Ok(())
+    }
+
+    /// Moves `in` to a field of the element for the body, then back to the element.
+    fn field(&mut self, offset: u16, body: &AssembledInstruction) -> Result<(), AssemblyError> {
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::PushInt(offset as i64),
+            Op::Add,
+            Op::StoreRegister(Register::In),
+        ]);
+        self.statement(body)?;
+        self.emit_all(&[
+            Op::LoadRegister(Register::In),
+            Op::PushInt(offset as i64),
+            Op::Sub,
+            Op::StoreRegister(Register::In),
+        ]);
+        Ok(())
+    }
+
+    /// Adds up the ints the gathering instructions reach into a cell, then points `in` at it for
+    /// the body, freeing it once the body is done.
+    fn sum(
+        &mut self,
+        index: u16,
+        gather: &AssembledInstruction,
+        body: &AssembledInstruction,
+    ) -> Result<(), AssemblyError> {
+        let cell = self.local();
+        self.emit_all(&[
+            Op::PushInt(4),
+            Op::Alloc,
+            Op::StoreLocal(cell),
+            Op::LoadLocal(cell),
+            Op::PushInt(0),
+            Op::Store(Width::Int),
+        ]);
+        self.sums.insert(index, cell);
+        self.statement(gather)?;
+        self.emit_all(&[
+            Op::LoadLocal(cell),
+            Op::StoreRegister(Register::In),
+            Op::PushInt(4),
+            Op::StoreRegister(Register::Len),
+        ]);
+        self.statement(body)?;
+        self.emit_all(&[Op::LoadLocal(cell), Op::Free]);
+        Ok(())
+    }
+
+    /// Points `in` at a cell holding the counter for each step, freed once the loop is done.
+    fn range(
+        &mut self,
+        bounds: &RangeBounds,
+        body: &AssembledInstruction,
+    ) -> Result<(), AssemblyError> {
+        let cell = self.local();
+        self.emit_all(&[Op::PushInt(4), Op::Alloc, Op::StoreLocal(cell)]);
+        let locals = self.bounds(bounds)?;
+        let top = self.position();
+        let exit = self.check_bounds(bounds, locals);
+        self.emit_all(&[
+            Op::LoadLocal(cell),
+            Op::LoadLocal(locals.0),
+            Op::Store(Width::Int),
+            Op::LoadLocal(cell),
+            Op::StoreRegister(Register::In),
+        ]);
+        self.statement(body)?;
+        self.step(locals, top);
+        self.patch(exit);
+        self.emit_all(&[Op::LoadLocal(cell), Op::Free]);
+        Ok(())
+    }
+
+    fn slice(
+        &mut self,
+        bytes: i64,
+        bounds: &RangeBounds,
+        body: &AssembledInstruction,
+    ) -> Result<(), AssemblyError> {
+        let base = self.local();
+        self.emit_all(&[Op::LoadRegister(Register::In), Op::StoreLocal(base)]);
+        let locals = self.bounds(bounds)?;
+        let top = self.position();
+        let exit = self.check_bounds(bounds, locals);
+        self.emit_all(&[
+            Op::LoadLocal(locals.0),
+            Op::PushInt(bytes),
+            Op::Mul,
+            Op::LoadRegister(Register::Len),
+            Op::Lt,
+        ]);
+        let past_end = self.jump(Op::JumpIfFalse);
+        self.emit_all(&[
+            Op::LoadLocal(base),
+            Op::LoadLocal(locals.0),
+            Op::PushInt(bytes),
+            Op::Mul,
+            Op::Add,
+            Op::StoreRegister(Register::In),
+        ]);
+        self.statement(body)?;
+        self.step(locals, top);
+        self.patch(exit);
+        self.patch(past_end);
+        self.emit_all(&[Op::LoadLocal(base), Op::StoreRegister(Register::In)]);
+        Ok(())
+    }
+
+    /// The number of steps from start to end, or none when the range is empty.
+    fn range_length(&mut self, bounds: &RangeBounds) -> Result<(), AssemblyError> {
+        let end = self.local();
+        self.expression(&bounds.end)?;
+        if bounds.inclusive {
+            self.emit_all(&[Op::PushInt(1), Op::Add]);
+        }
+        self.emit(Op::StoreLocal(end));
+        self.expression(&bounds.start)?;
+        self.emit_all(&[Op::LoadLocal(end), Op::Lt]);
+        let empty = self.jump(Op::JumpIfFalse);
+        self.emit(Op::LoadLocal(end));
+        self.expression(&bounds.start)?;
+        self.emit(Op::Sub);
+        self.expression(&bounds.step)?;
+        self.emit_all(&[Op::Add, Op::PushInt(1), Op::Sub]);
+        self.expression(&bounds.step)?;
+        self.emit(Op::Div);
+        let done = self.jump(Op::Jump);
+        self.patch(empty);
+        self.emit(Op::PushInt(0));
+        self.patch(done);
+        Ok(())
+    }
+}
+
+fn unsupported(instruction: &AssembledInstruction) -> AssemblyError {
+    AssemblyError::UnsupportedInstruction(instruction.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::assemble::assemble_root;
+    use crate::parse::token::parse_tokens;
+
+    #[test]
+    fn test_compile() -> Result<(), AssemblyError> {
+        let source = b"calc start: () {\n  0..2 => print;\n}\n";
+        let tokens = parse_tokens(&source[..]).map_err(AssemblyError::TokenParseError)?;
+        let program = compile(&assemble_root(&tokens)?)?;
+        assert_eq!(program.constants, vec![b"\n".to_vec()]);
+        assert!(program.code.contains(&Op::PrintInt));
+        assert_eq!(
+            program.code[program.code.len() - 2..],
+            [Op::PushInt(0), Op::Halt]
+        );
+        for op in program.code.iter() {
+            if let Op::Jump(to) | Op::JumpIfFalse(to) = op {
+                assert!((*to as usize) < program.code.len());
+            }
+        }
+        Ok(())
+    }
+}
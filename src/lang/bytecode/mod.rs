@@ -0,0 +1,457 @@
+use std::convert::TryInto;
+
+pub mod compile;
+pub mod vm;
+
+/// The first bytes of every `.ilbc` file.
+pub const MAGIC: &[u8; 4] = b"ILBC";
+/// The version of the format written by `Program::encode`. Programs of any other version are
+/// rejected when decoded rather than run with a different meaning.
+pub const VERSION: u16 = 1;
+
+/// The registers of the VM, named after the variables of the generated C they stand for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    In,
+    Out,
+    Len,
+    OutLen,
+}
+const REGISTERS: [Register; 4] = [Register::In, Register::Out, Register::Len, Register::OutLen];
+
+/// How a value is laid out in memory: a 4 byte int, or a handle of the given width naming a
+/// constant, which is what a string is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Int,
+    Handle(u8),
+}
+
+/// An operation of the VM. Operands come off the stack last pushed first, so `Sub` computes the
+/// second from the top minus the top.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    PushInt(i64),
+    /// Pushes the handle of a constant.
+    PushConst(u32),
+    LoadLocal(u16),
+    StoreLocal(u16),
+    LoadRegister(Register),
+    StoreRegister(Register),
+    /// Pops a size and pushes a pointer to that many zeroed bytes.
+    Alloc,
+    /// Pops a pointer to the start of an allocation and frees it.
+    Free,
+    /// Pops a pointer and pushes the value stored there.
+    Load(Width),
+    /// Pops a value, then the pointer to store it at.
+    Store(Width),
+    /// Pops a source pointer, then a destination, and copies that many bytes.
+    Copy(u32),
+    /// Pops a pointer and copies the bytes of a constant there.
+    WriteConst(u32),
+    LoadContext(u16),
+    LoadContextLen(u16),
+    /// Pops a length, then a pointer, into a slot of the context.
+    StoreContext(u16),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Lt,
+    Le,
+    Jump(u32),
+    /// Pops a condition and jumps when it is zero.
+    JumpIfFalse(u32),
+    PrintInt,
+    PrintHex,
+    /// Pops a handle and prints its constant.
+    PrintStr,
+    /// Pops a pointer and prints up to that many bytes, stopping at a zero byte.
+    PrintBytes(u32),
+    PrintConst(u32),
+    /// Pops the exit code and stops.
+    Halt,
+    /// Pops a size, then a pointer to the start of an allocation, and resizes it to that many
+    /// bytes, zeroing any new ones. The pointer is pushed back.
+    Realloc,
+    /// Jumps to a method with locals, registers and context of its own, which are given back to
+    /// the caller on `Return`. The stack is shared, so it carries arguments and results.
+    Call(u32),
+    Return,
+}
+
+/// Code with the constants it refers to: text to print, string literals and raw bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Program {
+    pub constants: Vec<Vec<u8>>,
+    pub locals: u16,
+    pub code: Vec<Op>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BytecodeError {
+    NotBytecode,
+    UnsupportedVersion(u16),
+    Truncated,
+    InvalidOpcode(u8),
+    InvalidOperand(u8),
+    TrailingBytes(usize),
+    /// A jump or call to somewhere outside of the code.
+    InvalidTarget(u32),
+    UnknownLocal(u16),
+    UnknownConstant(u32),
+    UnknownSlot(u16),
+}
+
+impl Program {
+    /// The magic, version, number of locals, constants and code, with every number little endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.locals.to_le_bytes());
+        out.extend_from_slice(&(self.constants.len() as u32).to_le_bytes());
+        for constant in self.constants.iter() {
+            out.extend_from_slice(&(constant.len() as u32).to_le_bytes());
+            out.extend_from_slice(constant);
+        }
+        out.extend_from_slice(&(self.code.len() as u32).to_le_bytes());
+        for op in self.code.iter() {
+            encode_op(op, &mut out);
+        }
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Program, BytecodeError> {
+        let mut reader = Reader { bytes, index: 0 };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(BytecodeError::NotBytecode);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(BytecodeError::UnsupportedVersion(version));
+        }
+        let locals = reader.u16()?;
+        let mut constants = Vec::new();
+        for _ in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            constants.push(reader.take(len)?.to_vec());
+        }
+        let mut code = Vec::new();
+        for _ in 0..reader.u32()? {
+            code.push(decode_op(&mut reader)?);
+        }
+        let program = match bytes.len() - reader.index {
+            0 => Program {
+                constants,
+                locals,
+                code,
+            },
+            trailing => return Err(BytecodeError::TrailingBytes(trailing)),
+        };
+        program.validate()?;
+        Ok(program)
+    }
+
+    /// Checks that every operand naming a place in the program names one that exists, so that
+    /// the VM only has to check what the running program computes.
+    fn validate(&self) -> Result<(), BytecodeError> {
+        let len = self.code.len() as u64;
+        for op in self.code.iter() {
+            match *op {
+                // jumping to the end stops the program, but a method has to have code
+                Op::Jump(to) | Op::JumpIfFalse(to) if to as u64 > len => {
+                    return Err(BytecodeError::InvalidTarget(to))
+                }
+                Op::Call(to) if to as u64 >= len => return Err(BytecodeError::InvalidTarget(to)),
+                Op::LoadLocal(l) | Op::StoreLocal(l) if l >= self.locals => {
+                    return Err(BytecodeError::UnknownLocal(l))
+                }
+                Op::PushConst(c) | Op::WriteConst(c) | Op::PrintConst(c)
+                    if c as usize >= self.constants.len() =>
+                {
+                    return Err(BytecodeError::UnknownConstant(c))
+                }
+                Op::LoadContext(s) | Op::LoadContextLen(s) | Op::StoreContext(s)
+                    if s as usize >= vm::CONTEXT_SLOTS =>
+                {
+                    return Err(BytecodeError::UnknownSlot(s))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn encode_op(op: &Op, out: &mut Vec<u8>) {
+    let register = |r: &Register| REGISTERS.iter().position(|x| x == r).unwrap() as u8;
+    let width = |w: &Width, out: &mut Vec<u8>| match w {
+        Width::Int => out.push(0),
+        Width::Handle(bytes) => out.extend_from_slice(&[1, *bytes]),
+    };
+    match op {
+        Op::PushInt(i) => {
+            out.push(0);
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        Op::PushConst(c) => {
+            out.push(1);
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+        Op::LoadLocal(l) => {
+            out.push(2);
+            out.extend_from_slice(&l.to_le_bytes());
+        }
+        Op::StoreLocal(l) => {
+            out.push(3);
+            out.extend_from_slice(&l.to_le_bytes());
+        }
+        Op::LoadRegister(r) => out.extend_from_slice(&[4, register(r)]),
+        Op::StoreRegister(r) => out.extend_from_slice(&[5, register(r)]),
+        Op::Alloc => out.push(6),
+        Op::Free => out.push(7),
+        Op::Load(w) => {
+            out.push(8);
+            width(w, out);
+        }
+        Op::Store(w) => {
+            out.push(9);
+            width(w, out);
+        }
+        Op::Copy(n) => {
+            out.push(10);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        Op::WriteConst(c) => {
+            out.push(11);
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+        Op::LoadContext(s) => {
+            out.push(12);
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+        Op::LoadContextLen(s) => {
+            out.push(13);
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+        Op::StoreContext(s) => {
+            out.push(14);
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+        Op::Add => out.push(15),
+        Op::Sub => out.push(16),
+        Op::Mul => out.push(17),
+        Op::Div => out.push(18),
+        Op::Lt => out.push(19),
+        Op::Le => out.push(20),
+        Op::Jump(to) => {
+            out.push(21);
+            out.extend_from_slice(&to.to_le_bytes());
+        }
+        Op::JumpIfFalse(to) => {
+            out.push(22);
+            out.extend_from_slice(&to.to_le_bytes());
+        }
+        Op::PrintInt => out.push(23),
+        Op::PrintHex => out.push(24),
+        Op::PrintStr => out.push(25),
+        Op::PrintBytes(n) => {
+            out.push(26);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        Op::PrintConst(c) => {
+            out.push(27);
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+        Op::Halt => out.push(28),
+        Op::Mod => out.push(29),
+        Op::Realloc => out.push(30),
+        Op::Call(to) => {
+            out.push(31);
+            out.extend_from_slice(&to.to_le_bytes());
+        }
+        Op::Return => out.push(32),
+    }
+}
+
+fn decode_op(reader: &mut Reader) -> Result<Op, BytecodeError> {
+    let register = |reader: &mut Reader| -> Result<Register, BytecodeError> {
+        let r = reader.u8()?;
+        REGISTERS
+            .get(r as usize)
+            .copied()
+            .ok_or(BytecodeError::InvalidOperand(r))
+    };
+    let width = |reader: &mut Reader| -> Result<Width, BytecodeError> {
+        match reader.u8()? {
+            0 => Ok(Width::Int),
+            1 => match reader.u8()? {
+                // a handle is read into an int of 8 bytes
+                bytes @ 0..=8 => Ok(Width::Handle(bytes)),
+                bytes => Err(BytecodeError::InvalidOperand(bytes)),
+            },
+            w => Err(BytecodeError::InvalidOperand(w)),
+        }
+    };
+    Ok(match reader.u8()? {
+        0 => Op::PushInt(i64::from_le_bytes(reader.array()?)),
+        1 => Op::PushConst(reader.u32()?),
+        2 => Op::LoadLocal(reader.u16()?),
+        3 => Op::StoreLocal(reader.u16()?),
+        4 => Op::LoadRegister(register(reader)?),
+        5 => Op::StoreRegister(register(reader)?),
+        6 => Op::Alloc,
+        7 => Op::Free,
+        8 => Op::Load(width(reader)?),
+        9 => Op::Store(width(reader)?),
+        10 => Op::Copy(reader.u32()?),
+        11 => Op::WriteConst(reader.u32()?),
+        12 => Op::LoadContext(reader.u16()?),
+        13 => Op::LoadContextLen(reader.u16()?),
+        14 => Op::StoreContext(reader.u16()?),
+        15 => Op::Add,
+        16 => Op::Sub,
+        17 => Op::Mul,
+        18 => Op::Div,
+        19 => Op::Lt,
+        20 => Op::Le,
+        21 => Op::Jump(reader.u32()?),
+        22 => Op::JumpIfFalse(reader.u32()?),
+        23 => Op::PrintInt,
+        24 => Op::PrintHex,
+        25 => Op::PrintStr,
+        26 => Op::PrintBytes(reader.u32()?),
+        27 => Op::PrintConst(reader.u32()?),
+        28 => Op::Halt,
+        29 => Op::Mod,
+        30 => Op::Realloc,
+        31 => Op::Call(reader.u32()?),
+        32 => Op::Return,
+        opcode => return Err(BytecodeError::InvalidOpcode(opcode)),
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self.index.checked_add(n).ok_or(BytecodeError::Truncated)?;
+        let taken = self
+            .bytes
+            .get(self.index..end)
+            .ok_or(BytecodeError::Truncated)?;
+        self.index = end;
+        Ok(taken)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BytecodeError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoding_round_trips() {
+        let program = Program {
+            constants: vec![b"Point(".to_vec(), vec![0xe2, 0x98, 0xba, 0]],
+            locals: 2,
+            code: vec![
+                Op::PushInt(-3),
+                Op::StoreLocal(1),
+                Op::LoadRegister(Register::OutLen),
+                Op::Load(Width::Handle(8)),
+                Op::Store(Width::Int),
+                Op::JumpIfFalse(7),
+                Op::PrintConst(0),
+                Op::Call(9),
+                Op::Halt,
+                Op::Mod,
+                Op::Realloc,
+                Op::Return,
+            ],
+        };
+        let bytes = program.encode();
+        assert_eq!(&bytes[..6], b"ILBC\x01\x00");
+        assert_eq!(Program::decode(&bytes), Ok(program));
+    }
+    #[test]
+    fn test_decode_errors() {
+        let bytes = Program::default().encode();
+        assert_eq!(Program::decode(b"ELF"), Err(BytecodeError::NotBytecode));
+        let mut future = bytes.clone();
+        future[4] = 2;
+        assert_eq!(
+            Program::decode(&future),
+            Err(BytecodeError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Program::decode(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::Truncated)
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Program::decode(&trailing),
+            Err(BytecodeError::TrailingBytes(1))
+        );
+        let mut invalid = bytes;
+        let ops = invalid.len() - 4;
+        invalid[ops] = 1;
+        invalid.push(99);
+        assert_eq!(
+            Program::decode(&invalid),
+            Err(BytecodeError::InvalidOpcode(99))
+        );
+    }
+    #[test]
+    fn test_decode_validates_operands() {
+        let decode = |locals: u16, code: Vec<Op>| {
+            let program = Program {
+                constants: vec![b"x".to_vec()],
+                locals,
+                code,
+            };
+            Program::decode(&program.encode())
+        };
+        assert!(decode(1, vec![Op::LoadLocal(0), Op::PrintConst(0), Op::Jump(3)]).is_ok());
+        assert_eq!(
+            decode(0, vec![Op::Jump(2)]),
+            Err(BytecodeError::InvalidTarget(2))
+        );
+        assert_eq!(
+            decode(0, vec![Op::Call(1)]),
+            Err(BytecodeError::InvalidTarget(1))
+        );
+        assert_eq!(
+            decode(1, vec![Op::StoreLocal(1)]),
+            Err(BytecodeError::UnknownLocal(1))
+        );
+        assert_eq!(
+            decode(0, vec![Op::WriteConst(1)]),
+            Err(BytecodeError::UnknownConstant(1))
+        );
+        assert_eq!(
+            decode(0, vec![Op::StoreContext(256)]),
+            Err(BytecodeError::UnknownSlot(256))
+        );
+        assert_eq!(
+            decode(0, vec![Op::Load(Width::Handle(9))]),
+            Err(BytecodeError::InvalidOperand(9))
+        );
+    }
+}
@@ -0,0 +1,675 @@
+use crate::lang::assemble::context;
+use crate::lang::bytecode::{Op, Program, Register, Width};
+
+use std::io::Write;
+
+/// The number of slots of the context, as in the `struct __CONTEXT__` of the generated C.
+pub(crate) const CONTEXT_SLOTS: usize = context::CONTEXT_SLOTS as usize;
+/// How deeply methods may call each other before the program is stopped.
+const MAX_CALL_DEPTH: usize = 1024;
+
+/// What a program may use before it is stopped, so that bytecode read from a file cannot hang or
+/// exhaust the machine running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// How many operations may be run.
+    pub steps: u64,
+    /// How many bytes may be allocated at once.
+    pub heap_bytes: usize,
+    /// How many allocations may be live at once.
+    pub allocations: usize,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            steps: 1 << 32,
+            heap_bytes: 1 << 30,
+            allocations: 1 << 20,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum VmError {
+    StackUnderflow,
+    /// An operation was given a pointer where it expected an int, or the other way around.
+    TypeMismatch,
+    UnknownLocal(u16),
+    UnknownConstant(u32),
+    UnknownSlot(u16),
+    EmptySlot(u16),
+    InvalidJump(u32),
+    InvalidAllocation(i64),
+    /// Memory was accessed outside of its allocation, or after it was freed and before its buffer
+    /// was allocated again.
+    OutOfBounds,
+    InvalidFree,
+    DivisionByZero,
+    /// `Return` was run outside of any method.
+    ReturnWithoutCall,
+    CallDepthExceeded,
+    StepLimitExceeded,
+    HeapLimitExceeded,
+    AllocationLimitExceeded,
+    Io(String),
+}
+
+/// A value on the stack, in a local or in a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Word {
+    Int(i64),
+    Ptr { buffer: usize, offset: i64 },
+}
+
+/// What a method call puts aside to give back to its caller, like the locals of a C function.
+struct Frame {
+    return_to: usize,
+    locals: Vec<Word>,
+    registers: [Word; 4],
+    context: Vec<Option<(Word, Word)>>,
+}
+
+/// Runs a program, writing what it prints to `output`, and returns its exit code.
+pub fn execute<W: Write>(program: &Program, output: &mut W) -> Result<i32, VmError> {
+    execute_within(program, output, Limits::default())
+}
+
+/// Runs a program like `execute`, stopping it with an error once it goes past `limits`.
+pub fn execute_within<W: Write>(
+    program: &Program,
+    output: &mut W,
+    limits: Limits,
+) -> Result<i32, VmError> {
+    let mut vm = Vm::new(program, output, limits);
+    let code = vm.run();
+    vm.output.flush().map_err(|e| VmError::Io(e.to_string()))?;
+    code
+}
+
+struct Vm<'a, W: Write> {
+    program: &'a Program,
+    output: &'a mut W,
+    limits: Limits,
+    /// The bytes and number of the allocations that have not been freed.
+    heap_bytes: usize,
+    allocations: usize,
+    stack: Vec<Word>,
+    locals: Vec<Word>,
+    registers: [Word; 4],
+    heap: Vec<Option<Vec<u8>>>,
+    /// The buffers of the heap that were freed, to be allocated again.
+    freed: Vec<usize>,
+    context: Vec<Option<(Word, Word)>>,
+    frames: Vec<Frame>,
+}
+
+impl<'a, W: Write> Vm<'a, W> {
+    fn new(program: &'a Program, output: &'a mut W, limits: Limits) -> Self {
+        Vm {
+            program,
+            output,
+            limits,
+            heap_bytes: 0,
+            allocations: 0,
+            stack: Vec::new(),
+            locals: vec![Word::Int(0); program.locals as usize],
+            registers: [Word::Int(0); 4],
+            heap: Vec::new(),
+            freed: Vec::new(),
+            context: vec![None; CONTEXT_SLOTS],
+            frames: Vec::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<i32, VmError> {
+        let mut pc = 0usize;
+        let mut steps = 0u64;
+        while let Some(op) = self.program.code.get(pc) {
+            pc += 1;
+            steps += 1;
+            if steps > self.limits.steps {
+                return Err(VmError::StepLimitExceeded);
+            }
+            match op {
+                Op::PushInt(i) => self.stack.push(Word::Int(*i)),
+                Op::PushConst(c) => {
+                    self.constant(*c)?;
+                    self.stack.push(Word::Int(*c as i64));
+                }
+                Op::LoadLocal(l) => {
+                    let value = *self
+                        .locals
+                        .get(*l as usize)
+                        .ok_or(VmError::UnknownLocal(*l))?;
+                    self.stack.push(value);
+                }
+                Op::StoreLocal(l) => {
+                    let value = self.pop()?;
+                    *self
+                        .locals
+                        .get_mut(*l as usize)
+                        .ok_or(VmError::UnknownLocal(*l))? = value;
+                }
+                Op::LoadRegister(r) => self.stack.push(self.registers[register(r)]),
+                Op::StoreRegister(r) => self.registers[register(r)] = self.pop()?,
+                Op::Alloc => {
+                    let size = self.pop_int()?;
+                    if size < 0 {
+                        return Err(VmError::InvalidAllocation(size));
+                    }
+                    if self.allocations >= self.limits.allocations {
+                        return Err(VmError::AllocationLimitExceeded);
+                    }
+                    self.reserve(0, size as u64)?;
+                    self.allocations += 1;
+                    let memory = Some(vec![0; size as usize]);
+                    let buffer = match self.freed.pop() {
+                        Some(buffer) => {
+                            self.heap[buffer] = memory;
+                            buffer
+                        }
+                        None => {
+                            self.heap.push(memory);
+                            self.heap.len() - 1
+                        }
+                    };
+                    self.stack.push(Word::Ptr { buffer, offset: 0 });
+                }
+                Op::Realloc => {
+                    let size = self.pop_int()?;
+                    if size < 0 {
+                        return Err(VmError::InvalidAllocation(size));
+                    }
+                    let ptr = self.pop()?;
+                    match ptr {
+                        Word::Ptr { buffer, offset: 0 } if self.heap[buffer].is_some() => {
+                            let old = self.heap[buffer].as_ref().unwrap().len();
+                            self.reserve(old, size as u64)?;
+                            self.heap[buffer].as_mut().unwrap().resize(size as usize, 0)
+                        }
+                        _ => return Err(VmError::InvalidFree),
+                    }
+                    self.stack.push(ptr);
+                }
+                Op::Free => match self.pop()? {
+                    Word::Ptr { buffer, offset: 0 } if self.heap[buffer].is_some() => {
+                        self.heap_bytes -= self.heap[buffer].take().unwrap().len();
+                        self.allocations -= 1;
+                        self.freed.push(buffer);
+                    }
+                    _ => return Err(VmError::InvalidFree),
+                },
+                Op::Load(width) => {
+                    let ptr = self.pop()?;
+                    let bytes = self.memory(ptr, size(width))?;
+                    let value = match width {
+                        Width::Int => {
+                            i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64
+                        }
+                        Width::Handle(_) => {
+                            let mut handle = [0u8; 8];
+                            handle[..bytes.len()].copy_from_slice(bytes);
+                            i64::from_le_bytes(handle)
+                        }
+                    };
+                    self.stack.push(Word::Int(value));
+                }
+                Op::Store(width) => {
+                    let value = self.pop_int()?;
+                    let ptr = self.pop()?;
+                    let n = size(width);
+                    let bytes = match width {
+                        Width::Int => (value as i32).to_le_bytes().to_vec(),
+                        Width::Handle(_) => value.to_le_bytes()[..n].to_vec(),
+                    };
+                    self.memory_mut(ptr, n)?.copy_from_slice(&bytes);
+                }
+                Op::Copy(n) => {
+                    let source = self.pop()?;
+                    let destination = self.pop()?;
+                    let bytes = self.memory(source, *n as usize)?.to_vec();
+                    self.memory_mut(destination, bytes.len())?
+                        .copy_from_slice(&bytes);
+                }
+                Op::WriteConst(c) => {
+                    let bytes = self.constant(*c)?.to_vec();
+                    let ptr = self.pop()?;
+                    self.memory_mut(ptr, bytes.len())?.copy_from_slice(&bytes);
+                }
+                Op::LoadContext(s) => {
+                    let (ptr, _) = self.slot(*s)?;
+                    self.stack.push(ptr);
+                }
+                Op::LoadContextLen(s) => {
+                    let (_, len) = self.slot(*s)?;
+                    self.stack.push(len);
+                }
+                Op::StoreContext(s) => {
+                    let len = self.pop()?;
+                    let ptr = self.pop()?;
+                    *self
+                        .context
+                        .get_mut(*s as usize)
+                        .ok_or(VmError::UnknownSlot(*s))? = Some((ptr, len));
+                }
+                Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod | Op::Lt | Op::Le => {
+                    let b = self.pop()?;
+                    let a = self.pop()?;
+                    self.stack.push(arithmetic(op, a, b)?);
+                }
+                Op::Jump(to) => pc = self.target(*to)?,
+                Op::JumpIfFalse(to) => {
+                    if self.pop_int()? == 0 {
+                        pc = self.target(*to)?;
+                    }
+                }
+                Op::PrintInt => {
+                    let value = self.pop_int()? as i32;
+                    self.write(value.to_string().as_bytes())?;
+                }
+                Op::PrintHex => {
+                    let value = self.pop_int()? as i32 as u32;
+                    self.write(format!("{:x}", value).as_bytes())?;
+                }
+                Op::PrintStr => {
+                    let handle = self.pop_int()?;
+                    let text = self.constant(handle as u32)?.to_vec();
+                    self.write(&text)?;
+                }
+                Op::PrintBytes(n) => {
+                    let ptr = self.pop()?;
+                    let bytes = self.memory(ptr, *n as usize)?;
+                    let text = bytes
+                        .iter()
+                        .take_while(|b| **b != 0)
+                        .copied()
+                        .collect::<Vec<u8>>();
+                    self.write(&text)?;
+                }
+                Op::PrintConst(c) => {
+                    let text = self.constant(*c)?.to_vec();
+                    self.write(&text)?;
+                }
+                Op::Halt => return Ok(self.pop_int()? as i32),
+                Op::Call(to) => {
+                    if self.frames.len() >= MAX_CALL_DEPTH {
+                        return Err(VmError::CallDepthExceeded);
+                    }
+                    let locals = vec![Word::Int(0); self.program.locals as usize];
+                    let context = vec![None; CONTEXT_SLOTS];
+                    self.frames.push(Frame {
+                        return_to: pc,
+                        locals: std::mem::replace(&mut self.locals, locals),
+                        registers: self.registers,
+                        context: std::mem::replace(&mut self.context, context),
+                    });
+                    pc = self.target(*to)?;
+                }
+                Op::Return => {
+                    let frame = self.frames.pop().ok_or(VmError::ReturnWithoutCall)?;
+                    pc = frame.return_to;
+                    self.locals = frame.locals;
+                    self.registers = frame.registers;
+                    self.context = frame.context;
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    fn pop(&mut self) -> Result<Word, VmError> {
+        self.stack.pop().ok_or(VmError::StackUnderflow)
+    }
+    fn pop_int(&mut self) -> Result<i64, VmError> {
+        match self.pop()? {
+            Word::Int(i) => Ok(i),
+            Word::Ptr { .. } => Err(VmError::TypeMismatch),
+        }
+    }
+    fn constant(&self, c: u32) -> Result<&'a [u8], VmError> {
+        match self.program.constants.get(c as usize) {
+            Some(constant) => Ok(constant),
+            None => Err(VmError::UnknownConstant(c)),
+        }
+    }
+    fn slot(&self, s: u16) -> Result<(Word, Word), VmError> {
+        match self.context.get(s as usize) {
+            Some(Some(slot)) => Ok(*slot),
+            Some(None) => Err(VmError::EmptySlot(s)),
+            None => Err(VmError::UnknownSlot(s)),
+        }
+    }
+    fn target(&self, to: u32) -> Result<usize, VmError> {
+        if to as usize <= self.program.code.len() {
+            Ok(to as usize)
+        } else {
+            Err(VmError::InvalidJump(to))
+        }
+    }
+    /// Accounts for an allocation of `old` bytes growing to `new`, if that fits in the heap.
+    fn reserve(&mut self, old: usize, new: u64) -> Result<(), VmError> {
+        let bytes = (self.heap_bytes - old) as u64 + new;
+        if bytes > self.limits.heap_bytes as u64 {
+            return Err(VmError::HeapLimitExceeded);
+        }
+        self.heap_bytes = bytes as usize;
+        Ok(())
+    }
+    fn write(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        self.output
+            .write_all(bytes)
+            .map_err(|e| VmError::Io(e.to_string()))
+    }
+
+    /// The range of an allocation `n` bytes long starting at `ptr`.
+    fn bounds(&self, ptr: Word, n: usize) -> Result<(usize, std::ops::Range<usize>), VmError> {
+        let (buffer, offset) = match ptr {
+            Word::Ptr { buffer, offset } => (buffer, offset),
+            Word::Int(_) => return Err(VmError::TypeMismatch),
+        };
+        let len = match self.heap.get(buffer) {
+            Some(Some(memory)) => memory.len(),
+            _ => return Err(VmError::OutOfBounds),
+        };
+        let end = (offset as usize).checked_add(n);
+        match end {
+            Some(end) if offset >= 0 && end <= len => Ok((buffer, offset as usize..end)),
+            _ => Err(VmError::OutOfBounds),
+        }
+    }
+    fn memory(&self, ptr: Word, n: usize) -> Result<&[u8], VmError> {
+        let (buffer, range) = self.bounds(ptr, n)?;
+        Ok(&self.heap[buffer].as_ref().unwrap()[range])
+    }
+    fn memory_mut(&mut self, ptr: Word, n: usize) -> Result<&mut [u8], VmError> {
+        let (buffer, range) = self.bounds(ptr, n)?;
+        Ok(&mut self.heap[buffer].as_mut().unwrap()[range])
+    }
+}
+
+fn register(r: &Register) -> usize {
+    match r {
+        Register::In => 0,
+        Register::Out => 1,
+        Register::Len => 2,
+        Register::OutLen => 3,
+    }
+}
+
+fn size(width: &Width) -> usize {
+    match width {
+        Width::Int => 4,
+        Width::Handle(bytes) => *bytes as usize,
+    }
+}
+
+/// Ints wrap like those of C. A pointer may be moved by an int, and two pointers into the same
+/// allocation may be subtracted and compared.
+fn arithmetic(op: &Op, a: Word, b: Word) -> Result<Word, VmError> {
+    let compare = |a: i64, b: i64| -> i64 {
+        match op {
+            Op::Lt => (a < b) as i64,
+            _ => (a <= b) as i64,
+        }
+    };
+    Ok(match (op, a, b) {
+        (Op::Add, Word::Int(a), Word::Int(b)) => Word::Int(a.wrapping_add(b)),
+        (Op::Sub, Word::Int(a), Word::Int(b)) => Word::Int(a.wrapping_sub(b)),
+        (Op::Mul, Word::Int(a), Word::Int(b)) => Word::Int(a.wrapping_mul(b)),
+        (Op::Div | Op::Mod, Word::Int(_), Word::Int(0)) => return Err(VmError::DivisionByZero),
+        // quotients of wrapped ints differ from those of wide ones, so divide as C ints do
+        (Op::Div, Word::Int(a), Word::Int(b)) => {
+            Word::Int((a as i32).wrapping_div(b as i32) as i64)
+        }
+        (Op::Mod, Word::Int(a), Word::Int(b)) => {
+            Word::Int((a as i32).wrapping_rem(b as i32) as i64)
+        }
+        (Op::Lt, Word::Int(a), Word::Int(b)) | (Op::Le, Word::Int(a), Word::Int(b)) => {
+            Word::Int(compare(a, b))
+        }
+        (Op::Add, Word::Ptr { buffer, offset }, Word::Int(i))
+        | (Op::Add, Word::Int(i), Word::Ptr { buffer, offset }) => Word::Ptr {
+            buffer,
+            offset: offset.wrapping_add(i),
+        },
+        (Op::Sub, Word::Ptr { buffer, offset }, Word::Int(i)) => Word::Ptr {
+            buffer,
+            offset: offset.wrapping_sub(i),
+        },
+        (
+            Op::Sub,
+            Word::Ptr {
+                buffer: x,
+                offset: a,
+            },
+            Word::Ptr {
+                buffer: y,
+                offset: b,
+            },
+        ) if x == y => Word::Int(a.wrapping_sub(b)),
+        (
+            Op::Lt,
+            Word::Ptr {
+                buffer: x,
+                offset: a,
+            },
+            Word::Ptr {
+                buffer: y,
+                offset: b,
+            },
+        )
+        | (
+            Op::Le,
+            Word::Ptr {
+                buffer: x,
+                offset: a,
+            },
+            Word::Ptr {
+                buffer: y,
+                offset: b,
+            },
+        ) if x == y => Word::Int(compare(a, b)),
+        _ => return Err(VmError::TypeMismatch),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::assemble::{assemble_root, AssemblyError};
+    use crate::lang::bytecode::compile::compile;
+    use crate::lang::interp;
+    use crate::parse::token::parse_tokens;
+
+    use std::fs;
+
+    #[test]
+    fn test_resources_match_interpreter() -> Result<(), AssemblyError> {
+        let mut compared = 0;
+        for entry in fs::read_dir("res/test").unwrap() {
+            let source = fs::read(entry.unwrap().path()).unwrap();
+            let tokens = parse_tokens(&source[..]).map_err(AssemblyError::TokenParseError)?;
+            let types = assemble_root(&tokens)?;
+            let program = Program::decode(&compile(&types)?.encode()).unwrap();
+            let mut output = Vec::new();
+            assert_eq!(execute(&program, &mut output), Ok(0));
+            let mut expected = Vec::new();
+            interp::run(&source, &mut expected).unwrap();
+            assert_eq!(String::from_utf8(output), String::from_utf8(expected));
+            compared += 1;
+        }
+        assert!(compared >= 10);
+        Ok(())
+    }
+    #[test]
+    fn test_errors() {
+        let run = |code: Vec<Op>| {
+            let program = Program {
+                constants: Vec::new(),
+                locals: 0,
+                code,
+            };
+            execute(&program, &mut Vec::new())
+        };
+        assert_eq!(run(vec![Op::Add]), Err(VmError::StackUnderflow));
+        assert_eq!(
+            run(vec![Op::PushInt(4), Op::Alloc, Op::Load(Width::Handle(8))]),
+            Err(VmError::OutOfBounds)
+        );
+        assert_eq!(
+            run(vec![
+                Op::PushInt(4),
+                Op::Alloc,
+                Op::PushInt(1),
+                Op::Add,
+                Op::Free
+            ]),
+            Err(VmError::InvalidFree)
+        );
+        assert_eq!(
+            run(vec![Op::PushInt(1), Op::PushInt(0), Op::Div]),
+            Err(VmError::DivisionByZero)
+        );
+        assert_eq!(run(vec![Op::LoadContext(3)]), Err(VmError::EmptySlot(3)));
+        assert_eq!(run(vec![Op::PushInt(7), Op::Halt]), Ok(7));
+        assert_eq!(run(vec![Op::Return]), Err(VmError::ReturnWithoutCall));
+        assert_eq!(run(vec![Op::Call(0)]), Err(VmError::CallDepthExceeded));
+    }
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            steps: 100,
+            heap_bytes: 16,
+            allocations: 2,
+        };
+        let run = |code: Vec<Op>| {
+            let program = Program {
+                constants: Vec::new(),
+                locals: 0,
+                code,
+            };
+            execute_within(&program, &mut Vec::new(), limits)
+        };
+        assert_eq!(run(vec![Op::Jump(0)]), Err(VmError::StepLimitExceeded));
+        assert_eq!(
+            run(vec![Op::PushInt(17), Op::Alloc]),
+            Err(VmError::HeapLimitExceeded)
+        );
+        assert_eq!(
+            run(vec![
+                Op::PushInt(8),
+                Op::Alloc,
+                Op::PushInt(8),
+                Op::Alloc,
+                Op::PushInt(9),
+                Op::Realloc
+            ]),
+            Err(VmError::HeapLimitExceeded)
+        );
+        assert_eq!(
+            run(vec![
+                Op::PushInt(0),
+                Op::Alloc,
+                Op::PushInt(0),
+                Op::Alloc,
+                Op::PushInt(0),
+                Op::Alloc
+            ]),
+            Err(VmError::AllocationLimitExceeded)
+        );
+        // what is freed may be allocated again
+        let code = vec![Op::PushInt(16), Op::Alloc, Op::Free, Op::Jump(0)];
+        assert_eq!(run(code), Err(VmError::StepLimitExceeded));
+    }
+    #[test]
+    fn test_freed_buffers_are_reused() {
+        // allocating and freeing forever once held on to a slot of the heap for every allocation
+        let bytes = Program {
+            constants: Vec::new(),
+            locals: 0,
+            code: vec![Op::PushInt(0), Op::Alloc, Op::Free, Op::Jump(0)],
+        }
+        .encode();
+        let program = Program::decode(&bytes).unwrap();
+        let mut output = Vec::new();
+        let limits = Limits {
+            steps: 10_000,
+            ..Limits::default()
+        };
+        let mut vm = Vm::new(&program, &mut output, limits);
+        assert_eq!(vm.run(), Err(VmError::StepLimitExceeded));
+        assert_eq!(vm.heap.len(), 1);
+    }
+    #[test]
+    fn test_corrupted_programs() -> Result<(), AssemblyError> {
+        // xorshift, so that every run corrupts the programs the same way
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move |below: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % below as u64) as usize
+        };
+        let limits = Limits {
+            steps: 10_000,
+            heap_bytes: 1 << 16,
+            allocations: 64,
+        };
+        for entry in fs::read_dir("res/test").unwrap() {
+            let source = fs::read(entry.unwrap().path()).unwrap();
+            let tokens = parse_tokens(&source[..]).map_err(AssemblyError::TokenParseError)?;
+            let bytes = compile(&assemble_root(&tokens)?)?.encode();
+            for _ in 0..200 {
+                let mut corrupted = bytes.clone();
+                for _ in 0..1 + random(4) {
+                    match random(3) {
+                        0 => corrupted.truncate(random(bytes.len())),
+                        _ if corrupted.is_empty() => {}
+                        _ => {
+                            let at = random(corrupted.len());
+                            corrupted[at] = random(256) as u8;
+                        }
+                    }
+                }
+                // any error will do, so long as nothing panics
+                if let Ok(program) = Program::decode(&corrupted) {
+                    let _ = execute_within(&program, &mut Vec::new(), limits);
+                }
+            }
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_calls() {
+        // the method doubles its argument and scribbles over the registers and context it has
+        let program = Program {
+            constants: Vec::new(),
+            locals: 1,
+            code: vec![
+                Op::PushInt(4),
+                Op::Alloc,
+                Op::StoreRegister(Register::In),
+                Op::PushInt(21),
+                Op::Call(12),
+                Op::LoadRegister(Register::In),
+                Op::PushInt(8),
+                Op::Realloc,
+                Op::Free,
+                Op::PushInt(-7),
+                Op::Mod,
+                Op::Halt,
+                Op::PushInt(2),
+                Op::Mul,
+                Op::PushInt(0),
+                Op::StoreRegister(Register::In),
+                Op::Return,
+            ],
+        };
+        assert_eq!(execute(&program, &mut Vec::new()), Ok(42 % -7));
+        let program = Program {
+            code: vec![Op::PushInt(43), Op::PushInt(-7), Op::Mod, Op::Halt],
+            ..program
+        };
+        assert_eq!(execute(&program, &mut Vec::new()), Ok(1));
+    }
+}
@@ -0,0 +1,436 @@
+use crate::lang::assemble::context::CONTEXT_SLOTS;
+use crate::lang::assemble::AssemblyError;
+use crate::lang::bytecode::vm::VmError;
+use crate::lang::bytecode::BytecodeError;
+use crate::lang::interp::InterpError;
+use crate::parse::lex::LexParseError;
+use crate::parse::token::TokenParseError;
+
+use std::fmt;
+
+/// Byte offsets into a source, `end` exclusive. Errors reported at a single position have
+/// `start` and `end` equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+impl Span {
+    pub fn at(position: usize) -> Span {
+        Span {
+            start: position,
+            end: position,
+        }
+    }
+}
+
+/// An error of any stage in a form tools can show: a stable code naming the stage and the kind
+/// of error, a message for people, where it is when that is known, and further notes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: String,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+}
+impl Diagnostic {
+    fn new(stage: &str, error: &dyn fmt::Debug, message: String, at: Option<usize>) -> Diagnostic {
+        Diagnostic {
+            code: format!("{}::{}", stage, variant(error)),
+            message,
+            span: at.map(Span::at),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn from_lex(err: &LexParseError) -> Diagnostic {
+        let (message, at) = match err {
+            LexParseError::QuoteNotEnded(pos) => ("string is never closed".to_string(), Some(pos)),
+            LexParseError::NoCharBetweenSingleQuotes(pos) => {
+                ("char literal is empty".to_string(), Some(pos))
+            }
+            LexParseError::MultipleCharsBetweenSingleQuotes(pos) => (
+                "char literal holds more than one char".to_string(),
+                Some(pos),
+            ),
+            LexParseError::InvalidCharacter(pos) => ("unexpected character".to_string(), Some(pos)),
+            LexParseError::CommentNotEnded(pos) => {
+                ("comment is never closed".to_string(), Some(pos))
+            }
+            LexParseError::InvalidEscape(pos, c) => {
+                (format!("unknown escape `\\{}`", c), Some(pos))
+            }
+            LexParseError::InvalidUnicodeEscape(pos) => {
+                ("invalid unicode escape".to_string(), Some(pos))
+            }
+            LexParseError::InvalidUtf8(pos) => ("source is not valid UTF-8".to_string(), Some(pos)),
+            LexParseError::InvalidNumber(pos) => ("invalid number".to_string(), Some(pos)),
+            LexParseError::InvalidNumberSuffix(pos) => {
+                ("unknown number suffix".to_string(), Some(pos))
+            }
+            LexParseError::NumberOverflow(pos) => {
+                ("number does not fit its type".to_string(), Some(pos))
+            }
+            LexParseError::Io(message) => (message.clone(), None),
+        };
+        Diagnostic::new("lex", err, message, at.copied())
+    }
+
+    pub fn from_token(err: &TokenParseError) -> Diagnostic {
+        let (message, at) = match err {
+            TokenParseError::Lex(err) => return Diagnostic::from_lex(err),
+            TokenParseError::UnmatchedBrace(pos) => (
+                "closing brace has no matching open brace".to_string(),
+                Some(pos),
+            ),
+            TokenParseError::UnclosedBrace(pos) => ("brace is never closed".to_string(), Some(pos)),
+            TokenParseError::ContextTriedToEscapeRootScope => {
+                ("more scopes closed than opened".to_string(), None)
+            }
+            TokenParseError::UnsupportedTokenizerOperator => {
+                ("operator cannot be used here".to_string(), None)
+            }
+            TokenParseError::UnsupportedKeywordToken(keyword) => (
+                format!("`{}` cannot be used here", keyword.spelling()),
+                None,
+            ),
+            TokenParseError::FirstArgumentIsNotAnIdentifier => {
+                ("expected an identifier first".to_string(), None)
+            }
+            TokenParseError::Stalled => ("could not read any further".to_string(), None),
+            TokenParseError::ExpectedOperandButFoundNone => {
+                ("operator is missing an operand".to_string(), None)
+            }
+            TokenParseError::OperatorIsNotUniary(op, pos) => (
+                format!("{:?} cannot be used as a unary operator", op),
+                Some(pos),
+            ),
+            TokenParseError::ParsedCloseBraceAtIncorrectScopeLevel(found, expected) => (
+                format!(
+                    "brace closed at level {} in a scope at level {}",
+                    found, expected
+                ),
+                None,
+            ),
+            TokenParseError::ExpectedBraceButFoundOtherBrace(expected, found) => (
+                format!("expected {:?} but found {:?}", expected, found),
+                None,
+            ),
+            TokenParseError::AttemptedToParseCloseBraceWithoutOpen(brace) => {
+                (format!("{:?} closed without being opened", brace), None)
+            }
+            TokenParseError::AttemptedToRetrieveScopeFromABraceStateOfNone => {
+                ("expected a braced scope".to_string(), None)
+            }
+            TokenParseError::ExpectedIdentifierNameAfterTypeDef(pos) => {
+                ("expected a name after `type`".to_string(), Some(pos))
+            }
+            TokenParseError::ExpectedColonAfterTypeDefIdentifier(pos) => {
+                ("expected `:` after the type name".to_string(), Some(pos))
+            }
+            TokenParseError::ExpectedBraceAfterTypeDef(pos, found) => (
+                format!("expected the body of the type, found {}", found),
+                Some(pos),
+            ),
+            TokenParseError::ExpectedImplBodyAfterImplDef(pos) => {
+                ("expected a body after `impl`".to_string(), Some(pos))
+            }
+            TokenParseError::TypeRequiresNameAndClassifier => {
+                ("a type needs a name and a body".to_string(), None)
+            }
+            TokenParseError::ExpectedBodyAfterMethodSignature => (
+                "expected a body after the method signature".to_string(),
+                None,
+            ),
+            TokenParseError::ExpectedParametersAfterMethodName => (
+                "expected parameters after the method name".to_string(),
+                None,
+            ),
+            TokenParseError::ExpectedMethodName => ("expected a method name".to_string(), None),
+            TokenParseError::ExpectedVoidIdentifier => ("expected `()`".to_string(), None),
+            TokenParseError::ExpectedIdentifier => ("expected an identifier".to_string(), None),
+            TokenParseError::CannotUseKeywordAsIdentifier(keyword) => (
+                format!(
+                    "keyword `{}` cannot be used as an identifier",
+                    keyword.spelling()
+                ),
+                None,
+            ),
+            TokenParseError::CannotUseNumberAsIdentifier(number) => (
+                format!("number {:?} cannot be used as an identifier", number),
+                None,
+            ),
+            TokenParseError::CannotUseStringAsIdentifier(text) => (
+                format!("string {:?} cannot be used as an identifier", text),
+                None,
+            ),
+            TokenParseError::ExpectedBindingValue(pos) => {
+                ("expected a value to bind".to_string(), Some(pos))
+            }
+            TokenParseError::UnmatchedInterpolationBrace(pos) => (
+                "brace in an interpolated string has no match".to_string(),
+                Some(pos),
+            ),
+            TokenParseError::ExpectedInterpolatedExpression(pos) => (
+                "expected an expression between the braces".to_string(),
+                Some(pos),
+            ),
+            TokenParseError::ExpectedBlock => ("expected a block".to_string(), None),
+            TokenParseError::ExpectedTypeName => ("expected a type name".to_string(), None),
+            TokenParseError::ExpectedField => ("expected a field".to_string(), None),
+        };
+        Diagnostic::new("token", err, message, at.copied())
+    }
+
+    /// The diagnostics of an assembly error, one for each identifier that could not be resolved.
+    pub fn from_assembly(err: &AssemblyError) -> Vec<Diagnostic> {
+        let message = match err {
+            AssemblyError::TokenParseError(err) => return vec![Diagnostic::from_token(err)],
+            AssemblyError::UnresolvedIdentifiers(unresolved) => {
+                return unresolved
+                    .iter()
+                    .map(|u| {
+                        let mut diagnostic = Diagnostic::new(
+                            "assembly",
+                            err,
+                            format!("cannot find `{}` in this scope", u.name),
+                            None,
+                        );
+                        diagnostic.span = u.span;
+                        if let Some(suggestion) = &u.suggestion {
+                            diagnostic
+                                .notes
+                                .push(format!("did you mean `{}`?", suggestion));
+                        }
+                        diagnostic
+                    })
+                    .collect();
+            }
+            AssemblyError::TypeNotFound => "type not found".to_string(),
+            AssemblyError::TriedToAddVariableToData => "data types cannot have fields".to_string(),
+            AssemblyError::NoStartMethodFound => "no `calc start` method".to_string(),
+            AssemblyError::ExpectedBlock => "expected a block".to_string(),
+            AssemblyError::ExpectedTypeParameter => "type parameters must be names".to_string(),
+            AssemblyError::WrongNumberOfTypeArguments(name, expected, found) => format!(
+                "`{}` takes {} type arguments but {} were given",
+                name, expected, found
+            ),
+            AssemblyError::UnboundTypeParameter(name) => {
+                format!("type parameter `{}` is not bound by the intake", name)
+            }
+            AssemblyError::WrongNumberOfArguments(name, expected, found) => format!(
+                "`{}` takes {} arguments but {} were given",
+                name, expected, found
+            ),
+            AssemblyError::TypeMismatch(expected, found) => {
+                format!("expected `{}`, found `{}`", expected, found)
+            }
+            AssemblyError::InvalidRangeStep(step) => {
+                format!("range step must be positive, found {}", step)
+            }
+            AssemblyError::UnsupportedExpression => "expression is not supported".to_string(),
+            AssemblyError::UnsupportedStage(name) => {
+                format!("`{}` cannot be used as a stage", name)
+            }
+            AssemblyError::MethodNotFound(type_name, name) => {
+                format!("`{}` has no method `{}`", type_name, name)
+            }
+            AssemblyError::RecursiveMethod(name) => {
+                format!("`{}` calls itself, which is not supported", name)
+            }
+            AssemblyError::UnknownIdentifier(name) => format!("unknown identifier `{}`", name),
+            AssemblyError::BindingUsedBeforeDefinition(name) => {
+                format!("`{}` is used before it is bound", name)
+            }
+            AssemblyError::BindingRedefined(name) => format!("`{}` is already bound", name),
+            AssemblyError::TypeRedefined(name) => format!("type `{}` is already declared", name),
+            AssemblyError::TypeParameterRedefined(name) => {
+                format!("type parameter `{}` is listed twice", name)
+            }
+            AssemblyError::TooManyBindings(name) => format!(
+                "cannot bind `{}`, a method may bind at most {} names",
+                name, CONTEXT_SLOTS
+            ),
+            AssemblyError::CannotBindVoid(name) => format!("cannot bind `{}` to nothing", name),
+            AssemblyError::NotPrintable(name) => format!("`{}` cannot be printed", name),
+            AssemblyError::NotSingleValue(name) => format!(
+                "`{}` may hold more than one element, only a single value can be interpolated",
+                name
+            ),
+            AssemblyError::UnsupportedInstruction(name) => {
+                format!("`{}` has no bytecode", name)
+            }
+            AssemblyError::Io(message) => message.clone(),
+        };
+        vec![Diagnostic::new("assembly", err, message, None)]
+    }
+
+    pub fn from_interp(err: &InterpError) -> Vec<Diagnostic> {
+        let message = match err {
+            InterpError::Assembly(err) => return Diagnostic::from_assembly(err),
+            InterpError::UnknownIdentifier(name) => format!("unknown identifier `{}`", name),
+            InterpError::UnknownMethod(type_name, name) => {
+                format!("`{}` has no method `{}`", type_name, name)
+            }
+            InterpError::TypeNotFound(name) => format!("type `{}` not found", name),
+            InterpError::WrongNumberOfArguments(name, expected, found) => format!(
+                "`{}` takes {} arguments but {} were given",
+                name, expected, found
+            ),
+            InterpError::TypeMismatch(expected, found) => {
+                format!("expected `{}`, found `{}`", expected, found)
+            }
+            InterpError::ExpectedValue => "expected a value".to_string(),
+            InterpError::DivisionByZero => "division by zero".to_string(),
+            InterpError::InvalidRangeStep(step) => {
+                format!("range step must be positive, found {}", step)
+            }
+            InterpError::CannotBindVoid(name) => format!("cannot bind `{}` to nothing", name),
+            InterpError::UnsupportedExpression => "expression is not supported".to_string(),
+            InterpError::Io(message) => message.clone(),
+        };
+        vec![Diagnostic::new("interp", err, message, None)]
+    }
+
+    pub fn from_bytecode(err: &BytecodeError) -> Diagnostic {
+        let message = match err {
+            BytecodeError::NotBytecode => "not a bytecode file".to_string(),
+            BytecodeError::UnsupportedVersion(version) => {
+                format!("bytecode version {} is not supported", version)
+            }
+            BytecodeError::Truncated => "bytecode ends early".to_string(),
+            BytecodeError::InvalidOpcode(opcode) => format!("unknown opcode {}", opcode),
+            BytecodeError::InvalidOperand(operand) => format!("invalid operand {}", operand),
+            BytecodeError::TrailingBytes(count) => {
+                format!("{} bytes follow the end of the code", count)
+            }
+            BytecodeError::InvalidTarget(target) => {
+                format!("jump to {}, outside of the code", target)
+            }
+            BytecodeError::UnknownLocal(local) => format!("there is no local {}", local),
+            BytecodeError::UnknownConstant(constant) => {
+                format!("there is no constant {}", constant)
+            }
+            BytecodeError::UnknownSlot(slot) => format!("there is no context slot {}", slot),
+        };
+        Diagnostic::new("bytecode", err, message, None)
+    }
+
+    pub fn from_vm(err: &VmError) -> Diagnostic {
+        let message = match err {
+            VmError::StackUnderflow => "the stack is empty".to_string(),
+            VmError::TypeMismatch => "an int and a pointer were mixed up".to_string(),
+            VmError::UnknownLocal(local) => format!("there is no local {}", local),
+            VmError::UnknownConstant(constant) => format!("there is no constant {}", constant),
+            VmError::UnknownSlot(slot) => format!("there is no context slot {}", slot),
+            VmError::EmptySlot(slot) => format!("context slot {} is empty", slot),
+            VmError::InvalidJump(target) => format!("jump to {}, outside of the code", target),
+            VmError::InvalidAllocation(size) => format!("cannot allocate {} bytes", size),
+            VmError::OutOfBounds => "memory accessed out of bounds".to_string(),
+            VmError::InvalidFree => "memory freed twice or never allocated".to_string(),
+            VmError::DivisionByZero => "division by zero".to_string(),
+            VmError::ReturnWithoutCall => "return outside of any method".to_string(),
+            VmError::CallDepthExceeded => "too many nested calls".to_string(),
+            VmError::StepLimitExceeded => "the program ran for too many steps".to_string(),
+            VmError::HeapLimitExceeded => "the program allocated too much memory".to_string(),
+            VmError::AllocationLimitExceeded => "the program made too many allocations".to_string(),
+            VmError::Io(message) => message.clone(),
+        };
+        Diagnostic::new("vm", err, message, None)
+    }
+
+    /// `file:line:column: error[code]: message`, counting lines and columns of `source` from 1,
+    /// or `file: error[code]: message` when the position is not known.
+    pub fn located(&self, file: &str, source: &[u8]) -> String {
+        let mut located = match self.span {
+            Some(span) => {
+                let before = &source[..span.start.min(source.len())];
+                let line_start = before
+                    .iter()
+                    .rposition(|&b| b == b'\n')
+                    .map_or(0, |i| i + 1);
+                let column = String::from_utf8_lossy(&before[line_start..])
+                    .chars()
+                    .count();
+                let line = before.iter().filter(|&&b| b == b'\n').count();
+                format!("{}:{}:{}: ", file, line + 1, column + 1)
+            }
+            None => format!("{}: ", file),
+        };
+        located.push_str(&format!("error[{}]: {}", self.code, self.message));
+        for note in self.notes.iter() {
+            located.push_str(&format!("\n  note: {}", note));
+        }
+        located
+    }
+}
+
+/// `error[code]: message`, with the position when it is known and each note on a line below.
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error[{}]: {}", self.code, self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at {}", span.start)?;
+        }
+        for note in self.notes.iter() {
+            write!(f, "\n  note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// The name of the enum variant of `value`.
+fn variant(value: &dyn fmt::Debug) -> String {
+    let debug = format!("{:?}", value);
+    debug
+        .split(['(', ' ', '{'])
+        .next()
+        .unwrap_or("")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::assemble::resolve::UnresolvedIdentifier;
+    use crate::parse::token::parse_tokens;
+
+    #[test]
+    fn test_diagnostics() {
+        let err = match parse_tokens(b"{}}") {
+            Err(err) => err,
+            Ok(_) => panic!("parsed an unmatched brace"),
+        };
+        let diagnostic = Diagnostic::from_token(&err);
+        assert_eq!(diagnostic.code, "token::UnmatchedBrace");
+        assert_eq!(diagnostic.span, Some(Span::at(3)));
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[token::UnmatchedBrace]: closing brace has no matching open brace at 3"
+        );
+        let err =
+            AssemblyError::TokenParseError(TokenParseError::Lex(LexParseError::QuoteNotEnded(2)));
+        assert_eq!(
+            Diagnostic::from_assembly(&err)[0].code,
+            "lex::QuoteNotEnded"
+        );
+        let err = AssemblyError::UnresolvedIdentifiers(vec![UnresolvedIdentifier {
+            name: "pritn".to_string(),
+            scope: 1,
+            span: Some(Span { start: 4, end: 9 }),
+            suggestion: Some("print".to_string()),
+        }]);
+        assert_eq!(
+            Diagnostic::from_assembly(&err)[0].to_string(),
+            "error[assembly::UnresolvedIdentifiers]: cannot find `pritn` in this scope at 4\n  note: did you mean `print`?"
+        );
+        assert_eq!(
+            Diagnostic::from_assembly(&err)[0].located("a.geo", b"cal\npritn"),
+            "a.geo:2:1: error[assembly::UnresolvedIdentifiers]: cannot find `pritn` in this scope\n  note: did you mean `print`?"
+        );
+        assert_eq!(
+            Diagnostic::from_vm(&VmError::DivisionByZero).located("a.geo", b""),
+            "a.geo: error[vm::DivisionByZero]: division by zero"
+        );
+    }
+}
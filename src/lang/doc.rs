@@ -0,0 +1,374 @@
+use crate::lang::assemble::context::AssembledTypeContext;
+use crate::lang::assemble::{assemble_types, AssemblyError};
+use crate::parse::constant::Brace;
+use crate::parse::context::TokenizerContext;
+use crate::parse::token::{parse_tokens, Literal, Token};
+
+use std::fs;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+struct TypeDoc {
+    name: String,
+    parameters: Vec<String>,
+    docs: Option<String>,
+    fields: Vec<(String, String)>, //Name, Type
+    layout: Vec<(String, u16)>,    //Type or instance name, Bytes
+    methods: Vec<MethodDoc>,
+}
+
+struct MethodDoc {
+    signature: String,
+    intake: String,
+    docs: Option<String>,
+}
+
+/// Parses and assembles `filename`, then documents its declarations.
+pub fn document(filename: String, format: DocFormat) -> Result<String, AssemblyError> {
+    let contents = fs::read_to_string(&filename)
+        .map_err(|e| AssemblyError::Io(format!("{}: {}", filename, e)))?;
+    let tokens = parse_tokens(contents.as_bytes()).map_err(AssemblyError::TokenParseError)?;
+    let types = assemble_types(&tokens)?;
+    let mut out = Vec::new();
+    write_docs(&tokens, &types, format, &mut out).map_err(|e| AssemblyError::Io(e.to_string()))?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Writes every type of the root scope with its fields, layout size, methods and `///` docs,
+/// followed by the methods whose intake is not a declared type.
+pub fn write_docs<W: Write>(
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+    format: DocFormat,
+    out: &mut W,
+) -> io::Result<()> {
+    let (type_docs, methods) = collect(tokens, types);
+    match format {
+        DocFormat::Markdown => write_markdown(&type_docs, &methods, out),
+        DocFormat::Html => write_html(&type_docs, &methods, out),
+    }
+}
+
+fn collect(
+    tokens: &TokenizerContext,
+    types: &AssembledTypeContext,
+) -> (Vec<TypeDoc>, Vec<MethodDoc>) {
+    let mut type_docs = Vec::new();
+    let mut methods = Vec::new();
+    for t in tokens.get_scope(0).unwrap().get_tokens().iter() {
+        match t.as_ref() {
+            Token::TypeDef(Literal::Identifier(name), parameters, body) => {
+                let parameters = parameters
+                    .as_ref()
+                    .map(|p| scope_sources(p, tokens))
+                    .unwrap_or_default();
+                let layout = if parameters.is_empty() {
+                    types
+                        .get_type_id(name)
+                        .and_then(|id| types.get_type(id))
+                        .map(|t| vec![(t.get_name(), t.get_bytes(types))])
+                        .unwrap_or_default()
+                } else {
+                    types
+                        .get_instances_of(name)
+                        .into_iter()
+                        .filter_map(|(id, _)| types.get_type(&id))
+                        .map(|t| (t.get_name(), t.get_bytes(types)))
+                        .collect()
+                };
+                type_docs.push(TypeDoc {
+                    name: name.clone(),
+                    parameters,
+                    docs: docs_of(body, tokens),
+                    fields: fields_of(body, tokens),
+                    layout,
+                    methods: Vec::new(),
+                });
+            }
+            Token::MethodDef(mode, name, parameters, intake, body) => {
+                let mut signature = format!("{} {}", mode.spelling(), name);
+                if let Some(parameters) = parameters {
+                    signature.push_str(&format!(
+                        "<{}>",
+                        scope_sources(parameters, tokens).join(", ")
+                    ));
+                }
+                signature.push_str(&format!(": {}", source_of(intake, tokens)));
+                methods.push(MethodDoc {
+                    signature,
+                    intake: intake_name(intake),
+                    docs: docs_of(body, tokens),
+                });
+            }
+            _ => {}
+        }
+    }
+    let mut free = Vec::new();
+    for method in methods.into_iter() {
+        match type_docs.iter_mut().find(|t| t.name == method.intake) {
+            Some(type_doc) => type_doc.methods.push(method),
+            None => free.push(method),
+        }
+    }
+    (type_docs, free)
+}
+
+fn docs_of(body: &Token, tokens: &TokenizerContext) -> Option<String> {
+    match body {
+        Token::Block(_, scope) => tokens.get_docs(*scope).cloned(),
+        _ => None,
+    }
+}
+
+fn fields_of(body: &Token, tokens: &TokenizerContext) -> Vec<(String, String)> {
+    let scope = match body {
+        Token::Block(_, scope) => *scope,
+        _ => return Vec::new(),
+    };
+    let mut fields = Vec::new();
+    for field in tokens.get_scope(scope).unwrap().get_tokens().iter() {
+        match field.as_ref() {
+            Token::VariableDef(Literal::Identifier(type_name), name) => {
+                fields.push((name.clone(), type_name.clone()))
+            }
+            Token::Constructor(type_name, names) => {
+                for name in scope_sources(names, tokens).into_iter() {
+                    fields.push((name, type_name.clone()));
+                }
+            }
+            Token::GenericConstructor(type_name, arguments, names) => {
+                let type_name = format!(
+                    "{}<{}>",
+                    type_name,
+                    scope_sources(arguments, tokens).join(", ")
+                );
+                for name in scope_sources(names, tokens).into_iter() {
+                    fields.push((name, type_name.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+    fields
+}
+
+/// The name of the type a method takes in, without its type arguments.
+fn intake_name(intake: &Token) -> String {
+    match intake {
+        Token::Literal(Literal::Identifier(name)) | Token::Generic(name, _) => name.clone(),
+        Token::Literal(Literal::Void) => "()".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn scope_sources(block: &Token, tokens: &TokenizerContext) -> Vec<String> {
+    match block {
+        Token::Block(_, scope) => tokens
+            .get_scope(*scope)
+            .unwrap()
+            .get_tokens()
+            .iter()
+            .map(|t| source_of(t, tokens))
+            .collect(),
+        _ => vec![source_of(block, tokens)],
+    }
+}
+
+/// Renders a type expression roughly as it was written.
+fn source_of(token: &Token, tokens: &TokenizerContext) -> String {
+    match token {
+        Token::Literal(Literal::Identifier(name)) => name.clone(),
+        Token::Literal(Literal::Void) => "()".to_string(),
+        Token::Generic(name, arguments) => {
+            format!("{}<{}>", name, scope_sources(arguments, tokens).join(", "))
+        }
+        Token::Block(brace, _) => {
+            let inner = scope_sources(token, tokens).join(", ");
+            match brace {
+                Brace::Square => format!("[{}]", inner),
+                Brace::Angle => format!("<{}>", inner),
+                _ => format!("({})", inner),
+            }
+        }
+        other => format!("{:?}", other),
+    }
+}
+
+fn type_title(type_doc: &TypeDoc) -> String {
+    if type_doc.parameters.is_empty() {
+        type_doc.name.clone()
+    } else {
+        format!("{}<{}>", type_doc.name, type_doc.parameters.join(", "))
+    }
+}
+
+fn write_markdown<W: Write>(
+    type_docs: &[TypeDoc],
+    methods: &[MethodDoc],
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "# Types")?;
+    for type_doc in type_docs.iter() {
+        writeln!(out, "\n## `{}`\n", type_title(type_doc))?;
+        if let Some(docs) = &type_doc.docs {
+            writeln!(out, "{}\n", docs)?;
+        }
+        for (name, bytes) in type_doc.layout.iter() {
+            writeln!(out, "- `{}`: {} bytes", name, bytes)?;
+        }
+        if !type_doc.fields.is_empty() {
+            writeln!(out, "\n| Field | Type |\n| --- | --- |")?;
+            for (name, field_type) in type_doc.fields.iter() {
+                writeln!(out, "| `{}` | `{}` |", name, field_type)?;
+            }
+        }
+        if !type_doc.methods.is_empty() {
+            writeln!(out, "\n### Methods")?;
+            write_markdown_methods(&type_doc.methods, out)?;
+        }
+    }
+    if !methods.is_empty() {
+        writeln!(out, "\n# Methods")?;
+        write_markdown_methods(methods, out)?;
+    }
+    Ok(())
+}
+
+fn write_markdown_methods<W: Write>(methods: &[MethodDoc], out: &mut W) -> io::Result<()> {
+    for method in methods.iter() {
+        writeln!(out, "\n#### `{}`", method.signature)?;
+        if let Some(docs) = &method.docs {
+            writeln!(out, "\n{}", docs)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_html<W: Write>(
+    type_docs: &[TypeDoc],
+    methods: &[MethodDoc],
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>\n<html>\n<body>\n<h1>Types</h1>")?;
+    for type_doc in type_docs.iter() {
+        writeln!(
+            out,
+            "<h2><code>{}</code></h2>",
+            escape(&type_title(type_doc))
+        )?;
+        if let Some(docs) = &type_doc.docs {
+            writeln!(out, "<p>{}</p>", escape(docs))?;
+        }
+        if !type_doc.layout.is_empty() {
+            writeln!(out, "<ul>")?;
+            for (name, bytes) in type_doc.layout.iter() {
+                writeln!(
+                    out,
+                    "<li><code>{}</code>: {} bytes</li>",
+                    escape(name),
+                    bytes
+                )?;
+            }
+            writeln!(out, "</ul>")?;
+        }
+        if !type_doc.fields.is_empty() {
+            writeln!(out, "<table>\n<tr><th>Field</th><th>Type</th></tr>")?;
+            for (name, field_type) in type_doc.fields.iter() {
+                writeln!(
+                    out,
+                    "<tr><td><code>{}</code></td><td><code>{}</code></td></tr>",
+                    escape(name),
+                    escape(field_type)
+                )?;
+            }
+            writeln!(out, "</table>")?;
+        }
+        if !type_doc.methods.is_empty() {
+            writeln!(out, "<h3>Methods</h3>")?;
+            write_html_methods(&type_doc.methods, out)?;
+        }
+    }
+    if !methods.is_empty() {
+        writeln!(out, "<h1>Methods</h1>")?;
+        write_html_methods(methods, out)?;
+    }
+    writeln!(out, "</body>\n</html>")
+}
+
+fn write_html_methods<W: Write>(methods: &[MethodDoc], out: &mut W) -> io::Result<()> {
+    for method in methods.iter() {
+        writeln!(out, "<h4><code>{}</code></h4>", escape(&method.signature))?;
+        if let Some(docs) = &method.docs {
+            writeln!(out, "<p>{}</p>", escape(docs))?;
+        }
+    }
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_markdown() -> Result<(), AssemblyError> {
+        let markdown = document("res/test/doc_0.geo".to_string(), DocFormat::Markdown)?;
+        assert_eq!(
+            markdown,
+            "# Types
+
+## `Point`
+
+A point on the grid.
+Both coordinates are whole numbers.
+
+- `Point`: 8 bytes
+
+| Field | Type |
+| --- | --- |
+| `x` | `int` |
+| `y` | `int` |
+
+## `Pair<A, B>`
+
+- `Pair<int, Point>`: 12 bytes
+- `Pair<Point, int>`: 12 bytes
+
+| Field | Type |
+| --- | --- |
+| `first` | `A` |
+| `second` | `B` |
+
+### Methods
+
+#### `trans swap<A, B>: Pair<A, B>`
+
+Flips the elements of a pair.
+
+# Methods
+
+#### `calc start: ()`
+"
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_document_html() -> Result<(), AssemblyError> {
+        let html = document("res/test/doc_0.geo".to_string(), DocFormat::Html)?;
+        assert!(html.contains("<h2><code>Pair&lt;A, B&gt;</code></h2>"));
+        assert!(html.contains("<p>Flips the elements of a pair.</p>"));
+        assert!(html.contains("<li><code>Point</code>: 8 bytes</li>"));
+        Ok(())
+    }
+}
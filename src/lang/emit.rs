@@ -0,0 +1,206 @@
+use crate::lang::assemble::print::Methods;
+use crate::lang::assemble::{declare_root, lower_root, write_program, AssemblyError};
+use crate::lang::bytecode::compile::compile;
+use crate::parse::context::{BraceState, TokenizerContext};
+use crate::parse::lex::Lexer;
+use crate::parse::token::{parse_tokens, TokenParseError};
+
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// A stage of the compiler whose output can be written with `--emit`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Emit {
+    Lex,
+    Tokens,
+    Scopes,
+    Types,
+    Instructions,
+    C,
+    Bytecode,
+}
+
+const EMITS: [(Emit, &str); 7] = [
+    (Emit::Lex, "lex"),
+    (Emit::Tokens, "tokens"),
+    (Emit::Scopes, "scopes"),
+    (Emit::Types, "types"),
+    (Emit::Instructions, "instructions"),
+    (Emit::C, "c"),
+    (Emit::Bytecode, "ilbc"),
+];
+
+impl Emit {
+    pub fn from_name(name: &str) -> Option<Emit> {
+        EMITS.iter().find(|(_, n)| *n == name).map(|(e, _)| *e)
+    }
+    /// The name on the command line, which is also the extension of the file it is written to.
+    pub fn name(&self) -> &'static str {
+        EMITS.iter().find(|(e, _)| e == self).unwrap().1
+    }
+
+    /// Parses a comma separated list such as `lex,types,c`, or gives back the first unknown name.
+    pub fn parse_list(list: &str) -> Result<Vec<Emit>, String> {
+        list.split(',')
+            .map(|name| Emit::from_name(name.trim()).ok_or_else(|| name.to_string()))
+            .collect()
+    }
+
+    /// Where this stage is written for a build to `output`. The C program goes to `output` itself
+    /// and every other stage next to it.
+    pub fn path(&self, output: &Path) -> PathBuf {
+        match self {
+            Emit::C => output.to_path_buf(),
+            _ => output.with_extension(self.name()),
+        }
+    }
+}
+
+/// Compiles `filename`, writing each stage in `emits` next to `output` as soon as it is done, so
+/// the stages before an error are still written.
+pub fn emit(filename: String, output: &Path, emits: &[Emit]) -> Result<(), AssemblyError> {
+    let source = fs::read(&filename).map_err(|err| io_error(Path::new(&filename), err))?;
+    let write = |emit: Emit, write: &dyn Fn(&mut dyn Write) -> io::Result<()>| {
+        if !emits.contains(&emit) {
+            return Ok(());
+        }
+        let path = emit.path(output);
+        let mut out = BufWriter::new(fs::File::create(&path).map_err(|err| io_error(&path, err))?);
+        write(&mut out)
+            .and_then(|_| out.flush())
+            .map_err(|err| io_error(&path, err))
+    };
+    write(Emit::Lex, &|out| write_lexes(&source, out))?;
+    let tokens = parse_tokens(&source).map_err(AssemblyError::TokenParseError)?;
+    write(Emit::Tokens, &|out| write_tokens(&tokens, out))?;
+    write(Emit::Scopes, &|out| write!(out, "{}", tokens))?;
+    let mut types = declare_root(&tokens)?;
+    // the types are written even when a method body cannot be lowered
+    let lowered = lower_root(&tokens, &mut types);
+    write(Emit::Types, &|out| write!(out, "{}", types))?;
+    lowered?;
+    write(Emit::Instructions, &|out| {
+        write!(out, "{}", Methods(&types))
+    })?;
+    if emits.contains(&Emit::C) {
+        let path = Emit::C.path(output);
+        let file = fs::File::create(&path).map_err(|err| io_error(&path, err))?;
+        write_program(&types, &mut BufWriter::new(file))?;
+    }
+    if emits.contains(&Emit::Bytecode) {
+        let program = compile(&types)?;
+        write(Emit::Bytecode, &|out| out.write_all(&program.encode()))?;
+    }
+    Ok(())
+}
+
+/// An error reading or writing `path`, which is named so the message says which file failed.
+fn io_error(path: &Path, err: io::Error) -> AssemblyError {
+    AssemblyError::Io(format!("{}: {}", path.display(), err))
+}
+
+/// Writes each lex of `source` with the byte range it was read from, ending at the first one
+/// that could not be lexed.
+pub fn write_lexes(source: &[u8], out: &mut dyn Write) -> io::Result<()> {
+    for spanned in Lexer::new(source) {
+        match spanned {
+            Ok(spanned) => writeln!(out, "{}..{} {:?}", spanned.start, spanned.end, spanned.node)?,
+            Err(err) => {
+                writeln!(out, "error: {:?}", TokenParseError::Lex(err))?;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the tokens of every scope in the order the scopes were opened, with the scope each one
+/// was opened in.
+pub fn write_tokens(tokens: &TokenizerContext, out: &mut dyn Write) -> io::Result<()> {
+    let mut index = 0;
+    while let Some(scope) = tokens.get_scope(index) {
+        write!(out, "scope {}", index)?;
+        if let BraceState::Braced(brace, _) = scope.get_brace_state() {
+            write!(out, " {:?}", brace)?;
+        }
+        match scope.get_parent() {
+            Some(parent) => writeln!(out, " in {}", parent)?,
+            None => writeln!(out)?,
+        }
+        for token in scope.get_tokens().iter() {
+            writeln!(out, "  {:?}", token)?;
+        }
+        index += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(
+            Emit::parse_list("lex, types,c"),
+            Ok(vec![Emit::Lex, Emit::Types, Emit::C])
+        );
+        assert_eq!(Emit::parse_list("lex,ast"), Err("ast".to_string()));
+        for (emit, name) in EMITS.iter() {
+            assert_eq!(Emit::from_name(name), Some(*emit));
+            assert_eq!(emit.name(), *name);
+        }
+        assert_eq!(
+            Emit::Types.path(Path::new("out/a.c")),
+            PathBuf::from("out/a.types")
+        );
+    }
+    #[test]
+    fn test_emit_every_stage() -> Result<(), AssemblyError> {
+        let dir = std::env::temp_dir().join(format!("intolang_emit_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("range.c");
+        let emits = EMITS.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+        emit("res/test/range_0.geo".to_string(), &output, &emits)?;
+        let read = |emit: Emit| fs::read_to_string(emit.path(&output)).unwrap();
+        assert!(read(Emit::Lex).starts_with("1..5 Keyword(Type, 5)\n"));
+        assert!(read(Emit::Tokens).contains("\nscope 1 Brace in 0\n"));
+        assert!(read(Emit::Scopes).contains("\n  scope 1 Brace\n"));
+        let types = read(Emit::Types);
+        assert!(types.contains("type 2 int: data, 4 bytes\n"));
+        assert!(types.contains("  y: int at 4, 4 bytes\n"));
+        let instructions = read(Emit::Instructions);
+        assert!(instructions.contains("() start() -> (), "));
+        assert!(instructions.contains("    range as 2 1..=9 step 4 {\n"));
+        assert!(read(Emit::C).contains("int main() {"));
+        let bytecode = fs::read(Emit::Bytecode.path(&output)).unwrap();
+        assert!(bytecode.starts_with(b"ILBC"));
+        fs::remove_dir_all(&dir).unwrap();
+        Ok(())
+    }
+    #[test]
+    fn test_emit_stages_before_an_error() {
+        let dir = std::env::temp_dir().join(format!("intolang_emit_err_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("test.c");
+        let emits = [Emit::Lex, Emit::Types];
+        assert!(emit("res/example/test.clf".to_string(), &output, &emits).is_err());
+        assert!(Emit::Lex.path(&output).exists());
+        assert!(!Emit::Types.path(&output).exists());
+        let source = dir.join("redefined.geo");
+        fs::write(
+            &source,
+            "type P: (int(x),)\ncalc start: () { p: 1; p: 2; }\n",
+        )
+        .unwrap();
+        let source = source.to_str().unwrap().to_string();
+        assert!(matches!(
+            emit(source, &output, &emits),
+            Err(AssemblyError::BindingRedefined(_))
+        ));
+        let types = fs::read_to_string(Emit::Types.path(&output)).unwrap();
+        assert!(types.contains(" P: "), "{}", types);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,559 @@
+use crate::parse::constant::{Brace, Delimiter, Keyword, Operator};
+use crate::parse::cst::{SyntaxGroup, SyntaxNode, SyntaxToken, SyntaxTree, TriviaPiece};
+use crate::parse::lex::{BraceStatus, Lex, LexParseError};
+use crate::parse::token::{parse_tokens, TokenParseError};
+
+use std::collections::HashSet;
+
+/// Lines longer than this are broken before each `=>` of their pipeline.
+pub const MAX_WIDTH: usize = 100;
+const INDENT: &str = "  ";
+
+/// Formats a source into the canonical style:
+/// - two spaces of indentation per `{}` block, one statement per line
+/// - one space around `=>` and other binary operators, and after `:` and `,`
+/// - no spaces inside `()` and `[]`, around `.`, `..` or `::`, or around type arguments
+/// - a trailing comma at the end of every type body
+/// - pipelines longer than `MAX_WIDTH` broken before each `=>`
+///
+/// Comments are kept, and so are the line breaks between top level declarations, with runs of
+/// blank lines collapsed to one. A `()` or `[]` whose contents start on a new line keeps one
+/// element per line.
+///
+/// Sources that do not parse are refused, as spacing them out could change what they mean.
+pub fn format_source(source: &str) -> Result<String, TokenParseError> {
+    parse_tokens(source.as_bytes())?;
+    layout(source).map_err(TokenParseError::Lex)
+}
+
+/// Lays out the tokens of a source, whether or not they parse.
+fn layout(source: &str) -> Result<String, LexParseError> {
+    let tree = SyntaxTree::parse(source.as_bytes())?;
+    let mut formatter = Formatter::default();
+    formatter.write_nodes(&tree.nodes, Context::Root);
+    formatter.write_leading(&tree.end, Context::Root);
+    formatter.finish_line();
+    Ok(formatter.out)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Context {
+    Root,
+    Block,  //Inside `{}`
+    Inline, //Inside `()` or `[]` kept on one line
+    Broken, //Inside `()` or `[]` with one element per line
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Kind {
+    Word,
+    Keyword,
+    Open(bool), //Is a `{`
+    Close,
+    Comma,
+    Semicolon,
+    Colon,
+    Tight,
+    Binary,
+    Unary,
+    GenericOpen,
+    GenericClose,
+    Comment,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Break {
+    None,
+    Line,
+    Blank,
+}
+
+#[derive(Default)]
+struct Formatter {
+    out: String,
+    text: String,
+    indent: usize,
+    line_indent: usize,
+    pipes: Vec<usize>, //Offsets in `text` of the `=>`s the line may be broken before
+    previous: Option<Kind>,
+    pending: Option<Break>,
+    weak: bool, //Whether the pending break gives way to a following `,`, `;` or close brace
+    joined: bool, //Whether the next token was written against the previous one, as in `>>`
+}
+
+impl Formatter {
+    fn write_nodes(&mut self, nodes: &[SyntaxNode], context: Context) {
+        let generics = generic_angles(nodes);
+        let mut after_type = false;
+        for (i, node) in nodes.iter().enumerate() {
+            match node {
+                SyntaxNode::Token(token) => {
+                    if let Lex::Keyword(Keyword::Type, _) = token.lex {
+                        after_type = true;
+                    }
+                    let kind = self.kind_of(token, generics.contains(&i));
+                    self.joined = i > 0 && joined_operators(&nodes[i - 1], token);
+                    self.write_token(token, kind, context);
+                    self.joined = false;
+                    if context == Context::Broken && kind == Kind::Comma {
+                        self.request(Break::Line, false);
+                    }
+                }
+                SyntaxNode::Group(group) => {
+                    let type_body = after_type && is_brace(&group.open, &Brace::Brace);
+                    after_type = false;
+                    self.write_group(group, context, type_body);
+                }
+            }
+        }
+    }
+
+    fn write_group(&mut self, group: &SyntaxGroup, context: Context, type_body: bool) {
+        if is_brace(&group.open, &Brace::Bracket) {
+            self.write_token(&group.open, Kind::Open(true), context);
+            self.indent += 1;
+            if !group.children.is_empty() {
+                self.request(Break::Line, false);
+            }
+            self.write_nodes(&group.children, Context::Block);
+            // comments before the `}` belong to the block
+            if let Some(close) = &group.close {
+                self.write_leading(&close.leading, Context::Block);
+            }
+            self.indent -= 1;
+            if let Some(close) = &group.close {
+                if !group.children.is_empty() {
+                    self.request(Break::Line, false);
+                }
+                self.write_lexeme(close, Kind::Close, Context::Block);
+                self.request(Break::Line, true);
+            }
+            return;
+        }
+        let broken = group
+            .children
+            .first()
+            .is_some_and(|child| starts_line(first_token(child)));
+        let inner = if broken {
+            Context::Broken
+        } else {
+            Context::Inline
+        };
+        self.write_token(&group.open, Kind::Open(false), context);
+        if broken {
+            self.indent += 1;
+            self.request(Break::Line, false);
+        }
+        self.write_nodes(&group.children, inner);
+        let ends_in_comma = matches!(
+            group.children.last(),
+            Some(SyntaxNode::Token(SyntaxToken {
+                lex: Lex::Delimiter(Delimiter::Comma),
+                ..
+            }))
+        );
+        if type_body && !group.children.is_empty() && !ends_in_comma {
+            self.write_text(",", Kind::Comma);
+        }
+        if broken {
+            if let Some(close) = &group.close {
+                self.write_leading(&close.leading, inner);
+            }
+            self.indent -= 1;
+            self.request(Break::Line, false);
+        }
+        match &group.close {
+            Some(close) if broken => self.write_lexeme(close, Kind::Close, inner),
+            Some(close) => self.write_token(close, Kind::Close, inner),
+            None => {}
+        }
+    }
+
+    fn kind_of(&self, token: &SyntaxToken, generic: bool) -> Kind {
+        match &token.lex {
+            Lex::Keyword(_, _) => Kind::Keyword,
+            Lex::Brace(brace, BraceStatus::Open, _, _) => Kind::Open(*brace == Brace::Bracket),
+            Lex::Brace(_, BraceStatus::Close, _, _) => Kind::Close,
+            Lex::Delimiter(Delimiter::Comma) => Kind::Comma,
+            Lex::Delimiter(Delimiter::Semicolon) => Kind::Semicolon,
+            Lex::Operator(Operator::Of, _) => Kind::Colon,
+            Lex::Operator(Operator::LessThan, _) if generic => Kind::GenericOpen,
+            Lex::Operator(Operator::GreaterThan, _) if generic => Kind::GenericClose,
+            Lex::Operator(Operator::OfClass, _)
+            | Lex::Operator(Operator::Accessor, _)
+            | Lex::Operator(Operator::RangeMiddle, _)
+            | Lex::Operator(Operator::RangeInclusive, _)
+            | Lex::Operator(Operator::ArrayContinuation, _) => Kind::Tight,
+            Lex::Operator(Operator::Minus, _) | Lex::Operator(Operator::Not, _)
+                if self.previous.is_none_or(|p| {
+                    matches!(
+                        p,
+                        Kind::Open(_)
+                            | Kind::Comma
+                            | Kind::Semicolon
+                            | Kind::Colon
+                            | Kind::Binary
+                            | Kind::Unary
+                            | Kind::Keyword
+                    )
+                }) =>
+            {
+                Kind::Unary
+            }
+            Lex::Operator(_, _) => Kind::Binary,
+            _ => Kind::Word,
+        }
+    }
+
+    fn write_token(&mut self, token: &SyntaxToken, kind: Kind, context: Context) {
+        self.write_leading(&token.leading, context);
+        self.write_lexeme(token, kind, context);
+    }
+
+    /// Writes a token whose leading comments have already been written.
+    fn write_lexeme(&mut self, token: &SyntaxToken, kind: Kind, context: Context) {
+        if (kind == Kind::Semicolon || kind == Kind::Comma || kind == Kind::Close) && self.weak {
+            self.pending = None;
+        }
+        if let (Lex::Operator(Operator::Into, _), Context::Root | Context::Block) =
+            (&token.lex, context)
+        {
+            self.flush();
+            self.pipes.push(self.text.len());
+        }
+        self.write_text(token.text, kind);
+        for piece in token.trailing.iter() {
+            if let TriviaPiece::Comment(text, _) = piece {
+                self.write_comment(text);
+            }
+        }
+        if kind == Kind::Semicolon && context != Context::Inline && context != Context::Broken {
+            self.request(Break::Line, false);
+        }
+    }
+
+    fn write_text(&mut self, text: &str, kind: Kind) {
+        self.flush();
+        if !self.text.is_empty() && !self.joined && self.previous.is_some_and(|p| spaced(p, kind)) {
+            self.text.push(' ');
+        }
+        if self.text.is_empty() {
+            self.line_indent = self.indent;
+        }
+        self.text.push_str(text);
+        self.previous = Some(kind);
+    }
+
+    /// Writes the comments before a token on lines of their own, keeping one blank line where the
+    /// source had any, and the line breaks of the source where statements are not `;` ended.
+    fn write_leading(&mut self, leading: &[TriviaPiece], context: Context) {
+        for piece in leading.iter() {
+            match piece {
+                TriviaPiece::Whitespace(space) => {
+                    let breaks = space.matches('\n').count();
+                    let kept = match context {
+                        Context::Root | Context::Broken => breaks > 0,
+                        Context::Block => breaks > 1 && self.pending.is_some(),
+                        Context::Inline => false,
+                    };
+                    if kept {
+                        if breaks > 1 && !matches!(self.previous, Some(Kind::Open(_))) {
+                            self.request(Break::Blank, false);
+                        } else {
+                            self.request(Break::Line, false);
+                        }
+                    }
+                }
+                TriviaPiece::Comment(text, _) => {
+                    if !self.text.is_empty() {
+                        self.request(Break::Line, false);
+                    }
+                    self.write_comment(text);
+                }
+            }
+        }
+    }
+
+    fn write_comment(&mut self, text: &str) {
+        self.flush();
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        } else {
+            self.line_indent = self.indent;
+        }
+        self.text.push_str(text.trim_end());
+        self.previous = Some(Kind::Comment);
+        if text.starts_with("//") {
+            self.request(Break::Line, false);
+        }
+    }
+
+    /// Asks for a line break before whatever is written next, keeping the largest one asked for.
+    fn request(&mut self, request: Break, weak: bool) {
+        let strongest = match (self.pending, request) {
+            (Some(Break::Blank), _) | (_, Break::Blank) => Break::Blank,
+            (Some(Break::Line), _) | (_, Break::Line) => Break::Line,
+            _ => Break::None,
+        };
+        self.weak = if self.pending.is_some() {
+            self.weak && weak
+        } else {
+            weak
+        };
+        self.pending = Some(strongest);
+    }
+
+    fn flush(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            self.finish_line();
+            if pending == Break::Blank && !self.out.is_empty() && !self.out.ends_with("\n\n") {
+                self.out.push('\n');
+            }
+        }
+        self.weak = false;
+    }
+
+    fn finish_line(&mut self) {
+        if self.text.is_empty() {
+            return;
+        }
+        let indent = INDENT.repeat(self.line_indent);
+        if indent.len() + self.text.len() > MAX_WIDTH && !self.pipes.is_empty() {
+            let mut start = 0;
+            for pipe in self.pipes.iter().chain(std::iter::once(&self.text.len())) {
+                let segment = self.text[start..*pipe].trim();
+                if !segment.is_empty() {
+                    self.out.push_str(&indent);
+                    if start != 0 {
+                        self.out.push_str(INDENT);
+                    }
+                    self.out.push_str(segment);
+                    self.out.push('\n');
+                }
+                start = *pipe;
+            }
+        } else {
+            self.out.push_str(&indent);
+            self.out.push_str(&self.text);
+            self.out.push('\n');
+        }
+        self.text.clear();
+        self.pipes.clear();
+    }
+}
+
+/// Whether a space goes between a token of kind `previous` and one of kind `next` on a line.
+fn spaced(previous: Kind, next: Kind) -> bool {
+    match (previous, next) {
+        (_, Kind::Comma) | (_, Kind::Semicolon) | (_, Kind::Colon) | (_, Kind::Close) => false,
+        (Kind::Open(_), _) => false,
+        (Kind::Tight, _) | (_, Kind::Tight) => false,
+        (Kind::GenericOpen, _) | (_, Kind::GenericOpen) | (_, Kind::GenericClose) => false,
+        (Kind::Unary, _) => false,
+        (_, Kind::Open(true)) => true,
+        (Kind::Word, Kind::Open(false))
+        | (Kind::Close, Kind::Open(false))
+        | (Kind::GenericClose, Kind::Open(false)) => false,
+        _ => true,
+    }
+}
+
+/// Whether `token` repeats the operator written directly before it, as the second `>` of `>>`.
+fn joined_operators(previous: &SyntaxNode, token: &SyntaxToken) -> bool {
+    match (previous, &token.lex) {
+        (SyntaxNode::Token(previous), Lex::Operator(operator, _)) => {
+            matches!(&previous.lex, Lex::Operator(p, _) if p == operator)
+                && previous.trailing.is_empty()
+                && token.leading.is_empty()
+        }
+        _ => false,
+    }
+}
+
+fn is_brace(token: &SyntaxToken, brace: &Brace) -> bool {
+    matches!(&token.lex, Lex::Brace(b, _, _, _) if b == brace)
+}
+
+fn first_token<'a, 'src>(node: &'a SyntaxNode<'src>) -> &'a SyntaxToken<'src> {
+    match node {
+        SyntaxNode::Token(token) => token,
+        SyntaxNode::Group(group) => &group.open,
+    }
+}
+
+fn starts_line(token: &SyntaxToken) -> bool {
+    token
+        .leading
+        .iter()
+        .any(|piece| piece.text().contains('\n'))
+}
+
+/// The positions of the `<` and `>` that enclose type arguments rather than compare, found the
+/// same way the tokenizer does: an identifier followed by a list of identifiers, commas and
+/// nested argument lists.
+fn generic_angles(nodes: &[SyntaxNode]) -> HashSet<usize> {
+    let mut angles = HashSet::new();
+    let lex_at = |i: usize| match nodes.get(i) {
+        Some(SyntaxNode::Token(token)) => Some(&token.lex),
+        _ => None,
+    };
+    for open in 1..nodes.len() {
+        if !matches!(lex_at(open - 1), Some(Lex::Identifier(_, _)))
+            || !matches!(lex_at(open), Some(Lex::Operator(Operator::LessThan, _)))
+        {
+            continue;
+        }
+        let mut depth = 0usize;
+        let mut list = Vec::new();
+        for i in open..nodes.len() {
+            match lex_at(i) {
+                Some(Lex::Operator(Operator::LessThan, _)) => depth += 1,
+                Some(Lex::Operator(Operator::GreaterThan, _)) => depth -= 1,
+                Some(Lex::Identifier(_, _)) | Some(Lex::Delimiter(Delimiter::Comma)) => continue,
+                _ => break,
+            }
+            list.push(i);
+            if depth == 0 {
+                angles.extend(list);
+                break;
+            }
+        }
+    }
+    angles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::token::parse_lexs;
+    use std::fs;
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = layout(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(layout(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        assert_formats(
+            "type Point :(int( x ),int(y))\ncalc start:(){p:[Point( 1,2 )]=>print ;}",
+            "type Point: (int(x), int(y),)
+calc start: () {
+  p: [Point(1, 2)] => print;
+}
+",
+        );
+        assert_formats(
+            "trans swap< A,B >: Pair< A , B >{Pair<B,A>(second,first)}",
+            "trans swap<A, B>: Pair<A, B> {
+  Pair<B, A>(second, first)
+}
+",
+        );
+        assert_formats(
+            "calc f: () { a:1+-2; b:a<3; c:0 .. 10; d: !a; x.y:: z; e: p>>1; }",
+            "calc f: () {
+  a: 1 + -2;
+  b: a < 3;
+  c: 0..10;
+  d: !a;
+  x.y::z;
+  e: p >> 1;
+}
+",
+        );
+    }
+    #[test]
+    fn test_format_comments_and_blank_lines() {
+        assert_formats(
+            "/// docs\ntype A: (int(x),) // trailing\n\n\n\n// about b\ncalc b: () {\n\n  // first\n  x => print; /* same line */\n\n\n  y => print;\n}\n",
+            "/// docs
+type A: (int(x),) // trailing
+
+// about b
+calc b: () {
+  // first
+  x => print; /* same line */
+
+  y => print;
+}
+",
+        );
+    }
+    #[test]
+    fn test_format_comments_before_close() {
+        assert_formats(
+            "calc b: () {\n  x => print;\n// last\n}\n",
+            "calc b: () {\n  x => print;\n  // last\n}\n",
+        );
+        assert_formats(
+            "type Point: (\nint(x),\nint(y), // y\n/* end */\n)\n",
+            "type Point: (\n  int(x),\n  int(y), // y\n  /* end */\n)\n",
+        );
+    }
+    #[test]
+    fn test_format_refuses_unparseable() {
+        assert!(format_source("calc start: () {\n  p := 1;\n}\n").is_err());
+        assert!(format_source("calc start: () {\n  p: 1;\n}\n").is_ok());
+    }
+    #[test]
+    fn test_format_broken_groups_and_pipelines() {
+        assert_formats(
+            "type Point: (\nint(x),\n    int(y)\n)\n",
+            "type Point: (
+  int(x),
+  int(y),
+)
+",
+        );
+        let long = format!(
+            "calc start: () {{ [{}] => first => second => third => print; }}",
+            (0..30)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let formatted = format_source(&long).unwrap();
+        let lines = formatted.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[1].starts_with("  [0, 1, 2"));
+        assert_eq!(lines[2], "    => first");
+        assert_eq!(lines[5], "    => print;");
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+    #[test]
+    fn test_format_resources() {
+        for dir in ["res/test", "res/example"].iter() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let source = fs::read_to_string(&path).unwrap();
+                let formatted = match format_source(&source) {
+                    Ok(formatted) => formatted,
+                    Err(_) => continue,
+                };
+                assert_eq!(
+                    format_source(&formatted).unwrap(),
+                    formatted,
+                    "formatting {:?} twice changed it",
+                    path
+                );
+                // only layout changes, apart from the trailing commas added to type bodies
+                let texts = |source: &str| {
+                    SyntaxTree::parse(source.as_bytes())
+                        .unwrap()
+                        .tokens()
+                        .iter()
+                        .map(|t| t.text.to_string())
+                        .filter(|t| t != ",")
+                        .collect::<Vec<String>>()
+                };
+                assert_eq!(texts(&formatted), texts(&source), "in {:?}", path);
+                assert_eq!(
+                    parse_lexs(formatted.as_bytes()).is_ok(),
+                    parse_lexs(source.as_bytes()).is_ok()
+                );
+            }
+        }
+    }
+}
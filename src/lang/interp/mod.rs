@@ -0,0 +1,615 @@
+use crate::lang::assemble::context::{AssembledTypeContext, CLASS_ID_INT, CLASS_ID_VOID};
+use crate::lang::assemble::{assemble_root, AssembledType, AssemblyError};
+use crate::parse::ast::{Block, Expr, Item, MethodDecl, Module, Pattern, TypeExpr};
+use crate::parse::constant::{Number, Operator};
+use crate::parse::token::{parse_tokens, Literal};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+
+use self::value::{Printed, Value};
+
+pub mod value;
+
+#[derive(Debug)]
+pub enum InterpError {
+    Assembly(AssemblyError),
+    UnknownIdentifier(String),
+    UnknownMethod(String, String), //Type, method
+    TypeNotFound(String),
+    WrongNumberOfArguments(String, usize, usize),
+    TypeMismatch(String, String),
+    ExpectedValue,
+    DivisionByZero,
+    InvalidRangeStep(i32),
+    CannotBindVoid(String),
+    UnsupportedExpression,
+    Io(String),
+}
+
+/// Parses, typechecks and runs a source file, writing what it prints to `output`.
+pub fn interpret<W: Write>(filename: String, output: &mut W) -> Result<(), InterpError> {
+    let contents =
+        fs::read(&filename).map_err(|e| InterpError::Io(format!("{}: {}", filename, e)))?;
+    run(&contents, output)
+}
+
+/// Runs the `start` method of `source`. The source is assembled and typechecked as for the C
+/// backend, so the same programs are rejected, methods are called on the same types and
+/// compositions are printed under the same names, but method bodies are evaluated from the
+/// syntax tree.
+pub fn run<W: Write>(source: &[u8], output: &mut W) -> Result<(), InterpError> {
+    let assembly = |e| InterpError::Assembly(AssemblyError::TokenParseError(e));
+    let tokens = parse_tokens(source).map_err(assembly)?;
+    let types = assemble_root(&tokens).map_err(InterpError::Assembly)?;
+    let module = Module::from_tokens(&tokens).map_err(assembly)?;
+    Interpreter::new(&module, &types, output).start()
+}
+
+/// The names visible in a method body: its bindings, the fields of the element it took in, and
+/// the types its type parameters stand for.
+#[derive(Default)]
+struct Frame {
+    values: HashMap<String, Value>,
+    type_arguments: HashMap<String, u16>,
+}
+
+/// Evaluates the methods of a module, element by element like the fused loops of the C backend,
+/// so what is printed comes out in the same order.
+pub struct Interpreter<'a, W: Write> {
+    module: &'a Module,
+    types: &'a AssembledTypeContext,
+    output: &'a mut W,
+    /// The declared methods by the scope of their body, which is how assembled methods point
+    /// back to their source.
+    methods: HashMap<usize, &'a MethodDecl>,
+}
+
+impl<'a, W: Write> Interpreter<'a, W> {
+    pub fn new(
+        module: &'a Module,
+        types: &'a AssembledTypeContext,
+        output: &'a mut W,
+    ) -> Interpreter<'a, W> {
+        let methods = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Method(method) => {
+                    let scope = module.scope(method.body.id)?.scope;
+                    Some((scope, method))
+                }
+                _ => None,
+            })
+            .collect();
+        Interpreter {
+            module,
+            types,
+            output,
+            methods,
+        }
+    }
+
+    /// Runs `calc start: ()`.
+    pub fn start(&mut self) -> Result<(), InterpError> {
+        let start = self.module.items.iter().find_map(|item| match item {
+            Item::Method(method)
+                if method.name == "start" && method.intake == Pattern::Type(TypeExpr::Void) =>
+            {
+                Some(method)
+            }
+            _ => None,
+        });
+        match start {
+            Some(start) => {
+                self.block(&start.body, &mut Frame::default())?;
+                self.output
+                    .flush()
+                    .map_err(|e| InterpError::Io(e.to_string()))
+            }
+            None => Err(InterpError::Assembly(AssemblyError::NoStartMethodFound)),
+        }
+    }
+
+    /// Evaluates each statement of a block in order, giving back the value of the last.
+    fn block(&mut self, block: &Block, frame: &mut Frame) -> Result<Value, InterpError> {
+        let mut last = Value::Void;
+        for expr in block.exprs.iter() {
+            last = match expr {
+                Expr::Binding(Pattern::Name(name), value) => {
+                    let value = self.eval(value, frame)?;
+                    if value == Value::Void {
+                        return Err(InterpError::CannotBindVoid(name.clone()));
+                    }
+                    frame.values.insert(name.clone(), value.into_stream());
+                    Value::Void
+                }
+                _ => self.eval(expr, frame)?,
+            };
+        }
+        Ok(last)
+    }
+
+    fn eval(&mut self, expr: &Expr, frame: &mut Frame) -> Result<Value, InterpError> {
+        match expr {
+            Expr::Literal(literal) => literal_value(literal),
+            Expr::Identifier(name) => match frame.values.get(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(InterpError::UnknownIdentifier(name.clone())),
+            },
+            Expr::Binary(Operator::Into, _, _) => {
+                let (source, stages) = pipeline(expr);
+                // a range is counted through rather than collected, like the loops of the C
+                let elements = self.eval(source, frame)?.into_elements();
+                let produced = self.flow_all(elements, &stages)?;
+                Ok(match stages.last() {
+                    Some(Expr::Identifier(name)) if name == "print" => Value::Void,
+                    _ => Value::Stream(produced),
+                })
+            }
+            Expr::Binary(Operator::RangeMiddle | Operator::RangeInclusive, _, _)
+            | Expr::Ternary(Operator::RangeMiddle | Operator::RangeInclusive, _, _, _) => {
+                self.range(expr, frame)
+            }
+            Expr::Binary(op, a, b) => {
+                let a = self.int(a, frame)?;
+                let b = self.int(b, frame)?;
+                arithmetic(*op, a, b).map(Value::Int)
+            }
+            Expr::Unary(Operator::Minus, a) => Ok(Value::Int(self.int(a, frame)?.wrapping_neg())),
+            Expr::Group(block) => self.block(block, frame),
+            Expr::Interpolated(prefix, parts, suffix) => {
+                let mut text = prefix.clone();
+                for part in parts.exprs.iter() {
+                    match part {
+                        Expr::Literal(Literal::String(s)) => text.push_str(s),
+                        _ => {
+                            let value = self.scalar(part, frame)?;
+                            text.push_str(&Printed(self.types, &value).to_string());
+                        }
+                    }
+                }
+                text.push_str(suffix);
+                Ok(Value::String(text))
+            }
+            Expr::Construct(ty, arguments) => self.construct(ty, arguments, frame),
+            Expr::Array(elements) => {
+                let mut values: Vec<Value> = Vec::new();
+                for element in elements.exprs.iter() {
+                    for value in self.eval(element, frame)?.into_elements() {
+                        if let Some(first) = values.first() {
+                            self.expect(first.class_id(), &value)?;
+                        }
+                        values.push(value);
+                    }
+                }
+                Ok(Value::Stream(values))
+            }
+            Expr::Index(collection, indices) => {
+                let elements = self
+                    .eval(collection, frame)?
+                    .into_elements()
+                    .collect::<Vec<_>>();
+                let indices = match indices.exprs.as_slice() {
+                    [index] => self.eval(index, frame)?.into_elements(),
+                    _ => return Err(InterpError::UnsupportedExpression),
+                };
+                let mut selected = Vec::new();
+                for index in indices {
+                    let index = match index {
+                        Value::Int(i) => i,
+                        other => return Err(self.mismatch(CLASS_ID_INT, &other)),
+                    };
+                    match elements.get(index as usize) {
+                        Some(element) if index >= 0 => selected.push(element.clone()),
+                        _ if index < 0 => {}
+                        _ => break,
+                    }
+                }
+                Ok(Value::Stream(selected))
+            }
+            _ => Err(InterpError::UnsupportedExpression),
+        }
+    }
+
+    /// Passes every element through the stages. A `sum` of ints adds up all that reach it, then
+    /// passes the total on through the stages after it.
+    fn flow_all<I: Iterator<Item = Value>>(
+        &mut self,
+        elements: I,
+        stages: &[&Expr],
+    ) -> Result<Vec<Value>, InterpError> {
+        let at = stages
+            .iter()
+            .position(|stage| matches!(stage, Expr::Identifier(name) if name == "sum"));
+        let (before, at) = match at {
+            Some(at) => (&stages[..at], at),
+            None => {
+                let mut produced = Vec::new();
+                for element in elements {
+                    produced.extend(self.flow(element, stages)?);
+                }
+                return Ok(produced);
+            }
+        };
+        let mut reaching = Vec::new();
+        for element in elements {
+            reaching.extend(self.flow(element, before)?);
+        }
+        // a method `sum` of the type of the elements comes first, as it does when assembling
+        let ints = reaching.iter().all(|value| matches!(value, Value::Int(_)));
+        let method = self
+            .types
+            .get_impl(&CLASS_ID_INT)
+            .is_some_and(|methods| methods.contains_key("sum"));
+        if ints && !method {
+            let total = reaching.iter().fold(0i32, |total, value| match value {
+                Value::Int(i) => total.wrapping_add(*i),
+                _ => total,
+            });
+            return self.flow_all(vec![Value::Int(total)].into_iter(), &stages[at + 1..]);
+        }
+        let mut summed = Vec::new();
+        for value in reaching.into_iter() {
+            summed.extend(self.flow(value, &stages[at..at + 1])?);
+        }
+        self.flow_all(summed.into_iter(), &stages[at + 1..])
+    }
+
+    /// Passes one element through the stages, giving back what comes out of the last.
+    fn flow(&mut self, element: Value, stages: &[&Expr]) -> Result<Vec<Value>, InterpError> {
+        let (stage, rest) = match stages.split_first() {
+            Some(split) => split,
+            None => return Ok(vec![element]),
+        };
+        let name = match stage {
+            Expr::Identifier(name) => name,
+            _ => return Err(InterpError::UnsupportedExpression),
+        };
+        if name == "print" {
+            writeln!(self.output, "{}", Printed(self.types, &element))
+                .map_err(|e| InterpError::Io(e.to_string()))?;
+            return Ok(Vec::new());
+        }
+        let mut produced = Vec::new();
+        for value in self.call(name, element)?.into_elements() {
+            produced.extend(self.flow(value, rest)?);
+        }
+        Ok(produced)
+    }
+
+    /// Calls the method `name` on an element, a built-in one or the one the assembler chose for
+    /// the type of the element.
+    fn call(&mut self, name: &str, element: Value) -> Result<Value, InterpError> {
+        if let ("cast", Value::Int(i)) = (name, &element) {
+            return Ok(Value::Int(*i));
+        }
+        let class_id = element.class_id();
+        let assembled = self
+            .types
+            .get_impl(&class_id)
+            .and_then(|methods| methods.get(name));
+        let declared = assembled
+            .and_then(|method| method.get_scope())
+            .and_then(|scope| self.methods.get(&scope).cloned());
+        if let (Some(assembled), Some(method)) = (assembled, declared) {
+            let mut frame = self.intake(method, element);
+            frame.type_arguments = assembled.get_type_arguments().clone();
+            return self.block(&method.body, &mut frame);
+        }
+        // a field used as a stage takes it from the element
+        if let (Value::Composition(_, values), Some(AssembledType::Composition(_, comp))) =
+            (&element, self.types.get_type(&class_id))
+        {
+            if let Some(i) = comp.get_fields().iter().position(|f| f.get_name() == name) {
+                return Ok(values[i].clone());
+            }
+        }
+        let type_name = match self.types.get_type(&class_id) {
+            Some(ty) => ty.get_name(),
+            None => class_id.to_string(),
+        };
+        Err(InterpError::UnknownMethod(type_name, name.to_string()))
+    }
+
+    /// The frame a method starts with when it takes in `element`, with the fields of the element
+    /// and the names its intake gives them.
+    fn intake(&self, method: &MethodDecl, element: Value) -> Frame {
+        let names = match &method.intake {
+            Pattern::Destructure(_, names) => Some(names),
+            _ => None,
+        };
+        let mut frame = Frame::default();
+        if let Value::Composition(id, values) = element {
+            if let Some(AssembledType::Composition(_, comp)) = self.types.get_type(&id) {
+                for (field, value) in comp.get_fields().iter().zip(values.iter()) {
+                    frame.values.insert(field.get_name().clone(), value.clone());
+                }
+            }
+            if let Some(names) = names {
+                for (name, value) in names.iter().zip(values.iter()) {
+                    frame.values.insert(name.clone(), value.clone());
+                }
+            }
+        }
+        frame
+    }
+
+    /// The id of the type `ty` names, with the type parameters of the method being run.
+    fn type_id(&self, ty: &TypeExpr, frame: &Frame) -> Result<u16, InterpError> {
+        match ty {
+            TypeExpr::Void => Ok(CLASS_ID_VOID),
+            TypeExpr::Named(name) => match frame.type_arguments.get(name) {
+                Some(id) => Ok(*id),
+                None => match self.types.get_type_id(name) {
+                    Some(id) => Ok(*id),
+                    None => Err(InterpError::TypeNotFound(name.clone())),
+                },
+            },
+            TypeExpr::Generic(name, arguments) => {
+                let mut ids = Vec::new();
+                for argument in arguments.iter() {
+                    ids.push(self.type_id(argument, frame)?);
+                }
+                match self.types.get_instance(name, &ids) {
+                    Some(id) => Ok(*id),
+                    None => Err(InterpError::TypeNotFound(name.clone())),
+                }
+            }
+        }
+    }
+
+    fn construct(
+        &mut self,
+        ty: &TypeExpr,
+        arguments: &Block,
+        frame: &mut Frame,
+    ) -> Result<Value, InterpError> {
+        let id = self.type_id(ty, frame)?;
+        let types = self.types;
+        let (name, fields) = match types.get_type(&id) {
+            Some(AssembledType::Composition(name, comp)) => (name, comp.get_fields()),
+            // `int(x)` is `x`, so long as it is an int
+            Some(AssembledType::Data(name, _)) => match arguments.exprs.as_slice() {
+                [argument] => {
+                    let value = self.scalar(argument, frame)?;
+                    self.expect(id, &value)?;
+                    return Ok(value);
+                }
+                _ => {
+                    return Err(InterpError::WrongNumberOfArguments(
+                        name.clone(),
+                        1,
+                        arguments.exprs.len(),
+                    ))
+                }
+            },
+            _ => return Err(InterpError::UnsupportedExpression),
+        };
+        if arguments.exprs.len() != fields.len() {
+            return Err(InterpError::WrongNumberOfArguments(
+                name.clone(),
+                fields.len(),
+                arguments.exprs.len(),
+            ));
+        }
+        let mut values = Vec::new();
+        for (field, argument) in fields.iter().zip(arguments.exprs.iter()) {
+            let value = self.scalar(argument, frame)?;
+            self.expect(field.get_class_id(), &value)?;
+            values.push(value);
+        }
+        Ok(Value::Composition(id, values))
+    }
+
+    /// The range `start..end by step` of `expr`, with the bounds evaluated before the first
+    /// element.
+    fn range(&mut self, expr: &Expr, frame: &mut Frame) -> Result<Value, InterpError> {
+        let (op, start, end, step) = match expr {
+            Expr::Binary(op @ (Operator::RangeMiddle | Operator::RangeInclusive), start, end) => {
+                (*op, start, end, None)
+            }
+            Expr::Ternary(
+                op @ (Operator::RangeMiddle | Operator::RangeInclusive),
+                start,
+                end,
+                step,
+            ) => (*op, start, end, Some(step)),
+            _ => return Err(InterpError::UnsupportedExpression),
+        };
+        let step = match step {
+            Some(step) => self.int(step, frame)?,
+            None => 1,
+        };
+        if step <= 0 {
+            return Err(InterpError::InvalidRangeStep(step));
+        }
+        let (start, end) = (self.int(start, frame)?, self.int(end, frame)?);
+        Ok(Value::Range {
+            start,
+            end,
+            step,
+            inclusive: op == Operator::RangeInclusive,
+        })
+    }
+
+    /// A single value, the first element of a binding used where one value is needed.
+    fn scalar(&mut self, expr: &Expr, frame: &mut Frame) -> Result<Value, InterpError> {
+        match self.eval(expr, frame)?.into_elements().next() {
+            Some(value) => Ok(value),
+            None => Err(InterpError::ExpectedValue),
+        }
+    }
+
+    fn int(&mut self, expr: &Expr, frame: &mut Frame) -> Result<i32, InterpError> {
+        match self.scalar(expr, frame)? {
+            Value::Int(i) => Ok(i),
+            other => Err(self.mismatch(CLASS_ID_INT, &other)),
+        }
+    }
+
+    fn expect(&self, class_id: u16, value: &Value) -> Result<(), InterpError> {
+        if value.class_id() == class_id {
+            Ok(())
+        } else {
+            Err(self.mismatch(class_id, value))
+        }
+    }
+
+    fn mismatch(&self, expected: u16, found: &Value) -> InterpError {
+        let name = |id: u16| match self.types.get_type(&id) {
+            Some(ty) => ty.get_name(),
+            None => id.to_string(),
+        };
+        InterpError::TypeMismatch(name(expected), name(found.class_id()))
+    }
+}
+
+/// Splits `source => a => b` into its source and its stages.
+fn pipeline(expr: &Expr) -> (&Expr, Vec<&Expr>) {
+    match expr {
+        Expr::Binary(Operator::Into, source, stage) => {
+            let (source, mut stages) = pipeline(source);
+            stages.push(stage);
+            (source, stages)
+        }
+        _ => (expr, Vec::new()),
+    }
+}
+
+fn literal_value(literal: &Literal) -> Result<Value, InterpError> {
+    match literal {
+        Literal::Number(Number::Integer(i)) => Ok(Value::Int(*i as i32)),
+        Literal::Number(Number::Char(c)) => Ok(Value::Char(*c)),
+        Literal::String(s) => Ok(Value::String(s.clone())),
+        Literal::Void => Ok(Value::Void),
+        _ => Err(InterpError::UnsupportedExpression),
+    }
+}
+
+fn arithmetic(op: Operator, a: i32, b: i32) -> Result<i32, InterpError> {
+    match op {
+        Operator::Plus => Ok(a.wrapping_add(b)),
+        Operator::Minus => Ok(a.wrapping_sub(b)),
+        Operator::Multiply => Ok(a.wrapping_mul(b)),
+        Operator::Divide | Operator::Modulus if b == 0 => Err(InterpError::DivisionByZero),
+        Operator::Divide => Ok(a.wrapping_div(b)),
+        Operator::Modulus => Ok(a.wrapping_rem(b)),
+        _ => Err(InterpError::UnsupportedExpression),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(source: &str) -> Result<String, InterpError> {
+        let mut printed = Vec::new();
+        run(source.as_bytes(), &mut printed)?;
+        Ok(String::from_utf8(printed).unwrap())
+    }
+    fn file(path: &str) -> String {
+        let mut printed = Vec::new();
+        interpret(path.to_string(), &mut printed).unwrap();
+        String::from_utf8(printed).unwrap()
+    }
+
+    #[test]
+    fn test_resources() {
+        assert_eq!(file("res/test/simple_0.geo"), "Point(1, 2)\n");
+        assert_eq!(
+            file("res/test/range_0.geo"),
+            "0\n1\n2\n1\n5\n9\nPoint(3, 4)\nPoint(5, 6)\n"
+        );
+        assert_eq!(
+            file("res/test/binding_0.geo"),
+            "Point(1, 2)\nPoint(3, 4)\n0\n1\n2\nPoint(3, 4)\n"
+        );
+        assert_eq!(
+            file("res/test/interpolation_0.geo"),
+            "n is 3, p is Point(1, 2)\n{literal} Point(5, 6) and c!\n"
+        );
+        assert_eq!(
+            file("res/test/generic_0.geo"),
+            "Pair<Point, int>(Point(1, 2), 1)\n"
+        );
+        assert_eq!(file("res/test/stage_0.geo"), "1\n3\n4\n10\n6\n10\n6\n");
+    }
+    #[test]
+    fn test_methods_and_arithmetic() -> Result<(), InterpError> {
+        let source = "type Point: (int(x), int(y),)
+            trans flip: Point { Point(y, x) }
+            trans both: Point { [x, y] }
+            calc start: () {
+              ps: [Point(1, 2), Point(3, 4)] => flip;
+              ps => both => cast => print;
+              n: 7 % 4;
+              n * 2 - 9 => print;
+            }";
+        assert_eq!(output(source)?, "2\n1\n4\n3\n-3\n");
+        Ok(())
+    }
+    #[test]
+    fn test_ranges_are_not_collected() {
+        // collecting every int of the range first would run out of memory instead
+        for start in [
+            "0..2147483647 => f => print;",
+            "n: 0..2147483647; n => f => print;",
+            "1 => up => f => print;",
+        ] {
+            let source = format!(
+                "trans up: int {{ 0..2147483647 }} trans f: int {{ 1 / 0 }} calc start: () {{ {} }}",
+                start
+            );
+            match output(&source) {
+                Err(InterpError::DivisionByZero) => {}
+                other => panic!("unexpected {:?} for {}", other, start),
+            }
+        }
+    }
+    #[test]
+    fn test_errors() {
+        let run = |source: &str| output(source).err().unwrap();
+        match run("calc start: () { 1 => pritn; }") {
+            InterpError::Assembly(AssemblyError::UnresolvedIdentifiers(_)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match run("type Point: (int(x), int(y),) calc start: () { Point(1, 2) => cast; }") {
+            InterpError::Assembly(AssemblyError::MethodNotFound(ty, name)) => {
+                assert_eq!((ty.as_str(), name.as_str()), ("Point", "cast"))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match run("calc start: () { 0..3 by 0 => print; }") {
+            InterpError::Assembly(AssemblyError::InvalidRangeStep(0)) => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match run("calc start: () { 1 / 0 => print; }") {
+            InterpError::DivisionByZero => {}
+            other => panic!("unexpected {:?}", other),
+        }
+        match run("calc start: () { p: 1; p: 2; }") {
+            InterpError::Assembly(AssemblyError::BindingRedefined(name)) => assert_eq!(name, "p"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match run("calc start: () { p => print; p: 1; }") {
+            InterpError::Assembly(AssemblyError::BindingUsedBeforeDefinition(name)) => {
+                assert_eq!(name, "p")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        // what the C backend cannot lower is not run either
+        for start in [
+            "1 + [1, 2] => print;",
+            "p: 1; [p, p] => print;",
+            "(0..5)[1..3] => print;",
+            "\"a {[1, 2]} b\" => print;",
+        ] {
+            match run(&format!("calc start: () {{ {} }}", start)) {
+                InterpError::Assembly(_) => {}
+                other => panic!("unexpected {:?} for {}", other, start),
+            }
+        }
+    }
+}
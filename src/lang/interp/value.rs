@@ -0,0 +1,139 @@
+use crate::lang::assemble::context::{
+    AssembledTypeContext, CLASS_ID_CHAR, CLASS_ID_INT, CLASS_ID_STRING, CLASS_ID_VOID,
+};
+
+use std::fmt;
+use std::vec;
+
+/// A value at run time. Ints are 32 bits wide and wrap, as they do in the generated C.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Void,
+    Int(i32),
+    String(String),
+    Char(char),
+    /// The fields of a composition in declaration order, with the id of its type.
+    Composition(u16, Vec<Value>),
+    /// The elements of a binding or of what a method produced.
+    Stream(Vec<Value>),
+    /// The ints of `start..end by step`, or `start..=end by step`, counted as they are used so
+    /// that a bound or returned range is never collected.
+    Range {
+        start: i32,
+        end: i32,
+        step: i32,
+        inclusive: bool,
+    },
+}
+
+impl Value {
+    /// The id of the type of the value, void for a stream since its elements have their own.
+    pub fn class_id(&self) -> u16 {
+        match self {
+            Value::Int(_) => CLASS_ID_INT,
+            Value::String(_) => CLASS_ID_STRING,
+            Value::Char(_) => CLASS_ID_CHAR,
+            Value::Composition(id, _) => *id,
+            Value::Void | Value::Stream(_) | Value::Range { .. } => CLASS_ID_VOID,
+        }
+    }
+    /// The elements of a stream or range, nothing for void, or the value itself as the only
+    /// element.
+    pub fn into_elements(self) -> Elements {
+        match self {
+            Value::Void => Elements::Values(Vec::new().into_iter()),
+            Value::Stream(elements) => Elements::Values(elements.into_iter()),
+            Value::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            } => Elements::Range {
+                next: Some(start),
+                end,
+                step,
+                inclusive,
+            },
+            value => Elements::Values(vec![value].into_iter()),
+        }
+    }
+    /// The value itself, or the elements of a single value as a stream. Ranges stay ranges.
+    pub fn into_stream(self) -> Value {
+        match self {
+            value @ (Value::Stream(_) | Value::Range { .. }) => value,
+            value => Value::Stream(value.into_elements().collect()),
+        }
+    }
+}
+
+/// The elements of a value, see `Value::into_elements`.
+pub enum Elements {
+    Values(vec::IntoIter<Value>),
+    /// The ints of a range, stopping before one would overflow.
+    Range {
+        next: Option<i32>,
+        end: i32,
+        step: i32,
+        inclusive: bool,
+    },
+}
+impl Iterator for Elements {
+    type Item = Value;
+    fn next(&mut self) -> Option<Value> {
+        match self {
+            Elements::Values(values) => values.next(),
+            Elements::Range {
+                next,
+                end,
+                step,
+                inclusive,
+            } => {
+                let i = (*next)?;
+                if i > *end || (i == *end && !*inclusive) {
+                    return None;
+                }
+                *next = i.checked_add(*step);
+                Some(Value::Int(i))
+            }
+        }
+    }
+}
+
+/// A value as `print` writes it: compositions as `Name(field, field)` and the elements of a
+/// stream between square brackets.
+pub struct Printed<'a>(pub &'a AssembledTypeContext, pub &'a Value);
+impl<'a> fmt::Display for Printed<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Printed(types, value) = self;
+        let list =
+            |f: &mut fmt::Formatter, values: &mut dyn Iterator<Item = Value>| -> fmt::Result {
+                for (i, v) in values.enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", Printed(types, &v))?;
+                }
+                Ok(())
+            };
+        match value {
+            Value::Void => write!(f, "()"),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Char(c) => write!(f, "{}", c),
+            Value::Composition(id, fields) => {
+                let name = match types.get_type(id) {
+                    Some(ty) => ty.get_name(),
+                    None => id.to_string(),
+                };
+                write!(f, "{}(", name)?;
+                list(f, &mut fields.iter().cloned())?;
+                write!(f, ")")
+            }
+            Value::Stream(_) | Value::Range { .. } => {
+                write!(f, "[")?;
+                list(f, &mut (*value).clone().into_elements())?;
+                write!(f, "]")
+            }
+        }
+    }
+}
@@ -0,0 +1,837 @@
+use crate::lang::assemble::context::AssembledTypeContext;
+use crate::lang::assemble::{declare_root, lower_root, AssembledType};
+use crate::lang::diagnostic::{Diagnostic, Span};
+use crate::parse::constant::{Brace, Delimiter, Number};
+use crate::parse::context::{BraceState, TokenizerContext};
+use crate::parse::lex::{BraceStatus, Lex, Lexer, Spanned};
+use crate::parse::token::{parse_tokens, Literal, OperatorGroup, Token};
+use crate::parse::tree::{brace_name, kebab};
+
+use std::fmt;
+
+/// The version of the JSON written by `--format json`. It goes up whenever a field is removed
+/// or changes meaning; new fields may appear without it changing.
+///
+/// Every document is an object `{"version", "kind", ..., "diagnostics"}` where `kind` is the
+/// command that wrote it and `diagnostics` lists the errors, empty on success:
+///
+/// - `lex`: `"lexes"`, every lex read before the first error.
+/// - `parse`: `"scopes"`, every scope in the order it was opened, empty on error.
+/// - `check`: `"types"` by id and `"generics"` by name. Both are empty when the source does not
+///   parse, or when its declarations do not, such as an unresolved name or a type that cannot be
+///   assembled. A method body that cannot be lowered leaves them as declared.
+///
+/// A span is `{"start", "end"}` in bytes from the start of the source, `end` exclusive. Names of
+/// operators, braces and value formats are their kebab-case spellings, as in `range-inclusive`.
+///
+/// - Diagnostic: `{"code", "message", "span": span or null, "notes": [string]}`.
+/// - Lex: `{"kind", "span"}` with, by kind, `identifier`, `integer`, `float`: `"text"`;
+///   `keyword`: `"keyword"`; `operator`: `"operator"`; `delimiter`: `"delimiter"`;
+///   `brace`: `"brace"`, `"status"` of `open`, `close` or `agnostic`, `"level"`, and
+///   `"text"` for chars, quotes and comments.
+/// - Scope: `{"index", "parent": index or null, "brace": name or null, "declared",
+///   "undeclared": [string], "docs": string or null, "tokens": [token]}`.
+/// - Token: `{"kind"}` with, by kind, `identifier`: `"name"`; `keyword`: `"keyword"`;
+///   `integer`, `unsigned`, `byte`, `float`, `char`, `string`: `"value"`; `void`; `operator`:
+///   `"operator"`, `"operands"`; `block`: `"brace"`, `"scope"`, the index of the scope holding
+///   its tokens; `interpolate`: `"prefix"`, `"parts"`, `"suffix"`; `constructor`: `"name"`,
+///   `"parameters"`; `generic`: `"name"`, `"arguments"`; `generic-constructor`: `"name"`,
+///   `"arguments"`, `"parameters"`; `array`: `"elements"`; `index`: `"collection"`,
+///   `"indices"`; `variable`: `"type"`, `"name"`; `bind`: `"name"`, `"value"`; `type`:
+///   `"name"`, `"parameters"` or null, `"body"`; `method`: `"mode"` of `calc` or `trans`,
+///   `"name"`, `"parameters"` or null, `"intake"`, `"body"`; `delimiter`.
+/// - Type: `{"id", "name", "kind"}` of `composition`, `data` or `interface`, with `"bytes"` and
+///   `"methods"`; compositions also have `"fields"`, each `{"name", "type", "offset",
+///   "bytes"}`. A method is `{"name", "parameters": [{"name", "type"}], "produces", "consume",
+///   "produce"}`, types given by id.
+/// - Generic: `{"name", "parameters": [string], "instances": [{"id", "arguments": [id]}]}`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A JSON value. Objects keep their fields in the order they were added.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+    pub fn string(text: &str) -> Json {
+        Json::String(text.to_string())
+    }
+    fn strings<'a, I: IntoIterator<Item = &'a String>>(texts: I) -> Json {
+        let mut texts = texts.into_iter().collect::<Vec<_>>();
+        texts.sort();
+        Json::Array(texts.into_iter().map(|t| Json::string(t)).collect())
+    }
+    fn or_null<T, F: FnOnce(T) -> Json>(value: Option<T>, f: F) -> Json {
+        value.map(f).unwrap_or(Json::Null)
+    }
+
+    /// The value of the field `name`, for an object that has one.
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None,
+        }
+    }
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Json::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Parses a single value with nothing but whitespace around it. Numbers without a fraction
+    /// or exponent are integers.
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            index: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(JsonError::UnexpectedCharacter(parser.index)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonError {
+    UnexpectedEnd,
+    UnexpectedCharacter(usize),
+    InvalidNumber(usize),
+    InvalidEscape(usize),
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.index).copied()
+    }
+    fn whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.index += 1;
+        }
+    }
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(b) if b == byte => {
+                self.index += 1;
+                Ok(())
+            }
+            Some(_) => Err(JsonError::UnexpectedCharacter(self.index)),
+            None => Err(JsonError::UnexpectedEnd),
+        }
+    }
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        if self.bytes[self.index..].starts_with(word.as_bytes()) {
+            self.index += word.len();
+            Ok(value)
+        } else {
+            Err(JsonError::UnexpectedCharacter(self.index))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.whitespace();
+        match self.peek() {
+            None => Err(JsonError::UnexpectedEnd),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.index += 1;
+                let mut values = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b']') {
+                    self.index += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.whitespace();
+                    if self.peek() == Some(b',') {
+                        self.index += 1;
+                    } else {
+                        self.expect(b']')?;
+                        return Ok(Json::Array(values));
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.index += 1;
+                let mut fields = Vec::new();
+                self.whitespace();
+                if self.peek() == Some(b'}') {
+                    self.index += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.whitespace();
+                    let name = self.string()?;
+                    self.whitespace();
+                    self.expect(b':')?;
+                    fields.push((name, self.value()?));
+                    self.whitespace();
+                    if self.peek() == Some(b',') {
+                        self.index += 1;
+                    } else {
+                        self.expect(b'}')?;
+                        return Ok(Json::Object(fields));
+                    }
+                }
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            Some(_) => Err(JsonError::UnexpectedCharacter(self.index)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.index;
+        let mut integer = true;
+        while let Some(b) = self.peek() {
+            match b {
+                b'0'..=b'9' | b'-' | b'+' => {}
+                b'.' | b'e' | b'E' => integer = false,
+                _ => break,
+            }
+            self.index += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.index]).unwrap();
+        let number = if integer {
+            text.parse().map(Json::Integer).ok()
+        } else {
+            text.parse().map(Json::Float).ok()
+        };
+        number.ok_or(JsonError::InvalidNumber(start))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let b = self.peek().ok_or(JsonError::UnexpectedEnd)?;
+            self.index += 1;
+            match b {
+                b'"' => return Ok(String::from_utf8(out).unwrap()),
+                b'\\' => {
+                    let escape = self.index - 1;
+                    let c = match self.peek().ok_or(JsonError::UnexpectedEnd)? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            self.index += 1;
+                            let mut code = self.hex(escape)?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                if !self.bytes[self.index..].starts_with(b"\\u") {
+                                    return Err(JsonError::InvalidEscape(escape));
+                                }
+                                self.index += 2;
+                                let low = self.hex(escape)?;
+                                code =
+                                    0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00));
+                            }
+                            let c = std::char::from_u32(code)
+                                .ok_or(JsonError::InvalidEscape(escape))?;
+                            let mut buffer = [0; 4];
+                            out.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                            continue;
+                        }
+                        _ => return Err(JsonError::InvalidEscape(escape)),
+                    };
+                    self.index += 1;
+                    let mut buffer = [0; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+                b => out.push(b),
+            }
+        }
+    }
+    /// The four hex digits of a `\\u` escape.
+    fn hex(&mut self, escape: usize) -> Result<u32, JsonError> {
+        let digits = self
+            .bytes
+            .get(self.index..self.index + 4)
+            .ok_or(JsonError::UnexpectedEnd)?;
+        let code = std::str::from_utf8(digits)
+            .ok()
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or(JsonError::InvalidEscape(escape))?;
+        self.index += 4;
+        Ok(code)
+    }
+}
+
+/// Compact JSON on one line.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Integer(n) => write!(f, "{}", n),
+            Json::Float(n) if n.is_finite() => write!(f, "{:?}", n),
+            Json::Float(_) => f.write_str("null"),
+            Json::String(text) => write_string(text, f),
+            Json::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(name, f)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(text: &str, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+fn document(kind: &str, name: &str, value: Json, diagnostics: &[Diagnostic]) -> Json {
+    Json::object(vec![
+        ("version", Json::Integer(SCHEMA_VERSION as i128)),
+        ("kind", Json::string(kind)),
+        (name, value),
+        (
+            "diagnostics",
+            Json::Array(diagnostics.iter().map(diagnostic).collect()),
+        ),
+    ])
+}
+
+/// The `lex` document of `source`.
+pub fn lex_document(source: &[u8]) -> Json {
+    let mut lexes = Vec::new();
+    let mut diagnostics = Vec::new();
+    for spanned in Lexer::new(source) {
+        match spanned {
+            Ok(spanned) => lexes.push(lex(&spanned)),
+            Err(err) => diagnostics.push(Diagnostic::from_lex(&err)),
+        }
+    }
+    document("lex", "lexes", Json::Array(lexes), &diagnostics)
+}
+
+/// The `parse` document of `source`.
+pub fn parse_document(source: &[u8]) -> Json {
+    match parse_tokens(source) {
+        Ok(tokens) => document("parse", "scopes", scopes(&tokens), &[]),
+        Err(err) => document(
+            "parse",
+            "scopes",
+            Json::Array(Vec::new()),
+            &[Diagnostic::from_token(&err)],
+        ),
+    }
+}
+
+/// The `check` document of `source`.
+pub fn check_document(source: &[u8]) -> Json {
+    // the declared types are reported even when a method body cannot be lowered
+    let declared = parse_tokens(source)
+        .map_err(|err| vec![Diagnostic::from_token(&err)])
+        .and_then(|tokens| {
+            let mut types = declare_root(&tokens).map_err(|err| Diagnostic::from_assembly(&err))?;
+            let diagnostics = match lower_root(&tokens, &mut types) {
+                Ok(()) => Vec::new(),
+                Err(err) => Diagnostic::from_assembly(&err),
+            };
+            Ok((types, diagnostics))
+        });
+    let (types, generics, diagnostics) = match declared {
+        Ok((types, diagnostics)) => (assembled_types(&types), generics(&types), diagnostics),
+        Err(diagnostics) => (
+            Json::Array(Vec::new()),
+            Json::Array(Vec::new()),
+            diagnostics,
+        ),
+    };
+    let mut document = document("check", "types", types, &diagnostics);
+    if let Json::Object(fields) = &mut document {
+        fields.insert(3, ("generics".to_string(), generics));
+    }
+    document
+}
+
+/// Whether a document has no diagnostics.
+pub fn succeeded(document: &Json) -> bool {
+    document.get("diagnostics") == Some(&Json::Array(Vec::new()))
+}
+
+pub fn diagnostic(diagnostic: &Diagnostic) -> Json {
+    Json::object(vec![
+        ("code", Json::string(&diagnostic.code)),
+        ("message", Json::string(&diagnostic.message)),
+        ("span", Json::or_null(diagnostic.span, span)),
+        (
+            "notes",
+            Json::Array(diagnostic.notes.iter().map(|n| Json::string(n)).collect()),
+        ),
+    ])
+}
+
+fn span(span: Span) -> Json {
+    Json::object(vec![
+        ("start", Json::Integer(span.start as i128)),
+        ("end", Json::Integer(span.end as i128)),
+    ])
+}
+
+pub fn lex(spanned: &Spanned<Lex>) -> Json {
+    let mut fields = match &spanned.node {
+        Lex::Identifier(text, _) => vec![
+            ("kind", Json::string("identifier")),
+            ("text", Json::string(text)),
+        ],
+        Lex::Keyword(keyword, _) => vec![
+            ("kind", Json::string("keyword")),
+            ("keyword", Json::string(keyword.spelling())),
+        ],
+        Lex::Integer(text, _) => vec![
+            ("kind", Json::string("integer")),
+            ("text", Json::string(text)),
+        ],
+        Lex::Float(text, _) => vec![
+            ("kind", Json::string("float")),
+            ("text", Json::string(text)),
+        ],
+        Lex::Operator(op, _) => vec![
+            ("kind", Json::string("operator")),
+            ("operator", Json::String(kebab(op))),
+        ],
+        Lex::Brace(brace, status, level, _) => {
+            let status = match status {
+                BraceStatus::Open => "open",
+                BraceStatus::Close => "close",
+                BraceStatus::Agnostic => "agnostic",
+            };
+            let mut fields = vec![
+                ("kind", Json::string("brace")),
+                ("brace", Json::string(brace_name(brace))),
+                ("status", Json::string(status)),
+                ("level", Json::Integer(*level as i128)),
+            ];
+            match brace {
+                Brace::Char(c) => fields.push(("text", Json::String(c.to_string()))),
+                Brace::Quote(text) | Brace::Comment(text) | Brace::DocComment(text) => {
+                    fields.push(("text", Json::string(text)))
+                }
+                _ => {}
+            }
+            fields
+        }
+        Lex::Delimiter(delimiter) => vec![
+            ("kind", Json::string("delimiter")),
+            (
+                "delimiter",
+                Json::string(match delimiter {
+                    Delimiter::Comma => "comma",
+                    Delimiter::Semicolon => "semicolon",
+                }),
+            ),
+        ],
+    };
+    fields.push((
+        "span",
+        span(Span {
+            start: spanned.start,
+            end: spanned.end,
+        }),
+    ));
+    Json::object(fields)
+}
+
+/// Every scope of `tokens` in the order it was opened.
+pub fn scopes(tokens: &TokenizerContext) -> Json {
+    let mut scopes = Vec::new();
+    while tokens.get_scope(scopes.len()).is_some() {
+        scopes.push(scope(tokens, scopes.len()));
+    }
+    Json::Array(scopes)
+}
+
+pub fn scope(tokens: &TokenizerContext, index: usize) -> Json {
+    let scope = tokens.get_scope(index).unwrap();
+    let brace = match scope.get_brace_state() {
+        BraceState::Braced(brace, _) => Json::string(brace_name(&brace)),
+        BraceState::None => Json::Null,
+    };
+    Json::object(vec![
+        ("index", Json::Integer(index as i128)),
+        (
+            "parent",
+            Json::or_null(scope.get_parent(), |p| Json::Integer(p as i128)),
+        ),
+        ("brace", brace),
+        ("declared", Json::strings(scope.get_declared_identifiers())),
+        (
+            "undeclared",
+            Json::strings(scope.get_undeclared_identifiers()),
+        ),
+        (
+            "docs",
+            Json::or_null(tokens.get_docs(index), |d| Json::string(d)),
+        ),
+        (
+            "tokens",
+            Json::Array(scope.get_tokens().iter().map(|t| token(t)).collect()),
+        ),
+    ])
+}
+
+pub fn token(token: &Token) -> Json {
+    let kind = |kind: &str| ("kind", Json::string(kind));
+    let name = |name: &str| ("name", Json::string(name));
+    let optional = |t: &Option<Box<Token>>| Json::or_null(t.as_ref(), |t| self::token(t));
+    Json::object(match token {
+        Token::Literal(literal) => return self::literal(literal),
+        Token::Operator(group) => {
+            let (op, operands) = match group {
+                OperatorGroup::UniOperator(op, a) => (op, vec![a]),
+                OperatorGroup::BiOperator(op, a, b) => (op, vec![a, b]),
+                OperatorGroup::TriOperator(op, a, b, c) => (op, vec![a, b, c]),
+            };
+            vec![
+                kind("operator"),
+                ("operator", Json::String(kebab(op))),
+                (
+                    "operands",
+                    Json::Array(operands.into_iter().map(|t| self::token(t)).collect()),
+                ),
+            ]
+        }
+        Token::Block(brace, scope) => vec![
+            kind("block"),
+            ("brace", Json::string(brace_name(brace))),
+            ("scope", Json::Integer(*scope as i128)),
+        ],
+        Token::AgrandizedString(prefix, parts, suffix) => vec![
+            kind("interpolate"),
+            ("prefix", Json::string(prefix)),
+            ("parts", self::token(parts)),
+            ("suffix", Json::string(suffix)),
+        ],
+        Token::Constructor(n, parameters) => vec![
+            kind("constructor"),
+            name(n),
+            ("parameters", self::token(parameters)),
+        ],
+        Token::Generic(n, arguments) => vec![
+            kind("generic"),
+            name(n),
+            ("arguments", self::token(arguments)),
+        ],
+        Token::GenericConstructor(n, arguments, parameters) => vec![
+            kind("generic-constructor"),
+            name(n),
+            ("arguments", self::token(arguments)),
+            ("parameters", self::token(parameters)),
+        ],
+        Token::Array(elements) => vec![kind("array"), ("elements", self::token(elements))],
+        Token::Index(collection, indices) => vec![
+            kind("index"),
+            ("collection", self::token(collection)),
+            ("indices", self::token(indices)),
+        ],
+        Token::VariableDef(ty, n) => vec![kind("variable"), ("type", literal(ty)), name(n)],
+        Token::Binding(n, value) => vec![kind("bind"), name(n), ("value", self::token(value))],
+        Token::TypeDef(n, parameters, body) => vec![
+            kind("type"),
+            ("name", literal(n)),
+            ("parameters", optional(parameters)),
+            ("body", self::token(body)),
+        ],
+        Token::MethodDef(mode, n, parameters, intake, body) => vec![
+            kind("method"),
+            ("mode", Json::string(mode.spelling())),
+            name(n),
+            ("parameters", optional(parameters)),
+            ("intake", self::token(intake)),
+            ("body", self::token(body)),
+        ],
+        Token::Delimiter => vec![kind("delimiter")],
+    })
+}
+
+fn literal(literal: &Literal) -> Json {
+    let value = |kind: &str, value: Json| {
+        Json::object(vec![("kind", Json::string(kind)), ("value", value)])
+    };
+    match literal {
+        Literal::Keyword(keyword) => Json::object(vec![
+            ("kind", Json::string("keyword")),
+            ("keyword", Json::string(keyword.spelling())),
+        ]),
+        Literal::Identifier(name) => Json::object(vec![
+            ("kind", Json::string("identifier")),
+            ("name", Json::string(name)),
+        ]),
+        Literal::Number(Number::Integer(n)) => value("integer", Json::Integer(*n as i128)),
+        Literal::Number(Number::UnsignedInt(n)) => value("unsigned", Json::Integer(*n as i128)),
+        Literal::Number(Number::Byte(n)) => value("byte", Json::Integer(*n as i128)),
+        Literal::Number(Number::Float(n)) => value("float", Json::Float(*n)),
+        Literal::Number(Number::Char(c)) => value("char", Json::String(c.to_string())),
+        Literal::String(text) => value("string", Json::string(text)),
+        Literal::Void => Json::object(vec![("kind", Json::string("void"))]),
+    }
+}
+
+/// Every type by id with its layout and methods.
+pub fn assembled_types(types: &AssembledTypeContext) -> Json {
+    let mut values = Vec::new();
+    let mut id = 0u16;
+    while let Some(ty) = types.get_type(&id) {
+        values.push(assembled_type(types, id, ty));
+        id += 1;
+    }
+    Json::Array(values)
+}
+
+pub fn assembled_type(types: &AssembledTypeContext, id: u16, ty: &AssembledType) -> Json {
+    let kind = match ty {
+        AssembledType::Composition(_, _) => "composition",
+        AssembledType::Data(_, _) => "data",
+        AssembledType::Interface(_, _) => "interface",
+    };
+    let mut fields = vec![
+        ("id", Json::Integer(id as i128)),
+        ("name", Json::String(ty.get_name())),
+        ("kind", Json::string(kind)),
+        ("bytes", Json::Integer(ty.get_bytes(types) as i128)),
+    ];
+    if let AssembledType::Composition(_, composition) = ty {
+        let mut offset = 0u16;
+        let mut layout = Vec::new();
+        for field in composition.get_fields().iter() {
+            let bytes = types
+                .get_type(&field.get_class_id())
+                .unwrap()
+                .get_bytes(types);
+            layout.push(Json::object(vec![
+                ("name", Json::string(field.get_name())),
+                ("type", Json::Integer(field.get_class_id() as i128)),
+                ("offset", Json::Integer(offset as i128)),
+                ("bytes", Json::Integer(bytes as i128)),
+            ]));
+            offset += bytes;
+        }
+        fields.push(("fields", Json::Array(layout)));
+    }
+    let mut methods = Vec::new();
+    if let Some(implemented) = types.get_impl(&id) {
+        let mut names = implemented.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let method = &implemented[name];
+            let parameters = method
+                .get_parameters()
+                .iter()
+                .map(|p| {
+                    Json::object(vec![
+                        ("name", Json::string(p.get_name())),
+                        ("type", Json::Integer(p.get_class_id() as i128)),
+                    ])
+                })
+                .collect();
+            methods.push(Json::object(vec![
+                ("name", Json::string(method.get_name())),
+                ("parameters", Json::Array(parameters)),
+                ("produces", Json::Integer(method.get_produce_type() as i128)),
+                ("consume", Json::String(kebab(&method.get_consume()))),
+                ("produce", Json::String(kebab(&method.get_produce()))),
+            ]));
+        }
+    }
+    fields.push(("methods", Json::Array(methods)));
+    Json::object(fields)
+}
+
+/// Every generic type by name with the instances made of it.
+pub fn generics(types: &AssembledTypeContext) -> Json {
+    let generics = types
+        .get_generic_type_names()
+        .into_iter()
+        .map(|name| {
+            let instances = types
+                .get_instances_of(name)
+                .into_iter()
+                .map(|(id, arguments)| {
+                    Json::object(vec![
+                        ("id", Json::Integer(id as i128)),
+                        (
+                            "arguments",
+                            Json::Array(
+                                arguments
+                                    .into_iter()
+                                    .map(|a| Json::Integer(a as i128))
+                                    .collect(),
+                            ),
+                        ),
+                    ])
+                })
+                .collect();
+            Json::object(vec![
+                ("name", Json::string(name)),
+                (
+                    "parameters",
+                    Json::strings(types.get_generic_type(name).unwrap().get_parameters()),
+                ),
+                ("instances", Json::Array(instances)),
+            ])
+        })
+        .collect();
+    Json::Array(generics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strings_are_escaped() {
+        let value = Json::object(vec![
+            ("text", Json::string("a \"b\"\n\\\u{1}")),
+            ("n", Json::Float(f64::NAN)),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            "{\"text\":\"a \\\"b\\\"\\n\\\\\\u0001\",\"n\":null}"
+        );
+    }
+    #[test]
+    fn test_parse() {
+        let text =
+            "{\"a\": [1, -2.5e1, true, null], \"b\": \"x\\n\\u00e9\\ud83d\\ude00\\\"\", \"c\": {}}";
+        let value = Json::parse(text).unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Json::Array(vec![
+                Json::Integer(1),
+                Json::Float(-25.0),
+                Json::Bool(true),
+                Json::Null
+            ]))
+        );
+        assert_eq!(
+            value.get("b").and_then(Json::as_str),
+            Some("x\n\u{e9}\u{1f600}\"")
+        );
+        assert_eq!(Json::parse(&value.to_string()), Ok(value));
+        assert_eq!(Json::parse("[1,"), Err(JsonError::UnexpectedEnd));
+        assert_eq!(Json::parse("[1] x"), Err(JsonError::UnexpectedCharacter(4)));
+        assert_eq!(Json::parse("\"\\q\""), Err(JsonError::InvalidEscape(1)));
+    }
+    #[test]
+    fn test_lex_document() {
+        let document = lex_document(b"type P: (int(x))");
+        assert!(succeeded(&document));
+        assert!(document.to_string().starts_with(
+            "{\"version\":1,\"kind\":\"lex\",\"lexes\":[{\"kind\":\"keyword\",\"keyword\":\"type\",\"span\":{\"start\":0,\"end\":4}},"
+        ));
+        let document = lex_document(b"x \"y");
+        assert!(!succeeded(&document));
+        assert!(document.to_string().ends_with(
+            "\"diagnostics\":[{\"code\":\"lex::QuoteNotEnded\",\"message\":\"string is never closed\",\"span\":{\"start\":2,\"end\":2},\"notes\":[]}]}"
+        ));
+    }
+    #[test]
+    fn test_parse_document() {
+        let document = parse_document(b"n: Point(1, 2);");
+        let scopes = match document.get("scopes") {
+            Some(Json::Array(scopes)) => scopes,
+            _ => panic!("no scopes"),
+        };
+        assert_eq!(scopes.len(), 2);
+        assert_eq!(
+            scopes[1].to_string(),
+            "{\"index\":1,\"parent\":0,\"brace\":\"paren\",\"declared\":[],\"undeclared\":[],\"docs\":null,\"tokens\":[{\"kind\":\"integer\",\"value\":1},{\"kind\":\"integer\",\"value\":2}]}"
+        );
+        assert!(scopes[0].to_string().contains(
+            "{\"kind\":\"bind\",\"name\":\"n\",\"value\":{\"kind\":\"constructor\",\"name\":\"Point\",\"parameters\":{\"kind\":\"block\",\"brace\":\"paren\",\"scope\":1}}}"
+        ));
+        assert!(!succeeded(&parse_document(b"{}}")));
+    }
+    #[test]
+    fn test_check_document() {
+        let source = std::fs::read("res/test/range_0.geo").unwrap();
+        let document = check_document(&source);
+        assert!(succeeded(&document));
+        let text = document.to_string();
+        assert!(
+            text.contains("{\"id\":2,\"name\":\"int\",\"kind\":\"data\",\"bytes\":4,\"methods\":[")
+        );
+        assert!(text.contains(
+            "\"fields\":[{\"name\":\"x\",\"type\":2,\"offset\":0,\"bytes\":4},{\"name\":\"y\",\"type\":2,\"offset\":4,\"bytes\":4}]"
+        ));
+        let document = check_document(b"calc start: () { 1 => pritn; }");
+        assert_eq!(document.get("types"), Some(&Json::Array(Vec::new())));
+        assert!(!succeeded(&document));
+        let document = check_document(b"type Point: (int(x), int(y),)");
+        assert!(!succeeded(&document));
+        let text = document.to_string();
+        assert!(text.contains("\"name\":\"Point\",\"kind\":\"composition\""));
+        assert!(text.contains("\"code\":\"assembly::NoStartMethodFound\""));
+    }
+}
@@ -0,0 +1,891 @@
+use crate::lang::assemble::context::AssembledTypeContext;
+use crate::lang::assemble::lower::{is_printable, lower_statement, Bindings};
+use crate::lang::assemble::print::{MethodSignature, TypeLayout};
+use crate::lang::assemble::{assemble_root, assemble_types, lower_methods};
+use crate::lang::diagnostic::{Diagnostic, Span};
+use crate::lang::json::Json;
+use crate::parse::constant::{Brace, Delimiter, Keyword, Operator};
+use crate::parse::context::TokenizerContext;
+use crate::parse::lex::{BraceStatus, Lex, Lexer, Spanned};
+use crate::parse::token::{parse_tokens, Literal, Token};
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// The name the statement being completed is bound to while it is typechecked.
+const COMPLETED: &str = "__completed";
+
+const PARSE_ERROR: i128 = -32700;
+const INVALID_REQUEST: i128 = -32600;
+const METHOD_NOT_FOUND: i128 = -32601;
+
+/// The longest message body accepted, so a bad header cannot make the server allocate at will.
+const MAX_CONTENT_LENGTH: usize = 64 << 20;
+
+const SEVERITY_ERROR: i128 = 1;
+const SYMBOL_METHOD: i128 = 6;
+const SYMBOL_FUNCTION: i128 = 12;
+const SYMBOL_STRUCT: i128 = 23;
+const COMPLETION_METHOD: i128 = 2;
+const COMPLETION_FUNCTION: i128 = 3;
+
+/// Serves the Language Server Protocol until the client sends `exit` or closes `input`, giving
+/// back the code to exit with: success only when the client asked to shut down first.
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<i32> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match Json::parse(&body) {
+            Ok(message) => server.handle(&message),
+            Err(err) => vec![error(
+                Json::Null,
+                PARSE_ERROR,
+                format!("message is not JSON: {:?}", err),
+            )],
+        };
+        for reply in replies.iter() {
+            write_message(&mut output, reply)?;
+        }
+        if let Some(code) = server.exit {
+            return Ok(code);
+        }
+    }
+    Ok(if server.shut_down { 0 } else { 1 })
+}
+
+/// Reads the body of the next message, framed by a `Content-Length` header, or gives back `None`
+/// at the end of the input. A header block without a length, or with one past
+/// `MAX_CONTENT_LENGTH`, is an error.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut length = None;
+    let mut headers = false;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse::<usize>().map_err(invalid_data)?);
+        } else if line.is_empty() && headers {
+            break;
+        }
+        headers |= !line.is_empty();
+    }
+    let length = match length {
+        Some(length) if length <= MAX_CONTENT_LENGTH => length,
+        Some(length) => {
+            return Err(invalid_data(format!(
+                "Content-Length {} is over the limit of {}",
+                length, MAX_CONTENT_LENGTH
+            )))
+        }
+        None => return Err(invalid_data("message has no Content-Length")),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body).map(Some).map_err(invalid_data)
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn invalid_data<E: ToString>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+/// The documents a client has open, analysed again whenever they change.
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shut_down: bool,
+    exit: Option<i32>,
+}
+impl Server {
+    pub fn new() -> Server {
+        Server {
+            documents: HashMap::new(),
+            shut_down: false,
+            exit: None,
+        }
+    }
+
+    /// Handles one request or notification, giving back the messages to send to the client.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.notify(method, params),
+        };
+        if self.shut_down {
+            return vec![error(
+                id,
+                INVALID_REQUEST,
+                "server is shut down".to_string(),
+            )];
+        }
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => {
+                self.shut_down = true;
+                Json::Null
+            }
+            "textDocument/hover" => self.at(params, hover),
+            "textDocument/definition" => self.at(params, definition),
+            "textDocument/completion" => self.at(params, completion),
+            "textDocument/documentSymbol" => match self.document(params) {
+                Some(document) => symbols(document),
+                None => Json::Null,
+            },
+            _ => {
+                return vec![error(
+                    id,
+                    METHOD_NOT_FOUND,
+                    format!("unknown method `{}`", method),
+                )]
+            }
+        };
+        vec![Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("id", id),
+            ("result", result),
+        ])]
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = match field(params, &["textDocument", "uri"]).and_then(Json::as_str) {
+            Some(uri) => uri.to_string(),
+            None if method == "exit" => {
+                self.exit = Some(if self.shut_down { 0 } else { 1 });
+                return Vec::new();
+            }
+            None => return Vec::new(),
+        };
+        let text = match method {
+            "textDocument/didOpen" => field(params, &["textDocument", "text"]),
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text")),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish(&uri, "", &[])];
+            }
+            _ => return Vec::new(),
+        };
+        match text.and_then(Json::as_str) {
+            Some(text) => {
+                let mut document = Document::new(text.to_string());
+                if document.tokens.is_none() {
+                    if let Some(previous) = self.documents.remove(&uri) {
+                        document.tokens = previous.tokens;
+                        document.types = previous.types;
+                    }
+                }
+                let message = publish(&uri, &document.text, &document.diagnostics);
+                self.documents.insert(uri, document);
+                vec![message]
+            }
+            None => Vec::new(),
+        }
+    }
+
+    fn document(&self, params: &Json) -> Option<&Document> {
+        let uri = field(params, &["textDocument", "uri"]).and_then(Json::as_str)?;
+        self.documents.get(uri)
+    }
+
+    /// Answers a request about a position in a document, or with null for a document that is
+    /// not open.
+    fn at(&self, params: &Json, answer: fn(&Document, &str, usize) -> Json) -> Json {
+        let position = (
+            field(params, &["position", "line"]).and_then(Json::as_integer),
+            field(params, &["position", "character"]).and_then(Json::as_integer),
+        );
+        match (self.document(params), position) {
+            (Some(document), (Some(line), Some(character))) => {
+                let uri = field(params, &["textDocument", "uri"])
+                    .and_then(Json::as_str)
+                    .unwrap();
+                let offset = offset(&document.text, line as usize, character as usize);
+                answer(document, uri, offset)
+            }
+            _ => Json::Null,
+        }
+    }
+}
+impl Default for Server {
+    fn default() -> Server {
+        Server::new()
+    }
+}
+
+fn field<'a>(json: &'a Json, path: &[&str]) -> Option<&'a Json> {
+    path.iter().try_fold(json, |json, name| json.get(name))
+}
+
+fn error(id: Json, code: i128, message: String) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object(vec![
+                ("code", Json::Integer(code)),
+                ("message", Json::String(message)),
+            ]),
+        ),
+    ])
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                ("textDocumentSync", Json::Integer(1)),
+                ("hoverProvider", Json::Bool(true)),
+                ("definitionProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+                (
+                    "completionProvider",
+                    Json::object(vec![(
+                        "triggerCharacters",
+                        Json::Array(vec![Json::string(">")]),
+                    )]),
+                ),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![
+                ("name", Json::string("intolang")),
+                ("version", Json::string(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+fn publish(uri: &str, text: &str, diagnostics: &[Diagnostic]) -> Json {
+    let diagnostics = diagnostics
+        .iter()
+        .map(|d| {
+            let mut message = d.message.clone();
+            for note in d.notes.iter() {
+                message.push_str("\nnote: ");
+                message.push_str(note);
+            }
+            Json::object(vec![
+                ("range", range(text, d.span.unwrap_or_else(|| Span::at(0)))),
+                ("severity", Json::Integer(SEVERITY_ERROR)),
+                ("code", Json::string(&d.code)),
+                ("source", Json::string("intolang")),
+                ("message", Json::String(message)),
+            ])
+        })
+        .collect();
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("method", Json::string("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Json::object(vec![
+                ("uri", Json::string(uri)),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+/// An open document with what could be made of it: every lex up to the first error, the tokens
+/// if it parses, its declared types if they assemble, and the errors of every stage. While a
+/// change does not parse, the tokens and types of the last version that did are kept.
+struct Document {
+    text: String,
+    lexes: Vec<Spanned<Lex<'static>>>,
+    tokens: Option<TokenizerContext>,
+    types: Option<AssembledTypeContext>,
+    diagnostics: Vec<Diagnostic>,
+}
+impl Document {
+    fn new(text: String) -> Document {
+        let lexes = Lexer::new(text.as_bytes())
+            .map_while(Result::ok)
+            .map(|spanned| Spanned {
+                node: spanned.node.shifted(0),
+                start: spanned.start,
+                end: spanned.end,
+            })
+            .collect();
+        let (tokens, types, diagnostics) = analyse(&text);
+        Document {
+            text,
+            lexes,
+            tokens,
+            types,
+            diagnostics,
+        }
+    }
+}
+
+type Analysis = (
+    Option<TokenizerContext>,
+    Option<AssembledTypeContext>,
+    Vec<Diagnostic>,
+);
+
+/// Parses and assembles `text`. The declared types are kept even when assembling `start` fails,
+/// so hover and completion keep working while the body is being written.
+fn analyse(text: &str) -> Analysis {
+    let tokens = match parse_tokens(text.as_bytes()) {
+        Ok(tokens) => tokens,
+        Err(err) => return (None, None, vec![Diagnostic::from_token(&err)]),
+    };
+    let (types, diagnostics) = match assemble_root(&tokens) {
+        Ok(types) => (Some(types), Vec::new()),
+        Err(err) => (
+            assemble_types(&tokens).ok(),
+            Diagnostic::from_assembly(&err),
+        ),
+    };
+    (Some(tokens), types, diagnostics)
+}
+
+/// The byte offset of an LSP position, whose character counts UTF-16 code units. Positions past
+/// the end of a line or of the text are moved back to it.
+fn offset(text: &str, line: usize, character: usize) -> usize {
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn position(text: &str, offset: usize) -> Json {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Json::object(vec![
+        ("line", Json::Integer(before.matches('\n').count() as i128)),
+        (
+            "character",
+            Json::Integer(before[line_start..].encode_utf16().count() as i128),
+        ),
+    ])
+}
+
+fn range(text: &str, span: Span) -> Json {
+    Json::object(vec![
+        ("start", position(text, span.start)),
+        ("end", position(text, span.end)),
+    ])
+}
+
+fn span(spanned: &Spanned<Lex>) -> Span {
+    Span {
+        start: spanned.start,
+        end: spanned.end,
+    }
+}
+
+/// The identifier under `offset`, counting the offset just past its end as on it.
+fn identifier_at<'a>(lexes: &'a [Spanned<Lex>], offset: usize) -> Option<(&'a str, Span)> {
+    lexes.iter().find_map(|spanned| match &spanned.node {
+        Lex::Identifier(name, _) if spanned.start <= offset && offset <= spanned.end => {
+            Some((name.as_ref(), span(spanned)))
+        }
+        _ => None,
+    })
+}
+
+/// A `type`, `calc` or `trans` declaration, found from the lexes so that it has a place in the
+/// source: the span of its name, and the span from its keyword to the end of its body.
+struct Declaration {
+    keyword: Keyword,
+    name: String,
+    name_span: Span,
+    span: Span,
+}
+
+fn declarations(lexes: &[Spanned<Lex>]) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    for (i, spanned) in lexes.iter().enumerate() {
+        let keyword = match spanned.node {
+            Lex::Keyword(keyword, _)
+                if matches!(keyword, Keyword::Type | Keyword::Calc | Keyword::Trans) =>
+            {
+                keyword
+            }
+            _ => continue,
+        };
+        let name = match lexes.get(i + 1) {
+            Some(name) => name,
+            None => continue,
+        };
+        if let Lex::Identifier(text, _) = &name.node {
+            let body = if keyword == Keyword::Type {
+                Brace::Brace
+            } else {
+                Brace::Bracket
+            };
+            declarations.push(Declaration {
+                keyword,
+                name: text.to_string(),
+                name_span: span(name),
+                span: Span {
+                    start: spanned.start,
+                    end: body_end(&lexes[i + 2..], &body).unwrap_or(name.end),
+                },
+            });
+        }
+    }
+    declarations
+}
+
+/// Where the first `brace` block of `lexes` closes, unless another declaration starts first.
+fn body_end(lexes: &[Spanned<Lex>], brace: &Brace) -> Option<usize> {
+    let mut depth = 0;
+    for spanned in lexes.iter() {
+        match &spanned.node {
+            Lex::Brace(b, BraceStatus::Open, _, _) if b == brace => depth += 1,
+            Lex::Brace(b, BraceStatus::Close, _, _) if b == brace && depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(spanned.end);
+                }
+            }
+            Lex::Keyword(Keyword::Type, _)
+            | Lex::Keyword(Keyword::Calc, _)
+            | Lex::Keyword(Keyword::Trans, _)
+                if depth == 0 =>
+            {
+                return None
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn symbols(document: &Document) -> Json {
+    let symbols = declarations(&document.lexes)
+        .into_iter()
+        .map(|d| {
+            let kind = match d.keyword {
+                Keyword::Type => SYMBOL_STRUCT,
+                Keyword::Calc => SYMBOL_FUNCTION,
+                _ => SYMBOL_METHOD,
+            };
+            Json::object(vec![
+                ("name", Json::String(d.name)),
+                ("detail", Json::string(d.keyword.spelling())),
+                ("kind", Json::Integer(kind)),
+                ("range", range(&document.text, d.span)),
+                ("selectionRange", range(&document.text, d.name_span)),
+            ])
+        })
+        .collect();
+    Json::Array(symbols)
+}
+
+/// The declarations of the name under the cursor.
+fn definition(document: &Document, uri: &str, offset: usize) -> Json {
+    let name = match identifier_at(&document.lexes, offset) {
+        Some((name, _)) => name,
+        None => return Json::Null,
+    };
+    let locations = declarations(&document.lexes)
+        .into_iter()
+        .filter(|d| d.name == name)
+        .map(|d| {
+            Json::object(vec![
+                ("uri", Json::string(uri)),
+                ("range", range(&document.text, d.name_span)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    if locations.is_empty() {
+        Json::Null
+    } else {
+        Json::Array(locations)
+    }
+}
+
+/// The declaration of the type or method under the cursor, as written, followed by the `///`
+/// docs of its declaration. Names declared outside the document show the signature of each
+/// method of that name.
+fn hover(document: &Document, _: &str, offset: usize) -> Json {
+    let (name, span) = match identifier_at(&document.lexes, offset) {
+        Some(identifier) => identifier,
+        None => return Json::Null,
+    };
+    let mut text = String::new();
+    for declaration in declarations(&document.lexes)
+        .iter()
+        .filter(|d| d.name == name)
+    {
+        text.push_str(header(document, declaration));
+        text.push('\n');
+        let id = match (&declaration.keyword, &document.types) {
+            (Keyword::Type, Some(types)) => {
+                types.get_type_id(&name.to_string()).map(|id| (types, id))
+            }
+            _ => None,
+        };
+        // the size of the type and where its fields are, without the id the listing starts with
+        if let Some((types, id)) = id {
+            let layout = TypeLayout(types, *id).to_string();
+            let prefix = format!("type {} ", id);
+            text.push_str(layout.strip_prefix(&prefix).unwrap_or(&layout));
+        }
+    }
+    if let (true, Some(types)) = (text.is_empty(), &document.types) {
+        let mut id = 0u16;
+        while let Some(ty) = types.get_type(&id) {
+            if let Some(method) = types.get_impl(&id).and_then(|m| m.get(name)) {
+                text.push_str(&format!(
+                    "{} {}\n",
+                    ty.get_name(),
+                    MethodSignature(types, method)
+                ));
+            }
+            id += 1;
+        }
+    }
+    if text.is_empty() {
+        return Json::Null;
+    }
+    let mut value = format!("```\n{}```", text);
+    if let Some(docs) = document.tokens.as_ref().and_then(|t| docs(t, name)) {
+        value.push_str("\n\n");
+        value.push_str(docs);
+    }
+    Json::object(vec![
+        (
+            "contents",
+            Json::object(vec![
+                ("kind", Json::string("markdown")),
+                ("value", Json::String(value)),
+            ]),
+        ),
+        ("range", range(&document.text, span)),
+    ])
+}
+
+/// The source of a declaration: all of a type, or a method up to its body.
+fn header<'a>(document: &'a Document, declaration: &Declaration) -> &'a str {
+    let end = match declaration.keyword {
+        Keyword::Type => declaration.span.end,
+        _ => document
+            .lexes
+            .iter()
+            .find(|l| {
+                l.start >= declaration.name_span.end
+                    && matches!(l.node, Lex::Brace(Brace::Bracket, BraceStatus::Open, _, _))
+            })
+            .map_or(declaration.span.end, |l| l.start),
+    };
+    document.text[declaration.span.start..end].trim_end()
+}
+
+/// The docs of the first type or method declared as `name` in the root scope.
+fn docs<'a>(tokens: &'a TokenizerContext, name: &str) -> Option<&'a String> {
+    tokens
+        .get_scope(0)?
+        .get_tokens()
+        .iter()
+        .find_map(|token| match token.as_ref() {
+            Token::TypeDef(Literal::Identifier(n), _, body)
+            | Token::MethodDef(_, n, _, _, body)
+                if n == name =>
+            {
+                match body.as_ref() {
+                    Token::Block(_, scope) => tokens.get_docs(*scope),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+}
+
+/// The methods that can follow the `=>` before the cursor, for the element type of the stream
+/// going into it.
+fn completion(document: &Document, _: &str, offset: usize) -> Json {
+    let lexes = &document.lexes;
+    let before = lexes.iter().take_while(|l| l.end <= offset).count();
+    let into = |i: usize| matches!(lexes[i].node, Lex::Operator(Operator::Into, _));
+    let (into, prefix) = match before.checked_sub(1).map(|i| (i, &lexes[i].node)) {
+        Some((i, _)) if into(i) => (i, ""),
+        Some((i, Lex::Identifier(name, _))) if i > 0 && into(i - 1) && lexes[i].end == offset => {
+            (i - 1, name.as_ref())
+        }
+        _ => return Json::Array(Vec::new()),
+    };
+    let (types, class_id) = match element_type(document, statement_start(lexes, into), into) {
+        Some(element) => element,
+        None => return Json::Array(Vec::new()),
+    };
+    let types = &types;
+    let mut items = Vec::new();
+    let item = |label: &str, kind: i128, detail: String| {
+        Json::object(vec![
+            ("label", Json::string(label)),
+            ("kind", Json::Integer(kind)),
+            ("detail", Json::String(detail)),
+        ])
+    };
+    let methods = types.get_impl(&class_id);
+    if let Some(methods) = methods {
+        let mut names = methods.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names.into_iter().filter(|n| n.starts_with(prefix)) {
+            let signature = MethodSignature(types, &methods[name]).to_string();
+            items.push(item(name, COMPLETION_METHOD, signature));
+        }
+    }
+    let has_print = methods.is_some_and(|m| m.contains_key("print"));
+    if !has_print && "print".starts_with(prefix) && is_printable(class_id, types) {
+        items.push(item("print", COMPLETION_FUNCTION, "built in".to_string()));
+    }
+    Json::Array(items)
+}
+
+/// The index of the first lex of the statement holding the lex at `end`.
+fn statement_start(lexes: &[Spanned<Lex>], end: usize) -> usize {
+    let mut start = end;
+    while start > 0 {
+        match lexes[start - 1].node {
+            Lex::Delimiter(Delimiter::Semicolon) | Lex::Brace(Brace::Bracket, _, _, _) => break,
+            _ => start -= 1,
+        }
+    }
+    start
+}
+
+/// The types of the document up to the statement of the lexes from `start` to `end`, with the
+/// type of the elements of that statement. The source before the statement is typechecked with
+/// the statement bound as the last one of its block, and its blocks closed after it.
+fn element_type(
+    document: &Document,
+    start: usize,
+    end: usize,
+) -> Option<(AssembledTypeContext, u16)> {
+    if start == end {
+        return None;
+    }
+    let lexes = &document.lexes;
+    let (from, to) = (lexes[start].start, lexes[end].start);
+    let mut source = format!(
+        "{}{}: {};",
+        &document.text[..from],
+        COMPLETED,
+        &document.text[from..to]
+    );
+    let mut open = Vec::new();
+    for lex in lexes[..end].iter() {
+        match &lex.node {
+            Lex::Brace(brace, BraceStatus::Open, _, _) => open.push(brace.clone()),
+            Lex::Brace(_, BraceStatus::Close, _, _) => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    for brace in open.iter().rev() {
+        source.push(match brace {
+            Brace::Bracket => '}',
+            Brace::Brace => ')',
+            Brace::Square => ']',
+            _ => return None,
+        });
+    }
+    let tokens = parse_tokens(source.as_bytes()).ok()?;
+    let mut types = assemble_types(&tokens).ok()?;
+    lower_methods(&tokens, &mut types).ok()?;
+    let (scope, context) = (0..)
+        .map_while(|i| tokens.get_scope(i).map(|scope| (i, scope)))
+        .find(|(_, scope)| scope.is_declared(COMPLETED))?;
+    let method = (0..)
+        .map_while(|id| types.get_type(&id).map(|_| id))
+        .find_map(|id| {
+            let methods = types.get_impl(&id)?;
+            let method = methods.values().find(|m| m.get_scope() == Some(scope))?;
+            Some((id, method))
+        });
+    let mut bindings = match method {
+        Some((id, method)) => Bindings::for_method(context, id, method, &types),
+        None => Bindings::new(context),
+    };
+    let mut class_id = None;
+    for statement in context.get_tokens().iter() {
+        if let Token::TypeDef(_, _, _) | Token::MethodDef(_, _, _, _, _) = statement.as_ref() {
+            continue;
+        }
+        let (statement_class, _) =
+            lower_statement(statement, &tokens, &types, &mut bindings).ok()?;
+        class_id = Some(statement_class);
+    }
+    Some((types, class_id?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(server: &mut Server, text: &str) -> Vec<Json> {
+        server.handle(
+            &Json::parse(&format!(
+                "{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/didOpen\",\"params\":{{\"textDocument\":{{\"uri\":\"file:///a.clf\",\"languageId\":\"intolang\",\"version\":1,\"text\":{}}}}}}}",
+                Json::string(text)
+            ))
+            .unwrap(),
+        )
+    }
+    fn request(server: &mut Server, method: &str, line: usize, character: usize) -> Json {
+        let message = Json::parse(&format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"{}\",\"params\":{{\"textDocument\":{{\"uri\":\"file:///a.clf\"}},\"position\":{{\"line\":{},\"character\":{}}}}}}}",
+            method, line, character
+        ))
+        .unwrap();
+        let mut replies = server.handle(&message);
+        assert_eq!(replies.len(), 1);
+        replies.pop().unwrap().get("result").unwrap().clone()
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "ab\n\u{e9}\u{1f600}x\n";
+        assert_eq!(offset(text, 0, 1), 1);
+        assert_eq!(offset(text, 1, 3), 9);
+        assert_eq!(offset(text, 1, 99), 10);
+        assert_eq!(offset(text, 5, 0), text.len());
+        assert_eq!(
+            position(text, 9).to_string(),
+            "{\"line\":1,\"character\":3}"
+        );
+    }
+    #[test]
+    fn test_framing() {
+        let mut input = "Content-Length: 2\r\n\r\n{}".as_bytes();
+        assert_eq!(read_message(&mut input).unwrap(), Some("{}".to_string()));
+        let mut input = "Content-Type: json\r\n\r\n{}".as_bytes();
+        assert!(read_message(&mut input).is_err());
+        let mut input = "Content-Length: 99999999999\r\n\r\n{}".as_bytes();
+        assert!(read_message(&mut input).is_err());
+    }
+    #[test]
+    fn test_diagnostics() {
+        let mut server = Server::new();
+        let published = open(
+            &mut server,
+            "type P: (int(x),)\ncalc start: () {\n  [P(1)] => pritn;\n}\n",
+        );
+        let diagnostics = field(&published[0], &["params", "diagnostics"]).unwrap();
+        assert!(diagnostics
+            .to_string()
+            .contains("\"code\":\"assembly::UnresolvedIdentifiers\""));
+        let published = open(&mut server, "x }");
+        assert!(field(&published[0], &["params", "diagnostics"])
+            .unwrap()
+            .to_string()
+            .contains("\"range\":{\"start\":{\"line\":0,\"character\":3}"));
+    }
+    #[test]
+    fn test_navigation() {
+        let mut server = Server::new();
+        let source = std::fs::read_to_string("res/test/doc_0.geo").unwrap();
+        open(&mut server, &source);
+        let hover = request(&mut server, "textDocument/hover", 14, 14);
+        let value = field(&hover, &["contents", "value"]).and_then(Json::as_str);
+        assert_eq!(
+            value,
+            Some("```\ntype Point: (int(x), int(y),)\nPoint: composition, 8 bytes\n  x: int at 0, 4 bytes\n  y: int at 4, 4 bytes\n```\n\nA point on the grid.\nBoth coordinates are whole numbers.")
+        );
+        let hover = request(&mut server, "textDocument/hover", 14, 41);
+        let value = field(&hover, &["contents", "value"]).and_then(Json::as_str);
+        assert_eq!(
+            value,
+            Some("```\ntrans swap<A, B>: Pair<A, B>\n```\n\nFlips the elements of a pair.")
+        );
+        let definition = request(&mut server, "textDocument/definition", 14, 25);
+        assert_eq!(
+            definition.to_string(),
+            "[{\"uri\":\"file:///a.clf\",\"range\":{\"start\":{\"line\":3,\"character\":5},\"end\":{\"line\":3,\"character\":10}}}]"
+        );
+        let symbols = request(&mut server, "textDocument/documentSymbol", 0, 0);
+        let names = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s.get("name").and_then(Json::as_str).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["Point", "Pair", "swap", "start"]);
+        assert_eq!(
+            field(&symbols.as_array().unwrap()[2], &["range", "end"])
+                .unwrap()
+                .to_string(),
+            "{\"line\":10,\"character\":1}"
+        );
+    }
+    #[test]
+    fn test_completion() {
+        let mut server = Server::new();
+        let source = std::fs::read_to_string("res/test/doc_0.geo").unwrap();
+        open(&mut server, &source);
+        let labels = |result: Json| {
+            result
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i.get("label").and_then(Json::as_str).unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            labels(request(&mut server, "textDocument/completion", 14, 39)),
+            vec!["swap", "print"]
+        );
+        open(&mut server, "type P: (int(x),)\nn: [P(1)];\n");
+        open(&mut server, "type P: (int(x),)\nn: [P(1)];\nn => pr");
+        assert_eq!(
+            labels(request(&mut server, "textDocument/completion", 2, 7)),
+            vec!["print"]
+        );
+        open(&mut server, "calc start: () { 1..3 => print; }");
+        open(&mut server, "calc start: () { 1..3 => ");
+        assert_eq!(
+            labels(request(&mut server, "textDocument/completion", 0, 25)),
+            vec!["cast", "print"]
+        );
+        // what comes out of a stage, and the fields of what a method takes in
+        let source = "type P: (int(x),)\ntrans up: P { x }\ncalc start: () {\n  [P(1)] => up => ";
+        open(&mut server, source);
+        assert_eq!(
+            labels(request(&mut server, "textDocument/completion", 3, 18)),
+            vec!["cast", "print"]
+        );
+        open(
+            &mut server,
+            "type P: (int(x),)\ntrans up: P { x }\ntrans f: P { [P(x)] => ",
+        );
+        assert_eq!(
+            labels(request(&mut server, "textDocument/completion", 2, 23)),
+            vec!["f", "up", "print"]
+        );
+    }
+}
@@ -1 +1,10 @@
 pub mod assemble;
+pub mod bytecode;
+pub mod diagnostic;
+pub mod doc;
+pub mod emit;
+pub mod format;
+pub mod interp;
+pub mod json;
+pub mod lsp;
+pub mod repl;
@@ -0,0 +1,505 @@
+use crate::lang::assemble::context::{AssembledTypeContext, CLASS_ID_VOID};
+use crate::lang::assemble::lower::{is_printable, lower_statement, Bindings};
+use crate::lang::assemble::print::TypeLayout;
+use crate::lang::assemble::{
+    assemble_root, assemble_types, declare_root, instantiate, lower_methods, write_program,
+    AssemblyError,
+};
+use crate::lang::diagnostic::{Diagnostic, Span};
+use crate::parse::context::TokenizerContext;
+use crate::parse::lex::{LexParseError, Lexer};
+use crate::parse::token::{parse_tokens, Literal, Token};
+use crate::parse::tree::sexpr;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::process::{self, Command};
+
+const PROMPT: &str = "> ";
+const CONTINUATION: &str = ". ";
+/// What diagnostics call the entry they locate a position in.
+const ENTRY: &str = "entry";
+
+const HELP: &str = "Enter type and method declarations to keep them for the rest of the session,
+`name: value` to bind a value, or an expression to run it. Values that are not printed are
+printed for you.
+
+  :type <expr>     Show the type of the elements an expression ends with
+  :layout <type>   Show the fields, offsets and size of a type
+  :tokens <expr>   Show the tokens an expression parses into
+  :history         List the entries so far
+  :reset           Forget every declaration and binding
+  :quit            Leave, as does the end of the input
+";
+
+/// What an entry came to.
+pub enum Evaluation {
+    /// Text to show as it is, nothing for an entry that only declares.
+    Text(String),
+    /// A program to run, its output being the result of the entry.
+    Program(Box<AssembledTypeContext>),
+    Quit,
+}
+
+/// The state kept between entries: the source of every declaration with the types assembled
+/// from it, the bindings made so far, and every entry in order.
+pub struct Session {
+    declarations: String,
+    tokens: TokenizerContext,
+    types: AssembledTypeContext,
+    bindings: Vec<String>,
+    history: Vec<String>,
+}
+impl Session {
+    pub fn new() -> Session {
+        let tokens = TokenizerContext::new();
+        let types = assemble_types(&tokens).unwrap();
+        Session {
+            declarations: String::new(),
+            tokens,
+            types,
+            bindings: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+    pub fn get_types(&self) -> &AssembledTypeContext {
+        &self.types
+    }
+    pub fn get_history(&self) -> &Vec<String> {
+        &self.history
+    }
+
+    /// Evaluates a complete entry, a command starting with `:` or source. The spans of the
+    /// diagnostics are into `entry`.
+    pub fn eval(&mut self, entry: &str) -> Result<Evaluation, Vec<Diagnostic>> {
+        let trimmed = entry.trim_start();
+        let lead = entry.len() - trimmed.len();
+        self.evaluate(trimmed.trim_end().trim_end_matches(';').trim_end())
+            .map_err(|diagnostics| onto_entry(diagnostics, 0, lead))
+    }
+
+    fn evaluate(&mut self, entry: &str) -> Result<Evaluation, Vec<Diagnostic>> {
+        if entry.is_empty() {
+            return Ok(Evaluation::Text(String::new()));
+        }
+        if entry != ":history" {
+            self.history.push(entry.to_string());
+        }
+        if let Some(command) = entry.strip_prefix(':') {
+            let (name, argument) = match command.find(char::is_whitespace) {
+                Some(i) => (&command[..i], command[i..].trim_start()),
+                None => (command, ""),
+            };
+            let at = entry.len() - argument.len();
+            return self
+                .command(name, argument.trim_end())
+                .map_err(|diagnostics| onto_entry(diagnostics, 0, at));
+        }
+        let parsed = parse(entry)?;
+        let statements = parsed.get_scope(0).unwrap().get_tokens();
+        let statements = statements
+            .iter()
+            .map(|t| t.as_ref())
+            .collect::<Vec<&Token>>();
+        let declares = |t: &&Token| matches!(t, Token::TypeDef(..) | Token::MethodDef(..));
+        if statements.iter().all(declares) {
+            return self.declare(entry);
+        }
+        if statements.iter().any(declares) {
+            return Err(vec![repl_error(
+                "MixedEntry",
+                "declarations and statements must be entered apart",
+            )]);
+        }
+        let binds = |t: &&Token| matches!(t, Token::Binding(_, _));
+        if statements.iter().any(binds) && !statements.iter().all(binds) {
+            return Err(vec![repl_error(
+                "MixedEntry",
+                "bindings must be entered apart from other statements",
+            )]);
+        }
+        if statements.iter().all(binds) {
+            self.assemble(entry)?;
+            self.bindings.push(entry.to_string());
+            return Ok(Evaluation::Text(String::new()));
+        }
+        let printable = matches!(self.class_of(entry)?, Some((_, true)));
+        if printable && statements.len() == 1 {
+            Ok(Evaluation::Program(
+                self.assemble(&format!("{} => print", entry))?,
+            ))
+        } else {
+            Ok(Evaluation::Program(self.assemble(entry)?))
+        }
+    }
+
+    /// Runs the command `name`, the spans of its diagnostics being into `argument`.
+    fn command(&mut self, name: &str, argument: &str) -> Result<Evaluation, Vec<Diagnostic>> {
+        let text = match name {
+            "type" => match self.class_of(argument)? {
+                Some((name, _)) => name,
+                None => "()".to_string(),
+            },
+            "layout" => self.layout(argument)?,
+            "tokens" => sexpr(&parse(argument)?, 0),
+            "history" => self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| format!("{:>4}  {}", i + 1, entry.replace('\n', "\n      ")))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "reset" => {
+                let history = std::mem::take(&mut self.history);
+                *self = Session::new();
+                self.history = history;
+                String::new()
+            }
+            "help" => HELP.trim_end().to_string(),
+            "quit" | "q" => return Ok(Evaluation::Quit),
+            _ => {
+                return Err(vec![repl_error(
+                    "UnknownCommand",
+                    &format!("unknown command `:{}`, see `:help`", name),
+                )])
+            }
+        };
+        Ok(Evaluation::Text(text))
+    }
+
+    /// Keeps the declarations of `entry` if they assemble together with the earlier ones.
+    fn declare(&mut self, entry: &str) -> Result<Evaluation, Vec<Diagnostic>> {
+        let declarations = format!("{}{}\n", self.declarations, entry);
+        let start = self.declarations.len();
+        let onto = |diagnostics| onto_entry(diagnostics, start, 0);
+        let tokens = parse(&declarations).map_err(onto)?;
+        let types = declare_root(&tokens).map_err(|err| onto(Diagnostic::from_assembly(&err)))?;
+        self.declarations = declarations;
+        self.tokens = tokens;
+        self.types = types;
+        Ok(Evaluation::Text(String::new()))
+    }
+
+    /// The declarations with a `start` method running the bindings so far and then `entry`, and
+    /// where `entry` starts in it.
+    fn program(&self, entry: &str) -> (String, usize) {
+        let mut program = format!("{}calc start: () {{\n", self.declarations);
+        for binding in self.bindings.iter() {
+            program.push_str(binding);
+            program.push_str(";\n");
+        }
+        let start = program.len();
+        program.push_str(entry);
+        program.push_str(";\n}\n");
+        (program, start)
+    }
+
+    fn assemble(&self, entry: &str) -> Result<Box<AssembledTypeContext>, Vec<Diagnostic>> {
+        let (program, start) = self.program(entry);
+        let onto = |diagnostics| onto_entry(diagnostics, start, 0);
+        let tokens = parse(&program).map_err(onto)?;
+        assemble_root(&tokens)
+            .map(Box::new)
+            .map_err(|err| onto(Diagnostic::from_assembly(&err)))
+    }
+
+    /// The name of the type of the elements the last statement of `entry` ends with, and whether
+    /// they can be printed, or `None` once they have been.
+    fn class_of(&self, entry: &str) -> Result<Option<(String, bool)>, Vec<Diagnostic>> {
+        let (program, start) = self.program(entry);
+        let onto = |diagnostics| onto_entry(diagnostics, start, 0);
+        let tokens = parse(&program).map_err(onto)?;
+        let class_of = || -> Result<Option<(String, bool)>, AssemblyError> {
+            let mut types = declare_root(&tokens)?;
+            lower_methods(&tokens, &mut types)?;
+            let start = types.get_impl(&CLASS_ID_VOID).unwrap()["start"].get_scope();
+            let body = start.ok_or(AssemblyError::NoStartMethodFound)?;
+            let mut bindings = Bindings::new(tokens.get_scope(body).unwrap());
+            let mut class_id = CLASS_ID_VOID;
+            for statement in tokens.get_scope(body).unwrap().get_tokens().iter() {
+                class_id = lower_statement(statement, &tokens, &types, &mut bindings)?.0;
+            }
+            if class_id == CLASS_ID_VOID {
+                return Ok(None);
+            }
+            let name = types.get_type(&class_id).unwrap().get_name();
+            Ok(Some((name, is_printable(class_id, &types))))
+        };
+        class_of().map_err(|err| onto(Diagnostic::from_assembly(&err)))
+    }
+
+    /// The layout of a type named by `name`, instantiating a generic type if it needs to.
+    fn layout(&self, name: &str) -> Result<String, Vec<Diagnostic>> {
+        let parsed = parse(name)?;
+        let token = match parsed.get_scope(0).unwrap().get_tokens().as_slice() {
+            [token] => token,
+            _ => return Err(Diagnostic::from_assembly(&AssemblyError::TypeNotFound)),
+        };
+        let mut types =
+            assemble_types(&self.tokens).map_err(|err| Diagnostic::from_assembly(&err))?;
+        let class_id = type_id(token, &parsed, &self.tokens, &mut types)
+            .map_err(|err| Diagnostic::from_assembly(&err))?;
+        Ok(TypeLayout(&types, class_id)
+            .to_string()
+            .trim_end()
+            .to_string())
+    }
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session::new()
+    }
+}
+
+/// The id of the type `token` names. The arguments of a generic type are read from `parsed`,
+/// while its template refers to the scopes of `declarations`.
+fn type_id(
+    token: &Token,
+    parsed: &TokenizerContext,
+    declarations: &TokenizerContext,
+    types: &mut AssembledTypeContext,
+) -> Result<u16, AssemblyError> {
+    match token {
+        Token::Literal(Literal::Void) => Ok(CLASS_ID_VOID),
+        Token::Literal(Literal::Identifier(name)) => types
+            .get_type_id(name)
+            .copied()
+            .ok_or(AssemblyError::TypeNotFound),
+        Token::Generic(name, arguments) => {
+            let scope = match arguments.as_ref() {
+                Token::Block(_, scope) => *scope,
+                _ => return Err(AssemblyError::ExpectedBlock),
+            };
+            let mut ids = Vec::new();
+            for argument in parsed.get_scope(scope).unwrap().get_tokens().iter() {
+                ids.push(type_id(argument, parsed, declarations, types)?);
+            }
+            instantiate(name, ids, declarations, types)
+        }
+        _ => Err(AssemblyError::TypeNotFound),
+    }
+}
+
+fn parse(source: &str) -> Result<TokenizerContext, Vec<Diagnostic>> {
+    parse_tokens(source.as_bytes()).map_err(|err| vec![Diagnostic::from_token(&err)])
+}
+
+/// Moves the spans of `diagnostics` from the source an entry starts at `start` in to the text the
+/// entry starts at `at` in. Spans before the entry are dropped, as they point at source that was
+/// not entered with it.
+fn onto_entry(mut diagnostics: Vec<Diagnostic>, start: usize, at: usize) -> Vec<Diagnostic> {
+    for diagnostic in diagnostics.iter_mut() {
+        diagnostic.span = diagnostic
+            .span
+            .filter(|span| span.start >= start)
+            .map(|span| Span {
+                start: span.start - start + at,
+                end: span.end - start + at,
+            });
+    }
+    diagnostics
+}
+
+fn repl_error(variant: &str, message: &str) -> Diagnostic {
+    Diagnostic {
+        code: format!("repl::{}", variant),
+        message: message.to_string(),
+        span: None,
+        notes: Vec::new(),
+    }
+}
+
+/// Whether `source` is a whole entry, or still has a brace, string or comment open so more lines
+/// are needed.
+pub fn is_complete(source: &str) -> bool {
+    let mut lexer = Lexer::new(source.as_bytes());
+    for lexed in lexer.by_ref() {
+        match lexed {
+            Err(LexParseError::QuoteNotEnded(_)) | Err(LexParseError::CommentNotEnded(_)) => {
+                return false
+            }
+            Err(_) => return true,
+            Ok(_) => {}
+        }
+    }
+    !lexer.braces().is_open()
+}
+
+/// Reads entries from `input` until it ends or `:quit`, writing a prompt before each line and the
+/// result of each entry to `output`. Lines are gathered into one entry while it is incomplete.
+pub fn repl<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut session = Session::new();
+    let mut entry = String::new();
+    loop {
+        output.write_all(
+            if entry.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION
+            }
+            .as_bytes(),
+        )?;
+        output.flush()?;
+        let mut line = String::new();
+        let ended = input.read_line(&mut line)? == 0;
+        entry.push_str(&line);
+        if !ended && !is_complete(&entry) {
+            continue;
+        }
+        if ended {
+            writeln!(output)?;
+        }
+        match session.eval(&entry) {
+            Ok(Evaluation::Text(text)) if text.is_empty() => {}
+            Ok(Evaluation::Text(text)) => writeln!(output, "{}", text)?,
+            Ok(Evaluation::Program(types)) => match execute(&types) {
+                Ok(printed) => output.write_all(printed.as_bytes())?,
+                Err(err) => writeln!(output, "error: {}", err)?,
+            },
+            Ok(Evaluation::Quit) => return Ok(()),
+            Err(diagnostics) => {
+                for diagnostic in diagnostics.iter() {
+                    writeln!(output, "{}", diagnostic.located(ENTRY, entry.as_bytes()))?;
+                }
+            }
+        }
+        if ended {
+            return Ok(());
+        }
+        entry.clear();
+    }
+}
+
+/// Writes `types` as a C program, compiles it with the C compiler and runs it, giving back what
+/// it printed.
+pub fn execute(types: &AssembledTypeContext) -> io::Result<String> {
+    let dir = env::temp_dir().join(format!("intolang_repl_{}", process::id()));
+    fs::create_dir_all(&dir)?;
+    let printed = compile_and_run(types, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    printed
+}
+
+fn compile_and_run(types: &AssembledTypeContext, dir: &Path) -> io::Result<String> {
+    let source = dir.join("entry.c");
+    let binary = dir.join("entry");
+    write_program(types, &mut BufWriter::new(fs::File::create(&source)?))
+        .map_err(|err| io::Error::other(format!("{:?}", err)))?;
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let compiled = Command::new(&compiler)
+        .arg(&source)
+        .arg("-o")
+        .arg(&binary)
+        .output()?;
+    if !compiled.status.success() {
+        return Err(io::Error::other(format!(
+            "`{}` could not compile the entry",
+            compiler
+        )));
+    }
+    let run = Command::new(&binary).output()?;
+    if !run.status.success() {
+        return Err(io::Error::other(match run.status.code() {
+            Some(code) => format!("the entry exited with status {}", code),
+            None => "the entry was stopped by a signal".to_string(),
+        }));
+    }
+    Ok(String::from_utf8_lossy(&run.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(session: &mut Session, entry: &str) -> String {
+        match session.eval(entry) {
+            Ok(Evaluation::Text(text)) => text,
+            Ok(_) => panic!("`{}` did not give back text", entry),
+            Err(diagnostics) => panic!("`{}` failed: {}", entry, diagnostics[0]),
+        }
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete("[Point(1,2)] => print"));
+        assert!(!is_complete("calc start: () {\n  [1, 2"));
+        assert!(!is_complete("\"open"));
+        assert!(is_complete("calc start: () {\n  [1, 2] => print;\n}\n"));
+    }
+
+    #[test]
+    fn test_declarations_accumulate() {
+        let mut session = Session::new();
+        assert_eq!(text(&mut session, "type Point: (int(x), int(y),)"), "");
+        assert_eq!(
+            text(&mut session, "type Pair<A, B>: (A(first), B(second),)"),
+            ""
+        );
+        assert_eq!(
+            text(&mut session, ":layout Point"),
+            "type 5 Point: composition, 8 bytes\n  x: int at 0, 4 bytes\n  y: int at 4, 4 bytes"
+        );
+        assert!(text(&mut session, ":layout Pair<int, Point>")
+            .starts_with("type 6 Pair<int, Point>: composition, 12 bytes"));
+        assert_eq!(
+            text(&mut session, ":type [Point(1,2), Point(3,4)]"),
+            "Point"
+        );
+        assert_eq!(text(&mut session, ":type [Point(1,2)] => print"), "()");
+        assert_eq!(
+            text(&mut session, ":type Pair<Point, int>(Point(1,2), 3)"),
+            "Pair<Point, int>"
+        );
+        assert_eq!(
+            text(&mut session, ":tokens [Point(1,2)] => print"),
+            "(into (array (square (constructor Point (paren 1 2)))) print)"
+        );
+        assert_eq!(session.get_history().len(), 8);
+    }
+
+    #[test]
+    fn test_stages() {
+        let mut session = Session::new();
+        text(&mut session, "type Point: (int(x), int(y),)");
+        assert_eq!(
+            text(&mut session, ":type [Point(1,2), Point(3,4)] => x => sum"),
+            "int"
+        );
+        assert!(matches!(
+            session.eval("[Point(1,2), Point(3,4)] => x => sum => print"),
+            Ok(Evaluation::Program(_))
+        ));
+    }
+
+    #[test]
+    fn test_bindings_and_errors() {
+        let mut session = Session::new();
+        assert_eq!(text(&mut session, "n: 1..3;"), "");
+        assert_eq!(text(&mut session, ":type n"), "int");
+        let err = session.eval("n => pritn").err().unwrap();
+        assert_eq!(err[0].code, "assembly::UnresolvedIdentifiers");
+        let err = session.eval(":nope").err().unwrap();
+        assert_eq!(err[0].code, "repl::UnknownCommand");
+        assert!(session.eval("type Broken: (int(x)").is_err());
+        assert_eq!(
+            session.eval("1 +").err().unwrap()[0].code,
+            "token::ExpectedOperandButFoundNone"
+        );
+        assert!(session.eval("type P<A>: ").is_err());
+        // positions are into the entry, not into the program it is assembled into
+        let err = session.eval("  n => pritn").err().unwrap();
+        assert_eq!(err[0].span.map(|span| span.start), Some(7));
+        let err = session.eval(":type  [Pont(1, 2)]").err().unwrap();
+        assert_eq!(err[0].span.map(|span| span.start), Some(8));
+        let err = session.eval("type Q: (nope(x),)").err().unwrap();
+        assert_eq!(err[0].span.map(|span| span.start), Some(9));
+        text(&mut session, ":reset");
+        assert_eq!(
+            session.eval(":type n").err().unwrap()[0].code,
+            "assembly::UnresolvedIdentifiers"
+        );
+    }
+}
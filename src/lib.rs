@@ -0,0 +1,6 @@
+extern crate failure;
+extern crate approx;
+extern crate unicode_xid;
+
+pub mod lang;
+pub mod parse;
@@ -1,13 +1,320 @@
-#[macro_use]
-extern crate failure;
-extern crate approx;
+extern crate componsition_lang;
 
 use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
 
-pub mod lang;
-pub mod parse;
+use componsition_lang::lang::assemble::{assemble, build, AssemblyError};
+use componsition_lang::lang::bytecode::compile::compile;
+use componsition_lang::lang::bytecode::vm::{execute, VmError};
+use componsition_lang::lang::bytecode::Program;
+use componsition_lang::lang::diagnostic::Diagnostic;
+use componsition_lang::lang::doc::{document, DocFormat};
+use componsition_lang::lang::emit::{emit, Emit};
+use componsition_lang::lang::format::format_source;
+use componsition_lang::lang::interp::{interpret, InterpError};
+use componsition_lang::lang::json::{check_document, lex_document, parse_document, succeeded};
+use componsition_lang::lang::lsp::serve;
+use componsition_lang::lang::repl::repl;
+use componsition_lang::parse::lex::Lexer;
+use componsition_lang::parse::token::parse_tokens;
+use componsition_lang::parse::tree::{self, Tree};
+
+const USAGE: &str = "Usage: intolang <command> [options] <file>
+
+Commands:
+  lex <file>                Print the lexemes of a source with their byte ranges
+  parse <file> [--sexpr]    Print the syntax tree of a source, or one S-expression per line
+  check <file>              Parse and typecheck a source without writing anything
+  build <file> [-o <out>]   Write a source as a C program, next to it by default
+        [--emit=<stages>]   Write the comma separated stages instead, next to the output:
+                            lex, tokens, scopes, types, instructions, c and ilbc
+  run <file> [--interp      Build a source, compile it with the C compiler and run it, or
+             | --vm]        evaluate it directly with the interpreter, or run it or a
+                            written .ilbc file on the bytecode VM
+  doc <file> [--html]       Print the documentation of a source as Markdown or HTML
+  fmt <files...> [--check]  Format sources in place, or list the ones that would change
+  repl                      Enter declarations and evaluate expressions one at a time
+  lsp                       Serve the Language Server Protocol over stdin and stdout
+
+Options:
+  --format <text|json>      Print lex, parse and check as one JSON document with the errors
+                            as diagnostics, for tools
+  -h, --help                Print this message
+
+The C compiler is `cc` unless the CC environment variable names another.
+";
+
+/// Exit code for a source that did not parse, typecheck or build.
+const EXIT_FAILURE: i32 = 1;
+/// Exit code for bad arguments or files that could not be read or written.
+const EXIT_USAGE: i32 = 2;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    println!("{:?}", crate::lang::assemble::assemble(args[1].clone()));
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        if args.is_empty() {
+            eprint!("{}", USAGE);
+            process::exit(EXIT_USAGE);
+        }
+        print!("{}", USAGE);
+        return;
+    }
+    let command = args[0].as_str();
+    let options = &args[1..];
+    let files: Vec<&String> = files(options);
+    if command == "lsp" {
+        let (stdin, stdout) = (io::stdin(), io::stdout());
+        let code = serve(stdin.lock(), stdout.lock()).unwrap_or_else(|err| {
+            eprintln!("error: {}", err);
+            EXIT_USAGE
+        });
+        process::exit(code);
+    }
+    if command == "repl" {
+        let (stdin, stdout) = (io::stdin(), io::stdout());
+        repl(stdin.lock(), stdout.lock()).unwrap_or_else(|err| fail_with(err));
+        return;
+    }
+    if command == "fmt" {
+        fmt(&files, options.iter().any(|a| a == "--check"));
+        return;
+    }
+    let file = match files.as_slice() {
+        [file] => (*file).clone(),
+        _ => usage(&format!("`{}` takes exactly one file", command)),
+    };
+    match option(options, "--format").map(|f| f.as_str()) {
+        None | Some("text") => {}
+        Some("json") => json(command, &file),
+        Some(format) => usage(&format!("unknown format `{}`", format)),
+    }
+    if options.iter().any(|a| a == "--interp") && options.iter().any(|a| a == "--vm") {
+        usage("`--interp` and `--vm` cannot be used together");
+    }
+    match command {
+        "lex" => lex(&file),
+        "parse" => parse(&file, options.iter().any(|a| a == "--sexpr")),
+        "check" => {
+            assemble(file.clone()).unwrap_or_else(|err| fail_assembly(&file, err));
+        }
+        "build" => {
+            let output = match option(options, "-o") {
+                Some(output) => PathBuf::from(output),
+                None => Path::new(&file).with_extension("c"),
+            };
+            let emits = match options.iter().find_map(|a| a.strip_prefix("--emit=")) {
+                Some(list) => Emit::parse_list(list)
+                    .unwrap_or_else(|name| usage(&format!("unknown stage `{}`", name))),
+                None => vec![Emit::C],
+            };
+            emit(file.clone(), &output, &emits).unwrap_or_else(|err| fail_assembly(&file, err));
+        }
+        "run" if options.iter().any(|a| a == "--interp") => {
+            let stdout = io::stdout();
+            interpret(file.clone(), &mut stdout.lock()).unwrap_or_else(|err| match err {
+                InterpError::Assembly(err) => fail_assembly(&file, err),
+                InterpError::Io(message) => fail_assembly(&file, AssemblyError::Io(message)),
+                err => fail(&file, &Diagnostic::from_interp(&err)),
+            });
+        }
+        "run" if options.iter().any(|a| a == "--vm") => run_vm(file),
+        "run" => run(file),
+        "doc" => {
+            let format = if options.iter().any(|a| a == "--html") {
+                DocFormat::Html
+            } else {
+                DocFormat::Markdown
+            };
+            print!(
+                "{}",
+                document(file.clone(), format).unwrap_or_else(|err| fail_assembly(&file, err))
+            );
+        }
+        _ => usage(&format!("unknown command `{}`", command)),
+    }
+}
+
+/// The arguments that are neither flags nor the value of `-o` or `--format`.
+fn files(options: &[String]) -> Vec<&String> {
+    let mut files = Vec::new();
+    let mut arguments = options.iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "-o" || argument == "--format" {
+            arguments.next();
+        } else if !argument.starts_with('-') {
+            files.push(argument);
+        }
+    }
+    files
+}
+
+fn option<'a>(options: &'a [String], name: &str) -> Option<&'a String> {
+    match options.iter().position(|a| a == name) {
+        Some(i) => match options.get(i + 1) {
+            Some(value) => Some(value),
+            None => usage(&format!("`{}` needs a value", name)),
+        },
+        None => None,
+    }
+}
+
+fn usage(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+/// Prints `diagnostics` at their lines and columns in `file`, then exits with a failure.
+fn fail(file: &str, diagnostics: &[Diagnostic]) -> ! {
+    let source = fs::read(file).unwrap_or_default();
+    for diagnostic in diagnostics.iter() {
+        eprintln!("{}", diagnostic.located(file, &source));
+    }
+    process::exit(EXIT_FAILURE);
+}
+
+/// Like `fail`, but files that could not be read or written are not the source's fault.
+fn fail_assembly(file: &str, err: AssemblyError) -> ! {
+    match err {
+        AssemblyError::Io(message) => {
+            eprintln!("error: {}", message);
+            process::exit(EXIT_USAGE);
+        }
+        err => fail(file, &Diagnostic::from_assembly(&err)),
+    }
+}
+
+/// Fails with an error that has no place in a source.
+fn fail_with<E: Display>(err: E) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(EXIT_FAILURE);
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|err| {
+        eprintln!("error: {}: {}", path, err);
+        process::exit(EXIT_USAGE);
+    })
+}
+
+/// Prints the JSON document of `command`, exiting with a failure if it has diagnostics.
+fn json(command: &str, file: &str) -> ! {
+    let source = read(file);
+    let document = match command {
+        "lex" => lex_document(&source),
+        "parse" => parse_document(&source),
+        "check" => check_document(&source),
+        _ => usage(&format!("`{}` has no JSON format", command)),
+    };
+    println!("{}", document);
+    process::exit(if succeeded(&document) {
+        0
+    } else {
+        EXIT_FAILURE
+    });
+}
+
+fn lex(file: &str) {
+    let source = read(file);
+    for spanned in Lexer::new(&source) {
+        let spanned = spanned.unwrap_or_else(|err| fail(file, &[Diagnostic::from_lex(&err)]));
+        println!("{}..{} {:?}", spanned.start, spanned.end, spanned.node);
+    }
+}
+
+fn parse(file: &str, sexpr: bool) {
+    let context =
+        parse_tokens(&read(file)).unwrap_or_else(|err| fail(file, &[Diagnostic::from_token(&err)]));
+    if sexpr {
+        println!("{}", tree::sexpr(&context, 0));
+    } else {
+        print!("{}", Tree(&context));
+    }
+}
+
+/// Builds `file` into a temporary directory, then compiles and runs it, exiting with the code
+/// the program exits with.
+fn run(file: String) {
+    let dir = env::temp_dir().join(format!("intolang_run_{}", process::id()));
+    fs::create_dir_all(&dir).unwrap_or_else(|err| fail_with(err));
+    let source = dir.join("program.c");
+    let binary = dir.join("program");
+    build(file.clone(), &source).unwrap_or_else(|err| fail_assembly(&file, err));
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let compiled = Command::new(&compiler)
+        .arg(&source)
+        .arg("-o")
+        .arg(&binary)
+        .status()
+        .unwrap_or_else(|err| {
+            eprintln!("error: could not run `{}`: {}", compiler, err);
+            process::exit(EXIT_USAGE);
+        });
+    if !compiled.success() {
+        eprintln!(
+            "error: `{}` could not compile the generated program",
+            compiler
+        );
+        process::exit(EXIT_FAILURE);
+    }
+    let status = Command::new(&binary)
+        .status()
+        .unwrap_or_else(|err| fail_with(err));
+    let _ = fs::remove_dir_all(&dir);
+    process::exit(status.code().unwrap_or(EXIT_FAILURE));
+}
+
+/// Runs a source, or bytecode written with `--emit=ilbc`, on the VM and exits with its code.
+fn run_vm(file: String) {
+    let program = if Path::new(&file).extension().is_some_and(|e| e == "ilbc") {
+        Program::decode(&read(&file))
+            .unwrap_or_else(|err| fail(&file, &[Diagnostic::from_bytecode(&err)]))
+    } else {
+        let types = assemble(file.clone()).unwrap_or_else(|err| fail_assembly(&file, err));
+        compile(&types).unwrap_or_else(|err| fail_assembly(&file, err))
+    };
+    let stdout = io::stdout();
+    let code = execute(&program, &mut stdout.lock()).unwrap_or_else(|err| match err {
+        VmError::Io(message) => fail_assembly(&file, AssemblyError::Io(message)),
+        err => fail(&file, &[Diagnostic::from_vm(&err)]),
+    });
+    process::exit(code);
+}
+
+/// Formats `files` in place, or with `check` lists the ones that are not formatted and exits
+/// with a failure if there are any.
+fn fmt(files: &[&String], check: bool) {
+    let mut differs = false;
+    let mut failed = false;
+    for path in files.iter() {
+        let source = String::from_utf8(read(path))
+            .unwrap_or_else(|err| fail_with(format!("{}: {}", path, err)));
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                eprintln!(
+                    "{}",
+                    Diagnostic::from_token(&err).located(path, source.as_bytes())
+                );
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        differs = true;
+        if check {
+            println!("{}", path);
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("error: {}: {}", path, err);
+            process::exit(EXIT_USAGE);
+        }
+    }
+    if failed || (check && differs) {
+        process::exit(EXIT_FAILURE);
+    }
 }
@@ -0,0 +1,458 @@
+use crate::parse::constant::{Brace, Keyword, Operator};
+use crate::parse::context::TokenizerContext;
+use crate::parse::token::{parse_tokens, Literal, OperatorGroup, Token, TokenParseError};
+
+use std::collections::{HashMap, HashSet};
+
+/// Identifies a module or block, and the scope data kept for it in `Module::scopes`.
+pub type NodeId = usize;
+
+/// A source file: its declarations and statements, with what the tokenizer knew about each
+/// scope kept beside the tree rather than in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub id: NodeId,
+    pub items: Vec<Item>,
+    pub scopes: HashMap<NodeId, ScopeData>,
+}
+
+/// The tokenizer scope a module or block was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeData {
+    pub scope: usize,
+    pub parent: Option<NodeId>,
+    pub declared: HashSet<String>,
+    pub undeclared: HashSet<String>,
+    pub docs: Option<String>, //`///` comments of the declaration this is the body of
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Type(TypeDecl),
+    Method(MethodDecl),
+    Expr(Expr),
+}
+
+/// `type Name<A, B>: (int(x), A(first),)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDecl {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub fields: Vec<Field>,
+    pub body: NodeId,
+}
+
+/// A field of a type, `int(x)` or `int x`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub ty: TypeExpr,
+    pub name: String,
+}
+
+/// `calc name<A>: Intake(a, b) { ... }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodDecl {
+    pub mode: Keyword,
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub intake: Pattern,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeExpr {
+    Void,
+    Named(String),
+    Generic(String, Vec<TypeExpr>),
+}
+
+/// What a method takes in or a binding binds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Name(String),
+    Type(TypeExpr),
+    Destructure(TypeExpr, Vec<String>), //`Point(x, y)`
+}
+
+/// The expressions between a pair of braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: NodeId,
+    pub brace: Brace,
+    pub exprs: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Literal),
+    Identifier(String),
+    Unary(Operator, Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Ternary(Operator, Box<Expr>, Box<Expr>, Box<Expr>),
+    Group(Block),
+    Interpolated(String, Block, String), //Text before, parts, text after
+    Construct(TypeExpr, Block),
+    Type(TypeExpr),
+    Array(Block),
+    Index(Box<Expr>, Block),
+    Variable(TypeExpr, String),
+    Binding(Pattern, Box<Expr>),
+    Declaration(Box<Item>),
+}
+
+impl Module {
+    /// Parses `input` into a module.
+    pub fn parse(input: &[u8]) -> Result<Module, TokenParseError> {
+        Module::from_tokens(&parse_tokens(input)?)
+    }
+
+    /// Builds the tree of the root scope of `tokens`, following every block into its scope.
+    pub fn from_tokens(tokens: &TokenizerContext) -> Result<Module, TokenParseError> {
+        let mut builder = Builder {
+            tokens,
+            scopes: HashMap::new(),
+            parents: Vec::new(),
+        };
+        let (id, exprs) = builder.scope(0)?;
+        let items = exprs
+            .into_iter()
+            .map(|expr| match expr {
+                Expr::Declaration(item) => *item,
+                expr => Item::Expr(expr),
+            })
+            .collect();
+        Ok(Module {
+            id,
+            items,
+            scopes: builder.scopes,
+        })
+    }
+
+    pub fn scope(&self, id: NodeId) -> Option<&ScopeData> {
+        self.scopes.get(&id)
+    }
+    /// The `///` comments of the declaration whose body is `id`.
+    pub fn docs(&self, id: NodeId) -> Option<&String> {
+        self.scopes.get(&id).and_then(|s| s.docs.as_ref())
+    }
+}
+
+struct Builder<'a> {
+    tokens: &'a TokenizerContext,
+    scopes: HashMap<NodeId, ScopeData>,
+    parents: Vec<NodeId>,
+}
+
+impl<'a> Builder<'a> {
+    /// Records `scope` under a new node id and builds the expressions of its tokens.
+    fn scope(&mut self, scope: usize) -> Result<(NodeId, Vec<Expr>), TokenParseError> {
+        let context = self
+            .tokens
+            .get_scope(scope)
+            .ok_or(TokenParseError::ExpectedBlock)?;
+        let id = self.scopes.len();
+        self.scopes.insert(
+            id,
+            ScopeData {
+                scope,
+                parent: self.parents.last().cloned(),
+                declared: context.get_declared_identifiers().clone(),
+                undeclared: context.get_undeclared_identifiers().clone(),
+                docs: self.tokens.get_docs(scope).cloned(),
+            },
+        );
+        self.parents.push(id);
+        let exprs = context
+            .get_tokens()
+            .iter()
+            .filter(|t| t.as_ref() != &Token::Delimiter)
+            .map(|t| self.expr(t))
+            .collect::<Result<Vec<Expr>, TokenParseError>>();
+        self.parents.pop();
+        Ok((id, exprs?))
+    }
+
+    fn block(&mut self, token: &Token) -> Result<Block, TokenParseError> {
+        match token {
+            Token::Block(brace, scope) => {
+                let (id, exprs) = self.scope(*scope)?;
+                Ok(Block {
+                    id,
+                    brace: brace.clone(),
+                    exprs,
+                })
+            }
+            _ => Err(TokenParseError::ExpectedBlock),
+        }
+    }
+
+    fn expr(&mut self, token: &Token) -> Result<Expr, TokenParseError> {
+        Ok(match token {
+            Token::Literal(Literal::Identifier(name)) => Expr::Identifier(name.clone()),
+            Token::Literal(literal) => Expr::Literal(literal.clone()),
+            Token::Operator(OperatorGroup::UniOperator(op, a)) => {
+                Expr::Unary(*op, Box::new(self.expr(a)?))
+            }
+            Token::Operator(OperatorGroup::BiOperator(op, a, b)) => {
+                Expr::Binary(*op, Box::new(self.expr(a)?), Box::new(self.expr(b)?))
+            }
+            Token::Operator(OperatorGroup::TriOperator(op, a, b, c)) => Expr::Ternary(
+                *op,
+                Box::new(self.expr(a)?),
+                Box::new(self.expr(b)?),
+                Box::new(self.expr(c)?),
+            ),
+            Token::Block(_, _) => Expr::Group(self.block(token)?),
+            Token::AgrandizedString(prefix, parts, suffix) => {
+                Expr::Interpolated(prefix.clone(), self.block(parts)?, suffix.clone())
+            }
+            Token::Constructor(name, parameters) => {
+                Expr::Construct(TypeExpr::Named(name.clone()), self.block(parameters)?)
+            }
+            Token::GenericConstructor(name, arguments, parameters) => Expr::Construct(
+                TypeExpr::Generic(name.clone(), self.type_list(arguments)?),
+                self.block(parameters)?,
+            ),
+            Token::Generic(_, _) => Expr::Type(self.type_expr(token)?),
+            Token::Array(elements) => Expr::Array(self.block(elements)?),
+            Token::Index(collection, indices) => {
+                Expr::Index(Box::new(self.expr(collection)?), self.block(indices)?)
+            }
+            Token::VariableDef(ty, name) => {
+                Expr::Variable(TypeExpr::Named(ty.as_identifier_string()?), name.clone())
+            }
+            Token::Binding(name, value) => {
+                Expr::Binding(Pattern::Name(name.clone()), Box::new(self.expr(value)?))
+            }
+            Token::TypeDef(name, parameters, body) => {
+                let parameters = match parameters {
+                    Some(parameters) => self.names(parameters)?,
+                    None => Vec::new(),
+                };
+                let body = self.block(body)?;
+                let fields = body
+                    .exprs
+                    .iter()
+                    .map(field)
+                    .collect::<Result<Vec<Vec<Field>>, TokenParseError>>()?;
+                Expr::Declaration(Box::new(Item::Type(TypeDecl {
+                    name: name.as_identifier_string()?,
+                    parameters,
+                    fields: fields.into_iter().flatten().collect(),
+                    body: body.id,
+                })))
+            }
+            Token::MethodDef(mode, name, parameters, intake, body) => {
+                let parameters = match parameters {
+                    Some(parameters) => self.names(parameters)?,
+                    None => Vec::new(),
+                };
+                Expr::Declaration(Box::new(Item::Method(MethodDecl {
+                    mode: *mode,
+                    name: name.clone(),
+                    parameters,
+                    intake: self.pattern(intake)?,
+                    body: self.block(body)?,
+                })))
+            }
+            Token::Delimiter => return Err(TokenParseError::ExpectedOperandButFoundNone),
+        })
+    }
+
+    fn type_expr(&mut self, token: &Token) -> Result<TypeExpr, TokenParseError> {
+        match token {
+            Token::Literal(Literal::Void) => Ok(TypeExpr::Void),
+            Token::Literal(Literal::Identifier(name)) => Ok(TypeExpr::Named(name.clone())),
+            Token::Generic(name, arguments) => {
+                Ok(TypeExpr::Generic(name.clone(), self.type_list(arguments)?))
+            }
+            _ => Err(TokenParseError::ExpectedTypeName),
+        }
+    }
+
+    /// The types in a `<...>` block.
+    fn type_list(&mut self, block: &Token) -> Result<Vec<TypeExpr>, TokenParseError> {
+        match block {
+            Token::Block(_, scope) => {
+                let tokens = self.tokens;
+                let scope = tokens
+                    .get_scope(*scope)
+                    .ok_or(TokenParseError::ExpectedBlock)?;
+                scope
+                    .get_tokens()
+                    .iter()
+                    .map(|t| self.type_expr(t))
+                    .collect()
+            }
+            _ => Err(TokenParseError::ExpectedBlock),
+        }
+    }
+
+    /// The identifiers in a block, such as type parameters or the names of a destructure.
+    fn names(&mut self, block: &Token) -> Result<Vec<String>, TokenParseError> {
+        self.block(block)?
+            .exprs
+            .into_iter()
+            .map(|expr| match expr {
+                Expr::Identifier(name) => Ok(name),
+                _ => Err(TokenParseError::ExpectedIdentifier),
+            })
+            .collect()
+    }
+
+    fn pattern(&mut self, token: &Token) -> Result<Pattern, TokenParseError> {
+        match token {
+            Token::Constructor(name, names) => Ok(Pattern::Destructure(
+                TypeExpr::Named(name.clone()),
+                self.names(names)?,
+            )),
+            Token::GenericConstructor(name, arguments, names) => Ok(Pattern::Destructure(
+                TypeExpr::Generic(name.clone(), self.type_list(arguments)?),
+                self.names(names)?,
+            )),
+            Token::Block(_, scope)
+                if self
+                    .tokens
+                    .get_scope(*scope)
+                    .is_some_and(|s| s.get_tokens().is_empty()) =>
+            {
+                Ok(Pattern::Type(TypeExpr::Void))
+            }
+            _ => Ok(Pattern::Type(self.type_expr(token)?)),
+        }
+    }
+}
+
+/// The fields a type body expression declares: `int(x, y)` declares two.
+fn field(expr: &Expr) -> Result<Vec<Field>, TokenParseError> {
+    match expr {
+        Expr::Variable(ty, name) => Ok(vec![Field {
+            ty: ty.clone(),
+            name: name.clone(),
+        }]),
+        Expr::Construct(ty, names) => names
+            .exprs
+            .iter()
+            .map(|name| match name {
+                Expr::Identifier(name) => Ok(Field {
+                    ty: ty.clone(),
+                    name: name.clone(),
+                }),
+                _ => Err(TokenParseError::ExpectedIdentifier),
+            })
+            .collect(),
+        _ => Err(TokenParseError::ExpectedField),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::constant::Number;
+
+    fn named(name: &str) -> TypeExpr {
+        TypeExpr::Named(name.to_string())
+    }
+
+    #[test]
+    fn test_types() -> Result<(), TokenParseError> {
+        let module = Module::parse(
+            b"/// A pair.\ntype Pair<A, B>: (A(first), Pair<B, int>(second), int x, int(y, z),)",
+        )?;
+        match &module.items[..] {
+            [Item::Type(decl)] => {
+                assert_eq!(decl.name, "Pair");
+                assert_eq!(decl.parameters, vec!["A", "B"]);
+                let fields = decl
+                    .fields
+                    .iter()
+                    .map(|f| (f.ty.clone(), f.name.as_str()))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    fields,
+                    vec![
+                        (named("A"), "first"),
+                        (
+                            TypeExpr::Generic("Pair".to_string(), vec![named("B"), named("int")]),
+                            "second"
+                        ),
+                        (named("int"), "x"),
+                        (named("int"), "y"),
+                        (named("int"), "z"),
+                    ]
+                );
+                assert_eq!(module.docs(decl.body), Some(&"A pair.".to_string()));
+                assert_eq!(module.scope(decl.body).unwrap().parent, Some(module.id));
+            }
+            other => panic!("expected a type, found {:?}", other),
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_methods_and_expressions() -> Result<(), TokenParseError> {
+        let module = Module::parse(
+            b"calc start: () { n: 3; [Point(1, n)] => print; }
+            trans swap<A, B>: Pair<A, B>(first, second) { second }",
+        )?;
+        let (start, swap) = match &module.items[..] {
+            [Item::Method(start), Item::Method(swap)] => (start, swap),
+            other => panic!("expected two methods, found {:?}", other),
+        };
+        assert_eq!(start.mode, Keyword::Calc);
+        assert_eq!(start.intake, Pattern::Type(TypeExpr::Void));
+        match &start.body.exprs[..] {
+            [Expr::Binding(Pattern::Name(n), three), Expr::Binary(Operator::Into, array, print)] => {
+                assert_eq!(n, "n");
+                assert_eq!(**three, Expr::Literal(Literal::Number(Number::Integer(3))));
+                assert_eq!(**print, Expr::Identifier("print".to_string()));
+                match array.as_ref() {
+                    Expr::Array(square) => match &square.exprs[..] {
+                        [Expr::Construct(ty, fields)] => {
+                            assert_eq!(*ty, named("Point"));
+                            assert_eq!(fields.exprs[1], Expr::Identifier("n".to_string()));
+                        }
+                        other => panic!("expected a constructor, found {:?}", other),
+                    },
+                    other => panic!("expected an array, found {:?}", other),
+                }
+            }
+            other => panic!("unexpected body {:?}", other),
+        }
+        let body = module.scope(start.body.id).unwrap();
+        assert!(body.declared.contains("n"));
+        assert_eq!(swap.parameters, vec!["A", "B"]);
+        assert_eq!(
+            swap.intake,
+            Pattern::Destructure(
+                TypeExpr::Generic("Pair".to_string(), vec![named("A"), named("B")]),
+                vec!["first".to_string(), "second".to_string()]
+            )
+        );
+        // every block has its own id and scope data
+        let mut ids = vec![module.id, start.body.id, swap.body.id];
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(
+            module.scopes.len(),
+            module
+                .scopes
+                .values()
+                .map(|s| s.scope)
+                .collect::<HashSet<_>>()
+                .len()
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_resources() {
+        for dir in ["res/test", "res/example"].iter() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let source = std::fs::read(entry.unwrap().path()).unwrap();
+                if let Ok(tokens) = parse_tokens(&source) {
+                    Module::from_tokens(&tokens).unwrap();
+                }
+            }
+        }
+    }
+}
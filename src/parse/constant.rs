@@ -13,7 +13,45 @@ pub enum Keyword {
     Calc,
     Trans,
     Inv,
-    Intake,
+    /// Introduces the step of a range, as in `0..10 by 2`.
+    By,
+}
+/// Every keyword and how it is spelled. Reserved keywords always lex as keywords, contextual
+/// ones lex as identifiers and are only keywords where the tokenizer looks for them by name.
+pub const KEYWORDS: [(Keyword, &str, KeywordKind); 9] = [
+    (Keyword::Data, "data", KeywordKind::Reserved),
+    (Keyword::Comp, "comp", KeywordKind::Reserved),
+    (Keyword::Type, "type", KeywordKind::Reserved),
+    (Keyword::Impl, "impl", KeywordKind::Reserved),
+    (Keyword::Enum, "enum", KeywordKind::Reserved),
+    (Keyword::Calc, "calc", KeywordKind::Reserved),
+    (Keyword::Trans, "trans", KeywordKind::Reserved),
+    (Keyword::Inv, "inv", KeywordKind::Reserved),
+    (Keyword::By, "by", KeywordKind::Contextual),
+];
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KeywordKind {
+    Reserved,
+    Contextual,
+}
+impl Keyword {
+    /// The keyword spelled `name`, whether reserved or contextual.
+    pub fn from_spelling(name: &str) -> Option<Keyword> {
+        KEYWORDS
+            .iter()
+            .find(|(_, spelling, _)| *spelling == name)
+            .map(|(keyword, _, _)| *keyword)
+    }
+    /// The reserved keyword spelled `name`, which can never be used as an identifier.
+    pub fn reserved(name: &str) -> Option<Keyword> {
+        Keyword::from_spelling(name).filter(|k| k.kind() == KeywordKind::Reserved)
+    }
+    pub fn spelling(&self) -> &'static str {
+        KEYWORDS.iter().find(|(k, _, _)| k == self).unwrap().1
+    }
+    pub fn kind(&self) -> KeywordKind {
+        KEYWORDS.iter().find(|(k, _, _)| k == self).unwrap().2
+    }
 }
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum Operator {
@@ -33,6 +71,7 @@ pub enum Operator {
     IsNotEquals,
     Accessor,
     RangeMiddle,
+    RangeInclusive,
     ArrayContinuation,
     Arrow,
     Into,
@@ -56,7 +95,7 @@ pub enum Number {
     Float(f64),
 
     Byte(u8),
-    Char(u8),
+    Char(char),
 }
 impl std::hash::Hash for Number {
     fn hash<H>(&self, state: &mut H)
@@ -70,8 +109,9 @@ impl std::hash::Hash for Number {
                 let bytes = f.to_bits().to_be_bytes();
                 state.write_i64(i64::from_be_bytes(bytes))
             }
-            Number::Byte(i) => state.write_u8(*i),
-            Number::Char(i) => state.write_u8(*i),
+            // bytes and chars compare equal by value, so they must hash alike
+            Number::Byte(i) => state.write_u32(*i as u32),
+            Number::Char(c) => state.write_u32(*c as u32),
         }
     }
 }
@@ -88,11 +128,11 @@ impl PartialEq for Number {
             },
             Number::Byte(num) => match *other {
                 Number::Byte(other_num) => num == other_num,
-                Number::Char(other_num) => num == other_num,
+                Number::Char(other_num) => num as u32 == other_num as u32,
                 _ => false,
             },
             Number::Char(num) => match *other {
-                Number::Byte(other_num) => num == other_num,
+                Number::Byte(other_num) => num as u32 == other_num as u32,
                 Number::Char(other_num) => num == other_num,
                 _ => false,
             },
@@ -111,7 +151,8 @@ pub enum Brace {
     Bracket,
     Angle,
     Square,
-    Char(u8),
+    Char(char),
     Quote(String),
     Comment(String),
+    DocComment(String),
 }
@@ -1,6 +1,7 @@
-use crate::parse::constant::Brace;
-use crate::parse::token::{Token, TokenParseError};
-use std::collections::HashSet;
+use crate::parse::constant::{Brace, Operator};
+use crate::parse::token::{Literal, OperatorGroup, Token, TokenParseError};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 #[derive(Clone, Copy, Debug)]
 pub enum ContextState {
@@ -18,6 +19,8 @@ pub struct ContextScope {
     state: ContextState,
     brace_state: BraceState,
     undeclared_identifiers: HashSet<String>,
+    /// Where each undeclared identifier is first used, as byte offsets, the end exclusive.
+    identifier_spans: HashMap<String, (usize, usize)>,
     declared_identifiers: HashSet<String>,
     tokens: Vec<Box<Token>>,
     index: usize,
@@ -29,6 +32,7 @@ impl ContextScope {
             state: ContextState::Root,
             brace_state: BraceState::None,
             undeclared_identifiers: HashSet::new(),
+            identifier_spans: HashMap::new(),
             declared_identifiers: HashSet::new(),
             tokens: Vec::new(),
             index: index,
@@ -45,6 +49,7 @@ impl ContextScope {
             state: new_state,
             brace_state: new_brace_state,
             undeclared_identifiers: HashSet::new(),
+            identifier_spans: HashMap::new(),
             declared_identifiers: HashSet::new(),
             tokens: Vec::new(),
             index: index,
@@ -60,17 +65,47 @@ impl ContextScope {
     pub fn get_tokens(&self) -> &Vec<Box<Token>> {
         &self.tokens
     }
+    pub fn get_mut_tokens(&mut self) -> &mut Vec<Box<Token>> {
+        &mut self.tokens
+    }
+    pub fn get_parent(&self) -> Option<usize> {
+        match self.state {
+            ContextState::Root => None,
+            _ => Some(self.parent),
+        }
+    }
+    pub fn get_undeclared_identifiers(&self) -> &HashSet<String> {
+        &self.undeclared_identifiers
+    }
+    pub fn get_identifier_span(&self, identifier: &str) -> Option<(usize, usize)> {
+        self.identifier_spans.get(identifier).copied()
+    }
+    pub fn get_declared_identifiers(&self) -> &HashSet<String> {
+        &self.declared_identifiers
+    }
+    /// Whether a binding of `identifier` is declared directly in this scope.
+    pub fn is_declared(&self, identifier: &str) -> bool {
+        self.declared_identifiers.contains(identifier)
+    }
     pub fn assert_eq(&self, compare_vec: Vec<Box<Token>>) {
         assert_eq!(self.tokens, compare_vec);
     }
-    pub fn println(&self) {
-        println!("{:?}", self.tokens);
-    }
+}
+
+/// Comments kept beside the tokens, so they never take part in parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trivia {
+    Comment(String, usize),    //Text, Position
+    DocComment(String, usize), //Text, Position
 }
 
 pub struct TokenizerContext {
     scope: Vec<ContextScope>,
     current_scope: usize,
+    trivia: Vec<Trivia>,
+    pending_docs: Vec<String>,
+    docs: HashMap<usize, String>,
+    quotes: HashMap<usize, (usize, String)>, //End, (Start, Spelling)
 }
 
 impl TokenizerContext {
@@ -81,6 +116,10 @@ impl TokenizerContext {
         TokenizerContext {
             scope: scope,
             current_scope: 0,
+            trivia: Vec::new(),
+            pending_docs: Vec::new(),
+            docs: HashMap::new(),
+            quotes: HashMap::new(),
         }
     }
 
@@ -119,19 +158,37 @@ impl TokenizerContext {
         }
     }
 
-    pub fn push_identifier(&mut self, identifier: String) {
-        if !self
-            .scope
-            .get(self.current_scope)
+    /// Whether `identifier` is bound in the current scope or any scope enclosing it.
+    pub fn is_declared(&self, identifier: &str) -> bool {
+        let mut scope = self.current_scope();
+        loop {
+            if scope.is_declared(identifier) {
+                return true;
+            }
+            match scope.get_parent() {
+                Some(parent) => scope = self.scope.get(parent).unwrap(),
+                None => return false,
+            }
+        }
+    }
+
+    pub fn declare_identifier(&mut self, identifier: String) {
+        self.scope
+            .get_mut(self.current_scope)
             .unwrap()
             .declared_identifiers
-            .contains(&identifier)
-        {
-            self.scope
-                .get_mut(self.current_scope)
-                .unwrap()
-                .undeclared_identifiers
-                .insert(identifier);
+            .insert(identifier);
+    }
+
+    /// Records a use of `identifier` ending at the byte offset `end`.
+    pub fn push_identifier(&mut self, identifier: String, end: usize) {
+        if !self.is_declared(&identifier) {
+            let scope = self.scope.get_mut(self.current_scope).unwrap();
+            scope
+                .identifier_spans
+                .entry(identifier.clone())
+                .or_insert((end - identifier.len(), end));
+            scope.undeclared_identifiers.insert(identifier);
         }
     }
 
@@ -152,13 +209,95 @@ impl TokenizerContext {
         }
     }
 
+    /// The previous token of the current scope, if it is a lone identifier.
+    pub fn peek_identifier(&self) -> Option<&str> {
+        match self.peek_token().map(|t| t.as_ref()) {
+            Some(Token::Literal(Literal::Identifier(name))) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The previous token of the current scope, if it is a `start..end` range without a step.
+    pub fn peek_range(&self) -> Option<&Token> {
+        self.peek_token().map(|t| t.as_ref()).filter(|t| match t {
+            Token::Operator(OperatorGroup::BiOperator(op, _, _)) => {
+                *op == Operator::RangeMiddle || *op == Operator::RangeInclusive
+            }
+            _ => false,
+        })
+    }
+
+    pub fn push_trivia(&mut self, trivia: Trivia) {
+        if let Trivia::DocComment(text, _) = &trivia {
+            self.pending_docs.push(
+                text.strip_prefix(' ')
+                    .unwrap_or(text)
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+        self.trivia.push(trivia);
+    }
+    pub fn get_trivia(&self) -> &Vec<Trivia> {
+        &self.trivia
+    }
+    /// Takes the doc comments read since the last token, for the declaration being parsed.
+    pub fn take_docs(&mut self) -> Option<String> {
+        if self.pending_docs.is_empty() {
+            None
+        } else {
+            Some(
+                self.pending_docs
+                    .drain(..)
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            )
+        }
+    }
+    /// Attaches docs to the declaration whose body is `scope`.
+    pub fn set_docs(&mut self, scope: usize, docs: String) {
+        self.docs.insert(scope, docs);
+    }
+    pub fn get_docs(&self, scope: usize) -> Option<&String> {
+        self.docs.get(&scope)
+    }
+    /// Remembers where the string literal ending at `end` starts and how it is spelled, escapes
+    /// and quotes included, so positions inside it can be mapped back onto the source.
+    pub fn record_quote(&mut self, end: usize, start: usize, spelling: &str) {
+        self.quotes.insert(end, (start, spelling.to_string()));
+    }
+    pub fn get_quote(&self, end: usize) -> Option<(usize, &str)> {
+        self.quotes
+            .get(&end)
+            .map(|(start, spelling)| (*start, spelling.as_str()))
+    }
+
     pub fn pop_token(&mut self) -> Option<Box<Token>> {
         self.scope.get_mut(self.current_scope).unwrap().tokens.pop()
     }
 
-    pub fn println(&self) {
-        for s in self.scope.iter() {
-            s.println();
+    /// Writes `scope` with its tokens, followed by the scopes opened in it one level deeper.
+    fn write_scope(&self, scope: usize, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        let current = &self.scope[scope];
+        match &current.brace_state {
+            BraceState::Braced(brace, _) => writeln!(f, "{}scope {} {:?}", indent, scope, brace)?,
+            BraceState::None => writeln!(f, "{}scope {}", indent, scope)?,
+        }
+        for token in current.tokens.iter() {
+            writeln!(f, "{}  {:?}", indent, token)?;
         }
+        for child in self.scope.iter().skip(scope + 1) {
+            if child.get_parent() == Some(scope) {
+                self.write_scope(child.index, depth + 1, f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TokenizerContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_scope(0, 0, f)
     }
 }
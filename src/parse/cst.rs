@@ -0,0 +1,329 @@
+use crate::parse::constant::Brace;
+use crate::parse::lex::{BraceStatus, Lex, LexParseError, Lexer};
+
+use std::fmt;
+
+/// Source text between tokens: runs of whitespace, and comments with the lex they were read as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TriviaPiece<'src> {
+    Whitespace(&'src str),
+    Comment(&'src str, Lex<'src>),
+}
+impl<'src> TriviaPiece<'src> {
+    pub fn text(&self) -> &'src str {
+        match self {
+            TriviaPiece::Whitespace(text) | TriviaPiece::Comment(text, _) => text,
+        }
+    }
+}
+
+/// A token with its exact spelling and the trivia around it. Trailing trivia runs up to the end
+/// of the token's line, everything after that leads the next token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxToken<'src> {
+    pub lex: Lex<'src>,
+    pub text: &'src str,
+    pub start: usize,
+    pub leading: Vec<TriviaPiece<'src>>,
+    pub trailing: Vec<TriviaPiece<'src>>,
+}
+
+/// Tokens grouped by the braces around them. A group whose close brace is missing keeps every
+/// token up to the end of the source, and a close brace without an open stays a plain token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxNode<'src> {
+    Token(SyntaxToken<'src>),
+    Group(SyntaxGroup<'src>),
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxGroup<'src> {
+    pub open: SyntaxToken<'src>,
+    pub children: Vec<SyntaxNode<'src>>,
+    pub close: Option<SyntaxToken<'src>>,
+}
+
+/// A lossless syntax tree: printing it gives back exactly the source it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree<'src> {
+    pub nodes: Vec<SyntaxNode<'src>>,
+    pub end: Vec<TriviaPiece<'src>>, //Trivia after the last token
+}
+impl<'src> SyntaxTree<'src> {
+    pub fn parse(source: &'src [u8]) -> Result<SyntaxTree<'src>, LexParseError> {
+        let mut tokens: Vec<SyntaxToken> = Vec::new();
+        let mut pending = Vec::new();
+        let mut on_token_line = false;
+        let mut pos = 0usize;
+        for spanned in Lexer::new(source) {
+            let spanned = spanned?;
+            let gap = text_of(source, pos, spanned.start)?;
+            push_whitespace(gap, &mut tokens, &mut pending, &mut on_token_line);
+            let text = text_of(source, spanned.start, spanned.end)?;
+            match spanned.node {
+                Lex::Brace(Brace::Comment(_), _, _, _)
+                | Lex::Brace(Brace::DocComment(_), _, _, _) => {
+                    let piece = TriviaPiece::Comment(text, spanned.node);
+                    match tokens.last_mut() {
+                        Some(token) if on_token_line => token.trailing.push(piece),
+                        _ => pending.push(piece),
+                    }
+                    on_token_line &= !text.contains('\n');
+                }
+                lex => {
+                    tokens.push(SyntaxToken {
+                        lex,
+                        text,
+                        start: spanned.start,
+                        leading: std::mem::take(&mut pending),
+                        trailing: Vec::new(),
+                    });
+                    on_token_line = true;
+                }
+            }
+            pos = spanned.end;
+        }
+        let gap = text_of(source, pos, source.len())?;
+        push_whitespace(gap, &mut tokens, &mut pending, &mut on_token_line);
+        Ok(SyntaxTree {
+            nodes: group(tokens),
+            end: pending,
+        })
+    }
+
+    /// Every token in source order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken<'src>> {
+        let mut tokens = Vec::new();
+        for node in self.nodes.iter() {
+            node.collect_tokens(&mut tokens);
+        }
+        tokens
+    }
+
+    /// The lexes the tokenizer reads: every token with the comments among them, in source order.
+    pub fn lexes(&self) -> Vec<Lex<'src>> {
+        let mut lexes = Vec::new();
+        let comments = |pieces: &[TriviaPiece<'src>], lexes: &mut Vec<Lex<'src>>| {
+            for piece in pieces.iter() {
+                if let TriviaPiece::Comment(_, lex) = piece {
+                    lexes.push(lex.clone());
+                }
+            }
+        };
+        for token in self.tokens().into_iter() {
+            comments(&token.leading, &mut lexes);
+            lexes.push(token.lex.clone());
+            comments(&token.trailing, &mut lexes);
+        }
+        comments(&self.end, &mut lexes);
+        lexes
+    }
+}
+
+impl<'src> SyntaxNode<'src> {
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken<'src>>) {
+        match self {
+            SyntaxNode::Token(token) => tokens.push(token),
+            SyntaxNode::Group(group) => {
+                tokens.push(&group.open);
+                for child in group.children.iter() {
+                    child.collect_tokens(tokens);
+                }
+                if let Some(close) = &group.close {
+                    tokens.push(close);
+                }
+            }
+        }
+    }
+}
+
+impl<'src> fmt::Display for SyntaxToken<'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for piece in self.leading.iter() {
+            f.write_str(piece.text())?;
+        }
+        f.write_str(self.text)?;
+        for piece in self.trailing.iter() {
+            f.write_str(piece.text())?;
+        }
+        Ok(())
+    }
+}
+impl<'src> fmt::Display for SyntaxNode<'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyntaxNode::Token(token) => write!(f, "{}", token),
+            SyntaxNode::Group(group) => {
+                write!(f, "{}", group.open)?;
+                for child in group.children.iter() {
+                    write!(f, "{}", child)?;
+                }
+                match &group.close {
+                    Some(close) => write!(f, "{}", close),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+impl<'src> fmt::Display for SyntaxTree<'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for node in self.nodes.iter() {
+            write!(f, "{}", node)?;
+        }
+        for piece in self.end.iter() {
+            f.write_str(piece.text())?;
+        }
+        Ok(())
+    }
+}
+
+fn text_of(source: &[u8], start: usize, end: usize) -> Result<&str, LexParseError> {
+    std::str::from_utf8(&source[start..end])
+        .map_err(|e| LexParseError::InvalidUtf8(start + e.valid_up_to()))
+}
+
+/// Splits whitespace between the end of the last token's line, which trails that token, and the
+/// rest, which leads the next token.
+fn push_whitespace<'src>(
+    gap: &'src str,
+    tokens: &mut [SyntaxToken<'src>],
+    pending: &mut Vec<TriviaPiece<'src>>,
+    on_token_line: &mut bool,
+) {
+    if gap.is_empty() {
+        return;
+    }
+    let rest = match tokens.last_mut() {
+        Some(token) if *on_token_line => {
+            let (same_line, rest) = gap.split_at(gap.find('\n').unwrap_or(gap.len()));
+            if !same_line.is_empty() {
+                token.trailing.push(TriviaPiece::Whitespace(same_line));
+            }
+            rest
+        }
+        _ => gap,
+    };
+    if !rest.is_empty() {
+        pending.push(TriviaPiece::Whitespace(rest));
+        *on_token_line = false;
+    }
+}
+
+/// Nests the tokens between each open brace and its close into a group.
+fn group(tokens: Vec<SyntaxToken>) -> Vec<SyntaxNode> {
+    let mut stack: Vec<SyntaxGroup> = Vec::new();
+    let mut root = Vec::new();
+    for token in tokens {
+        let node = match &token.lex {
+            Lex::Brace(_, BraceStatus::Open, _, _) => {
+                stack.push(SyntaxGroup {
+                    open: token,
+                    children: Vec::new(),
+                    close: None,
+                });
+                continue;
+            }
+            Lex::Brace(brace, BraceStatus::Close, _, _)
+                if stack.last().is_some_and(
+                    |g| matches!(&g.open.lex, Lex::Brace(open, _, _, _) if open == brace),
+                ) =>
+            {
+                let mut group = stack.pop().unwrap();
+                group.close = Some(token);
+                SyntaxNode::Group(group)
+            }
+            _ => SyntaxNode::Token(token),
+        };
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => root.push(node),
+        }
+    }
+    // groups left open run to the end of the source
+    while let Some(group) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(SyntaxNode::Group(group)),
+            None => root.push(SyntaxNode::Group(group)),
+        }
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn round_trip(source: &str) -> Result<SyntaxTree<'_>, LexParseError> {
+        let tree = SyntaxTree::parse(source.as_bytes())?;
+        assert_eq!(tree.to_string(), source);
+        Ok(tree)
+    }
+
+    #[test]
+    fn test_round_trip_resources() -> Result<(), LexParseError> {
+        for dir in ["res/test", "res/example"].iter() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let source = fs::read_to_string(entry.unwrap().path()).unwrap();
+                if SyntaxTree::parse(source.as_bytes()).is_ok() {
+                    round_trip(&source)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_round_trip_trivia() -> Result<(), LexParseError> {
+        round_trip("")?;
+        round_trip("  \n\t")?;
+        round_trip("// only a comment")?;
+        round_trip("a")?;
+        round_trip("\r\n  a  // trailing\n\n  /* leading */ b /* same line */ c  \n")?;
+        round_trip("x: \"a\\n{y}\" ; '\\'' /* /* nested */ */ 0x_ff_u")?;
+        round_trip("calc start: () {\n  [1..10] => print;\n}\n")?;
+        round_trip("{ ( ] } )")?;
+        round_trip("( [ unclosed")?;
+        Ok(())
+    }
+    #[test]
+    fn test_trivia_attachment() -> Result<(), LexParseError> {
+        let tree = round_trip("a // after a\n/// before b\n  b\n")?;
+        let tokens = tree.tokens();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(
+            tokens[0]
+                .trailing
+                .iter()
+                .map(|p| p.text())
+                .collect::<Vec<_>>(),
+            vec![" ", "// after a"]
+        );
+        assert_eq!(
+            tokens[1]
+                .leading
+                .iter()
+                .map(|p| p.text())
+                .collect::<Vec<_>>(),
+            vec!["\n", "/// before b", "\n  "]
+        );
+        assert_eq!(tree.end, vec![TriviaPiece::Whitespace("\n")]);
+        Ok(())
+    }
+    #[test]
+    fn test_groups() -> Result<(), LexParseError> {
+        let tree = round_trip("f(a, [b]) ) {c")?;
+        assert_eq!(tree.nodes.len(), 4);
+        match &tree.nodes[1] {
+            SyntaxNode::Group(group) => {
+                assert_eq!(group.open.text, "(");
+                assert_eq!(group.children.len(), 3);
+                assert_eq!(group.close.as_ref().map(|c| c.text), Some(")"));
+            }
+            other => panic!("expected a group, found {:?}", other),
+        }
+        // the stray `)` stays a token and the unclosed `{` takes the rest
+        assert!(matches!(&tree.nodes[2], SyntaxNode::Token(t) if t.text == ")"));
+        assert!(matches!(&tree.nodes[3], SyntaxNode::Group(g) if g.close.is_none()));
+        Ok(())
+    }
+}
@@ -1,118 +1,116 @@
 use crate::parse::constant::Brace;
-use crate::parse::lex::{BraceContext, BraceStatus, Lex, LexParseError};
+use crate::parse::lex::{peek_char, BraceContext, BraceStatus, Lex, LexParseError};
 
 pub fn lex_brace(
     input: &[u8],
     index: &mut usize,
     acc: BraceContinuation,
-    lex_index: &usize,
     context: &mut BraceContext,
-) -> Result<Option<Lex>, LexParseError> {
+) -> Result<Option<Lex<'static>>, LexParseError> {
     *index += 1;
     match acc {
         BraceContinuation::Paranthese(status) => {
-            let level = context.update_refs(Brace::Brace, status, *index, *lex_index)?;
+            let level = context.update_level(Brace::Brace, status);
             return Ok(Some(Lex::Brace(Brace::Brace, status, level, *index)));
         }
         BraceContinuation::SquareBracket(status) => {
-            let level = context.update_refs(Brace::Square, status, *index, *lex_index)?;
+            let level = context.update_level(Brace::Square, status);
             return Ok(Some(Lex::Brace(Brace::Square, status, level, *index)));
         }
         BraceContinuation::CurlyBrackets(status) => {
-            let level = context.update_refs(Brace::Bracket, status, *index, *lex_index)?;
+            let level = context.update_level(Brace::Bracket, status);
             return Ok(Some(Lex::Brace(Brace::Bracket, status, level, *index)));
         }
         BraceContinuation::AngleBrackets(status) => {
-            let level = context.update_refs(Brace::Angle, status, *index, *lex_index)?;
+            let level = context.update_level(Brace::Angle, status);
             return Ok(Some(Lex::Brace(Brace::Angle, status, level, *index)));
         }
         BraceContinuation::Quote => {
-            let mut escaped = false;
-            let mut c = input[*index];
+            let open = *index - 1;
             let mut s = Vec::new();
-            while *index < input.len() && (!escaped && c != 34) {
-                if c == 10 {
-                    return Err(LexParseError::QuoteNotEnded(*index));
-                }
-                escaped = match c as char {
-                    '\\' => !escaped,
-                    _ => {
-                        s.push(c);
-                        false
+            loop {
+                match input.get(*index) {
+                    None | Some(b'\n') => return Err(LexParseError::QuoteNotEnded(open)),
+                    Some(b'"') => break,
+                    Some(b'\\') => {
+                        let c = lex_escape(input, index)?;
+                        s.extend_from_slice(c.encode_utf8(&mut [0u8; 4]).as_bytes());
                     }
-                };
-                *index += 1;
-                c = input[*index];
+                    Some(c) => {
+                        s.push(*c);
+                        *index += 1;
+                    }
+                }
             }
             *index += 1;
+            let s = String::from_utf8(s)
+                .map_err(|e| LexParseError::InvalidUtf8(open + 1 + e.utf8_error().valid_up_to()))?;
             return Ok(Some(Lex::Brace(
-                Brace::Quote(s.into_iter().map(|x| x as char).collect()),
+                Brace::Quote(s),
                 BraceStatus::Agnostic,
                 0,
                 *index,
             )));
         }
         BraceContinuation::Char => {
-            return match input[*index] as char {
-                '\'' => Err(LexParseError::NoCharBetweenSingleQuotes(*index)),
-                '\\' => {
-                    *index += 1;
-                    let c = input[*index];
-                    *index += 1;
-                    if input[*index] != 27 {
-                        Err(LexParseError::MultipleCharsBetweenSingleQuotes(*index))
-                    } else {
-                        *index += 1;
-                        Ok(Some(Lex::Brace(
-                            Brace::Char(c),
-                            BraceStatus::Agnostic,
-                            0,
-                            *index,
-                        )))
-                    }
-                }
-                _ => {
-                    let c = input[*index];
+            let open = *index - 1;
+            let c = match input.get(*index) {
+                None => return Err(LexParseError::QuoteNotEnded(open)),
+                Some(b'\'') => return Err(LexParseError::NoCharBetweenSingleQuotes(*index)),
+                Some(b'\\') => lex_escape(input, index)?,
+                Some(_) => lex_utf8(input, index)?,
+            };
+            match input.get(*index) {
+                Some(b'\'') => {
                     *index += 1;
-                    if input[*index] != 27 {
-                        Err(LexParseError::NoCharBetweenSingleQuotes(*index))
-                    } else {
-                        *index += 1;
-                        Ok(Some(Lex::Brace(
-                            Brace::Char(c),
-                            BraceStatus::Agnostic,
-                            0,
-                            *index,
-                        )))
-                    }
+                    Ok(Some(Lex::Brace(
+                        Brace::Char(c),
+                        BraceStatus::Agnostic,
+                        0,
+                        *index,
+                    )))
                 }
+                None => Err(LexParseError::QuoteNotEnded(open)),
+                Some(_) => Err(LexParseError::MultipleCharsBetweenSingleQuotes(*index)),
+            }
+        }
+        BraceContinuation::LineComment => {
+            *index += 1;
+            let start = *index;
+            while *index < input.len() && input[*index] != b'\n' {
+                *index += 1;
+            }
+            let s = &input[start..*index];
+            // `///` documents the declaration that follows, `////` is a plain comment again
+            let brace = match s {
+                [b'/', b'/', ..] => Brace::Comment(comment_text(s, start)?),
+                [b'/', doc @ ..] => Brace::DocComment(comment_text(doc, start + 1)?),
+                _ => Brace::Comment(comment_text(s, start)?),
             };
+            Ok(Some(Lex::Brace(brace, BraceStatus::Agnostic, 0, *index)))
         }
-        BraceContinuation::Comments => {
-            let mut c = input[*index];
-            let mut s = Vec::new();
-            match c as char {
-                '/' => {
-                    while *index < input.len() && c != 10 {
-                        s.push(c);
-                        *index += 1;
-                        c = input[*index];
+        BraceContinuation::BlockComment => {
+            let open = *index - 1;
+            *index += 1;
+            let start = *index;
+            let mut depth = 1usize;
+            while depth > 0 {
+                match (input.get(*index), input.get(*index + 1)) {
+                    (Some(b'/'), Some(b'*')) => {
+                        depth += 1;
+                        *index += 2;
                     }
-                }
-                '*' => {
-                    while *index < input.len() && c != 10 {
-                        s.push(c);
-                        *index += 1;
-                        c = input[*index];
+                    (Some(b'*'), Some(b'/')) => {
+                        depth -= 1;
+                        *index += 2;
                     }
+                    (Some(_), _) => *index += 1,
+                    (None, _) => return Err(LexParseError::CommentNotEnded(open)),
                 }
-                _ => {
-                    return Err(LexParseError::InvalidCharacter(*index));
-                }
-            };
-            *index += 1;
+            }
+            let s = &input[start..*index - 2];
             return Ok(Some(Lex::Brace(
-                Brace::Comment(s.into_iter().map(|x| x as char).collect()),
+                Brace::Comment(comment_text(s, start)?),
                 BraceStatus::Agnostic,
                 0,
                 *index,
@@ -120,25 +118,95 @@ pub fn lex_brace(
         }
     }
 }
+
+/// Decodes the text of a comment found at `start`, reporting the first byte that is not UTF-8.
+fn comment_text(s: &[u8], start: usize) -> Result<String, LexParseError> {
+    std::str::from_utf8(s)
+        .map(str::to_string)
+        .map_err(|e| LexParseError::InvalidUtf8(start + e.valid_up_to()))
+}
+
+/// Decodes the escape starting at the backslash under `index`, leaving `index` after it.
+fn lex_escape(input: &[u8], index: &mut usize) -> Result<char, LexParseError> {
+    let start = *index;
+    *index += 1;
+    let c = match input.get(*index) {
+        Some(b'n') => '\n',
+        Some(b't') => '\t',
+        Some(b'r') => '\r',
+        Some(b'0') => '\0',
+        Some(b'\\') => '\\',
+        Some(b'"') => '"',
+        Some(b'\'') => '\'',
+        Some(b'u') => return lex_unicode_escape(input, index, start),
+        Some(_) => {
+            let c = lex_utf8(input, index)?;
+            return Err(LexParseError::InvalidEscape(start, c));
+        }
+        None => return Err(LexParseError::QuoteNotEnded(start)),
+    };
+    *index += 1;
+    Ok(c)
+}
+
+/// Decodes `\u{...}` holding one to six hex digits of a unicode scalar value.
+fn lex_unicode_escape(
+    input: &[u8],
+    index: &mut usize,
+    start: usize,
+) -> Result<char, LexParseError> {
+    *index += 1;
+    if input.get(*index) != Some(&b'{') {
+        return Err(LexParseError::InvalidUnicodeEscape(start));
+    }
+    *index += 1;
+    let digits = *index;
+    while let Some(c) = input.get(*index) {
+        if !c.is_ascii_hexdigit() {
+            break;
+        }
+        *index += 1;
+    }
+    let hex = &input[digits..*index];
+    if hex.is_empty() || hex.len() > 6 || input.get(*index) != Some(&b'}') {
+        return Err(LexParseError::InvalidUnicodeEscape(start));
+    }
+    *index += 1;
+    std::str::from_utf8(hex)
+        .ok()
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .and_then(std::char::from_u32)
+        .ok_or(LexParseError::InvalidUnicodeEscape(start))
+}
+
+/// Decodes the UTF-8 encoded character under `index`, leaving `index` after it.
+fn lex_utf8(input: &[u8], index: &mut usize) -> Result<char, LexParseError> {
+    let (c, len) = peek_char(input, *index).ok_or(LexParseError::InvalidUtf8(*index))?;
+    *index += len;
+    Ok(c)
+}
+
+/// Comments start with `//` or `/*`, any other `/` is left to the operators.
+pub fn start_comment(input: &[u8], index: usize) -> Option<BraceContinuation> {
+    match (input.get(index), input.get(index + 1)) {
+        (Some(b'/'), Some(b'/')) => Some(BraceContinuation::LineComment),
+        (Some(b'/'), Some(b'*')) => Some(BraceContinuation::BlockComment),
+        _ => None,
+    }
+}
+
 pub fn take_brace(c: u8, _context: &mut BraceContext) -> Option<BraceContinuation> {
     match c as char {
-        '(' => Some(BraceContinuation::Paranthese(BraceStatus::SpeculativeOpen)),
+        '(' => Some(BraceContinuation::Paranthese(BraceStatus::Open)),
         ')' => Some(BraceContinuation::Paranthese(BraceStatus::Close)),
-        '[' => Some(BraceContinuation::SquareBracket(
-            BraceStatus::SpeculativeOpen,
-        )),
+        '[' => Some(BraceContinuation::SquareBracket(BraceStatus::Open)),
         ']' => Some(BraceContinuation::SquareBracket(BraceStatus::Close)),
-        '{' => Some(BraceContinuation::CurlyBrackets(
-            BraceStatus::SpeculativeOpen,
-        )),
+        '{' => Some(BraceContinuation::CurlyBrackets(BraceStatus::Open)),
         '}' => Some(BraceContinuation::CurlyBrackets(BraceStatus::Close)),
-        '<' => Some(BraceContinuation::AngleBrackets(
-            BraceStatus::SpeculativeOpen,
-        )),
+        '<' => Some(BraceContinuation::AngleBrackets(BraceStatus::Open)),
         '>' => Some(BraceContinuation::AngleBrackets(BraceStatus::Close)),
         '\"' => Some(BraceContinuation::Quote),
         '\'' => Some(BraceContinuation::Char),
-        '/' => Some(BraceContinuation::Comments),
         _ => None,
     }
 }
@@ -150,5 +218,6 @@ pub enum BraceContinuation {
     AngleBrackets(BraceStatus),
     Quote,
     Char,
-    Comments,
+    LineComment,
+    BlockComment,
 }
@@ -8,7 +8,7 @@ pub fn is_delimiter(c: u8) -> bool {
     }
 }
 
-pub fn lex_delim(c: u8) -> Result<Option<Lex>, LexParseError> {
+pub fn lex_delim(c: u8) -> Result<Option<Lex<'static>>, LexParseError> {
     match c as char {
         ',' => Ok(Some(Lex::Delimiter(Delimiter::Comma))),
         ';' => Ok(Some(Lex::Delimiter(Delimiter::Semicolon))),
@@ -0,0 +1,168 @@
+use crate::parse::lex::{is_whitespace, lex, BraceContext, Lex, LexParseError};
+use std::io::{self, Read};
+
+/// A lexeme with the byte range of the source it was lexed from.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The most bytes any lexeme looks past its own end to decide where it stops, such as the `.`
+/// and digit after `1` in `1.5`.
+const LOOKAHEAD: usize = 8;
+const CHUNK: usize = 8 * 1024;
+
+enum Source<'src> {
+    Slice(&'src [u8]),
+    Reader(ReadSource<'src>),
+}
+
+struct ReadSource<'src> {
+    reader: Box<dyn Read + 'src>,
+    buffer: Vec<u8>,
+    offset: usize, //Position in the source of the start of the buffer
+    eof: bool,
+}
+
+/// Lexes a source one lexeme at a time. Braces come out as they are found, with their nesting
+/// level, and are matched to each other by the tokenizer. Nothing more is lexed after an error.
+pub struct Lexer<'src> {
+    source: Source<'src>,
+    index: usize,
+    braces: BraceContext,
+    done: bool,
+}
+impl<'src> Lexer<'src> {
+    /// Lexes a source held in memory, borrowing identifier and number spellings from it.
+    pub fn new(input: &'src [u8]) -> Lexer<'src> {
+        Lexer {
+            source: Source::Slice(input),
+            index: 0,
+            braces: BraceContext::new(),
+            done: false,
+        }
+    }
+    /// Lexes `reader` incrementally, reading only as far as the next lexeme needs. Positions
+    /// still count bytes from the start of the reader.
+    pub fn from_reader<R: Read + 'src>(reader: R) -> Lexer<'src> {
+        Lexer {
+            source: Source::Reader(ReadSource {
+                reader: Box::new(reader),
+                buffer: Vec::new(),
+                offset: 0,
+                eof: false,
+            }),
+            index: 0,
+            braces: BraceContext::new(),
+            done: false,
+        }
+    }
+    /// How deeply each kind of brace is nested after the lexemes lexed so far.
+    pub fn braces(&self) -> &BraceContext {
+        &self.braces
+    }
+}
+impl<'src> Iterator for Lexer<'src> {
+    type Item = Result<Spanned<Lex<'src>>, LexParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = match self.source {
+            Source::Slice(input) => lex_spanned(input, &mut self.index, &mut self.braces),
+            Source::Reader(ref mut source) => source.next(&mut self.index, &mut self.braces),
+        };
+        if let Some(Err(_)) = next {
+            self.done = true;
+        }
+        next
+    }
+}
+
+/// Lexes whatever follows the whitespace at `index`, or `None` at the end of `input`.
+fn lex_spanned<'a>(
+    input: &'a [u8],
+    index: &mut usize,
+    braces: &mut BraceContext,
+) -> Option<Result<Spanned<Lex<'a>>, LexParseError>> {
+    while input.get(*index).is_some_and(|c| is_whitespace(*c)) {
+        *index += 1;
+    }
+    if *index >= input.len() {
+        return None;
+    }
+    let start = *index;
+    Some(match lex(input, index, braces) {
+        Ok(Some(node)) => Ok(Spanned {
+            node,
+            start,
+            end: *index,
+        }),
+        Ok(None) => Err(LexParseError::InvalidCharacter(start)),
+        Err(err) => Err(err),
+    })
+}
+
+impl<'src> ReadSource<'src> {
+    /// Lexes the next lexeme from the buffer, reading more whenever the lexeme, or the bytes
+    /// that decide where it ends, could still run past what has been read so far.
+    fn next(
+        &mut self,
+        index: &mut usize,
+        braces: &mut BraceContext,
+    ) -> Option<Result<Spanned<Lex<'static>>, LexParseError>> {
+        loop {
+            let mut end = *index;
+            let mut attempt = braces.clone();
+            let next = lex_spanned(&self.buffer, &mut end, &mut attempt);
+            let settled =
+                self.eof || (matches!(next, Some(Ok(_))) && end + LOOKAHEAD <= self.buffer.len());
+            if !settled {
+                if let Err(err) = self.fill() {
+                    return Some(Err(LexParseError::Io(err.to_string())));
+                }
+                continue;
+            }
+            let offset = self.offset;
+            let next = next.map(|result| match result {
+                Ok(spanned) => Ok(Spanned {
+                    node: spanned.node.shifted(offset),
+                    start: spanned.start + offset,
+                    end: spanned.end + offset,
+                }),
+                Err(err) => Err(err.shifted(offset)),
+            });
+            *braces = attempt;
+            *index = end;
+            if *index >= CHUNK && *index * 2 >= self.buffer.len() {
+                self.buffer.drain(..*index);
+                self.offset += *index;
+                *index = 0;
+            }
+            return next;
+        }
+    }
+
+    /// Reads at least another chunk, doubling the buffer for lexemes longer than it.
+    fn fill(&mut self) -> io::Result<()> {
+        let len = self.buffer.len();
+        self.buffer.resize(len + std::cmp::max(CHUNK, len), 0);
+        loop {
+            match self.reader.read(&mut self.buffer[len..]) {
+                Ok(read) => {
+                    self.buffer.truncate(len + read);
+                    self.eof = read == 0;
+                    return Ok(());
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.buffer.truncate(len);
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
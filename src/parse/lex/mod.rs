@@ -1,156 +1,139 @@
 use crate::parse::constant::{Brace, Delimiter, Keyword, Operator};
-use std::collections::HashMap;
+use std::borrow::Cow;
 mod brace;
 mod delimiter;
 mod identifier;
+mod lexer;
 mod number;
 mod operator;
 
-use crate::parse::lex::brace::{lex_brace, take_brace};
+use crate::parse::lex::brace::{lex_brace, start_comment, take_brace};
 use crate::parse::lex::delimiter::{is_delimiter, lex_delim};
 use crate::parse::lex::identifier::{is_ident_start, lex_ident};
+pub use crate::parse::lex::lexer::{Lexer, Spanned};
+pub use crate::parse::lex::number::parse_number;
 use crate::parse::lex::number::{is_number_start, lex_num};
 use crate::parse::lex::operator::{lex_op, start_operator};
 
-#[derive(PartialEq, Eq, Debug)]
-pub enum Lex {
-    Identifier(String, usize),
+/// A lexeme. Identifiers and numbers borrow their spelling from the source when it is in memory,
+/// and own it when lexed from a reader.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Lex<'src> {
+    Identifier(Cow<'src, str>, usize),
     Keyword(Keyword, usize),
-    Integer(String, usize),
-    Float(String, usize),
+    Integer(Cow<'src, str>, usize),
+    Float(Cow<'src, str>, usize),
     Operator(Operator, usize),
-    Brace(Brace, BraceStatus, usize, usize),
+    Brace(Brace, BraceStatus, usize, usize), //Brace, Status, Level, Position
     Delimiter(Delimiter),
 }
+impl<'src> Lex<'src> {
+    /// Copies out any borrowed spelling and moves every position `by` bytes further on.
+    pub fn shifted(self, by: usize) -> Lex<'static> {
+        match self {
+            Lex::Identifier(s, pos) => Lex::Identifier(Cow::Owned(s.into_owned()), pos + by),
+            Lex::Keyword(k, pos) => Lex::Keyword(k, pos + by),
+            Lex::Integer(s, pos) => Lex::Integer(Cow::Owned(s.into_owned()), pos + by),
+            Lex::Float(s, pos) => Lex::Float(Cow::Owned(s.into_owned()), pos + by),
+            Lex::Operator(op, pos) => Lex::Operator(op, pos + by),
+            Lex::Brace(brace, status, level, pos) => Lex::Brace(brace, status, level, pos + by),
+            Lex::Delimiter(d) => Lex::Delimiter(d),
+        }
+    }
+}
+/// Whether a brace opens or closes a block. Quotes, chars and comments are complete on their own.
+/// Matching an open brace to its close is left to the tokenizer.
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum BraceStatus {
-    SpeculativeOpen,
-    Open(usize),
+    Open,
     Close,
     Agnostic,
 }
-#[derive(PartialEq, Eq, Debug)]
+/// How deeply each kind of brace is nested at the current point of the source.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
 pub struct BraceContext {
-    paranthese_map: HashMap<usize, usize>,
-    squares_map: HashMap<usize, usize>,
-    curls_map: HashMap<usize, usize>,
-    angles_map: HashMap<usize, usize>,
-    //
-    paranthese: Vec<usize>,
-    squares: Vec<usize>,
-    curls: Vec<usize>,
-    angles: Vec<usize>,
+    paranthese: usize,
+    squares: usize,
+    curls: usize,
+    angles: usize,
 }
 impl BraceContext {
     pub fn new() -> BraceContext {
-        return BraceContext {
-            paranthese_map: HashMap::new(),
-            squares_map: HashMap::new(),
-            curls_map: HashMap::new(),
-            angles_map: HashMap::new(),
-            //
-            paranthese: Vec::new(),
-            squares: Vec::new(),
-            curls: Vec::new(),
-            angles: Vec::new(),
-        };
+        BraceContext::default()
     }
-    pub fn distribute_braces(&self, lexes: &mut Vec<Lex>) {
-        for (insert_at, len_of_scope) in self.paranthese_map.iter() {
-            let (level, pos) = match lexes[*insert_at] {
-                Lex::Brace(_, _, level, pos) => (level, pos),
-                _ => (0usize, 0usize),
-            };
-            std::mem::replace(
-                &mut lexes[*insert_at],
-                Lex::Brace(Brace::Brace, BraceStatus::Open(*len_of_scope), level, pos),
-            );
-        }
-        for (insert_at, len_of_scope) in self.squares_map.iter() {
-            let (level, pos) = match lexes[*insert_at] {
-                Lex::Brace(_, _, level, pos) => (level, pos),
-                _ => (0usize, 0usize),
-            };
-            std::mem::replace(
-                &mut lexes[*insert_at],
-                Lex::Brace(Brace::Square, BraceStatus::Open(*len_of_scope), level, pos),
-            );
-        }
-        for (insert_at, len_of_scope) in self.curls_map.iter() {
-            let (level, pos) = match lexes[*insert_at] {
-                Lex::Brace(_, _, level, pos) => (level, pos),
-                _ => (0usize, 0usize),
-            };
-            std::mem::replace(
-                &mut lexes[*insert_at],
-                Lex::Brace(Brace::Bracket, BraceStatus::Open(*len_of_scope), level, pos),
-            );
-        }
-        for (insert_at, len_of_scope) in self.angles_map.iter() {
-            let (level, pos) = match lexes[*insert_at] {
-                Lex::Brace(_, _, level, pos) => (level, pos),
-                _ => (0usize, 0usize),
-            };
-            std::mem::replace(
-                &mut lexes[*insert_at],
-                Lex::Brace(Brace::Angle, BraceStatus::Open(*len_of_scope), level, pos),
-            );
-        }
+    /// Whether any brace opened so far is still waiting for its close.
+    pub fn is_open(&self) -> bool {
+        self.paranthese + self.squares + self.curls + self.angles > 0
     }
-
-    pub fn update_refs(
-        &mut self,
-        brace: Brace,
-        status: BraceStatus,
-        index: usize,
-        lex_index: usize,
-    ) -> Result<usize, LexParseError> {
-        let stack = match brace {
+    /// The level of a brace: the depth outside it when it opens, or inside it when it closes.
+    /// A close without an open is left at level zero for the tokenizer to report.
+    pub fn update_level(&mut self, brace: Brace, status: BraceStatus) -> usize {
+        let depth = match brace {
             Brace::Brace => &mut self.paranthese,
             Brace::Angle => &mut self.angles,
             Brace::Bracket => &mut self.curls,
             Brace::Square => &mut self.squares,
-            _ => &mut self.paranthese,
+            _ => return 0,
         };
         match status {
-            BraceStatus::SpeculativeOpen => {
-                stack.push(lex_index);
-                Ok(stack.len() - 1usize)
+            BraceStatus::Open => {
+                *depth += 1;
+                *depth - 1
             }
             BraceStatus::Close => {
-                if let Some(index_to_insert) = stack.pop() {
-                    match brace {
-                        Brace::Brace => &mut self.paranthese_map,
-                        Brace::Angle => &mut self.angles_map,
-                        Brace::Bracket => &mut self.curls_map,
-                        Brace::Square => &mut self.squares_map,
-                        _ => &mut self.paranthese_map,
-                    }
-                    .insert(index_to_insert, lex_index);
-                    Ok(stack.len() + 1usize)
-                } else {
-                    Err(LexParseError::InvalidDelimiter(index))
-                }
+                let level = *depth;
+                *depth = depth.saturating_sub(1);
+                level
             }
-            BraceStatus::Agnostic => Ok(0usize),
-            BraceStatus::Open(_) => Err(LexParseError::InvalidDelimiter(index)),
+            BraceStatus::Agnostic => 0,
         }
     }
 }
 #[derive(Debug, PartialEq, Eq)]
 pub enum LexParseError {
-    InvalidDelimiter(usize),
     QuoteNotEnded(usize),
     NoCharBetweenSingleQuotes(usize),
     MultipleCharsBetweenSingleQuotes(usize),
     InvalidCharacter(usize),
+    CommentNotEnded(usize),
+    InvalidEscape(usize, char),
+    InvalidUnicodeEscape(usize),
+    InvalidUtf8(usize),
+    InvalidNumber(usize),
+    InvalidNumberSuffix(usize),
+    NumberOverflow(usize),
+    Io(String),
 }
-pub fn lex(
-    input: &[u8],
+impl LexParseError {
+    /// Moves the position of the error `by` bytes further on.
+    pub fn shifted(self, by: usize) -> LexParseError {
+        match self {
+            LexParseError::QuoteNotEnded(pos) => LexParseError::QuoteNotEnded(pos + by),
+            LexParseError::NoCharBetweenSingleQuotes(pos) => {
+                LexParseError::NoCharBetweenSingleQuotes(pos + by)
+            }
+            LexParseError::MultipleCharsBetweenSingleQuotes(pos) => {
+                LexParseError::MultipleCharsBetweenSingleQuotes(pos + by)
+            }
+            LexParseError::InvalidCharacter(pos) => LexParseError::InvalidCharacter(pos + by),
+            LexParseError::CommentNotEnded(pos) => LexParseError::CommentNotEnded(pos + by),
+            LexParseError::InvalidEscape(pos, c) => LexParseError::InvalidEscape(pos + by, c),
+            LexParseError::InvalidUnicodeEscape(pos) => {
+                LexParseError::InvalidUnicodeEscape(pos + by)
+            }
+            LexParseError::InvalidUtf8(pos) => LexParseError::InvalidUtf8(pos + by),
+            LexParseError::InvalidNumber(pos) => LexParseError::InvalidNumber(pos + by),
+            LexParseError::InvalidNumberSuffix(pos) => LexParseError::InvalidNumberSuffix(pos + by),
+            LexParseError::NumberOverflow(pos) => LexParseError::NumberOverflow(pos + by),
+            LexParseError::Io(err) => LexParseError::Io(err),
+        }
+    }
+}
+pub fn lex<'src>(
+    input: &'src [u8],
     index: &mut usize,
-    lex_index: &usize,
     brace_context: &mut BraceContext,
-) -> Result<Option<Lex>, LexParseError> {
+) -> Result<Option<Lex<'src>>, LexParseError> {
     if *index >= input.len() {
         return Ok(None);
     } else {
@@ -163,17 +146,19 @@ pub fn lex(
             c = input[*index];
         }
         let fallback = *index;
-        if is_ident_start(c) {
-            return lex_ident(input, index, c);
+        if peek_char(input, *index).is_some_and(|(c, _)| is_ident_start(c)) {
+            return lex_ident(input, index);
         } else if is_delimiter(c) {
             *index = *index + 1;
             return lex_delim(c);
         } else if is_number_start(c) {
             return lex_num(input, index, c);
+        } else if let Some(comment) = start_comment(input, *index) {
+            return lex_brace(input, index, comment, brace_context);
         } else if let Some(op) = start_operator(c, *index) {
             return lex_op(input, index, op);
         } else if let Some(brace) = take_brace(c, brace_context) {
-            return lex_brace(input, index, brace, lex_index, brace_context);
+            return lex_brace(input, index, brace, brace_context);
         }
 
         *index = fallback;
@@ -181,7 +166,28 @@ pub fn lex(
     }
 }
 
-fn is_whitespace(c: u8) -> bool {
+/// The source between `start` and `end`, which the lexer has already checked is valid UTF-8.
+fn source_str(input: &[u8], start: usize, end: usize) -> Result<&str, LexParseError> {
+    std::str::from_utf8(&input[start..end]).map_err(|_| LexParseError::InvalidUtf8(start))
+}
+
+/// The char encoded at byte `index` of `input` and its length in bytes, if it is valid UTF-8.
+pub fn peek_char(input: &[u8], index: usize) -> Option<(char, usize)> {
+    let len = match *input.get(index)? {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return None,
+    };
+    let c = std::str::from_utf8(input.get(index..index + len)?)
+        .ok()?
+        .chars()
+        .next()?;
+    Some((c, len))
+}
+
+pub fn is_whitespace(c: u8) -> bool {
     match c as char {
         ' ' => true,
         '\t' => true,
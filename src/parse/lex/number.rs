@@ -1,84 +1,118 @@
-use crate::parse::lex::{Lex, LexParseError};
+use crate::parse::constant::Number;
+use crate::parse::lex::{source_str, Lex, LexParseError};
 
 pub fn is_number_start(c: u8) -> bool {
-    (c >= 48 && c <= 57) //number
+    c.is_ascii_digit()
 }
 
-pub fn lex_num(input: &[u8], index: &mut usize, mut c: u8) -> Result<Option<Lex>, LexParseError> {
-    *index += 1;
-    if *index >= input.len() {
-        return Ok(Some(Lex::Integer((c as char).to_string(), *index)));
-    }
-    let mut num = Vec::new();
-    let mut classification = NumberClassification::Integer(NumberStopCondition::CanContinue);
-    num.push(c);
-    c = input[*index];
-    classification = classification.classifiy_num(c);
-    loop {
-        match classification.condition() {
-            NumberStopCondition::NotNumber => break,
-            NumberStopCondition::MustEnd => {
-                num.push(c);
+/// Scans a number literal: an optional `0x`, `0b` or `0o` prefix, digits with `_` separators,
+/// a decimal fraction and exponent, then an optional `u`, `b` or `f` suffix. The literal is
+/// validated here so malformed or overflowing numbers are reported with their position.
+pub fn lex_num<'src>(
+    input: &'src [u8],
+    index: &mut usize,
+    _c: u8,
+) -> Result<Option<Lex<'src>>, LexParseError> {
+    let start = *index;
+    let radix = radix_of(input, start);
+    *index += if radix == 10 { 1 } else { 2 };
+    let mut float = false;
+    while let Some(c) = input.get(*index) {
+        let next = input.get(*index + 1).copied().unwrap_or(0);
+        match c {
+            b'.' if radix == 10 && !float && next.is_ascii_digit() => float = true,
+            b'e' | b'E'
+                if radix == 10 && (next.is_ascii_digit() || next == b'+' || next == b'-') =>
+            {
+                float = true;
                 *index += 1;
-                break;
-            }
-            NumberStopCondition::CanContinue => {
-                num.push(c);
             }
+            c if c.is_ascii_alphanumeric() || *c == b'_' => {}
+            _ => break,
         }
         *index += 1;
-        if *index >= input.len() {
-            break;
-        }
-        c = input[*index];
-        classification = classification.classifiy_num(c);
     }
-    return match classification {
-        NumberClassification::Integer(_) => Ok(Some(Lex::Integer(
-            num.into_iter().map(|x| x as char).collect(),
-            *index,
-        ))),
-        NumberClassification::Floating(_) => Ok(Some(Lex::Float(
-            num.into_iter().map(|x| x as char).collect(),
-            *index,
-        ))),
-    };
+    let text = source_str(input, start, *index)?;
+    match parse_number(text, start)? {
+        Number::Float(_) => Ok(Some(Lex::Float(text.into(), *index))),
+        _ => Ok(Some(Lex::Integer(text.into(), *index))),
+    }
 }
-#[derive(Clone, Copy, Debug)]
-enum NumberStopCondition {
-    MustEnd,
-    CanContinue,
-    NotNumber,
+
+/// The radix of the literal starting at `start`. A prefix only counts when a digit of its radix
+/// follows, so `0b` on its own is the byte zero rather than an empty binary literal.
+fn radix_of(input: &[u8], start: usize) -> u32 {
+    let radix = match (input.get(start), input.get(start + 1)) {
+        (Some(b'0'), Some(b'x')) | (Some(b'0'), Some(b'X')) => 16,
+        (Some(b'0'), Some(b'b')) | (Some(b'0'), Some(b'B')) => 2,
+        (Some(b'0'), Some(b'o')) | (Some(b'0'), Some(b'O')) => 8,
+        _ => return 10,
+    };
+    match input.get(start + 2) {
+        Some(c) if (*c as char).is_digit(radix) || *c == b'_' => radix,
+        _ => 10,
+    }
 }
-#[derive(Clone, Copy, Debug)]
-enum NumberClassification {
-    Integer(NumberStopCondition),
-    Floating(NumberStopCondition),
+
+/// Converts the spelling of a number literal into its value. `pos` is where the literal starts
+/// and is reported with any error. In hex literals `b` and `f` are digits, so only `u` applies.
+/// An unsuffixed literal must fit the 4 byte `int`: a decimal one as a signed value, a prefixed
+/// one as its bit pattern, so `0xffff_ffff` is accepted.
+pub fn parse_number(text: &str, pos: usize) -> Result<Number, LexParseError> {
+    let radix = radix_of(text.as_bytes(), 0);
+    let body = if radix == 10 { text } else { &text[2..] };
+    let digits_end = body
+        .char_indices()
+        .find(|(i, c)| !is_digit_of(body, *i, *c, radix))
+        .map(|(i, _)| i)
+        .unwrap_or(body.len());
+    let (digits, suffix) = body.split_at(digits_end);
+    let digits = digits.replace('_', "");
+    if digits.is_empty() || !digits.starts_with(|c: char| c.is_digit(radix)) {
+        return Err(LexParseError::InvalidNumber(pos));
+    }
+    if radix != 10 && !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(LexParseError::InvalidNumber(pos));
+    }
+    let float = radix == 10 && digits.contains(['.', 'e', 'E']);
+    match (suffix, float) {
+        ("", false) if radix == 10 => i32::from_str_radix(&digits, radix)
+            .map(|i| Number::Integer(i as i64))
+            .map_err(|_| LexParseError::NumberOverflow(pos)),
+        ("", false) => u32::from_str_radix(&digits, radix)
+            .map(|i| Number::Integer(i as i64))
+            .map_err(|_| LexParseError::NumberOverflow(pos)),
+        ("u", false) => u64::from_str_radix(&digits, radix)
+            .map(Number::UnsignedInt)
+            .map_err(|_| LexParseError::NumberOverflow(pos)),
+        ("b", false) => u8::from_str_radix(&digits, radix)
+            .map(Number::Byte)
+            .map_err(|_| LexParseError::NumberOverflow(pos)),
+        ("", true) | ("f", _) if radix == 10 => match digits.parse::<f64>() {
+            Ok(f) if f.is_finite() => Ok(Number::Float(f)),
+            Ok(_) => Err(LexParseError::NumberOverflow(pos)),
+            Err(_) => Err(LexParseError::InvalidNumber(pos)),
+        },
+        _ => Err(LexParseError::InvalidNumberSuffix(pos)),
+    }
 }
-impl NumberClassification {
-    fn classifiy_num(&self, c: u8) -> NumberClassification {
-        match *self {
-            NumberClassification::Integer(_) => match is_number_start(c) {
-                true => NumberClassification::Integer(NumberStopCondition::CanContinue),
-                false => match c {
-                    46 => NumberClassification::Floating(NumberStopCondition::CanContinue),
-                    102 => NumberClassification::Floating(NumberStopCondition::MustEnd),
-                    _ => NumberClassification::Integer(NumberStopCondition::NotNumber),
-                },
-            },
-            NumberClassification::Floating(_) => match is_number_start(c) {
-                true => NumberClassification::Floating(NumberStopCondition::CanContinue),
-                false => match c {
-                    102 => NumberClassification::Floating(NumberStopCondition::MustEnd),
-                    _ => NumberClassification::Floating(NumberStopCondition::NotNumber),
-                },
-            },
-        }
+
+/// Whether the char at `i` still belongs to the digits, rather than the suffix, of `body`.
+fn is_digit_of(body: &str, i: usize, c: char, radix: u32) -> bool {
+    if c == '_' || c.is_digit(radix) {
+        return true;
+    }
+    if radix != 10 {
+        // a decimal digit out of range is a typo, not the start of a suffix
+        return c.is_ascii_digit();
     }
-    fn condition(&self) -> NumberStopCondition {
-        match *self {
-            NumberClassification::Integer(condition) => condition,
-            NumberClassification::Floating(condition) => condition,
+    let previous = body[..i].chars().last();
+    match c {
+        '.' => true,
+        'e' | 'E' => {
+            body[i + 1..].starts_with(|n: char| n.is_ascii_digit() || n == '+' || n == '-')
         }
+        '+' | '-' => matches!(previous, Some('e') | Some('E')),
+        _ => false,
     }
 }
@@ -5,7 +5,7 @@ pub fn lex_op(
     input: &[u8],
     index: &mut usize,
     mut acc: OperatorContinuation,
-) -> Result<Option<Lex>, LexParseError> {
+) -> Result<Option<Lex<'static>>, LexParseError> {
     *index += 1;
     while *index < input.len() {
         if let Some(next) = acc.next(input[*index], *index) {
@@ -55,6 +55,7 @@ pub enum OperatorContinuation {
     AsteriskEquals(usize),
     Dot(usize),
     DoubleDot(usize),
+    DoubleDotEquals(usize),
     TripleDot(usize),
     Not(usize),
     NotEquals(usize),
@@ -73,7 +74,7 @@ pub enum OperatorContinuation {
     Into(usize),
 }
 impl OperatorContinuation {
-    fn as_lex(&self) -> Option<Lex> {
+    fn as_lex(&self) -> Option<Lex<'static>> {
         match *self {
             OperatorContinuation::Plus(index) => Some(Lex::Operator(Operator::Plus, index + 1)),
             OperatorContinuation::PlusEquals(index) => {
@@ -126,6 +127,9 @@ impl OperatorContinuation {
             OperatorContinuation::DoubleDot(index) => {
                 Some(Lex::Operator(Operator::RangeMiddle, index + 1))
             }
+            OperatorContinuation::DoubleDotEquals(index) => {
+                Some(Lex::Operator(Operator::RangeInclusive, index + 1))
+            }
             OperatorContinuation::TripleDot(index) => {
                 Some(Lex::Operator(Operator::ArrayContinuation, index + 1))
             }
@@ -199,8 +203,10 @@ impl OperatorContinuation {
             },
             OperatorContinuation::DoubleDot(_) => match c as char {
                 '.' => Some(OperatorContinuation::TripleDot(index)),
+                '=' => Some(OperatorContinuation::DoubleDotEquals(index)),
                 _ => None,
             },
+            OperatorContinuation::DoubleDotEquals(_) => None,
             OperatorContinuation::TripleDot(_) => None,
             OperatorContinuation::Colon(_) => match c as char {
                 ':' => Some(OperatorContinuation::DoubleColon(index)),
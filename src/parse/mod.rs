@@ -1,5 +1,9 @@
+pub mod ast;
 pub mod constant;
+pub mod cst;
 pub mod lex;
 
 pub mod context;
 pub mod token;
+pub mod tree;
+pub mod visit;
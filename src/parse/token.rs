@@ -1,6 +1,7 @@
-use crate::parse::constant::{Brace, Keyword, Number, Operator};
-use crate::parse::context::{BraceState, TokenizerContext};
-use crate::parse::lex::{lex, BraceContext, BraceStatus, Lex, LexParseError};
+use crate::parse::constant::{Brace, Delimiter, Keyword, Number, Operator};
+use crate::parse::context::{BraceState, TokenizerContext, Trivia};
+use crate::parse::cst::SyntaxTree;
+use crate::parse::lex::{parse_number, BraceStatus, Lex, LexParseError, Lexer};
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub enum Literal {
@@ -8,7 +9,6 @@ pub enum Literal {
     Identifier(String),
     Number(Number),
     String(String),
-    Comment(String),
     Void,
 }
 #[derive(Debug, PartialEq, Eq)]
@@ -25,16 +25,21 @@ pub enum Token {
     Block(Brace, usize),
     AgrandizedString(String, Box<Token>, String),
     Constructor(String, Box<Token>),
+    Generic(String, Box<Token>), //Identifier, Type arguments
+    GenericConstructor(String, Box<Token>, Box<Token>), //Identifier, Type arguments, Parameters
     Array(Box<Token>),
+    Index(Box<Token>, Box<Token>), //Collection, Indices
     VariableDef(Literal, String),
-    TypeDef(Literal, Box<Token>), //Identifier, Body
-    MethodDef(Keyword, String, Box<Token>, Box<Token>),
+    Binding(String, Box<Token>),                      //Identifier, Value
+    TypeDef(Literal, Option<Box<Token>>, Box<Token>), //Identifier, Type parameters, Body
+    MethodDef(Keyword, String, Option<Box<Token>>, Box<Token>, Box<Token>), //Mode, Identifier, Type parameters, Parameters, Body
     Delimiter,
 }
 #[derive(Debug, PartialEq, Eq)]
 pub enum TokenParseError {
     Lex(LexParseError),
-    UnableToFindNextLex(usize),
+    UnmatchedBrace(usize),
+    UnclosedBrace(usize),
     ContextTriedToEscapeRootScope,
     UnsupportedTokenizerOperator,
     UnsupportedKeywordToken(Keyword),
@@ -59,33 +64,37 @@ pub enum TokenParseError {
     CannotUseKeywordAsIdentifier(Keyword),
     CannotUseNumberAsIdentifier(Number),
     CannotUseStringAsIdentifier(String),
-    CannotUseCommentAsIdentifier(String),
+    ExpectedBindingValue(usize),
+    UnmatchedInterpolationBrace(usize),
+    ExpectedInterpolatedExpression(usize),
+    ExpectedBlock,
+    ExpectedTypeName,
+    ExpectedField,
 }
 
-pub fn parse_lexs(input: &[u8]) -> Result<Vec<Lex>, TokenParseError> {
-    let mut result = Vec::with_capacity(input.len());
-    let mut index = 0usize;
-    let mut brace_context = BraceContext::new();
-    while index < input.len() {
-        let previous = index;
-        if let Some(token) = match lex(input, &mut index, &result.len(), &mut brace_context) {
-            Ok(lex) => lex,
-            Err(err) => return Err(TokenParseError::Lex(err)),
-        } {
-            result.push(token);
-        }
-        if previous == index {
-            return Err(TokenParseError::UnableToFindNextLex(index));
-        }
-    }
-    //TODO verify braces are all closed
-    brace_context.distribute_braces(&mut result);
-    Ok(result)
+pub fn parse_lexs(input: &[u8]) -> Result<Vec<Lex<'_>>, TokenParseError> {
+    Lexer::new(input)
+        .map(|lex| {
+            lex.map(|spanned| spanned.node)
+                .map_err(TokenParseError::Lex)
+        })
+        .collect()
 }
 
 pub fn parse_tokens(input: &[u8]) -> Result<TokenizerContext, TokenParseError> {
+    let tree = SyntaxTree::parse(input).map_err(TokenParseError::Lex)?;
+    parse_tokens_from_tree(&tree)
+}
+
+/// Tokenizes the lexes of a syntax tree, keeping its comments as trivia.
+pub fn parse_tokens_from_tree(tree: &SyntaxTree) -> Result<TokenizerContext, TokenParseError> {
     let mut context = TokenizerContext::new();
-    let lexes = parse_lexs(input)?;
+    for token in tree.tokens().into_iter() {
+        if let Lex::Brace(Brace::Quote(_), _, _, end) = token.lex {
+            context.record_quote(end, token.start, token.text);
+        }
+    }
+    let lexes = tree.lexes();
     let mut index = 0usize;
     let mut previous_index = std::usize::MAX;
 
@@ -123,38 +132,90 @@ fn next_token<'a>(
     length: usize,
     context: &'a mut TokenizerContext,
 ) -> Result<(), TokenParseError> {
-    while match lexes.get(*index) {
-        Some(Lex::Delimiter(_)) => true,
-        _ => false,
-    } {
+    loop {
+        match lexes.get(*index) {
+            Some(Lex::Delimiter(_)) => {}
+            Some(Lex::Brace(Brace::Comment(c), _, _, pos)) => {
+                context.push_trivia(Trivia::Comment(c.clone(), *pos))
+            }
+            Some(Lex::Brace(Brace::DocComment(c), _, _, pos)) => {
+                context.push_trivia(Trivia::DocComment(c.clone(), *pos))
+            }
+            _ => break,
+        }
         *index += 1;
     }
-    if *index >= length {
-        return Ok(());
-    }
-    match match lexes.get(*index).unwrap() {
+    let lex = match lexes.get(*index) {
+        Some(lex) if *index < length => lex,
+        _ => return Ok(()),
+    };
+    match match lex {
         //Literals
-        Lex::Identifier(name, _) => {
-            context.push_identifier(name.clone());
-            if *index + 1 < lexes.len() {
+        Lex::Identifier(name, _)
+            if Keyword::from_spelling(name) == Some(Keyword::By)
+                && context.peek_range().is_some() =>
+        {
+            let range = context.pop_token().unwrap();
+            *index += 1;
+            next_token(lexes, index, length, context)?;
+            *index -= 1;
+            match (*range, context.pop_token()) {
+                (Token::Operator(OperatorGroup::BiOperator(op, start, end)), Some(step)) => {
+                    Ok(OperatorGroup::as_tri(start, op, end, step))
+                }
+                _ => Err(TokenParseError::ExpectedOperandButFoundNone),
+            }
+        }
+        Lex::Identifier(name, end) => {
+            context.push_identifier(name.to_string(), *end);
+            if let Some(close) = generic_close(lexes, *index + 1) {
+                *index += 1;
+                let arguments = push_generic_block(close, index, lexes, context)?;
+                if let Some(Lex::Brace(Brace::Brace, BraceStatus::Open, level, _)) =
+                    lexes.get(*index + 1)
+                {
+                    *index += 1;
+                    let parameters =
+                        push_braced_block(&Brace::Brace, *level, index, lexes, context)?;
+                    Ok(Box::new(Token::GenericConstructor(
+                        name.to_string(),
+                        arguments,
+                        parameters,
+                    )))
+                } else {
+                    Ok(Box::new(Token::Generic(name.to_string(), arguments)))
+                }
+            } else if *index + 1 < lexes.len() {
                 *index = *index + 1;
                 match lexes.get(*index).unwrap() {
-                    Lex::Identifier(variable_name, _) => Ok(Box::new(Token::VariableDef(
-                        Literal::Identifier(name.clone()),
-                        variable_name.clone(),
-                    ))),
-                    Lex::Brace(Brace::Brace, BraceStatus::Open(len), level, _pos) => {
+                    Lex::Identifier(variable_name, _)
+                        if Keyword::from_spelling(variable_name) != Some(Keyword::By) =>
+                    {
+                        Ok(Box::new(Token::VariableDef(
+                            Literal::Identifier(name.to_string()),
+                            variable_name.to_string(),
+                        )))
+                    }
+                    Lex::Brace(Brace::Brace, BraceStatus::Open, level, _pos) => {
                         let parameters =
-                            push_braced_block(&Brace::Brace, *len, *level, index, lexes, context)?;
-                        Ok(Literal::as_parameterized(name.clone(), parameters))
+                            push_braced_block(&Brace::Brace, *level, index, lexes, context)?;
+                        Ok(Literal::as_parameterized(name.to_string(), parameters))
+                    }
+                    Lex::Brace(Brace::Square, BraceStatus::Open, level, _pos) => {
+                        let indices =
+                            push_braced_block(&Brace::Square, *level, index, lexes, context)?;
+                        Ok(Box::new(Token::Index(
+                            Literal::as_identifier(name.to_string()),
+                            indices,
+                        )))
                     }
                     _ => {
                         *index = *index - 1;
-                        Ok(Literal::as_identifier(name.clone()))
+                        Ok(Literal::as_identifier(name.to_string()))
                     }
                 }
             } else {
-                Ok(Literal::as_identifier(name.clone()))
+                Ok(Literal::as_identifier(name.to_string()))
             }
         }
         Lex::Keyword(Keyword::Calc, _) => {
@@ -165,12 +226,14 @@ fn next_token<'a>(
         }
         Lex::Keyword(Keyword::Type, pos) => push_type_def(index, pos, length, lexes, context),
         Lex::Keyword(key, _) => Ok(Literal::as_keyword(key.clone())),
-        Lex::Integer(i, _) => Ok(Literal::as_integer(i.parse::<i64>().unwrap())),
-        Lex::Float(f, _) => Ok(Literal::as_float(f.parse::<f64>().unwrap())),
-        Lex::Brace(Brace::Quote(s), _, _, _) => Ok(Literal::as_string(s.clone())),
-        Lex::Brace(Brace::Comment(c), _, _, _) => Ok(Literal::as_comment(c.clone())),
+        Lex::Integer(n, pos) | Lex::Float(n, pos) => match parse_number(n, *pos - n.len()) {
+            Ok(number) => Ok(Literal::as_number(number)),
+            Err(err) => Err(TokenParseError::Lex(err)),
+        },
+        Lex::Brace(Brace::Quote(s), _, level, pos) => push_string(s, *level, *pos, context),
+        Lex::Brace(Brace::Char(c), _, _, _) => Ok(Literal::as_char(*c)),
         //Braces
-        Lex::Brace(brace, BraceStatus::Open(len), level, _) => {
+        Lex::Brace(brace, BraceStatus::Open, level, _) => {
             if let Brace::Brace = brace {
                 if let Some(Lex::Brace(Brace::Brace, BraceStatus::Close, _, _)) =
                     lexes.get(*index + 1)
@@ -178,16 +241,31 @@ fn next_token<'a>(
                     *index += 1;
                     Ok(Box::new(Token::Literal(Literal::Void)))
                 } else {
-                    push_braced_block(brace, *len, *level, index, lexes, context)
+                    push_braced_block(brace, *level, index, lexes, context)
                 }
             } else if let Brace::Square = brace {
-                let parameters =
-                    push_braced_block(&Brace::Square, *len, *level, index, lexes, context)?;
-                Ok(Literal::as_array(parameters))
+                let parameters = push_braced_block(&Brace::Square, *level, index, lexes, context)?;
+                if let Some(Lex::Brace(Brace::Square, BraceStatus::Open, level, _)) =
+                    lexes.get(*index + 1)
+                {
+                    *index += 1;
+                    let indices = push_braced_block(&Brace::Square, *level, index, lexes, context)?;
+                    Ok(Box::new(Token::Index(
+                        Literal::as_array(parameters),
+                        indices,
+                    )))
+                } else {
+                    Ok(Literal::as_array(parameters))
+                }
             } else {
-                push_braced_block(brace, *len, *level, index, lexes, context)
+                push_braced_block(brace, *level, index, lexes, context)
             }
         }
+        Lex::Brace(_, BraceStatus::Close, _, pos) => Err(TokenParseError::UnmatchedBrace(*pos)),
+        //Bindings
+        Lex::Operator(Operator::Of, pos) if context.peek_identifier().is_some() => {
+            push_binding(*pos, index, length, lexes, context)
+        }
         //Operators
         Lex::Operator(op, pos) => {
             if let Some(previous_token) = context.pop_token() {
@@ -203,12 +281,11 @@ fn next_token<'a>(
                 Ok(OperatorGroup::as_uni_op(*op, *pos, lexes, index, context)?)
             }
         }
-        _ => {
-            println!("{:?}", lexes.get(*index).unwrap());
-            Err(TokenParseError::UnsupportedTokenizerOperator)
-        }
+        _ => Err(TokenParseError::UnsupportedTokenizerOperator),
     } {
         Ok(result) => {
+            // docs only document the declaration directly following them
+            context.take_docs();
             context.push_token(result);
             *index = *index + 1;
             Ok(())
@@ -227,24 +304,33 @@ fn push_type_def(
     if *index + 4 >= lexes.len() {
         return Err(TokenParseError::TypeRequiresNameAndClassifier);
     }
+    let docs = context.take_docs();
     *index = *index + 1;
     if let Some(Lex::Identifier(name, name_pos)) = lexes.get(*index) {
         *index = *index + 1;
+        let parameters = push_generic_parameters(index, lexes, context)?;
         if let Some(Lex::Operator(Operator::Of, classifier_pos)) = lexes.get(*index) {
             *index = *index + 1;
-            if let Some(Lex::Brace(Brace::Brace, BraceStatus::Open(len), level, _pos)) =
+            if let Some(Lex::Brace(Brace::Brace, BraceStatus::Open, level, _pos)) =
                 lexes.get(*index)
             {
-                let body = push_braced_block(&Brace::Brace, *len, *level, index, lexes, context)?;
+                let body = push_braced_block(&Brace::Brace, *level, index, lexes, context)?;
+                if let (Some(docs), Token::Block(_, scope)) = (docs, body.as_ref()) {
+                    context.set_docs(*scope, docs);
+                }
                 Ok(Box::new(Token::TypeDef(
-                    Literal::Identifier(name.clone()),
+                    Literal::Identifier(name.to_string()),
+                    parameters,
                     body,
                 )))
             } else {
-                Err(TokenParseError::ExpectedBraceAfterTypeDef(
-                    *classifier_pos,
-                    format!("{:?}", lexes.get(*index).unwrap()),
-                ))
+                match lexes.get(*index) {
+                    Some(lex) => Err(TokenParseError::ExpectedBraceAfterTypeDef(
+                        *classifier_pos,
+                        format!("{:?}", lex),
+                    )),
+                    None => Err(TokenParseError::TypeRequiresNameAndClassifier),
+                }
             }
         } else {
             Err(TokenParseError::ExpectedColonAfterTypeDefIdentifier(
@@ -262,33 +348,44 @@ fn push_method_declaration(
     lexes: &Vec<Lex>,
     context: &mut TokenizerContext,
 ) -> Result<Box<Token>, TokenParseError> {
+    let docs = context.take_docs();
     *index += 1;
     if let Some(Lex::Identifier(name, _)) = lexes.get(*index) {
         *index += 1;
+        let type_parameters = push_generic_parameters(index, lexes, context)?;
         if let Some(Lex::Operator(Operator::Of, _)) = lexes.get(*index) {
             *index += 1;
-            if let Some(Lex::Brace(Brace::Brace, BraceStatus::Open(len), level, _)) =
-                lexes.get(*index)
+            let parameters = match lexes.get(*index) {
+                Some(Lex::Brace(Brace::Brace, BraceStatus::Open, level, _)) => {
+                    let parameters =
+                        push_braced_block(&Brace::Brace, *level, index, lexes, context)?;
+                    *index += 1;
+                    parameters
+                }
+                Some(Lex::Identifier(_, _)) => {
+                    next_token(lexes, index, lexes.len(), context)?;
+                    match context.pop_token() {
+                        Some(parameters) => parameters,
+                        None => return Err(TokenParseError::ExpectedParametersAfterMethodName),
+                    }
+                }
+                _ => return Err(TokenParseError::ExpectedParametersAfterMethodName),
+            };
+            if let Some(Lex::Brace(Brace::Bracket, BraceStatus::Open, level, _)) = lexes.get(*index)
             {
-                let parameters =
-                    push_braced_block(&Brace::Brace, *len, *level, index, lexes, context)?;
-                *index += 1;
-                if let Some(Lex::Brace(Brace::Bracket, BraceStatus::Open(len), level, _)) =
-                    lexes.get(*index)
-                {
-                    let body =
-                        push_braced_block(&Brace::Bracket, *len, *level, index, lexes, context)?;
-                    Ok(Box::new(Token::MethodDef(
-                        mode,
-                        name.clone(),
-                        parameters,
-                        body,
-                    )))
-                } else {
-                    Err(TokenParseError::ExpectedBodyAfterMethodSignature)
+                let body = push_braced_block(&Brace::Bracket, *level, index, lexes, context)?;
+                if let (Some(docs), Token::Block(_, scope)) = (docs, body.as_ref()) {
+                    context.set_docs(*scope, docs);
                 }
+                Ok(Box::new(Token::MethodDef(
+                    mode,
+                    name.to_string(),
+                    type_parameters,
+                    parameters,
+                    body,
+                )))
             } else {
-                Err(TokenParseError::ExpectedParametersAfterMethodName)
+                Err(TokenParseError::ExpectedBodyAfterMethodSignature)
             }
         } else {
             Err(TokenParseError::ExpectedParametersAfterMethodName)
@@ -298,14 +395,193 @@ fn push_method_declaration(
     }
 }
 
+/// Parses `name: value;` once `name` has been pushed, declaring `name` in the current scope.
+/// The value runs up to the next `;` so that `p: [Point(1,2)] => x;` binds the whole pipeline.
+fn push_binding(
+    pos: usize,
+    index: &mut usize,
+    length: usize,
+    lexes: &Vec<Lex>,
+    context: &mut TokenizerContext,
+) -> Result<Box<Token>, TokenParseError> {
+    let name = match *context.pop_token().unwrap() {
+        Token::Literal(Literal::Identifier(name)) => name,
+        _ => return Err(TokenParseError::ExpectedIdentifier),
+    };
+    let depth = context.current_scope().get_tokens().len();
+    *index += 1;
+    while *index < length.min(lexes.len()) {
+        if let Lex::Delimiter(Delimiter::Semicolon) = lexes[*index] {
+            break;
+        }
+        next_token(lexes, index, length, context)?;
+    }
+    // step back onto the `;`, the caller steps past it once the binding is pushed
+    *index -= 1;
+    if context.current_scope().get_tokens().len() != depth + 1 {
+        return Err(TokenParseError::ExpectedBindingValue(pos));
+    }
+    let value = context.pop_token().unwrap();
+    context.declare_identifier(name.to_string());
+    Ok(Box::new(Token::Binding(name, value)))
+}
+
+enum StringSegment {
+    Text(String),
+    Expression(String),
+}
+
+/// Splits a string literal into text and `{expr}` segments, with `{{` and `}}` standing for
+/// literal braces.
+fn string_segments(s: &str, pos: usize) -> Result<Vec<StringSegment>, TokenParseError> {
+    let mut segments = Vec::new();
+    let mut text = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => return Err(TokenParseError::UnmatchedInterpolationBrace(pos)),
+            '{' => {
+                let mut expression = String::new();
+                let mut depth = 1usize;
+                loop {
+                    let inner = chars.next();
+                    match inner {
+                        Some('{') => depth += 1,
+                        Some('}') if depth == 1 => break,
+                        Some('}') => depth -= 1,
+                        Some(_) => {}
+                        None => return Err(TokenParseError::UnmatchedInterpolationBrace(pos)),
+                    }
+                    expression.extend(inner);
+                }
+                segments.push(StringSegment::Text(std::mem::take(&mut text)));
+                segments.push(StringSegment::Expression(expression));
+            }
+            _ => text.push(c),
+        }
+    }
+    segments.push(StringSegment::Text(text));
+    Ok(segments)
+}
+
+/// The offsets into the spelling of a string literal, opening quote included, at which each of
+/// its `{expr}` segments starts. Escapes are stepped over, `\u{..}` with its braces.
+fn interpolation_offsets(spelling: &str) -> Vec<usize> {
+    let bytes = spelling.as_bytes();
+    let mut offsets = Vec::new();
+    let mut i = 1;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'\\', Some(b'u')) => {
+                while i < bytes.len() && bytes[i] != b'}' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            (b'\\', _) | (b'{', Some(b'{')) | (b'}', Some(b'}')) => i += 2,
+            (b'{', _) => {
+                offsets.push(i + 1);
+                let mut depth = 0usize;
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'{' => depth += 1,
+                        b'}' if depth == 1 => break,
+                        b'}' => depth -= 1,
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    offsets
+}
+
+/// Pushes a string literal, or an `AgrandizedString` when it interpolates expressions.
+/// `"a{x}b{y}c"` becomes `AgrandizedString("a", Block([x, "b", y]), "c")`, the block being a
+/// scope of its own so identifiers inside resolve through the enclosing scopes.
+fn push_string(
+    s: &str,
+    level: usize,
+    pos: usize,
+    context: &mut TokenizerContext,
+) -> Result<Box<Token>, TokenParseError> {
+    let mut segments = string_segments(s, pos)?;
+    let offsets = match context.get_quote(pos) {
+        Some((start, spelling)) => interpolation_offsets(spelling)
+            .into_iter()
+            .map(|offset| start + offset)
+            .collect(),
+        None => Vec::new(),
+    };
+    let mut offsets = offsets.into_iter();
+    if segments.len() == 1 {
+        return match segments.pop() {
+            Some(StringSegment::Text(text)) => Ok(Literal::as_string(text)),
+            _ => Err(TokenParseError::ExpectedInterpolatedExpression(pos)),
+        };
+    }
+    let suffix = match segments.pop() {
+        Some(StringSegment::Text(text)) => text,
+        _ => String::new(),
+    };
+    let mut segments = segments.into_iter();
+    let prefix = match segments.next() {
+        Some(StringSegment::Text(text)) => text,
+        _ => String::new(),
+    };
+    context.push_scope(
+        context.get_state(),
+        BraceState::Braced(Brace::Bracket, level),
+    );
+    for segment in segments {
+        match segment {
+            StringSegment::Text(text) => context.push_token(Literal::as_string(text)),
+            StringSegment::Expression(expression) => {
+                let offset = offsets.next().unwrap_or(pos);
+                let mut lexes = Vec::new();
+                for lex in Lexer::new(expression.as_bytes()) {
+                    match lex {
+                        Ok(lex) => lexes.push(lex.node.shifted(offset)),
+                        Err(err) => return Err(TokenParseError::Lex(err.shifted(offset))),
+                    }
+                }
+                let depth = context.current_scope().get_tokens().len();
+                let mut previous_index = usize::MAX;
+                parse_tokens_from_lexes(&mut 0, &mut previous_index, lexes.len(), &lexes, context)?;
+                if context.current_scope().get_tokens().len() != depth + 1 {
+                    return Err(TokenParseError::ExpectedInterpolatedExpression(pos));
+                }
+            }
+        }
+    }
+    let scope_index = context.current_scope().get_index();
+    context.pop_scope()?;
+    Ok(Box::new(Token::AgrandizedString(
+        prefix,
+        Box::new(Token::Block(Brace::Bracket, scope_index)),
+        suffix,
+    )))
+}
+
 fn push_braced_block(
     brace: &Brace,
-    len: usize,
     level: usize,
     index: &mut usize,
     lexes: &Vec<Lex>,
     context: &mut TokenizerContext,
 ) -> Result<Box<Token>, TokenParseError> {
+    let len = brace_close(lexes, *index)?;
     context.push_scope(
         context.get_state(),
         BraceState::Braced(brace.clone(), level),
@@ -318,6 +594,100 @@ fn push_braced_block(
     Ok(Box::new(Token::Block(brace.clone(), scope_index)))
 }
 
+/// Finds the brace closing the one opened at `open`, stepping over the blocks nested inside it.
+fn brace_close(lexes: &[Lex], open: usize) -> Result<usize, TokenParseError> {
+    let mut opened = Vec::new();
+    for (i, lex) in lexes.iter().enumerate().skip(open) {
+        match lex {
+            Lex::Brace(brace, BraceStatus::Open, _, _) => opened.push(brace),
+            Lex::Brace(brace, BraceStatus::Close, _, pos) => match opened.pop() {
+                Some(expected) if expected == brace => {
+                    if opened.is_empty() {
+                        return Ok(i);
+                    }
+                }
+                _ => return Err(TokenParseError::UnmatchedBrace(*pos)),
+            },
+            _ => {}
+        }
+    }
+    match lexes.get(open) {
+        Some(Lex::Brace(_, _, _, pos)) => Err(TokenParseError::UnclosedBrace(*pos)),
+        _ => Err(TokenParseError::UnclosedBrace(open)),
+    }
+}
+
+/// Finds the `>` closing a list of type arguments opened by the `<` at `open`.
+/// `<` is lexed as an operator, so the list is only treated as generic when it holds
+/// nothing but identifiers, commas and nested argument lists; otherwise it is a comparison.
+fn generic_close(lexes: &[Lex], open: usize) -> Option<usize> {
+    if let Some(Lex::Operator(Operator::LessThan, _)) = lexes.get(open) {
+    } else {
+        return None;
+    }
+    let mut depth = 0usize;
+    let mut expect_identifier = true;
+    for (offset, lex) in lexes[open..].iter().enumerate() {
+        match lex {
+            Lex::Operator(Operator::LessThan, _) if !expect_identifier || offset == 0 => {
+                depth += 1;
+                expect_identifier = true;
+            }
+            Lex::Operator(Operator::GreaterThan, _) if !expect_identifier => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + offset);
+                }
+            }
+            Lex::Identifier(_, _) if expect_identifier => expect_identifier = false,
+            Lex::Delimiter(Delimiter::Comma) if !expect_identifier => expect_identifier = true,
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn push_generic_block(
+    close: usize,
+    index: &mut usize,
+    lexes: &[Lex],
+    context: &mut TokenizerContext,
+) -> Result<Box<Token>, TokenParseError> {
+    context.push_scope(context.get_state(), BraceState::Braced(Brace::Angle, 0));
+    *index += 1;
+    while *index < close {
+        if let Some(Lex::Identifier(name, end)) = lexes.get(*index) {
+            context.push_identifier(name.to_string(), *end);
+            if let Some(inner_close) = generic_close(lexes, *index + 1) {
+                *index += 1;
+                let arguments = push_generic_block(inner_close, index, lexes, context)?;
+                context.push_token(Box::new(Token::Generic(name.to_string(), arguments)));
+            } else {
+                context.push_token(Literal::as_identifier(name.to_string()));
+            }
+        }
+        *index += 1;
+    }
+    let scope_index = context.current_scope().get_index();
+    context.pop_scope()?;
+    *index = close;
+    Ok(Box::new(Token::Block(Brace::Angle, scope_index)))
+}
+
+fn push_generic_parameters(
+    index: &mut usize,
+    lexes: &[Lex],
+    context: &mut TokenizerContext,
+) -> Result<Option<Box<Token>>, TokenParseError> {
+    if let Some(close) = generic_close(lexes, *index) {
+        let parameters = push_generic_block(close, index, lexes, context)?;
+        *index += 1;
+        Ok(Some(parameters))
+    } else {
+        Ok(None)
+    }
+}
+
 impl Literal {
     pub fn as_identifier_string(&self) -> Result<String, TokenParseError> {
         match self {
@@ -325,7 +695,6 @@ impl Literal {
             Literal::Identifier(s) => Ok(s.clone()),
             Literal::Number(n) => Err(TokenParseError::CannotUseNumberAsIdentifier(n.clone())),
             Literal::String(s) => Err(TokenParseError::CannotUseStringAsIdentifier(s.clone())),
-            Literal::Comment(s) => Err(TokenParseError::CannotUseCommentAsIdentifier(s.clone())),
             Literal::Void => Ok(("()").to_string()),
         }
     }
@@ -343,18 +712,15 @@ impl Literal {
         Box::new(Token::Array(parameters))
     }
 
-    fn as_integer(i: i64) -> Box<Token> {
-        Box::new(Token::Literal(Literal::Number(Number::Integer(i))))
+    fn as_number(n: Number) -> Box<Token> {
+        Box::new(Token::Literal(Literal::Number(n)))
     }
-    fn as_float(f: f64) -> Box<Token> {
-        Box::new(Token::Literal(Literal::Number(Number::Float(f))))
+    fn as_char(c: char) -> Box<Token> {
+        Literal::as_number(Number::Char(c))
     }
     fn as_string(s: String) -> Box<Token> {
         Box::new(Token::Literal(Literal::String(s)))
     }
-    fn as_comment(c: String) -> Box<Token> {
-        Box::new(Token::Literal(Literal::Comment(c)))
-    }
 }
 
 impl OperatorGroup {
@@ -366,7 +732,6 @@ impl OperatorGroup {
             op, operand, parameter,
         )))
     }
-    /*
     fn as_tri(
         operand: Box<Token>,
         op: Operator,
@@ -376,7 +741,7 @@ impl OperatorGroup {
         Box::new(Token::Operator(OperatorGroup::TriOperator(
             op, operand, parameter1, parameter2,
         )))
-    } */
+    }
 
     fn as_uni_op(
         op: Operator,
@@ -392,11 +757,8 @@ impl OperatorGroup {
             return Err(e);
         }
         *index = *index + 1;
-        next_token(lexes, index, std::usize::MAX, context)?;
-        match context.pop_token() {
-            Some(token) => Ok(OperatorGroup::as_uni(token, op)),
-            None => Err(TokenParseError::ExpectedOperandButFoundNone),
-        }
+        let operand = next_operand(lexes, index, context)?;
+        Ok(OperatorGroup::as_uni(operand, op))
     }
 
     fn as_bi_op(
@@ -408,12 +770,25 @@ impl OperatorGroup {
         context: &mut TokenizerContext,
     ) -> Result<Box<Token>, TokenParseError> {
         *index = *index + 1;
-        next_token(lexes, index, std::usize::MAX, context)?;
-        match context.pop_token() {
-            Some(token) => Ok(OperatorGroup::as_bi(previous_token, op, token)),
-            None => Err(TokenParseError::ExpectedOperandButFoundNone),
-        }
+        let operand = next_operand(lexes, index, context)?;
+        Ok(OperatorGroup::as_bi(previous_token, op, operand))
+    }
+}
+
+/// Parses the token an operator applies to, failing when the source ends before one.
+fn next_operand(
+    lexes: &Vec<Lex>,
+    index: &mut usize,
+    context: &mut TokenizerContext,
+) -> Result<Box<Token>, TokenParseError> {
+    let depth = context.current_scope().get_tokens().len();
+    next_token(lexes, index, usize::MAX, context)?;
+    // the caller steps past the operand once the operator is pushed
+    *index -= 1;
+    if context.current_scope().get_tokens().len() != depth + 1 {
+        return Err(TokenParseError::ExpectedOperandButFoundNone);
     }
+    Ok(context.pop_token().unwrap())
 }
 
 //--------===============---------
@@ -423,116 +798,188 @@ impl OperatorGroup {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::parse::constant::{KeywordKind, KEYWORDS};
+    use crate::parse::tree::sexpr;
+    use std::fs;
+    /// Parses `source` and checks its root scope against `expected`, one S-expression per token.
+    fn assert_sexpr(source: &[u8], expected: &str) -> Result<TokenizerContext, TokenParseError> {
+        let context = parse_tokens(source)?;
+        assert_eq!(sexpr(&context, 0), expected);
+        Ok(context)
+    }
     #[test]
     fn test_parse_impl_tokens() -> Result<(), TokenParseError> {
-        let context = parse_tokens(b"calc start: () { [Point(1,2)] => print; } ")?;
-        context
-            .get_scope(0)
-            .unwrap()
-            .assert_eq(vec![Box::new(Token::MethodDef(
-                Keyword::Calc,
-                "start".to_string(),
-                Box::new(Token::Block(Brace::Brace, 1)),
-                Box::new(Token::Block(Brace::Bracket, 2)),
-            ))]);
-        context
-            .get_scope(2)
-            .unwrap()
-            .assert_eq(vec![Box::new(Token::Operator(OperatorGroup::BiOperator(
-                Operator::Into,
-                Literal::as_array(Box::new(Token::Block(Brace::Square, 3))),
-                Literal::as_identifier("print".to_string()),
-            )))]);
-        context
-            .get_scope(3)
-            .unwrap()
-            .assert_eq(vec![Literal::as_parameterized(
-                "Point".to_string(),
-                Box::new(Token::Block(Brace::Brace, 4)),
-            )]);
-        context.get_scope(4).unwrap().assert_eq(vec![
-            Box::new(Token::Literal(Literal::Number(Number::Integer(1)))),
-            Box::new(Token::Literal(Literal::Number(Number::Integer(2)))),
-        ]);
+        assert_sexpr(
+            b"calc start: () { [Point(1,2)] => print; } ",
+            "(calc start (paren) (curly (into (array (square (constructor Point (paren 1 2)))) print)))",
+        )?;
         Ok(())
     }
     #[test]
     fn test_parse_type_tokens() -> Result<(), TokenParseError> {
-        let context = parse_tokens(b"type Geheusie: (int(x), int(y),)")?;
-        let expected_scope_index = 1;
-        context
+        assert_sexpr(
+            b"type Geheusie: (int(x), int(y),)",
+            "(type Geheusie (paren (constructor int (paren x)) (constructor int (paren y))))",
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_parse_generic_tokens() -> Result<(), TokenParseError> {
+        assert_sexpr(
+            b"type Pair<A, B>: (A(first), Pair<B, int>(second))",
+            "(type Pair (angle A B) (paren (constructor A (paren first)) \
+             (generic-constructor Pair (angle B int) (paren second))))",
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_parse_truncated_type_header() {
+        assert_eq!(
+            parse_tokens(b"type P<A>: ").err(),
+            Some(TokenParseError::TypeRequiresNameAndClassifier)
+        );
+    }
+    #[test]
+    fn test_parse_generic_method_tokens() -> Result<(), TokenParseError> {
+        assert_sexpr(
+            b"trans swap<A,B>: Pair<A,B> { second }",
+            "(trans swap (angle A B) (generic Pair (angle A B)) (curly second))",
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_parse_less_than_is_not_generic() -> Result<(), TokenParseError> {
+        assert_sexpr(b"a < b", "(less-than a b)")?;
+        Ok(())
+    }
+    #[test]
+    fn test_parse_range_tokens() -> Result<(), TokenParseError> {
+        assert_sexpr(b"0..=10 by 2", "(range-inclusive 0 10 2)")?;
+        assert_sexpr(
+            b"points[1..n]",
+            "(index points (square (range-middle 1 n)))",
+        )?;
+        Ok(())
+    }
+    #[test]
+    fn test_parse_binding_tokens() -> Result<(), TokenParseError> {
+        let context = assert_sexpr(
+            b"n: 3 => x; n => print;",
+            "(bind n (into 3 x))\n(into n print)",
+        )?;
+        assert!(context.current_scope().is_declared("n"));
+        assert!(!context.current_scope().is_declared("x"));
+        Ok(())
+    }
+    #[test]
+    fn test_parse_comments_are_trivia() -> Result<(), TokenParseError> {
+        let context = assert_sexpr(b"a => /* b */ c // d\n", "(into a c)")?;
+        assert_eq!(
+            context.get_trivia(),
+            &vec![
+                Trivia::Comment(" b ".to_string(), 12),
+                Trivia::Comment(" d".to_string(), 19),
+            ]
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_parse_doc_comments() -> Result<(), TokenParseError> {
+        let context = parse_tokens(
+            b"/// A point.\n/// Two ints.\ntype Point: (int(x), int(y),)
+            /// dropped by the binding\nn: 3;\ncalc start: () { }",
+        )?;
+        let scopes = context
             .current_scope()
-            .assert_eq(vec![Box::new(Token::TypeDef(
-                Literal::Identifier("Geheusie".to_string()),
-                Box::new(Token::Block(Brace::Brace, expected_scope_index)),
-            ))]);
-        context
-            .get_scope(expected_scope_index)
-            .unwrap()
-            .assert_eq(vec![
-                Box::new(Token::Constructor(
-                    "int".to_string(),
-                    Box::new(Token::Block(Brace::Brace, expected_scope_index + 1)),
-                )),
-                Box::new(Token::Constructor(
-                    "int".to_string(),
-                    Box::new(Token::Block(Brace::Brace, expected_scope_index + 2)),
-                )),
-            ]);
-        context
-            .get_scope(expected_scope_index + 1)
-            .unwrap()
-            .assert_eq(vec![Literal::as_identifier("x".to_string())]);
-        context
-            .get_scope(expected_scope_index + 2)
-            .unwrap()
-            .assert_eq(vec![Literal::as_identifier("y".to_string())]);
+            .get_tokens()
+            .iter()
+            .filter_map(|t| match t.as_ref() {
+                Token::TypeDef(_, _, body) | Token::MethodDef(_, _, _, _, body) => {
+                    match body.as_ref() {
+                        Token::Block(_, scope) => Some(*scope),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect::<Vec<usize>>();
+        assert_eq!(scopes.len(), 2);
+        assert_eq!(
+            context.get_docs(scopes[0]),
+            Some(&"A point.\nTwo ints.".to_string())
+        );
+        assert_eq!(context.get_docs(scopes[1]), None);
+        Ok(())
+    }
+    #[test]
+    fn test_parse_interpolated_strings() -> Result<(), TokenParseError> {
+        assert_sexpr(
+            b"\"a{x}b{ 1 }c\" \"{{x}}\"",
+            "(interpolate \"a\" (curly x \"b\" 1) \"c\")\n\"{x}\"",
+        )?;
+        assert_eq!(
+            parse_tokens(b"\"a}\"").err(),
+            Some(TokenParseError::UnmatchedInterpolationBrace(4))
+        );
+        assert_eq!(
+            parse_tokens(b"\"{1 2}\"").err(),
+            Some(TokenParseError::ExpectedInterpolatedExpression(7))
+        );
         Ok(())
     }
     #[test]
+    fn test_interpolation_errors_point_into_the_source() {
+        // positions count from the start of the source, past the escape before the expression
+        assert_eq!(
+            parse_tokens("n: 1; \"\\t{n → 1}\"".as_bytes()).err(),
+            Some(TokenParseError::Lex(LexParseError::InvalidCharacter(12)))
+        );
+        assert_eq!(
+            parse_tokens(b"n: 1;\n\"\\u{41}{{ {n ]}\"").err(),
+            Some(TokenParseError::UnmatchedBrace(20))
+        );
+    }
+    #[test]
     fn test_parse_basic_braces() -> Result<(), TokenParseError> {
-        let context = parse_tokens(b"(Goose)")?;
-        let expected_scope_index = 1;
-        let expected_outer = Box::new(Token::Block(Brace::Brace, expected_scope_index));
-        context.current_scope().assert_eq(vec![expected_outer]);
-        context
-            .get_scope(expected_scope_index)
-            .unwrap()
-            .assert_eq(vec![Literal::as_identifier("Goose".to_string())]);
+        assert_sexpr(b"(Goose)", "(paren Goose)")?;
         Ok(())
     }
 
     #[test]
     fn test_parse_uni_op_token() -> Result<(), TokenParseError> {
-        parse_tokens(b"!Puff")?
-            .current_scope()
-            .assert_eq(vec![OperatorGroup::as_uni(
-                Literal::as_identifier("Puff".to_string()),
-                Operator::Not,
-            )]);
+        assert_sexpr(b"!Puff", "(not Puff)")?;
         Ok(())
     }
     #[test]
     fn test_parse_bi_op_token() -> Result<(), TokenParseError> {
-        parse_tokens(b"Goose + Ocelot")?
-            .current_scope()
-            .assert_eq(vec![OperatorGroup::as_bi(
-                Literal::as_identifier("Goose".to_string()),
-                Operator::Plus,
-                Literal::as_identifier("Ocelot".to_string()),
-            )]);
+        assert_sexpr(b"Goose + Ocelot", "(plus Goose Ocelot)")?;
         Ok(())
     }
     #[test]
+    fn test_parse_trailing_operator() {
+        assert_eq!(
+            parse_tokens(b"a +").err(),
+            Some(TokenParseError::ExpectedOperandButFoundNone)
+        );
+        assert_eq!(
+            parse_tokens(b"x: 4 /").err(),
+            Some(TokenParseError::ExpectedOperandButFoundNone)
+        );
+    }
+    #[test]
+    fn test_parse_resource_prefixes() {
+        // editors hand over half-typed files, so every prefix must tokenize or fail cleanly
+        for entry in fs::read_dir("res/test").unwrap() {
+            let source = fs::read(entry.unwrap().path()).unwrap();
+            for end in 0..source.len() {
+                let _ = parse_tokens(&source[..end]);
+            }
+        }
+    }
+    #[test]
     fn test_parse_identifier_token() -> Result<(), TokenParseError> {
-        parse_tokens(b"Goose")?
-            .current_scope()
-            .assert_eq(vec![Box::new(Token::Literal(Literal::Identifier(
-                "Goose".to_string(),
-            )))]);
-        parse_tokens(b"()")?
-            .current_scope()
-            .assert_eq(vec![Box::new(Token::Literal(Literal::Void))]);
+        assert_sexpr(b"Goose", "Goose")?;
+        assert_sexpr(b"()", "()")?;
         Ok(())
     }
 
@@ -540,19 +987,266 @@ mod tests {
     fn test_lex_identifiers() -> Result<(), TokenParseError> {
         assert_eq!(
             parse_lexs(b"Goose")?,
-            vec!(Lex::Identifier("Goose".to_string(), 5))
+            vec!(Lex::Identifier("Goose".into(), 5))
         );
-        assert_eq!(parse_lexs(b"G")?, vec!(Lex::Identifier("G".to_string(), 1)));
+        assert_eq!(parse_lexs(b"G")?, vec!(Lex::Identifier("G".into(), 1)));
         assert_eq!(parse_lexs(b"data")?, vec!(Lex::Keyword(Keyword::Data, 4)));
         assert_eq!(parse_lexs(b"comp")?, vec!(Lex::Keyword(Keyword::Comp, 4)));
         assert_eq!(parse_lexs(b"calc")?, vec!(Lex::Keyword(Keyword::Calc, 4)));
         Ok(())
     }
     #[test]
+    fn test_keywords_round_trip() -> Result<(), TokenParseError> {
+        let all = [
+            Keyword::Data,
+            Keyword::Comp,
+            Keyword::Type,
+            Keyword::Impl,
+            Keyword::Enum,
+            Keyword::Calc,
+            Keyword::Trans,
+            Keyword::Inv,
+            Keyword::By,
+        ];
+        for keyword in all.iter() {
+            // fails to compile when a keyword is added without being listed above
+            match keyword {
+                Keyword::Data
+                | Keyword::Comp
+                | Keyword::Type
+                | Keyword::Impl
+                | Keyword::Enum
+                | Keyword::Calc
+                | Keyword::Trans
+                | Keyword::Inv
+                | Keyword::By => {}
+            }
+            let spelling = keyword.spelling();
+            assert_eq!(Keyword::from_spelling(spelling), Some(*keyword));
+            let expected = match keyword.kind() {
+                KeywordKind::Reserved => Lex::Keyword(*keyword, spelling.len()),
+                KeywordKind::Contextual => Lex::Identifier(spelling.into(), spelling.len()),
+            };
+            assert_eq!(parse_lexs(spelling.as_bytes())?, vec!(expected));
+        }
+        assert_eq!(KEYWORDS.len(), all.len());
+        // keywords are only recognised as whole identifiers
+        for name in [
+            "dat", "datas", "Data", "in", "invs", "calc_", "enums", "typ",
+        ]
+        .iter()
+        {
+            assert_eq!(
+                parse_lexs(name.as_bytes())?,
+                vec!(Lex::Identifier((*name).into(), name.len()))
+            );
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_lex_unicode_identifiers() -> Result<(), TokenParseError> {
+        let identifier = |name: &'static str| Lex::Identifier(name.into(), name.len());
+        for name in [
+            "_private", "_", "größe", "Ωmega", "名前", "x1", "a_b", "café_2",
+        ]
+        .iter()
+        {
+            assert_eq!(parse_lexs(name.as_bytes())?, vec!(identifier(name)));
+        }
+        // positions are byte offsets, so `ö` and `ß` each count twice
+        assert_eq!(
+            parse_lexs("größe + 1".as_bytes())?,
+            vec!(
+                Lex::Identifier("größe".into(), 7),
+                Lex::Operator(Operator::Plus, 9),
+                Lex::Integer("1".into(), 11),
+            )
+        );
+        // a char that can only continue an identifier, or is no identifier char, cannot start one
+        assert_eq!(
+            parse_lexs("\u{300}a".as_bytes()).err(),
+            Some(TokenParseError::Lex(LexParseError::InvalidCharacter(0)))
+        );
+        assert_eq!(
+            parse_lexs("a→b".as_bytes()).err(),
+            Some(TokenParseError::Lex(LexParseError::InvalidCharacter(1)))
+        );
+        assert_eq!(
+            parse_lexs(b"a\xffb").err(),
+            Some(TokenParseError::Lex(LexParseError::InvalidCharacter(1)))
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_lex_identifier_boundaries() -> Result<(), TokenParseError> {
+        let operators = [
+            ("+", Operator::Plus),
+            ("+=", Operator::PlusEquals),
+            ("-", Operator::Minus),
+            ("-=", Operator::MinusEquals),
+            ("->", Operator::Arrow),
+            ("=", Operator::Assignment),
+            ("==", Operator::IsEquals),
+            ("=>", Operator::Into),
+            ("&", Operator::And),
+            ("|", Operator::Or),
+            ("*", Operator::Multiply),
+            ("*=", Operator::MultiplyEquals),
+            ("/", Operator::Divide),
+            ("/=", Operator::DivideEquals),
+            ("%", Operator::Modulus),
+            ("^", Operator::PowerOf),
+            ("!", Operator::Not),
+            ("!=", Operator::IsNotEquals),
+            ("<", Operator::LessThan),
+            ("<=", Operator::LessThanOrEquals),
+            (">", Operator::GreaterThan),
+            (">=", Operator::GreaterThanOrEquals),
+            (".", Operator::Accessor),
+            ("..", Operator::RangeMiddle),
+            ("..=", Operator::RangeInclusive),
+            ("...", Operator::ArrayContinuation),
+            ("?", Operator::QuestionMark),
+            (":", Operator::Of),
+            ("::", Operator::OfClass),
+        ];
+        for (left, right) in [("a", "b"), ("größe", "_x"), ("data", "calc")].iter() {
+            for (spelling, operator) in operators.iter() {
+                let source = format!("{}{}{}", left, spelling, right);
+                let lexes = parse_lexs(source.as_bytes())?;
+                assert_eq!(lexes.len(), 3, "`{}` lexed as {:?}", source, lexes);
+                match (&lexes[0], &lexes[1], &lexes[2]) {
+                    (first, Lex::Operator(found, _), last) => {
+                        assert_eq!(found, operator, "in `{}`", source);
+                        for (lex, name) in [(first, *left), (last, *right)].iter() {
+                            match lex {
+                                Lex::Identifier(found, _) => assert_eq!(*found, *name),
+                                Lex::Keyword(_, _) => assert!(*name == "data" || *name == "calc"),
+                                other => panic!("`{}` lexed {:?} for `{}`", source, other, name),
+                            }
+                        }
+                    }
+                    other => panic!("`{}` lexed as {:?}", source, other),
+                }
+            }
+        }
+        // a hyphen always ends an identifier, even between letters
+        assert_eq!(
+            parse_lexs(b"kebab-case")?,
+            vec!(
+                Lex::Identifier("kebab".into(), 5),
+                Lex::Operator(Operator::Minus, 6),
+                Lex::Identifier("case".into(), 10),
+            )
+        );
+        assert_eq!(
+            parse_lexs(b"data-x")?,
+            vec!(
+                Lex::Keyword(Keyword::Data, 4),
+                Lex::Operator(Operator::Minus, 5),
+                Lex::Identifier("x".into(), 6),
+            )
+        );
+        assert_eq!(
+            parse_lexs(b"datas")?,
+            vec!(Lex::Identifier("datas".into(), 5))
+        );
+        // braces, delimiters and literals end an identifier too
+        assert_eq!(parse_lexs(b"a(b)")?.len(), 4);
+        assert_eq!(parse_lexs(b"a;b,c")?.len(), 5);
+        assert_eq!(parse_lexs(b"a\"s\"")?[0], Lex::Identifier("a".into(), 1));
+        assert_eq!(
+            parse_lexs(b"a 1")?,
+            vec!(Lex::Identifier("a".into(), 1), Lex::Integer("1".into(), 3),)
+        );
+        Ok(())
+    }
+    #[test]
     fn test_lex_numbers() -> Result<(), TokenParseError> {
-        assert_eq!(parse_lexs(b"1")?, vec!(Lex::Integer("1".to_string(), 1)));
-        assert_eq!(parse_lexs(b"10")?, vec!(Lex::Integer("10".to_string(), 2)));
-        assert_eq!(parse_lexs(b"1.0")?, vec!(Lex::Float("1.0".to_string(), 3)));
+        assert_eq!(parse_lexs(b"1")?, vec!(Lex::Integer("1".into(), 1)));
+        assert_eq!(parse_lexs(b"10")?, vec!(Lex::Integer("10".into(), 2)));
+        assert_eq!(parse_lexs(b"1.0")?, vec!(Lex::Float("1.0".into(), 3)));
+        Ok(())
+    }
+    #[test]
+    fn test_lex_rich_numbers() -> Result<(), TokenParseError> {
+        let number = |source: &[u8]| -> Result<Number, TokenParseError> {
+            match *parse_tokens(source)?.current_scope().get_tokens()[0] {
+                Token::Literal(Literal::Number(ref n)) => Ok(*n),
+                ref other => panic!("expected a number, found {:?}", other),
+            }
+        };
+        assert_eq!(number(b"0xff")?, Number::Integer(255));
+        assert_eq!(number(b"0XdeadBEEF")?, Number::Integer(0xdead_beef));
+        assert_eq!(number(b"0b1010")?, Number::Integer(10));
+        assert_eq!(number(b"0o17")?, Number::Integer(15));
+        assert_eq!(number(b"1_000_000")?, Number::Integer(1_000_000));
+        assert_eq!(number(b"2147483647")?, Number::Integer(2_147_483_647));
+        assert_eq!(number(b"0xffff_ffff")?, Number::Integer(0xffff_ffff));
+        assert_eq!(number(b"0x_ff_ff")?, Number::Integer(0xffff));
+        assert_eq!(number(b"1e-3")?, Number::Float(1e-3));
+        assert_eq!(number(b"2.5E+2")?, Number::Float(250.0));
+        assert_eq!(number(b"1_0.2_5")?, Number::Float(10.25));
+        assert_eq!(number(b"3f")?, Number::Float(3.0));
+        assert_eq!(number(b"7u")?, Number::UnsignedInt(7));
+        assert_eq!(number(b"0xffu")?, Number::UnsignedInt(255));
+        assert_eq!(
+            number(b"18446744073709551615u")?,
+            Number::UnsignedInt(u64::MAX)
+        );
+        assert_eq!(number(b"255b")?, Number::Byte(255));
+        assert_eq!(number(b"0b")?, Number::Byte(0));
+        assert_eq!(number(b"0b11b")?, Number::Byte(3));
+        // in hex `b` and `f` are digits rather than suffixes
+        assert_eq!(number(b"0x1b")?, Number::Integer(0x1b));
+        assert_eq!(number(b"0x1f")?, Number::Integer(0x1f));
+
+        let error = |source: &[u8]| parse_lexs(source).err();
+        let lex_error = |err| Some(TokenParseError::Lex(err));
+        assert_eq!(
+            error(b"a 99999999999999999999"),
+            lex_error(LexParseError::NumberOverflow(2))
+        );
+        assert_eq!(
+            error(b"9223372036854775808"),
+            lex_error(LexParseError::NumberOverflow(0))
+        );
+        assert_eq!(
+            error(b"2147483648"),
+            lex_error(LexParseError::NumberOverflow(0))
+        );
+        assert_eq!(
+            error(b"0x1_0000_0000"),
+            lex_error(LexParseError::NumberOverflow(0))
+        );
+        assert_eq!(error(b"256b"), lex_error(LexParseError::NumberOverflow(0)));
+        assert_eq!(error(b"1e999"), lex_error(LexParseError::NumberOverflow(0)));
+        assert_eq!(error(b"0b102"), lex_error(LexParseError::InvalidNumber(0)));
+        assert_eq!(
+            error(b"0o"),
+            lex_error(LexParseError::InvalidNumberSuffix(0))
+        );
+        assert_eq!(
+            error(b"12abc"),
+            lex_error(LexParseError::InvalidNumberSuffix(0))
+        );
+        assert_eq!(
+            error(b"1.5u"),
+            lex_error(LexParseError::InvalidNumberSuffix(0))
+        );
+        assert_eq!(
+            error(b"0x1u8"),
+            lex_error(LexParseError::InvalidNumberSuffix(0))
+        );
+        // ranges and accessors still split off the number
+        assert_eq!(
+            parse_lexs(b"1..2")?,
+            vec!(
+                Lex::Integer("1".into(), 1),
+                Lex::Operator(Operator::RangeMiddle, 3),
+                Lex::Integer("2".into(), 4),
+            )
+        );
         Ok(())
     }
     #[test]
@@ -569,6 +1263,18 @@ mod tests {
             parse_lexs(b"...")?,
             vec!(Lex::Operator(Operator::ArrayContinuation, 3))
         );
+        assert_eq!(
+            parse_lexs(b"..=")?,
+            vec!(Lex::Operator(Operator::RangeInclusive, 3))
+        );
+        assert_eq!(
+            parse_lexs(b"0..10")?,
+            vec!(
+                Lex::Integer("0".into(), 1),
+                Lex::Operator(Operator::RangeMiddle, 3),
+                Lex::Integer("10".into(), 5)
+            )
+        );
 
         assert_eq!(parse_lexs(b"+")?, vec!(Lex::Operator(Operator::Plus, 1)));
         assert_eq!(
@@ -581,29 +1287,249 @@ mod tests {
     fn test_lex_braces() -> Result<(), TokenParseError> {
         assert_eq!(
             parse_lexs(b"(")?,
-            vec!(Lex::Brace(Brace::Brace, BraceStatus::SpeculativeOpen, 0, 1))
+            vec!(Lex::Brace(Brace::Brace, BraceStatus::Open, 0, 1))
         );
         assert_eq!(
             parse_lexs(b"{}")?,
             vec!(
-                Lex::Brace(Brace::Bracket, BraceStatus::Open(1usize), 0, 1),
+                Lex::Brace(Brace::Bracket, BraceStatus::Open, 0, 1),
                 Lex::Brace(Brace::Bracket, BraceStatus::Close, 1, 2)
             )
         );
+        // unbalanced braces still lex, the tokenizer is what matches them up
         assert_eq!(
-            parse_lexs(b"{}}"),
-            Err(TokenParseError::Lex(LexParseError::InvalidDelimiter(3)))
+            parse_lexs(b"{}}")?[2],
+            Lex::Brace(Brace::Bracket, BraceStatus::Close, 0, 3)
         );
 
         assert_eq!(
             parse_lexs(b"{data Goose}")?,
             vec!(
-                Lex::Brace(Brace::Bracket, BraceStatus::Open(3usize), 0, 1),
+                Lex::Brace(Brace::Bracket, BraceStatus::Open, 0, 1),
                 Lex::Keyword(Keyword::Data, 5),
-                Lex::Identifier("Goose".to_string(), 11),
+                Lex::Identifier("Goose".into(), 11),
                 Lex::Brace(Brace::Bracket, BraceStatus::Close, 1, 12)
             )
         );
         Ok(())
     }
+    #[test]
+    fn test_parse_unmatched_braces() {
+        let error = |source: &[u8]| parse_tokens(source).err();
+        assert_eq!(error(b"{}}"), Some(TokenParseError::UnmatchedBrace(3)));
+        assert_eq!(error(b"[a(b])"), Some(TokenParseError::UnmatchedBrace(5)));
+        assert_eq!(error(b"x ( [1]"), Some(TokenParseError::UnclosedBrace(3)));
+        assert!(parse_tokens(b"[a(b)] ([1], {c})").is_ok());
+    }
+    #[test]
+    fn test_lexer_spans() -> Result<(), LexParseError> {
+        let lexes = Lexer::new(b"a  += \"b\"\n1.5").collect::<Result<Vec<_>, _>>()?;
+        let spans = lexes
+            .iter()
+            .map(|l| (l.start, l.end))
+            .collect::<Vec<(usize, usize)>>();
+        assert_eq!(spans, vec![(0, 1), (3, 5), (6, 9), (10, 13)]);
+        assert_eq!(lexes[3].node, Lex::Float("1.5".into(), 13));
+        // lexing stops at the first error
+        let mut lexer = Lexer::new(b"a \xff b");
+        assert!(lexer.next().unwrap().is_ok());
+        assert_eq!(lexer.next(), Some(Err(LexParseError::InvalidCharacter(2))));
+        assert_eq!(lexer.next(), None);
+        Ok(())
+    }
+    /// Hands out at most `step` bytes per read, to split lexemes across reads.
+    struct Trickle<'a> {
+        source: &'a [u8],
+        step: usize,
+    }
+    impl<'a> std::io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = self.step.min(buf.len()).min(self.source.len());
+            buf[..len].copy_from_slice(&self.source[..len]);
+            self.source = &self.source[len..];
+            Ok(len)
+        }
+    }
+    #[test]
+    fn test_lexer_from_reader() {
+        let mut sources = fs::read_dir("res/test")
+            .unwrap()
+            .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+            .collect::<Vec<Vec<u8>>>();
+        sources.push(
+            "größe: 0x_ff; \"a {größe}\" /* /* */ */ 1.5e3f"
+                .as_bytes()
+                .to_vec(),
+        );
+        sources.push(b"\"not ended".to_vec());
+        // spread over several chunks, so lexemes straddle the reads and the buffer is drained
+        let mut large = Vec::new();
+        while large.len() < 40_000 {
+            large.extend_from_slice(b"calc start: () { n: 1_000; \"n is {n}\" => print; }\n");
+        }
+        sources.push(large);
+        for source in sources.iter() {
+            let expected = Lexer::new(source).collect::<Vec<_>>();
+            for step in [1usize, 3, 7, 4096, usize::MAX].iter() {
+                let reader = Trickle {
+                    source,
+                    step: *step,
+                };
+                assert_eq!(Lexer::from_reader(reader).collect::<Vec<_>>(), expected);
+            }
+        }
+    }
+    #[test]
+    fn test_lex_comments() -> Result<(), TokenParseError> {
+        assert_eq!(
+            parse_lexs(b"a // b\nc")?,
+            vec!(
+                Lex::Identifier("a".into(), 1),
+                Lex::Brace(
+                    Brace::Comment(" b".to_string()),
+                    BraceStatus::Agnostic,
+                    0,
+                    6
+                ),
+                Lex::Identifier("c".into(), 8),
+            )
+        );
+        assert_eq!(
+            parse_lexs(b"/* a /* b */\n c */d")?,
+            vec!(
+                Lex::Brace(
+                    Brace::Comment(" a /* b */\n c ".to_string()),
+                    BraceStatus::Agnostic,
+                    0,
+                    18
+                ),
+                Lex::Identifier("d".into(), 19),
+            )
+        );
+        assert_eq!(
+            parse_lexs(b"/* a /* b */"),
+            Err(TokenParseError::Lex(LexParseError::CommentNotEnded(0)))
+        );
+        assert_eq!(
+            parse_lexs("/// Größe des Punkts\n/* Ärger */".as_bytes())?,
+            vec!(
+                Lex::Brace(
+                    Brace::DocComment(" Größe des Punkts".to_string()),
+                    BraceStatus::Agnostic,
+                    0,
+                    22
+                ),
+                Lex::Brace(
+                    Brace::Comment(" Ärger ".to_string()),
+                    BraceStatus::Agnostic,
+                    0,
+                    35
+                ),
+            )
+        );
+        assert_eq!(
+            parse_lexs(b"// a\xffb"),
+            Err(TokenParseError::Lex(LexParseError::InvalidUtf8(4)))
+        );
+        assert_eq!(
+            parse_lexs(b"a / b /= c")?,
+            vec!(
+                Lex::Identifier("a".into(), 1),
+                Lex::Operator(Operator::Divide, 3),
+                Lex::Identifier("b".into(), 5),
+                Lex::Operator(Operator::DivideEquals, 8),
+                Lex::Identifier("c".into(), 10),
+            )
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_lex_strings() -> Result<(), TokenParseError> {
+        let quote = |s: &str, pos| {
+            vec![Lex::Brace(
+                Brace::Quote(s.to_string()),
+                BraceStatus::Agnostic,
+                0,
+                pos,
+            )]
+        };
+        assert_eq!(parse_lexs(b"\"a\\nb\"")?, quote("a\nb", 6));
+        assert_eq!(
+            parse_lexs(b"\"\\t\\\"\\\\\\r\\0\"")?,
+            quote("\t\"\\\r\0", 12)
+        );
+        assert_eq!(
+            parse_lexs("\"gr\u{f6}\u{df}e \\u{1F600}\"".as_bytes())?,
+            quote("größe 😀", 19)
+        );
+        assert_eq!(
+            parse_lexs(b"\"abc"),
+            Err(TokenParseError::Lex(LexParseError::QuoteNotEnded(0)))
+        );
+        assert_eq!(
+            parse_lexs(b"\"a\\qb\""),
+            Err(TokenParseError::Lex(LexParseError::InvalidEscape(2, 'q')))
+        );
+        assert_eq!(
+            parse_lexs(b"\"\\u{110000}\""),
+            Err(TokenParseError::Lex(LexParseError::InvalidUnicodeEscape(1)))
+        );
+        assert_eq!(
+            parse_lexs(b"\"\\u{}\""),
+            Err(TokenParseError::Lex(LexParseError::InvalidUnicodeEscape(1)))
+        );
+        assert_eq!(
+            parse_lexs(b"\"a\xffb\""),
+            Err(TokenParseError::Lex(LexParseError::InvalidUtf8(2)))
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_lex_chars() -> Result<(), TokenParseError> {
+        let char_lex = |c, pos| vec![Lex::Brace(Brace::Char(c), BraceStatus::Agnostic, 0, pos)];
+        assert_eq!(parse_lexs(b"'a'")?, char_lex('a', 3));
+        assert_eq!(parse_lexs("'é'".as_bytes())?, char_lex('é', 4));
+        assert_eq!(parse_lexs(b"'\\''")?, char_lex('\'', 4));
+        assert_eq!(parse_lexs(b"'\\n'")?, char_lex('\n', 4));
+        assert_eq!(parse_lexs(b"'\\u{41}'")?, char_lex('A', 8));
+        assert_eq!(
+            parse_lexs(b"''"),
+            Err(TokenParseError::Lex(
+                LexParseError::NoCharBetweenSingleQuotes(1)
+            ))
+        );
+        assert_eq!(
+            parse_lexs(b"'ab'"),
+            Err(TokenParseError::Lex(
+                LexParseError::MultipleCharsBetweenSingleQuotes(2)
+            ))
+        );
+        assert_eq!(
+            parse_lexs(b"'a"),
+            Err(TokenParseError::Lex(LexParseError::QuoteNotEnded(0)))
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_lex_resources() {
+        let mut files = Vec::new();
+        let mut directories = vec![std::path::PathBuf::from("res")];
+        while let Some(directory) = directories.pop() {
+            for entry in std::fs::read_dir(directory).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        assert!(!files.is_empty());
+        for file in files.iter() {
+            let contents = std::fs::read(file).unwrap();
+            if let Err(err) = parse_lexs(&contents) {
+                panic!("could not lex {}: {:?}", file.display(), err);
+            }
+        }
+    }
 }
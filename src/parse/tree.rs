@@ -0,0 +1,254 @@
+use crate::parse::constant::{Brace, Number};
+use crate::parse::context::TokenizerContext;
+use crate::parse::token::{Literal, OperatorGroup, Token};
+
+use std::fmt;
+
+/// A token with every `Token::Block` replaced by the tokens of the scope it refers to. Names and
+/// values are leaves, everything else a branch labelled with its kind and holding its operands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Leaf(String),
+    Branch(String, Vec<Node>),
+}
+impl Node {
+    fn leaf(text: String) -> Node {
+        Node::Leaf(text)
+    }
+    fn branch(head: &str, children: Vec<Node>) -> Node {
+        Node::Branch(head.to_string(), children)
+    }
+
+    /// Builds the node of `token`, following blocks into the scopes of `context`.
+    pub fn from_token(context: &TokenizerContext, token: &Token) -> Node {
+        let node = |t: &Token| Node::from_token(context, t);
+        match token {
+            Token::Literal(literal) => Node::literal(literal),
+            Token::Operator(OperatorGroup::UniOperator(op, a)) => {
+                Node::branch(&kebab(op), vec![node(a)])
+            }
+            Token::Operator(OperatorGroup::BiOperator(op, a, b)) => {
+                Node::branch(&kebab(op), vec![node(a), node(b)])
+            }
+            Token::Operator(OperatorGroup::TriOperator(op, a, b, c)) => {
+                Node::branch(&kebab(op), vec![node(a), node(b), node(c)])
+            }
+            Token::Block(brace, scope) => Node::block(context, brace, *scope),
+            Token::AgrandizedString(prefix, parts, suffix) => Node::branch(
+                "interpolate",
+                vec![
+                    Node::leaf(format!("{:?}", prefix)),
+                    node(parts),
+                    Node::leaf(format!("{:?}", suffix)),
+                ],
+            ),
+            Token::Constructor(name, parameters) => Node::branch(
+                "constructor",
+                vec![Node::leaf(name.clone()), node(parameters)],
+            ),
+            Token::Generic(name, arguments) => {
+                Node::branch("generic", vec![Node::leaf(name.clone()), node(arguments)])
+            }
+            Token::GenericConstructor(name, arguments, parameters) => Node::branch(
+                "generic-constructor",
+                vec![Node::leaf(name.clone()), node(arguments), node(parameters)],
+            ),
+            Token::Array(elements) => Node::branch("array", vec![node(elements)]),
+            Token::Index(collection, indices) => {
+                Node::branch("index", vec![node(collection), node(indices)])
+            }
+            Token::VariableDef(ty, name) => Node::branch(
+                "variable",
+                vec![Node::literal(ty), Node::leaf(name.clone())],
+            ),
+            Token::Binding(name, value) => {
+                Node::branch("bind", vec![Node::leaf(name.clone()), node(value)])
+            }
+            Token::TypeDef(name, parameters, body) => {
+                let mut children = vec![Node::literal(name)];
+                children.extend(parameters.iter().map(|p| node(p)));
+                children.push(node(body));
+                Node::branch("type", children)
+            }
+            Token::MethodDef(mode, name, parameters, intake, body) => {
+                let mut children = vec![Node::leaf(name.clone())];
+                children.extend(parameters.iter().map(|p| node(p)));
+                children.push(node(intake));
+                children.push(node(body));
+                Node::branch(mode.spelling(), children)
+            }
+            Token::Delimiter => Node::leaf("delimiter".to_string()),
+        }
+    }
+
+    /// The nodes of every token in `scope`, or none for a scope that does not exist.
+    pub fn from_scope(context: &TokenizerContext, scope: usize) -> Vec<Node> {
+        match context.get_scope(scope) {
+            Some(scope) => scope
+                .get_tokens()
+                .iter()
+                .map(|t| Node::from_token(context, t))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn block(context: &TokenizerContext, brace: &Brace, scope: usize) -> Node {
+        Node::branch(brace_name(brace), Node::from_scope(context, scope))
+    }
+
+    fn literal(literal: &Literal) -> Node {
+        match literal {
+            Literal::Keyword(keyword) => Node::leaf(keyword.spelling().to_string()),
+            Literal::Identifier(name) => Node::leaf(name.clone()),
+            Literal::Number(Number::Integer(n)) => Node::leaf(n.to_string()),
+            Literal::Number(Number::Float(n)) => Node::leaf(format!("{:?}", n)),
+            Literal::Number(Number::Char(c)) => Node::leaf(format!("{:?}", c)),
+            Literal::Number(Number::UnsignedInt(n)) => {
+                Node::branch("unsigned", vec![Node::leaf(n.to_string())])
+            }
+            Literal::Number(Number::Byte(n)) => {
+                Node::branch("byte", vec![Node::leaf(n.to_string())])
+            }
+            Literal::String(text) => Node::leaf(format!("{:?}", text)),
+            Literal::Void => Node::leaf("()".to_string()),
+        }
+    }
+
+    /// Renders the node on one line as an S-expression: a leaf as its text, a branch as
+    /// `(head child...)`.
+    pub fn sexpr(&self) -> String {
+        let mut out = String::new();
+        self.write_sexpr(&mut out);
+        out
+    }
+    fn write_sexpr(&self, out: &mut String) {
+        match self {
+            Node::Leaf(text) => out.push_str(text),
+            Node::Branch(head, children) => {
+                out.push('(');
+                out.push_str(head);
+                for child in children.iter() {
+                    out.push(' ');
+                    child.write_sexpr(out);
+                }
+                out.push(')');
+            }
+        }
+    }
+
+    fn write_tree(&self, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        match self {
+            Node::Leaf(text) => writeln!(f, "{}{}", indent, text),
+            Node::Branch(head, children) => {
+                writeln!(f, "{}{}", indent, head)?;
+                for child in children.iter() {
+                    child.write_tree(depth + 1, f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// One node per line, with its children indented below it.
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_tree(0, f)
+    }
+}
+
+/// The tokens of the root scope as a tree, with every block inlined.
+pub struct Tree<'a>(pub &'a TokenizerContext);
+impl<'a> fmt::Display for Tree<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for node in Node::from_scope(self.0, 0).iter() {
+            write!(f, "{}", node)?;
+        }
+        Ok(())
+    }
+}
+
+/// The S-expressions of the tokens in `scope`, one per line.
+pub fn sexpr(context: &TokenizerContext, scope: usize) -> String {
+    Node::from_scope(context, scope)
+        .iter()
+        .map(Node::sexpr)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The name of a kind of brace, after the block it opens for those that open one.
+pub fn brace_name(brace: &Brace) -> &'static str {
+    match brace {
+        Brace::Brace => "paren",
+        Brace::Bracket => "curly",
+        Brace::Angle => "angle",
+        Brace::Square => "square",
+        Brace::Char(_) => "char",
+        Brace::Quote(_) => "quote",
+        Brace::Comment(_) => "comment",
+        Brace::DocComment(_) => "doc-comment",
+    }
+}
+
+/// `RangeInclusive` as `range-inclusive`.
+pub fn kebab<T: fmt::Debug>(value: &T) -> String {
+    let mut out = String::new();
+    for (i, c) in format!("{:?}", value).chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                out.push('-');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::token::{parse_tokens, TokenParseError};
+
+    #[test]
+    fn test_sexpr() -> Result<(), TokenParseError> {
+        let context = parse_tokens(b"calc start: () { [Point(1, 2.5)] => print; }")?;
+        assert_eq!(
+            sexpr(&context, 0),
+            "(calc start (paren) (curly (into (array (square (constructor Point (paren 1 2.5)))) print)))"
+        );
+        let context = parse_tokens(b"n: 0..=10 by 2; \"{n}!\" => print;")?;
+        assert_eq!(
+            sexpr(&context, 0),
+            "(bind n (range-inclusive 0 10 2))\n(into (interpolate \"\" (curly n) \"!\") print)"
+        );
+        Ok(())
+    }
+    #[test]
+    fn test_tree() -> Result<(), TokenParseError> {
+        let context = parse_tokens(b"type Pair<A, B>: (A(first), B(second))")?;
+        assert_eq!(
+            Tree(&context).to_string(),
+            "type
+  Pair
+  angle
+    A
+    B
+  paren
+    constructor
+      A
+      paren
+        first
+    constructor
+      B
+      paren
+        second
+"
+        );
+        Ok(())
+    }
+}
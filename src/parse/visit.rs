@@ -0,0 +1,213 @@
+use crate::parse::context::TokenizerContext;
+use crate::parse::token::{OperatorGroup, Token};
+
+/// Walks tokens by reference. Every method defaults to walking into the children of what it is
+/// given, following a `Token::Block` into the scope it refers to, so an implementation overrides
+/// the methods for what it looks for and calls the matching `walk_` function to keep going below.
+pub trait Visitor<'t> {
+    type Error;
+
+    fn visit_token(
+        &mut self,
+        tokens: &'t TokenizerContext,
+        token: &'t Token,
+    ) -> Result<(), Self::Error> {
+        walk_token(self, tokens, token)
+    }
+    fn visit_scope(
+        &mut self,
+        tokens: &'t TokenizerContext,
+        scope: usize,
+    ) -> Result<(), Self::Error> {
+        walk_scope(self, tokens, scope)
+    }
+}
+
+/// Visits each token directly below `token`, or the scope of a block.
+pub fn walk_token<'t, V: Visitor<'t> + ?Sized>(
+    visitor: &mut V,
+    tokens: &'t TokenizerContext,
+    token: &'t Token,
+) -> Result<(), V::Error> {
+    match token {
+        Token::Literal(_) | Token::VariableDef(_, _) | Token::Delimiter => Ok(()),
+        Token::Operator(OperatorGroup::UniOperator(_, a)) => visitor.visit_token(tokens, a),
+        Token::Operator(OperatorGroup::BiOperator(_, a, b)) => {
+            visitor.visit_token(tokens, a)?;
+            visitor.visit_token(tokens, b)
+        }
+        Token::Operator(OperatorGroup::TriOperator(_, a, b, c)) => {
+            visitor.visit_token(tokens, a)?;
+            visitor.visit_token(tokens, b)?;
+            visitor.visit_token(tokens, c)
+        }
+        Token::Block(_, scope) => visitor.visit_scope(tokens, *scope),
+        Token::AgrandizedString(_, inner, _)
+        | Token::Constructor(_, inner)
+        | Token::Generic(_, inner)
+        | Token::Array(inner)
+        | Token::Binding(_, inner) => visitor.visit_token(tokens, inner),
+        Token::GenericConstructor(_, a, b) | Token::Index(a, b) => {
+            visitor.visit_token(tokens, a)?;
+            visitor.visit_token(tokens, b)
+        }
+        Token::TypeDef(_, parameters, body) => {
+            if let Some(parameters) = parameters {
+                visitor.visit_token(tokens, parameters)?;
+            }
+            visitor.visit_token(tokens, body)
+        }
+        Token::MethodDef(_, _, parameters, intake, body) => {
+            if let Some(parameters) = parameters {
+                visitor.visit_token(tokens, parameters)?;
+            }
+            visitor.visit_token(tokens, intake)?;
+            visitor.visit_token(tokens, body)
+        }
+    }
+}
+
+/// Visits every token of `scope` in order. A scope that does not exist has no tokens.
+pub fn walk_scope<'t, V: Visitor<'t> + ?Sized>(
+    visitor: &mut V,
+    tokens: &'t TokenizerContext,
+    scope: usize,
+) -> Result<(), V::Error> {
+    if let Some(scope) = tokens.get_scope(scope) {
+        for token in scope.get_tokens().iter() {
+            visitor.visit_token(tokens, token)?;
+        }
+    }
+    Ok(())
+}
+
+/// Rebuilds tokens, replacing the tokens of each scope it walks into with the folded ones. Like
+/// `Visitor`, every method defaults to folding the children and keeping the token itself.
+pub trait Folder {
+    fn fold_token(&mut self, tokens: &mut TokenizerContext, token: Box<Token>) -> Box<Token> {
+        walk_fold_token(self, tokens, token)
+    }
+    fn fold_scope(&mut self, tokens: &mut TokenizerContext, scope: usize) {
+        walk_fold_scope(self, tokens, scope)
+    }
+}
+
+/// Folds each token directly below `token`, or the scope of a block, and puts it back together.
+pub fn walk_fold_token<F: Folder + ?Sized>(
+    folder: &mut F,
+    tokens: &mut TokenizerContext,
+    mut token: Box<Token>,
+) -> Box<Token> {
+    let mut fold = |t: Box<Token>| folder.fold_token(tokens, t);
+    *token = match *token {
+        Token::Operator(OperatorGroup::UniOperator(op, a)) => {
+            Token::Operator(OperatorGroup::UniOperator(op, fold(a)))
+        }
+        Token::Operator(OperatorGroup::BiOperator(op, a, b)) => {
+            Token::Operator(OperatorGroup::BiOperator(op, fold(a), fold(b)))
+        }
+        Token::Operator(OperatorGroup::TriOperator(op, a, b, c)) => {
+            Token::Operator(OperatorGroup::TriOperator(op, fold(a), fold(b), fold(c)))
+        }
+        Token::Block(brace, scope) => {
+            folder.fold_scope(tokens, scope);
+            Token::Block(brace, scope)
+        }
+        Token::AgrandizedString(prefix, parts, suffix) => {
+            Token::AgrandizedString(prefix, fold(parts), suffix)
+        }
+        Token::Constructor(name, parameters) => Token::Constructor(name, fold(parameters)),
+        Token::Generic(name, arguments) => Token::Generic(name, fold(arguments)),
+        Token::GenericConstructor(name, arguments, parameters) => {
+            Token::GenericConstructor(name, fold(arguments), fold(parameters))
+        }
+        Token::Array(elements) => Token::Array(fold(elements)),
+        Token::Index(collection, indices) => Token::Index(fold(collection), fold(indices)),
+        Token::Binding(name, value) => Token::Binding(name, fold(value)),
+        Token::TypeDef(name, parameters, body) => {
+            Token::TypeDef(name, parameters.map(&mut fold), fold(body))
+        }
+        Token::MethodDef(mode, name, parameters, intake, body) => {
+            let parameters = parameters.map(&mut fold);
+            Token::MethodDef(mode, name, parameters, fold(intake), fold(body))
+        }
+        other => other,
+    };
+    token
+}
+
+/// Folds every token of `scope` in order. The tokens are taken out of the scope while they are
+/// folded, so a folder looking into the scope it is folding finds it empty.
+pub fn walk_fold_scope<F: Folder + ?Sized>(
+    folder: &mut F,
+    tokens: &mut TokenizerContext,
+    scope: usize,
+) {
+    let taken = match tokens.get_mut_scope(scope) {
+        Some(scope) => std::mem::take(scope.get_mut_tokens()),
+        None => return,
+    };
+    let folded = taken
+        .into_iter()
+        .map(|t| folder.fold_token(tokens, t))
+        .collect();
+    *tokens.get_mut_scope(scope).unwrap().get_mut_tokens() = folded;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::token::{parse_tokens, Literal, TokenParseError};
+    use crate::parse::tree::sexpr;
+
+    /// Every identifier in order, including the names of constructors and generics.
+    struct Names(Vec<String>);
+    impl<'t> Visitor<'t> for Names {
+        type Error = ();
+        fn visit_token(
+            &mut self,
+            tokens: &'t TokenizerContext,
+            token: &'t Token,
+        ) -> Result<(), ()> {
+            match token {
+                Token::Literal(Literal::Identifier(name))
+                | Token::Constructor(name, _)
+                | Token::Generic(name, _)
+                | Token::GenericConstructor(name, _, _) => self.0.push(name.clone()),
+                _ => {}
+            }
+            walk_token(self, tokens, token)
+        }
+    }
+
+    struct Rename<'a>(&'a str, &'a str);
+    impl<'a> Folder for Rename<'a> {
+        fn fold_token(&mut self, tokens: &mut TokenizerContext, token: Box<Token>) -> Box<Token> {
+            match *token {
+                Token::Literal(Literal::Identifier(ref name)) if name == self.0 => {
+                    Box::new(Token::Literal(Literal::Identifier(self.1.to_string())))
+                }
+                _ => walk_fold_token(self, tokens, token),
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_follows_blocks() -> Result<(), TokenParseError> {
+        let tokens = parse_tokens(b"calc start: () { [Point(a, b + 1)] => print; }")?;
+        let mut names = Names(Vec::new());
+        names.visit_scope(&tokens, 0).unwrap();
+        assert_eq!(names.0, vec!["Point", "a", "b", "print"]);
+        Ok(())
+    }
+    #[test]
+    fn test_folder_rewrites_scopes() -> Result<(), TokenParseError> {
+        let mut tokens = parse_tokens(b"calc start: () { [Point(a, a + 1)] => print; }")?;
+        Rename("a", "z").fold_scope(&mut tokens, 0);
+        assert_eq!(
+            sexpr(&tokens, 0),
+            "(calc start (paren) (curly (into (array (square (constructor Point (paren z (plus z 1))))) print)))"
+        );
+        Ok(())
+    }
+}
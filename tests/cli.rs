@@ -0,0 +1,174 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn intolang(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_intolang"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_help_and_usage_errors() {
+    let help = intolang(&["--help"]);
+    assert_eq!(help.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&help.stdout).starts_with("Usage: intolang"));
+    assert_eq!(intolang(&[]).status.code(), Some(2));
+    assert_eq!(
+        intolang(&["frob", "res/test/simple_0.geo"]).status.code(),
+        Some(2)
+    );
+    assert_eq!(intolang(&["check"]).status.code(), Some(2));
+    let missing = intolang(&["check", "res/test/missing.geo"]);
+    assert_eq!(missing.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&missing.stderr).starts_with("error: res/test/missing.geo: "));
+    let backends = intolang(&["run", "--interp", "--vm", "res/test/simple_0.geo"]);
+    assert_eq!(backends.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&backends.stderr).contains("`--interp` and `--vm`"));
+}
+
+#[test]
+fn test_check_prints_nothing() {
+    let check = intolang(&["check", "res/test/range_0.geo"]);
+    assert_eq!(check.status.code(), Some(0));
+    assert!(check.stdout.is_empty());
+    assert!(check.stderr.is_empty());
+    let failed = intolang(&["check", "res/example/test.clf"]);
+    assert_eq!(failed.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&failed.stderr)
+        .starts_with("res/example/test.clf:3:11: error[token::"));
+}
+
+#[test]
+fn test_lex_and_parse() {
+    let lex = intolang(&["lex", "res/test/simple_0.geo"]);
+    assert_eq!(lex.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&lex.stdout).starts_with("1..5 Keyword(Type, 5)\n"));
+    let parse = intolang(&["parse", "res/test/simple_0.geo"]);
+    assert_eq!(parse.status.code(), Some(0));
+    let tree = String::from_utf8_lossy(&parse.stdout).into_owned();
+    assert!(tree.starts_with("type\n  Point\n  paren\n    constructor\n"));
+    let sexpr = intolang(&["parse", "res/test/simple_0.geo", "--sexpr"]);
+    assert_eq!(
+        String::from_utf8_lossy(&sexpr.stdout).lines().next(),
+        Some("(type Point (paren (constructor int (paren x)) (constructor int (paren y))))")
+    );
+}
+
+#[test]
+fn test_build_and_run() {
+    let output = std::env::temp_dir().join(format!("intolang_cli_{}.c", std::process::id()));
+    let build = intolang(&[
+        "build",
+        "res/test/range_0.geo",
+        "-o",
+        output.to_str().unwrap(),
+    ]);
+    assert_eq!(build.status.code(), Some(0));
+    assert!(fs::read_to_string(&output)
+        .unwrap()
+        .contains("int main() {"));
+    fs::remove_file(&output).unwrap();
+    if Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let run = intolang(&["run", "res/test/range_0.geo"]);
+    assert_eq!(run.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "0\n1\n2\n1\n5\n9\nPoint(3, 4)\nPoint(5, 6)\n"
+    );
+}
+
+#[test]
+fn test_json_format() {
+    let check = intolang(&["check", "--format", "json", "res/test/range_0.geo"]);
+    assert_eq!(check.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&check.stdout).into_owned();
+    assert!(stdout.starts_with("{\"version\":1,\"kind\":\"check\",\"types\":["));
+    assert!(stdout.ends_with(",\"diagnostics\":[]}\n"));
+    // a body that cannot be lowered still reports the types that were declared
+    let path = std::env::temp_dir().join(format!("intolang_json_{}.geo", std::process::id()));
+    fs::write(
+        &path,
+        "type Point: (int(x), int(y),)\ncalc start: () { p: 1; p: 2; }\n",
+    )
+    .unwrap();
+    let lowered = intolang(&["check", "--format", "json", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(lowered.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&lowered.stdout).into_owned();
+    assert!(stdout.contains("\"name\":\"Point\""), "{}", stdout);
+    assert!(stdout.contains("\"code\":\"assembly::BindingRedefined\""));
+    let failed = intolang(&["parse", "res/example/test.clf", "--format", "json"]);
+    assert_eq!(failed.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&failed.stdout)
+        .contains("\"code\":\"token::ExpectedColonAfterTypeDefIdentifier\""));
+    assert_eq!(
+        intolang(&["build", "res/test/range_0.geo", "--format", "json"])
+            .status
+            .code(),
+        Some(2)
+    );
+    assert_eq!(
+        intolang(&["lex", "res/test/range_0.geo", "--format", "xml"])
+            .status
+            .code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn test_repl() {
+    let mut repl = Command::new(env!("CARGO_BIN_EXE_intolang"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut script = String::from("type Point: (\n  int(x),\n  int(y),\n)\n:type [Point(1,2)]\n");
+    let cc = Command::new("cc").arg("--version").output().is_ok();
+    if cc {
+        script.push_str("ps: [Point(1,2), Point(3,4)]\nps\n");
+        script.push_str("[Point(1,2), Point(3,4)] => x => sum => print\n");
+    }
+    script.push_str(":nope\n:quit\n");
+    repl.stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    let output = repl.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let mut expected = String::from("> . . . > Point\n> ");
+    if cc {
+        expected.push_str("> Point(1, 2)\nPoint(3, 4)\n> 4\n> ");
+    }
+    expected
+        .push_str("entry: error[repl::UnknownCommand]: unknown command `:nope`, see `:help`\n> ");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+}
+
+#[test]
+fn test_fmt() {
+    let dir = std::env::temp_dir();
+    let good = dir.join(format!("intolang_fmt_{}.geo", std::process::id()));
+    let bad = dir.join(format!("intolang_fmt_bad_{}.geo", std::process::id()));
+    fs::write(&good, "calc start: () {\n  x => print;\n// last\n}\n").unwrap();
+    fs::write(&bad, "calc start: () {\n  p := 1;\n}\n").unwrap();
+    let fmt = intolang(&["fmt", good.to_str().unwrap(), bad.to_str().unwrap()]);
+    assert_eq!(fmt.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&fmt.stderr).into_owned();
+    assert!(stderr.starts_with(bad.to_str().unwrap()), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(&good).unwrap(),
+        "calc start: () {\n  x => print;\n  // last\n}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(&bad).unwrap(),
+        "calc start: () {\n  p := 1;\n}\n"
+    );
+    fs::remove_file(&good).unwrap();
+    fs::remove_file(&bad).unwrap();
+}
@@ -0,0 +1,94 @@
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+fn intolang(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_intolang"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Every test source must build with the C backend and print the same when interpreted, and when
+/// run on the bytecode VM both directly and from a written `.ilbc` file.
+#[test]
+fn test_interpreter_matches_c() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping test_interpreter_matches_c: no C compiler `cc` was found");
+        return;
+    }
+    let mut compared = 0;
+    for entry in fs::read_dir("res/test").unwrap() {
+        let path = entry.unwrap().path();
+        let path = path.to_str().unwrap();
+        let interpreted = intolang(&["run", "--interp", path]);
+        let compiled = intolang(&["run", path]);
+        assert_eq!(
+            compiled.status.code(),
+            Some(0),
+            "{}: {}",
+            path,
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+        let bytecode = env::temp_dir().join(format!("intolang_differential_{}.ilbc", compared));
+        let bytecode = bytecode.to_str().unwrap();
+        let built = intolang(&["build", path, "-o", bytecode, "--emit=ilbc"]);
+        assert_eq!(built.status.code(), Some(0), "{}", path);
+        for output in [
+            interpreted,
+            intolang(&["run", "--vm", path]),
+            intolang(&["run", "--vm", bytecode]),
+        ] {
+            assert_eq!(output.status.code(), Some(0), "{}", path);
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&compiled.stdout),
+                "{}",
+                path
+            );
+        }
+        let _ = fs::remove_file(bytecode);
+        compared += 1;
+    }
+    assert!(compared >= 10);
+}
+
+/// A source rejected by one backend must be rejected by all of them, with the same error and
+/// before anything is printed.
+#[test]
+fn test_errors_match() {
+    let sources = [
+        ("redefined", "calc start: () { p: 1; p: 2; p => print; }"),
+        ("before_definition", "calc start: () { p => print; p: 1; }"),
+        ("int_plus_array", "calc start: () { 1 + [1, 2] => print; }"),
+        ("binding_array", "calc start: () { p: 1; [p, p] => print; }"),
+        ("indexed_range", "calc start: () { (0..5)[1..3] => print; }"),
+        ("interpolation", "calc start: () { \"{[1, 2]}\" => print; }"),
+        (
+            "interpolated_stream",
+            "calc start: () { p: [1, 2]; \"a {p} b\" => print; }",
+        ),
+    ];
+    for (name, source) in sources.iter() {
+        let path = env::temp_dir().join(format!("intolang_differential_{}.geo", name));
+        fs::write(&path, source).unwrap();
+        let path = path.to_str().unwrap();
+        let compiled = intolang(&["run", path]);
+        assert_eq!(compiled.status.code(), Some(1), "{}", source);
+        assert!(compiled.stdout.is_empty(), "{}", source);
+        for output in [
+            intolang(&["run", "--interp", path]),
+            intolang(&["run", "--vm", path]),
+        ] {
+            assert_eq!(output.status.code(), Some(1), "{}", source);
+            assert!(output.stdout.is_empty(), "{}", source);
+            assert_eq!(
+                String::from_utf8_lossy(&output.stderr),
+                String::from_utf8_lossy(&compiled.stderr),
+                "{}",
+                source
+            );
+        }
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,175 @@
+extern crate componsition_lang;
+
+use componsition_lang::lang::json::Json;
+use componsition_lang::lang::lsp::{read_message, write_message};
+
+use std::io::{BufReader, Write};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+/// Drives `intolang lsp` the way an editor would, one message at a time.
+struct Client {
+    server: Child,
+    output: BufReader<ChildStdout>,
+    next_id: i128,
+}
+impl Client {
+    fn start() -> Client {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_intolang"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let output = BufReader::new(server.stdout.take().unwrap());
+        Client {
+            server,
+            output,
+            next_id: 1,
+        }
+    }
+    fn send(&mut self, method: &str, params: &str) {
+        let mut message = format!("{{\"jsonrpc\":\"2.0\",\"method\":\"{}\"", method);
+        if !params.is_empty() {
+            message.push_str(&format!(",\"params\":{}", params));
+        }
+        message.push('}');
+        let stdin = self.server.stdin.as_mut().unwrap();
+        write_message(stdin, &Json::parse(&message).unwrap()).unwrap();
+    }
+    fn receive(&mut self) -> Json {
+        let body = read_message(&mut self.output).unwrap().unwrap();
+        Json::parse(&body).unwrap()
+    }
+    /// Sends a request and gives back the result of its response.
+    fn request(&mut self, method: &str, params: &str) -> Json {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = format!(
+            "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":\"{}\",\"params\":{}}}",
+            id, method, params
+        );
+        let stdin = self.server.stdin.as_mut().unwrap();
+        write!(
+            stdin,
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        )
+        .unwrap();
+        stdin.flush().unwrap();
+        let response = self.receive();
+        assert_eq!(response.get("id"), Some(&Json::Integer(id)));
+        response.get("result").unwrap().clone()
+    }
+}
+
+const URI: &str = "file:///project/range.clf";
+
+fn at(line: usize, character: usize) -> String {
+    format!(
+        "{{\"textDocument\":{{\"uri\":\"{}\"}},\"position\":{{\"line\":{},\"character\":{}}}}}",
+        URI, line, character
+    )
+}
+
+fn open(client: &mut Client, text: &str, version: usize) -> Json {
+    let method = if version == 1 {
+        "textDocument/didOpen"
+    } else {
+        "textDocument/didChange"
+    };
+    let params = if version == 1 {
+        format!(
+            "{{\"textDocument\":{{\"uri\":\"{}\",\"languageId\":\"intolang\",\"version\":1,\"text\":{}}}}}",
+            URI,
+            Json::string(text)
+        )
+    } else {
+        format!(
+            "{{\"textDocument\":{{\"uri\":\"{}\",\"version\":{}}},\"contentChanges\":[{{\"text\":{}}}]}}",
+            URI,
+            version,
+            Json::string(text)
+        )
+    };
+    client.send(method, &params);
+    let published = client.receive();
+    assert_eq!(
+        published.get("method").and_then(Json::as_str),
+        Some("textDocument/publishDiagnostics")
+    );
+    published
+        .get("params")
+        .unwrap()
+        .get("diagnostics")
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn test_session() {
+    let mut client = Client::start();
+    let initialized = client.request("initialize", "{\"capabilities\":{}}");
+    let capabilities = initialized.get("capabilities").unwrap();
+    assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+    client.send("initialized", "{}");
+
+    let source =
+        "type Point: (int(x), int(y),)\n\ncalc start: () {\n  [Point(1, 2)] => print;\n}\n";
+    assert_eq!(open(&mut client, source, 1), Json::Array(Vec::new()));
+
+    let hover = client.request("textDocument/hover", &at(3, 5));
+    assert!(hover
+        .get("contents")
+        .unwrap()
+        .to_string()
+        .contains("type Point: (int(x), int(y),)"));
+
+    let definition = client.request("textDocument/definition", &at(3, 5));
+    assert_eq!(
+        definition.to_string(),
+        format!("[{{\"uri\":\"{}\",\"range\":{{\"start\":{{\"line\":0,\"character\":5}},\"end\":{{\"line\":0,\"character\":10}}}}}}]", URI)
+    );
+
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        &format!("{{\"textDocument\":{{\"uri\":\"{}\"}}}}", URI),
+    );
+    let symbols = symbols.as_array().unwrap();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[1].get("name").and_then(Json::as_str), Some("start"));
+    assert_eq!(symbols[1].get("kind"), Some(&Json::Integer(12)));
+
+    let completion = client.request("textDocument/completion", &at(3, 19));
+    assert_eq!(
+        completion.to_string(),
+        "[{\"label\":\"print\",\"kind\":3,\"detail\":\"built in\"}]"
+    );
+
+    let broken = source.replace("print", "pritn");
+    let diagnostics = open(&mut client, &broken, 2);
+    assert_eq!(
+        diagnostics.as_array().unwrap()[0]
+            .get("message")
+            .and_then(Json::as_str),
+        Some("cannot find `pritn` in this scope\nnote: did you mean `print`?")
+    );
+    let diagnostics = open(&mut client, "type Point: (int(x)", 3);
+    assert_eq!(
+        diagnostics.as_array().unwrap()[0]
+            .get("code")
+            .and_then(Json::as_str),
+        Some("token::UnclosedBrace")
+    );
+
+    assert_eq!(client.request("shutdown", "null"), Json::Null);
+    client.send("exit", "");
+    assert_eq!(client.server.wait().unwrap().code(), Some(0));
+}
+
+#[test]
+fn test_exit_without_shutdown() {
+    let mut client = Client::start();
+    client.send("exit", "");
+    assert_eq!(client.server.wait().unwrap().code(), Some(1));
+}